@@ -0,0 +1,18 @@
+// Compares typing a multi-kilobyte string with and without the layout lookup cache:
+//   cargo run --release --example layout_cache
+
+use std::time::Instant;
+
+use virt_hid::key::Keyboard;
+
+fn main() {
+    let text = "The quick brown fox jumps over the lazy dog. 0123456789\n".repeat(256);
+    for capacity in [0, 1024] {
+        let mut keyboard = Keyboard::new();
+        keyboard.set_layout_cache_capacity(capacity);
+        let start = Instant::now();
+        keyboard.press_string_checked("LAYOUT_US_ENGLISH", &text);
+        let packets = keyboard.take_packets();
+        println!("cache capacity {:>4}: {} bytes, {} packets in {:?}", capacity, text.len(), packets.len(), start.elapsed());
+    }
+}
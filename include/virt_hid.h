@@ -0,0 +1,81 @@
+#ifndef VIRT_HID_H
+#define VIRT_HID_H
+
+/* Generated with cbindgen, do not edit. Regenerate with `cbindgen --config cbindgen.toml --output include/virt_hid.h` */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Opaque handle bundling a HID interface with a virtual keyboard and mouse
+ */
+typedef struct VirtHID VirtHID;
+
+/**
+ * Create a new HID handle from the mouse, keyboard and LED device paths.
+ * Returns null on failure.
+ *
+ * # Safety
+ * All paths must be valid null terminated strings.
+ */
+struct VirtHID *virt_hid_new(const char *mouse, const char *keyboard, const char *led);
+
+/**
+ * Destroy a HID handle created by [virt_hid_new]. Null is ignored.
+ *
+ * # Safety
+ * The handle must have been created by [virt_hid_new] and not already destroyed.
+ */
+void virt_hid_free(struct VirtHID *handle);
+
+/**
+ * Type a string. If layout is null the basic US keyboard table is used,
+ * otherwise the named layout from [Keyboard::available_layouts].
+ * Returns 0 on success and -1 on failure. Nothing is typed if the layout is unknown or can't type all of the text.
+ *
+ * # Safety
+ * The handle must be valid, the text must be a valid null terminated string and the layout must be null or a valid null terminated string.
+ */
+int virt_hid_type_string(struct VirtHID *handle,
+                         const char *layout,
+                         const char *text);
+
+/**
+ * Hold a key down by keycode until [virt_hid_key_up] is called.
+ * Returns 0 on success and -1 on failure.
+ *
+ * # Safety
+ * The handle must be valid.
+ */
+int virt_hid_key_down(struct VirtHID *handle, uint8_t keycode);
+
+/**
+ * Release a key held by [virt_hid_key_down].
+ * Returns 0 on success and -1 on failure.
+ *
+ * # Safety
+ * The handle must be valid.
+ */
+int virt_hid_key_up(struct VirtHID *handle, uint8_t keycode);
+
+/**
+ * Move the mouse by a relative amount.
+ * Returns 0 on success and -1 on failure.
+ *
+ * # Safety
+ * The handle must be valid.
+ */
+int virt_hid_mouse_move(struct VirtHID *handle, int8_t x, int8_t y);
+
+/**
+ * Click a mouse button (0 = left, 1 = right, 2 = middle).
+ * Returns 0 on success and -1 on failure, including any other button.
+ *
+ * # Safety
+ * The handle must be valid.
+ */
+int virt_hid_mouse_click(struct VirtHID *handle, uint32_t button);
+
+#endif  /* VIRT_HID_H */
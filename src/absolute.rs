@@ -0,0 +1,252 @@
+#![warn(missing_docs)]
+
+use std::borrow::Cow;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{descriptor, device::VirtualDevice, mouse::MouseButton};
+
+/// Largest logical X or Y position, see [descriptor::ABSOLUTE_MOUSE]
+pub const ABSOLUTE_MAX: u16 = 32767;
+const ABSOLUTE_REPORT_LEN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// Rectangle on the host desktop in pixels, e.g. a monitor. The desktop's origin is the top left of the primary
+/// monitor, so monitors left of or above it have negative positions.
+pub struct Rect {
+    /// Left edge
+    pub x: i32,
+    /// Top edge
+    pub y: i32,
+    /// Width
+    pub width: u32,
+    /// Height
+    pub height: u32,
+}
+
+impl Rect {
+    /// New
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    /// Check if a pixel is inside
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (x as i64 - self.x as i64, y as i64 - self.y as i64);
+        (0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&y)
+    }
+
+    /// Smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x as i64 + self.width as i64).max(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).max(other.y as i64 + other.height as i64);
+        Rect::new(left, top, (right - left as i64) as u32, (bottom - top as i64) as u32)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// Calibration of an absolute pointer to a host's monitor layout. The host stretches the logical range
+/// (0..=[ABSOLUTE_MAX] on both axes) over an area of its desktop, all monitors by default, and the map turns
+/// pixels on a monitor into the logical position landing on them.
+pub struct ScreenMap {
+    monitors: Vec<Rect>,
+    mapped: Rect,
+}
+
+impl ScreenMap {
+    /// New for a host's monitors, numbered in order, with the logical range mapped over all of them
+    pub fn new(monitors: Vec<Rect>) -> ScreenMap {
+        let mapped = monitors.iter().skip(1).fold(monitors.first().copied().unwrap_or_default(), |mapped, monitor| {
+            mapped.union(monitor)
+        });
+        ScreenMap { monitors, mapped }
+    }
+
+    /// New for a host with a single monitor
+    pub fn single(width: u32, height: u32) -> ScreenMap {
+        ScreenMap::new(vec![Rect::new(0, 0, width, height)])
+    }
+
+    /// Monitors, in order
+    pub fn monitors(&self) -> &[Rect] {
+        &self.monitors
+    }
+
+    /// Set the area the host maps the logical range over, e.g. a single monitor on hosts mapping absolute pointers
+    /// to one screen. Points outside it can't be reached.
+    pub fn set_mapped(&mut self, mapped: Rect) {
+        self.mapped = mapped;
+    }
+
+    /// Area the host maps the logical range over
+    pub fn mapped(&self) -> Rect {
+        self.mapped
+    }
+
+    /// Logical position of a desktop pixel. None if it's outside the mapped area.
+    pub fn to_logical(&self, x: i32, y: i32) -> Option<(u16, u16)> {
+        if !self.mapped.contains(x, y) {
+            return None;
+        }
+        let scale = |offset: i64, len: u32| match len {
+            0 | 1 => 0,
+            len => ((offset * ABSOLUTE_MAX as i64 + (len as i64 - 1) / 2) / (len as i64 - 1)) as u16,
+        };
+        Some((
+            scale(x as i64 - self.mapped.x as i64, self.mapped.width),
+            scale(y as i64 - self.mapped.y as i64, self.mapped.height),
+        ))
+    }
+
+    /// Logical position of a pixel on a monitor, relative to the monitor's top left. None if there is no such monitor,
+    /// or the pixel is off it or outside the mapped area.
+    pub fn monitor_to_logical(&self, monitor: usize, x: i32, y: i32) -> Option<(u16, u16)> {
+        let monitor = self.monitors.get(monitor)?;
+        let (x, y) = (monitor.x.checked_add(x)?, monitor.y.checked_add(y)?);
+        if !monitor.contains(x, y) {
+            return None;
+        }
+        self.to_logical(x, y)
+    }
+}
+
+/// Virtual absolute pointer, jumping straight to positions instead of moving relative to the last one, see
+/// [descriptor::ABSOLUTE_MOUSE]. Register its gadget interface with [crate::HID::register_device] under "absolute"
+/// and send it with [crate::HID::send_device].
+pub struct AbsolutePointer {
+    map: ScreenMap,
+    position: (u16, u16),
+    hold: u8,
+    reports: Vec<[u8; ABSOLUTE_REPORT_LEN]>,
+}
+
+impl AbsolutePointer {
+    /// New, calibrated with a screen map
+    pub fn new(map: ScreenMap) -> AbsolutePointer {
+        AbsolutePointer { map, position: (0, 0), hold: 0, reports: Vec::new() }
+    }
+
+    /// Set the screen map, e.g. after the host's monitor layout changes
+    pub fn set_map(&mut self, map: ScreenMap) {
+        self.map = map;
+    }
+
+    /// Screen map
+    pub fn map(&self) -> &ScreenMap {
+        &self.map
+    }
+
+    /// Logical position of the last move
+    pub fn position(&self) -> (u16, u16) {
+        self.position
+    }
+
+    fn report(&self, buttons: u8) -> [u8; ABSOLUTE_REPORT_LEN] {
+        let [x_lo, x_hi] = self.position.0.to_le_bytes();
+        let [y_lo, y_hi] = self.position.1.to_le_bytes();
+        [buttons, x_lo, x_hi, y_lo, y_hi]
+    }
+
+    /// Move to a logical position, clamped to [ABSOLUTE_MAX]
+    pub fn move_logical(&mut self, x: u16, y: u16) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("move absolute {:?}", (x, y));
+        }
+        self.position = (x.min(ABSOLUTE_MAX), y.min(ABSOLUTE_MAX));
+        self.reports.push(self.report(self.hold));
+    }
+
+    /// Move to a desktop pixel. None if the screen map can't reach it.
+    pub fn move_to(&mut self, x: i32, y: i32) -> Option<()> {
+        let (x, y) = self.map.to_logical(x, y)?;
+        self.move_logical(x, y);
+        Some(())
+    }
+
+    /// Move to a pixel on a monitor, relative to the monitor's top left. None if the screen map can't reach it.
+    pub fn move_to_monitor(&mut self, monitor: usize, x: i32, y: i32) -> Option<()> {
+        let (x, y) = self.map.monitor_to_logical(monitor, x, y)?;
+        self.move_logical(x, y);
+        Some(())
+    }
+
+    /// Click a button at the current position
+    pub fn press_button(&mut self, button: MouseButton) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("press {:?}", button);
+        }
+        self.reports.push(self.report(self.hold | button.to_byte()));
+        self.reports.push(self.report(self.hold));
+    }
+
+    /// Hold a button down
+    pub fn hold_button(&mut self, button: MouseButton) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("hold {:?}", button);
+        }
+        self.hold |= button.to_byte();
+        self.reports.push(self.report(self.hold));
+    }
+
+    /// Release a held button
+    pub fn release_button(&mut self, button: MouseButton) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("release {:?}", button);
+        }
+        self.hold &= !button.to_byte();
+        self.reports.push(self.report(self.hold));
+    }
+
+    /// Take buffered moves and clicks as raw reports
+    pub fn take_reports(&mut self) -> Vec<[u8; ABSOLUTE_REPORT_LEN]> {
+        std::mem::take(&mut self.reports)
+    }
+}
+
+impl VirtualDevice for AbsolutePointer {
+    fn descriptor(&self) -> Cow<'static, [u8]> {
+        Cow::Borrowed(descriptor::ABSOLUTE_MOUSE)
+    }
+
+    fn interface_name(&self) -> &str {
+        "absolute"
+    }
+
+    fn build_reports(&mut self) -> Vec<Vec<u8>> {
+        self.take_reports().iter().map(|report| report.to_vec()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AbsolutePointer, Rect, ScreenMap, ABSOLUTE_MAX};
+    use crate::mouse::MouseButton;
+
+    #[test]
+    fn monitor_calibration() {
+        // 1080p primary with a 1440p monitor to its left
+        let mut map = ScreenMap::new(vec![Rect::new(0, 0, 1920, 1080), Rect::new(-2560, 0, 2560, 1440)]);
+        assert_eq!(map.mapped(), Rect::new(-2560, 0, 4480, 1440));
+        assert_eq!(map.monitor_to_logical(1, 0, 0), Some((0, 0)));
+        assert_eq!(map.monitor_to_logical(0, 1919, 1079), Some((ABSOLUTE_MAX, 24570)));
+        assert_eq!(map.monitor_to_logical(0, 0, 0), map.to_logical(0, 0));
+        assert_eq!(map.monitor_to_logical(0, 1920, 0), None);
+        assert_eq!(map.monitor_to_logical(2, 0, 0), None);
+
+        map.set_mapped(map.monitors()[0]);
+        assert_eq!(map.monitor_to_logical(0, 1919, 1079), Some((ABSOLUTE_MAX, ABSOLUTE_MAX)));
+        assert_eq!(map.monitor_to_logical(1, 0, 0), None);
+
+        let mut pointer = AbsolutePointer::new(map);
+        assert!(pointer.move_to_monitor(1, 10, 10).is_none());
+        pointer.move_to_monitor(0, 0, 1079).unwrap();
+        pointer.press_button(MouseButton::Left);
+        assert_eq!(pointer.take_reports(), vec![[0, 0, 0, 0xFF, 0x7F], [1, 0, 0, 0xFF, 0x7F], [0, 0, 0, 0xFF, 0x7F]]);
+    }
+}
@@ -0,0 +1,240 @@
+#![warn(missing_docs)]
+
+use serde::{Serialize, Deserialize};
+
+use crate::config::HostOS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Common OS and application shortcut, resolved to the chords for a host OS with [Accel::chords] so scripts read by
+/// intent and work when retargeted to another OS. Linux follows GNOME, falling back to KDE where GNOME has no default.
+pub enum Accel {
+    /// Copy the selection
+    Copy,
+    /// Cut the selection
+    Cut,
+    /// Paste
+    Paste,
+    /// Undo
+    Undo,
+    /// Redo
+    Redo,
+    /// Select all
+    SelectAll,
+    /// Find in page or document
+    Find,
+    /// Save
+    Save,
+    /// Open a file
+    Open,
+    /// New document or window
+    New,
+    /// Print
+    Print,
+    /// Close the tab or document
+    Close,
+    /// Quit the application
+    Quit,
+    /// Open a browser tab
+    NewTab,
+    /// Reopen the last closed browser tab
+    ReopenTab,
+    /// Next tab
+    NextTab,
+    /// Previous tab
+    PreviousTab,
+    /// Reload the page
+    Refresh,
+    /// Focus the browser address bar
+    AddressBar,
+    /// Zoom in
+    ZoomIn,
+    /// Zoom out
+    ZoomOut,
+    /// Reset the zoom
+    ZoomReset,
+    /// Go back in browser history
+    Back,
+    /// Go forward in browser history
+    Forward,
+    /// Toggle bold text
+    Bold,
+    /// Toggle italic text
+    Italic,
+    /// Toggle underlined text
+    Underline,
+    /// Move the cursor to the start of the line
+    LineStart,
+    /// Move the cursor to the end of the line
+    LineEnd,
+    /// Move the cursor to the start of the document
+    DocumentStart,
+    /// Move the cursor to the end of the document
+    DocumentEnd,
+    /// Move the cursor a word left
+    WordLeft,
+    /// Move the cursor a word right
+    WordRight,
+    /// Delete the word before the cursor
+    DeleteWord,
+    /// Screenshot of the whole screen
+    Screenshot,
+    /// Screenshot of a selected region
+    ScreenshotRegion,
+    /// Lock the screen
+    LockScreen,
+    /// Open the task manager, Force Quit on MacOS
+    TaskManager,
+    /// Open the system search, Spotlight on MacOS
+    Search,
+    /// Open a prompt for running a command
+    RunDialog,
+    /// Open a terminal
+    Terminal,
+    /// Open the file manager
+    FileManager,
+    /// Show the desktop
+    ShowDesktop,
+    /// Switch to the previously used application
+    SwitchApp,
+    /// Minimize the window
+    Minimize,
+    /// Maximize the window, full screen on MacOS
+    Maximize,
+}
+
+impl Accel {
+    /// Every shortcut
+    pub const ALL: [Accel; 46] = [
+        Accel::Copy, Accel::Cut, Accel::Paste, Accel::Undo, Accel::Redo, Accel::SelectAll, Accel::Find, Accel::Save,
+        Accel::Open, Accel::New, Accel::Print, Accel::Close, Accel::Quit, Accel::NewTab, Accel::ReopenTab,
+        Accel::NextTab, Accel::PreviousTab, Accel::Refresh, Accel::AddressBar, Accel::ZoomIn, Accel::ZoomOut,
+        Accel::ZoomReset, Accel::Back, Accel::Forward, Accel::Bold, Accel::Italic, Accel::Underline, Accel::LineStart,
+        Accel::LineEnd, Accel::DocumentStart, Accel::DocumentEnd, Accel::WordLeft, Accel::WordRight,
+        Accel::DeleteWord, Accel::Screenshot, Accel::ScreenshotRegion, Accel::LockScreen, Accel::TaskManager,
+        Accel::Search, Accel::RunDialog, Accel::Terminal, Accel::FileManager, Accel::ShowDesktop, Accel::SwitchApp,
+        Accel::Minimize, Accel::Maximize,
+    ];
+
+    /// Chords pressed in order for the shortcut on a host OS, see [crate::key::KeyPacket::parse].
+    /// None if the host has no shortcut for it.
+    pub fn chords(&self, host: HostOS) -> Option<Vec<String>> {
+        let mac = host == HostOS::MacOS;
+        // application shortcuts use Cmd on MacOS where other hosts use Ctrl
+        let primary = if mac { "GUI" } else { "CTRL" };
+        let app_key = match self {
+            Accel::Copy => Some("c"),
+            Accel::Cut => Some("x"),
+            Accel::Paste => Some("v"),
+            Accel::Undo => Some("z"),
+            Accel::SelectAll => Some("a"),
+            Accel::Find => Some("f"),
+            Accel::Save => Some("s"),
+            Accel::Open => Some("o"),
+            Accel::New => Some("n"),
+            Accel::Print => Some("p"),
+            Accel::Close => Some("w"),
+            Accel::NewTab => Some("t"),
+            Accel::ReopenTab => Some("SHIFT+t"),
+            Accel::AddressBar => Some("l"),
+            Accel::ZoomIn => Some("="),
+            Accel::ZoomOut => Some("-"),
+            Accel::ZoomReset => Some("0"),
+            Accel::Bold => Some("b"),
+            Accel::Italic => Some("i"),
+            Accel::Underline => Some("u"),
+            _ => None,
+        };
+        if let Some(key) = app_key {
+            return Some(vec![format!("{}+{}", primary, key)]);
+        }
+
+        let chords: &[&str] = match (self, host) {
+            (Accel::Redo, HostOS::Windows) => &["CTRL+y"],
+            (Accel::Redo, HostOS::Linux) => &["CTRL+SHIFT+z"],
+            (Accel::Redo, HostOS::MacOS) => &["GUI+SHIFT+z"],
+            (Accel::Quit, HostOS::Windows) => &["ALT+F4"],
+            (Accel::Quit, HostOS::Linux) => &["CTRL+q"],
+            (Accel::Quit, HostOS::MacOS) => &["GUI+q"],
+            (Accel::NextTab, _) => &["CTRL+Tab"],
+            (Accel::PreviousTab, _) => &["CTRL+SHIFT+Tab"],
+            (Accel::Refresh, HostOS::MacOS) => &["GUI+r"],
+            (Accel::Refresh, _) => &["F5"],
+            (Accel::Back, HostOS::MacOS) => &["GUI+["],
+            (Accel::Back, _) => &["ALT+LeftArrow"],
+            (Accel::Forward, HostOS::MacOS) => &["GUI+]"],
+            (Accel::Forward, _) => &["ALT+RightArrow"],
+            (Accel::LineStart, HostOS::MacOS) => &["GUI+LeftArrow"],
+            (Accel::LineStart, _) => &["Home"],
+            (Accel::LineEnd, HostOS::MacOS) => &["GUI+RightArrow"],
+            (Accel::LineEnd, _) => &["End"],
+            (Accel::DocumentStart, HostOS::MacOS) => &["GUI+UpArrow"],
+            (Accel::DocumentStart, _) => &["CTRL+Home"],
+            (Accel::DocumentEnd, HostOS::MacOS) => &["GUI+DownArrow"],
+            (Accel::DocumentEnd, _) => &["CTRL+End"],
+            (Accel::WordLeft, HostOS::MacOS) => &["ALT+LeftArrow"],
+            (Accel::WordLeft, _) => &["CTRL+LeftArrow"],
+            (Accel::WordRight, HostOS::MacOS) => &["ALT+RightArrow"],
+            (Accel::WordRight, _) => &["CTRL+RightArrow"],
+            (Accel::DeleteWord, HostOS::MacOS) => &["ALT+Backspace"],
+            (Accel::DeleteWord, _) => &["CTRL+Backspace"],
+            (Accel::Screenshot, HostOS::Windows) => &["GUI+PrintScreen"],
+            (Accel::Screenshot, HostOS::Linux) => &["PrintScreen"],
+            (Accel::Screenshot, HostOS::MacOS) => &["GUI+SHIFT+3"],
+            (Accel::ScreenshotRegion, HostOS::Windows) => &["GUI+SHIFT+s"],
+            (Accel::ScreenshotRegion, HostOS::Linux) => &["SHIFT+PrintScreen"],
+            (Accel::ScreenshotRegion, HostOS::MacOS) => &["GUI+SHIFT+4"],
+            (Accel::LockScreen, HostOS::MacOS) => &["CTRL+GUI+q"],
+            (Accel::LockScreen, _) => &["GUI+l"],
+            (Accel::TaskManager, HostOS::Windows) => &["CTRL+SHIFT+Escape"],
+            (Accel::TaskManager, HostOS::Linux) => &["CTRL+Escape"],
+            (Accel::TaskManager, HostOS::MacOS) => &["GUI+ALT+Escape"],
+            (Accel::Search, HostOS::Windows) => &["GUI+s"],
+            (Accel::Search, HostOS::Linux) => &["GUI"],
+            (Accel::Search, HostOS::MacOS) => &["GUI+Spacebar"],
+            (Accel::RunDialog, HostOS::Windows) => &["GUI+r"],
+            (Accel::RunDialog, HostOS::Linux) => &["ALT+F2"],
+            (Accel::RunDialog, HostOS::MacOS) => &["GUI+Spacebar"],
+            // Windows Terminal from the quick link menu
+            (Accel::Terminal, HostOS::Windows) => &["GUI+x", "i"],
+            (Accel::Terminal, HostOS::Linux) => &["CTRL+ALT+t"],
+            (Accel::FileManager, HostOS::MacOS) => &["GUI+ALT+Spacebar"],
+            (Accel::FileManager, _) => &["GUI+e"],
+            (Accel::ShowDesktop, HostOS::MacOS) => &["F11"],
+            (Accel::ShowDesktop, _) => &["GUI+d"],
+            (Accel::SwitchApp, HostOS::MacOS) => &["GUI+Tab"],
+            (Accel::SwitchApp, _) => &["ALT+Tab"],
+            (Accel::Minimize, HostOS::Windows) => &["GUI+DownArrow"],
+            (Accel::Minimize, HostOS::Linux) => &["GUI+h"],
+            (Accel::Minimize, HostOS::MacOS) => &["GUI+m"],
+            (Accel::Maximize, HostOS::MacOS) => &["CTRL+GUI+f"],
+            (Accel::Maximize, _) => &["GUI+UpArrow"],
+            _ => return None,
+        };
+        Some(chords.iter().map(|chord| chord.to_string()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Accel;
+    use crate::{config::HostOS, key::{Keyboard, KeyPacket}};
+
+    #[test]
+    fn accel_chords() {
+        for host in [HostOS::Linux, HostOS::Windows, HostOS::MacOS] {
+            for accel in Accel::ALL {
+                for chord in accel.chords(host).into_iter().flatten() {
+                    assert!(KeyPacket::parse(&chord).is_some(), "{:?} {:?} {}", accel, host, chord);
+                }
+            }
+        }
+        assert_eq!(Accel::Copy.chords(HostOS::MacOS), Some(vec!["GUI+c".to_string()]));
+        assert_eq!(Accel::Terminal.chords(HostOS::MacOS), None);
+
+        let mut keyboard = Keyboard::new();
+        keyboard.press_accel(Accel::Terminal, HostOS::Windows).unwrap();
+        let packets: Vec<KeyPacket> = keyboard.take_packets().into_iter().filter(|packet| !packet.is_empty()).collect();
+        assert_eq!(packets, vec![KeyPacket::parse("GUI+x").unwrap(), KeyPacket::parse("i").unwrap()]);
+        assert!(keyboard.press_accel(Accel::Terminal, HostOS::MacOS).is_none());
+    }
+}
@@ -0,0 +1,152 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+#[cfg(feature = "hid")]
+use std::time::Instant;
+
+use crate::{key::{BootOverflow, KeyPacket, ReportProtocol}, mouse::MouseFormat};
+#[cfg(feature = "hid")]
+use crate::key::{LEDState, SpecialKey};
+
+/// Transport key and mouse packets are sent over and LED states received from. [crate::key::Keyboard] and
+/// [crate::mouse::Mouse] send to any backend, so they can target more than the hidg character devices of the `hid` feature's
+/// `HID`, e.g. a test double, a network bridge or a microcontroller's USB stack.
+pub trait HidBackend {
+    /// Send a raw key packet
+    fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// Send a raw mouse packet
+    fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()>;
+
+    /// Receive a raw LED states packet with a timeout, None if none arrived.
+    /// [crate::key::LEDStatePacket] provides an abstraction for raw state packets.
+    fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>>;
+
+    /// Send a raw report to a named interface, e.g. one registered with `HID::register_device`. Unsupported by default,
+    /// for backends with only a keyboard and mouse.
+    fn send_device_report(&mut self, name: &str, _data: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("no interface registered for {}", name)))
+    }
+
+    /// Report protocol of the keyboard interface, [ReportProtocol::Bitmap] by default
+    fn keyboard_protocol(&self) -> ReportProtocol {
+        ReportProtocol::Bitmap
+    }
+
+    /// Send a key packet in the backend's [HidBackend::keyboard_protocol]. Boot reports can't carry more than 6 keys,
+    /// so larger packets send the phantom state (see [BootOverflow::Rollover]). Every key packet sent outside a
+    /// [crate::key::Keyboard], e.g. by schedules, transactions and releases, goes through here.
+    fn send_key_report(&mut self, packet: &KeyPacket) -> io::Result<()> {
+        match self.keyboard_protocol() {
+            ReportProtocol::Bitmap => self.send_key_packet(packet.as_bytes()),
+            ReportProtocol::Boot => {
+                for report in packet.to_boot_reports(BootOverflow::Rollover)? {
+                    self.send_key_packet(&report)?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    /// Release every key and button in the backend's formats. Both are attempted, the first error is returned.
+    fn release_all(&mut self) -> io::Result<()> {
+        let keys = self.send_key_report(&KeyPacket::new());
+        let buttons = self.send_mouse_report(&[0; 5]);
+        keys.and(buttons)
+    }
+
+    /// Format of the mouse interface's reports, None by default for 5 byte packets (see
+    /// [crate::mouse::ReportLayout::mouse])
+    fn mouse_format(&self) -> Option<&MouseFormat> {
+        None
+    }
+
+    /// Send a 5 byte mouse packet converted to the backend's [HidBackend::mouse_format]. Every mouse packet sent
+    /// outside a [crate::mouse::Mouse] with its own format, e.g. by schedules, transactions and releases, goes through here.
+    fn send_mouse_report(&mut self, packet: &[u8; 5]) -> io::Result<()> {
+        match self.mouse_format().map(|format| format.encode(packet)) {
+            Some(report) => self.send_mouse_packet(&report),
+            None => self.send_mouse_packet(packet),
+        }
+    }
+
+    /// Last LED states packet received, if any. None by default, for backends that don't keep it.
+    fn last_led_state(&self) -> Option<u8> {
+        None
+    }
+
+    /// Get the last LED states packet received, waiting for the first one with a timeout if none has been received yet
+    fn wait_led_state(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        match self.last_led_state() {
+            Some(data) => Ok(Some(data)),
+            None => self.receive_states_packet(timeout),
+        }
+    }
+}
+
+/// Lock key round trips on any backend
+#[cfg(feature = "hid")]
+pub(crate) trait LockKeys: HidBackend {
+    /// Input oracle toggling NumLock, see `HID::handshake`
+    fn handshake(&mut self, timeout: Duration) -> io::Result<Duration> {
+        let before = self.last_led_state();
+        let start = Instant::now();
+        let echoed = match self.toggle_lock(&SpecialKey::NumLockAndClear, &LEDState::NumLock, before, start + timeout) {
+            Ok(echoed) => echoed,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                // the host may have toggled it without echoing, so tap it again to leave it as it was
+                self.tap_key(&SpecialKey::NumLockAndClear)?;
+                return Err(e);
+            },
+            Err(e) => return Err(e),
+        };
+        let rtt = start.elapsed();
+        self.toggle_lock(&SpecialKey::NumLockAndClear, &LEDState::NumLock, Some(echoed), Instant::now() + timeout)?;
+        Ok(rtt)
+    }
+
+    /// Heartbeat timing how fast the host answers, see `HID::heartbeat`
+    fn heartbeat(&mut self, timeout: Duration) -> io::Result<Option<Duration>> {
+        self.lock_round_trip(&SpecialKey::ScrollLock, &LEDState::ScrollLock, timeout)
+    }
+
+    /// Toggle a lock key and back, returning the round trip of the first echo, None if the host didn't echo it
+    fn lock_round_trip(&mut self, key: &SpecialKey, led: &LEDState, timeout: Duration) -> io::Result<Option<Duration>> {
+        let start = Instant::now();
+        match self.toggle_lock(key, led, self.last_led_state(), start + timeout) {
+            Ok(echoed) => {
+                let rtt = start.elapsed();
+                self.toggle_lock(key, led, Some(echoed), Instant::now() + timeout)?;
+                Ok(Some(rtt))
+            },
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                // the host may have toggled it without echoing, so tap it again to leave it as it was
+                self.tap_key(key)?;
+                Ok(None)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Tap a key and release it, in the backend's report protocol
+    fn tap_key(&mut self, key: &SpecialKey) -> io::Result<()> {
+        self.send_key_report(&KeyPacket::from_special(key))?;
+        self.send_key_report(&KeyPacket::new())
+    }
+
+    /// Tap a lock key and wait for an LED state with its LED changed from before (or any LED state if before is unknown)
+    fn toggle_lock(&mut self, key: &SpecialKey, led: &LEDState, before: Option<u8>, deadline: Instant) -> io::Result<u8> {
+        self.tap_key(key)?;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.receive_states_packet(remaining)? {
+                Some(data) if before.is_none_or(|before| led.get_state(before) != led.get_state(data)) => return Ok(data),
+                Some(_) if !remaining.is_zero() => (),
+                _ => return Err(io::Error::new(io::ErrorKind::TimedOut, format!("host didn't echo {:?}", led))),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "hid")]
+impl<B: HidBackend + ?Sized> LockKeys for B {}
@@ -0,0 +1,109 @@
+#![warn(missing_docs)]
+
+use std::{
+    error::Error,
+    fmt,
+    io,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by operations cancelled through a [CancellationToken]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Check if an error was caused by a cancellation
+pub fn is_cancelled_error(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+}
+
+#[derive(Debug, Clone, Default)]
+/// Handle for cancelling long operations from another thread, e.g. a UI thread. Clones share the same state.
+/// Cancellable operations stop between reports, release everything they pressed and fail with a [Cancelled] error.
+pub struct CancellationToken {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancellationToken {
+    /// New, not cancelled
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel operations using this token, waking any waiting in [CancellationToken::sleep]
+    pub fn cancel(&self) {
+        let (cancelled, wake) = &*self.state;
+        *cancelled.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wake.notify_all();
+    }
+
+    /// Allow operations using this token to run again
+    pub fn reset(&self) {
+        let (cancelled, _) = &*self.state;
+        *cancelled.lock().unwrap_or_else(|e| e.into_inner()) = false;
+    }
+
+    /// Check if cancelled
+    pub fn is_cancelled(&self) -> bool {
+        let (cancelled, _) = &*self.state;
+        *cancelled.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Fail with a [Cancelled] error if cancelled
+    pub fn check(&self) -> io::Result<()> {
+        match self.is_cancelled() {
+            true => Err(io::Error::other(Cancelled)),
+            false => Ok(()),
+        }
+    }
+
+    /// Sleep for a duration, waking early if cancelled. Fails with a [Cancelled] error if cancelled.
+    pub fn sleep(&self, duration: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + duration;
+        let (cancelled, wake) = &*self.state;
+        let mut guard = cancelled.lock().unwrap_or_else(|e| e.into_inner());
+        while !*guard {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            if wait.is_zero() {
+                return Ok(());
+            }
+            guard = wake.wait_timeout(guard, wait).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        Err(io::Error::other(Cancelled))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::{Duration, Instant}};
+
+    use super::{is_cancelled_error, CancellationToken};
+
+    #[test]
+    fn cancel_wakes_sleep() {
+        let token = CancellationToken::new();
+        assert!(token.sleep(Duration::from_millis(1)).is_ok());
+
+        let canceller = token.clone();
+        let start = Instant::now();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let err = token.sleep(Duration::from_secs(10)).unwrap_err();
+        assert!(is_cancelled_error(&err));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        handle.join().unwrap();
+
+        token.reset();
+        assert!(token.check().is_ok());
+    }
+}
@@ -0,0 +1,203 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+
+use crate::{key::KeyPacket, macros::{Macro, MacroAction, MacroEvent}};
+
+/// usbmon timestamps are the low 16 bits of the seconds in microseconds, so they wrap around
+const USBMON_WRAP: u64 = 0x10000 * 1_000_000;
+const BOOT_KEYBOARD_LEN: usize = 8;
+const BOOT_MOUSE_LEN: std::ops::RangeInclusive<usize> = 3..=5;
+/// Keycode reported in every key slot when a keyboard has more keys held than it can report
+const ERROR_ROLL_OVER: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Interrupt IN transfer captured from a device
+pub struct Transfer {
+    /// Offset from the start of the capture
+    pub at: Duration,
+    /// Source endpoint as "bus:device:endpoint", e.g. "1:3:1"
+    pub endpoint: String,
+    /// Report data
+    pub data: Vec<u8>,
+}
+
+fn invalid_line(line: usize, what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("capture line {}: {}", line + 1, what))
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex: String = hex.chars().filter(|c| !c.is_whitespace() && *c != ':').collect();
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Normalise an endpoint address, dropping leading zeros from each part ("1:003:1" and "1.3.1" become "1:3:1")
+fn endpoint_name(parts: &[&str]) -> Option<String> {
+    let parts = parts.iter().map(|part| part.parse::<u32>().ok().map(|part| part.to_string())).collect::<Option<Vec<_>>>()?;
+    Some(parts.join(":"))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Captured session of a real keyboard or mouse, replayable as a [Macro] with its original timing
+pub struct Capture {
+    transfers: Vec<Transfer>,
+}
+
+impl Capture {
+    /// Parse the text output of usbmon (`cat /sys/kernel/debug/usb/usbmon/<bus>u`).
+    /// Only successful interrupt IN completions with data are kept.
+    pub fn from_usbmon(text: &str) -> io::Result<Capture> {
+        let mut transfers = Vec::new();
+        let mut start = None;
+        let (mut previous, mut wrapped) = (0, 0);
+        for (i, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 7 || fields[2] != "C" || fields[6] != "=" {
+                continue;
+            }
+            let address: Vec<&str> = fields[3].split(':').collect();
+            if address[0] != "Ii" || fields[4].split(':').next() != Some("0") {
+                continue;
+            }
+            let endpoint = endpoint_name(&address[1..]).ok_or_else(|| invalid_line(i, "bad address"))?;
+            let stamp: u64 = fields[1].parse().map_err(|_| invalid_line(i, "bad timestamp"))?;
+            let length: usize = fields[5].parse().map_err(|_| invalid_line(i, "bad length"))?;
+            let mut data = parse_hex(&fields[7..].concat()).ok_or_else(|| invalid_line(i, "bad data"))?;
+            data.truncate(length);
+
+            if stamp < previous {
+                wrapped += USBMON_WRAP;
+            }
+            previous = stamp;
+            let stamp = stamp + wrapped;
+            let start = *start.get_or_insert(stamp);
+            transfers.push(Transfer { at: Duration::from_micros(stamp - start), endpoint, data });
+        }
+        Ok(Capture { transfers })
+    }
+
+    /// Parse tab separated fields exported by Wireshark's tshark, in the order time, source and data:
+    /// `tshark -r session.pcapng -Y usbhid.data -T fields -e frame.time_relative -e usb.src -e usbhid.data`.
+    /// usb.capdata works in place of usbhid.data. Transfers sent by the host are skipped.
+    pub fn from_tshark(text: &str) -> io::Result<Capture> {
+        let mut transfers = Vec::new();
+        let mut start = None;
+        for (i, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            if fields.len() < 3 || fields[2].is_empty() || fields[1] == "host" {
+                continue;
+            }
+            let time: f64 = fields[0].parse().map_err(|_| invalid_line(i, "bad time"))?;
+            let at = Duration::try_from_secs_f64(time).map_err(|_| invalid_line(i, "bad time"))?;
+            let address: Vec<&str> = fields[1].split('.').collect();
+            let endpoint = endpoint_name(&address).ok_or_else(|| invalid_line(i, "bad source"))?;
+            let data = parse_hex(fields[2]).ok_or_else(|| invalid_line(i, "bad data"))?;
+            let start = *start.get_or_insert(at);
+            transfers.push(Transfer { at: at.saturating_sub(start), endpoint, data });
+        }
+        Ok(Capture { transfers })
+    }
+
+    /// Load a capture file, parsed as tshark fields if its lines are tab separated and as usbmon text otherwise
+    pub fn load(path: &str) -> io::Result<Capture> {
+        let text = std::fs::read_to_string(path)?;
+        match text.lines().any(|line| line.contains('\t')) {
+            true => Capture::from_tshark(&text),
+            false => Capture::from_usbmon(&text),
+        }
+    }
+
+    /// Captured transfers
+    pub fn transfers(&self) -> &[Transfer] {
+        &self.transfers
+    }
+
+    /// Endpoints that sent transfers, in the order they first appear
+    pub fn endpoints(&self) -> Vec<&str> {
+        let mut endpoints: Vec<&str> = Vec::new();
+        for transfer in &self.transfers {
+            if !endpoints.contains(&transfer.endpoint.as_str()) {
+                endpoints.push(&transfer.endpoint);
+            }
+        }
+        endpoints
+    }
+
+    /// Convert into a macro of boot protocol key and mouse reports, keeping the delays between them.
+    /// Keyboard and mouse select the endpoints to replay, None guesses from the report length (8 bytes for a
+    /// keyboard, 3 to 5 bytes for a mouse). Reports of other lengths and keyboard roll over errors are skipped.
+    pub fn to_macro(&self, keyboard: Option<&str>, mouse: Option<&str>) -> Macro {
+        let mut recorded = Macro::new();
+        let mut previous = None;
+        for transfer in &self.transfers {
+            let endpoint = Some(transfer.endpoint.as_str());
+            let is_keyboard = match keyboard {
+                Some(_) => endpoint == keyboard,
+                None => endpoint != mouse && transfer.data.len() == BOOT_KEYBOARD_LEN,
+            };
+            let is_mouse = match mouse {
+                Some(_) => endpoint == mouse,
+                None => endpoint != keyboard && BOOT_MOUSE_LEN.contains(&transfer.data.len()),
+            };
+            let action = if is_keyboard && transfer.data.len() == BOOT_KEYBOARD_LEN {
+                if transfer.data[2..].contains(&ERROR_ROLL_OVER) {
+                    continue;
+                }
+                match KeyPacket::from_bytes(&transfer.data) {
+                    Some(packet) => MacroAction::Key(packet),
+                    None => continue,
+                }
+            } else if is_mouse && BOOT_MOUSE_LEN.contains(&transfer.data.len()) {
+                let mut packet = [0; 5];
+                packet[..transfer.data.len()].copy_from_slice(&transfer.data);
+                MacroAction::Mouse(packet)
+            } else {
+                continue;
+            };
+            let delay = previous.map(|previous| transfer.at.saturating_sub(previous)).unwrap_or(Duration::ZERO);
+            previous = Some(transfer.at);
+            recorded.push(MacroEvent::new(delay, action));
+        }
+        recorded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Capture;
+    use crate::{key::KeyPacket, macros::MacroAction};
+
+    #[test]
+    fn imports_captures() {
+        let usbmon = "\
+ffff8880 65535990000 S Ii:1:003:1 -115:8 8 <
+ffff8880 65535999000 C Ii:1:003:1 0:8 8 = 02000400 00000000
+ffff8880 65535999000 S Ii:1:003:1 -115:8 8 <
+ffff8881 3000 C Ii:1:003:1 0:8 8 = 00000000 00000000
+ffff8882 4000 C Ii:1:003:1 0:8 8 = 00000101 01010101
+ffff8883 9000 C Ii:1:004:2 0:1 4 = 010500ff
+ffff8884 9500 C Io:1:003:0 0:8 1 = 01
+";
+        let capture = Capture::from_usbmon(usbmon).unwrap();
+        assert_eq!(capture.endpoints(), vec!["1:3:1", "1:4:2"]);
+        assert_eq!(capture.transfers()[1].at, Duration::from_micros(4_000));
+        let recorded = capture.to_macro(None, None);
+        let actions: Vec<&MacroAction> = recorded.events().iter().map(|event| &event.action).collect();
+        assert_eq!(actions, vec![
+            &MacroAction::Key(KeyPacket::parse("LSHIFT+a").unwrap()),
+            &MacroAction::Key(KeyPacket::new()),
+            &MacroAction::Mouse([0x01, 0x05, 0x00, 0xFF, 0x00]),
+        ]);
+        assert_eq!(capture.to_macro(Some("1:3:1"), Some("1:3:1")).len(), 2);
+
+        let tshark = "0.000000\t1.3.1\t00:00:04:00:00:00:00:00\n0.250000\thost\t01\n0.500000\t1.3.1\t0000000000000000\n";
+        let recorded = Capture::from_tshark(tshark).unwrap().to_macro(Some("1:3:1"), None);
+        assert_eq!(recorded.events()[1].delay, Duration::from_millis(500));
+        assert!(Capture::from_tshark("x\t1.3.1\t00").is_err());
+    }
+}
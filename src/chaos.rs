@@ -0,0 +1,257 @@
+#![warn(missing_docs)]
+
+use std::{io, sync::Arc, time::Duration};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    HID,
+    HidBackend,
+    clock::{Clock, SystemClock},
+    key::{KeyPacket, ReportProtocol},
+    mouse::MouseFormat,
+    rng::{next_f64, BoxedRng, RngCore, SplitMix64},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// Probabilities of faults a [ChaosHID] injects into each packet, from 0 to 1. The default injects nothing.
+pub struct ChaosConfig {
+    /// Probability of failing the send with an error, without sending
+    pub fail: f64,
+    /// Probability of silently dropping the packet
+    pub drop: f64,
+    /// Probability of sending the packet twice
+    pub duplicate: f64,
+    /// Probability of waiting before sending
+    pub delay: f64,
+    /// Longest wait before a delayed send, the wait is drawn uniformly up to it
+    pub max_delay: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Faults injected by a [ChaosHID]
+pub struct ChaosStats {
+    /// Packets sent, counting duplicates once
+    pub sent: u64,
+    /// Sends failed with an injected error
+    pub failed: u64,
+    /// Packets dropped
+    pub dropped: u64,
+    /// Packets sent twice
+    pub duplicated: u64,
+    /// Packets delayed
+    pub delayed: u64,
+}
+
+/// Report sent through a [ChaosHID]
+enum Packet<'a> {
+    Key(&'a [u8]),
+    Mouse(&'a [u8]),
+    Device(&'a str, &'a [u8]),
+}
+
+/// Decorates a HID interface with random failures, drops, duplicates and delays, so retry and verification logic can be
+/// tested without an unreliable physical setup. The same config and seed always inject the same faults. As a
+/// [HidBackend] it can stand in for the interface, e.g. flushing a [crate::key::Keyboard] through it; LED states are
+/// received without faults.
+pub struct ChaosHID {
+    hid: HID,
+    config: ChaosConfig,
+    rng: BoxedRng,
+    clock: Arc<dyn Clock>,
+    stats: ChaosStats,
+}
+
+impl ChaosHID {
+    /// New, injecting faults drawn from a seed
+    pub fn new(hid: HID, config: ChaosConfig, seed: u64) -> ChaosHID {
+        ChaosHID::with_rng(hid, config, SplitMix64::new(seed))
+    }
+
+    /// New, injecting faults drawn from a random number generator
+    pub fn with_rng(hid: HID, config: ChaosConfig, rng: impl RngCore + Send + 'static) -> ChaosHID {
+        ChaosHID { hid, config, rng: Box::new(rng), clock: Arc::new(SystemClock), stats: ChaosStats::default() }
+    }
+
+    /// Set the fault probabilities
+    pub fn set_config(&mut self, config: ChaosConfig) {
+        self.config = config;
+    }
+
+    /// Fault probabilities
+    pub fn config(&self) -> ChaosConfig {
+        self.config
+    }
+
+    /// Set the clock delays wait on
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Faults injected so far
+    pub fn stats(&self) -> ChaosStats {
+        self.stats
+    }
+
+    /// Decorated HID interface, sending through it directly injects no faults
+    pub fn hid(&mut self) -> &mut HID {
+        &mut self.hid
+    }
+
+    /// Get the decorated HID interface
+    pub fn into_inner(self) -> HID {
+        self.hid
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && next_f64(self.rng.as_mut()) < probability
+    }
+
+    fn send(&mut self, packet: Packet<'_>) -> io::Result<()> {
+        if self.chance(self.config.fail) {
+            self.stats.failed += 1;
+            return Err(io::Error::other("injected failure"));
+        }
+        if self.chance(self.config.drop) {
+            self.stats.dropped += 1;
+            return Ok(());
+        }
+        if self.chance(self.config.delay) {
+            self.stats.delayed += 1;
+            let delay = self.config.max_delay.mul_f64(next_f64(self.rng.as_mut()));
+            self.clock.sleep(delay);
+        }
+        let copies = match self.chance(self.config.duplicate) {
+            true => {
+                self.stats.duplicated += 1;
+                2
+            },
+            false => 1,
+        };
+        for _ in 0..copies {
+            match packet {
+                Packet::Key(data) => self.hid.send_key_packet(data)?,
+                Packet::Mouse(data) => self.hid.send_mouse_packet(data)?,
+                Packet::Device(name, data) => self.hid.send_device_report(name, data)?,
+            }
+        }
+        self.stats.sent += 1;
+        Ok(())
+    }
+
+    /// Send raw key packet, possibly with a fault
+    pub fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        self.send(Packet::Key(data))
+    }
+
+    /// Send raw mouse packet, possibly with a fault
+    pub fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        self.send(Packet::Mouse(data))
+    }
+
+    /// Send raw report to a registered interface, possibly with a fault, see [HID::register_device]
+    pub fn send_device_report(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        self.send(Packet::Device(name, data))
+    }
+
+    /// Send key packets in order, e.g. from [crate::key::Keyboard::take_packets], stopping at the first error
+    pub fn send_packets(&mut self, packets: &[KeyPacket]) -> io::Result<()> {
+        packets.iter().try_for_each(|packet| self.send_key_packet(packet.as_bytes()))
+    }
+}
+
+impl HidBackend for ChaosHID {
+    fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        ChaosHID::send_key_packet(self, data)
+    }
+
+    fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        ChaosHID::send_mouse_packet(self, data)
+    }
+
+    fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        self.hid.receive_states_packet(timeout)
+    }
+
+    fn send_device_report(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        ChaosHID::send_device_report(self, name, data)
+    }
+
+    fn keyboard_protocol(&self) -> ReportProtocol {
+        self.hid.keyboard_protocol()
+    }
+
+    fn mouse_format(&self) -> Option<&MouseFormat> {
+        self.hid.mouse_format()
+    }
+
+    fn last_led_state(&self) -> Option<u8> {
+        self.hid.last_led_state()
+    }
+
+    fn wait_led_state(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        self.hid.wait_led_state(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn injects_faults() {
+        use std::{sync::Arc, time::Duration};
+
+        use super::{ChaosConfig, ChaosHID};
+        use crate::{clock::VirtualClock, HID};
+
+        let config = ChaosConfig { fail: 0.1, drop: 0.2, duplicate: 0.2, delay: 0.5, max_delay: Duration::from_millis(100) };
+        let run = |seed| {
+            let clock = VirtualClock::new();
+            let mut chaos = ChaosHID::new(HID::new("", "", "").unwrap(), config, seed);
+            chaos.set_clock(Arc::new(clock.clone()));
+            let errors = (0..200).filter(|_| chaos.send_mouse_packet(&[0; 5]).is_err()).count();
+            let stats = chaos.stats();
+            assert_eq!(errors as u64, stats.failed);
+            assert_eq!(stats.sent + stats.failed + stats.dropped, 200);
+            assert!(stats.failed > 0 && stats.dropped > 0 && stats.duplicated > 0 && stats.delayed > 0);
+            assert!(clock.elapsed() > Duration::ZERO);
+            let written = std::fs::read(chaos.hid().get_mouse_path()).unwrap().len() as u64;
+            assert_eq!(written, (stats.sent + stats.duplicated) * 5);
+            stats
+        };
+        assert_eq!(run(3), run(3));
+
+        let mut clean = ChaosHID::new(HID::new("", "", "").unwrap(), ChaosConfig::default(), 0);
+        clean.send_key_packet(&[0; 33]).unwrap();
+        assert_eq!(clean.stats().sent, 1);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn flushes_keyboard() {
+        use super::{ChaosConfig, ChaosHID};
+        use crate::{HID, key::{Keyboard, KeyPacket}};
+
+        let written = |chaos: &mut ChaosHID| std::fs::read(chaos.hid().get_keyboard_path()).unwrap()
+            .chunks(KeyPacket::new().as_bytes().len())
+            .map(|data| KeyPacket::from_bytes(data).unwrap())
+            .collect::<Vec<_>>();
+
+        let config = ChaosConfig { duplicate: 1.0, ..ChaosConfig::default() };
+        let mut chaos = ChaosHID::new(HID::new("", "", "").unwrap(), config, 0);
+        let mut keyboard = Keyboard::new();
+        keyboard.press_basic_string_checked("a");
+        keyboard.flush(&mut chaos).unwrap();
+        let a = KeyPacket::parse("a").unwrap();
+        // press, release and the flush's release of held keys, each sent twice
+        assert_eq!(written(&mut chaos), vec![a.clone(), a, KeyPacket::new(), KeyPacket::new(), KeyPacket::new(), KeyPacket::new()]);
+        assert_eq!(chaos.stats().duplicated, 3);
+
+        chaos.set_config(ChaosConfig { fail: 1.0, ..ChaosConfig::default() });
+        keyboard.press_basic_string_checked("b");
+        assert!(keyboard.flush(&mut chaos).is_err());
+        assert_eq!(written(&mut chaos).len(), 6);
+        assert_eq!(chaos.stats().failed, 1);
+    }
+}
@@ -0,0 +1,92 @@
+#![warn(missing_docs)]
+
+use std::{io, thread, time::{Duration, Instant}};
+
+use crate::{HID, schedule::Scheduler};
+
+/// Time given to the device threads to start before the first report
+const START_LEAD: Duration = Duration::from_millis(20);
+
+/// Track of reports for one device, offset against the shared clock
+struct Track {
+    scheduler: Scheduler,
+    offset: Duration,
+}
+
+#[derive(Default)]
+/// Runs schedules on several HID interfaces at once against a shared clock, e.g. pressing Enter on two hosts within
+/// a few milliseconds of each other. Each device is driven from its own thread so one slow write doesn't delay the others.
+pub struct Choreography {
+    tracks: Vec<Track>,
+}
+
+impl Choreography {
+    /// New, without tracks
+    pub fn new() -> Choreography {
+        Choreography::default()
+    }
+
+    /// Add a track for a device, starting offset after the shared start. Returns its index.
+    pub fn add_track(&mut self, offset: Duration) -> usize {
+        self.tracks.push(Track { scheduler: Scheduler::new(), offset });
+        self.tracks.len() - 1
+    }
+
+    /// Schedule of a track, with offsets from the track's start
+    pub fn track(&mut self, index: usize) -> Option<&mut Scheduler> {
+        self.tracks.get_mut(index).map(|track| &mut track.scheduler)
+    }
+
+    /// Number of tracks
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    /// Check if there are no tracks
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    /// Run every track on the HID interface at the same index, then clear the tracks.
+    /// Returns how late the latest report of each track was sent.
+    pub fn run(&mut self, hids: &mut [HID]) -> io::Result<Vec<Duration>> {
+        if hids.len() != self.tracks.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "need one HID interface per track"));
+        }
+        let start = Instant::now() + START_LEAD;
+        thread::scope(|scope| {
+            let handles: Vec<_> = self.tracks.iter_mut()
+                .zip(hids.iter_mut())
+                .map(|(track, hid)| scope.spawn(move || track.scheduler.run_at(hid, start + track.offset)))
+                .collect();
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|_| Err(io::Error::other("device thread panicked"))))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn runs_tracks_together() {
+        use std::time::Duration;
+
+        use super::Choreography;
+        use crate::{HID, key::{KeyPacket, SpecialKey}, schedule::Report};
+
+        let mut choreography = Choreography::new();
+        for offset in [Duration::ZERO, Duration::from_millis(5)] {
+            let track = choreography.add_track(offset);
+            let scheduler = choreography.track(track).unwrap();
+            scheduler.push(Duration::from_millis(10), Report::Keyboard(KeyPacket::from_special(&SpecialKey::ReturnEnter)));
+            scheduler.push(Duration::from_millis(20), Report::Keyboard(KeyPacket::new()));
+        }
+        let mut hids = vec![HID::new("", "", "").unwrap(), HID::new("", "", "").unwrap()];
+        let lateness = choreography.run(&mut hids).unwrap();
+        assert_eq!(lateness.len(), 2);
+        assert!(hids.iter().all(|hid| hid.events().len() == 2));
+        assert!(choreography.track(0).unwrap().is_empty());
+    }
+}
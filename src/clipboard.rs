@@ -0,0 +1,158 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{HidBackend, config::HostOS, key::{BasicKey, Keyboard, SpecialKey}, transfer::base64_encode, vendor::VendorHID};
+
+/// Placeholder in [ClipboardTemplate::command] replaced with the text as base64
+pub const BASE64_PLACEHOLDER: &str = "{base64}";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// How to set a host's clipboard by typing: open a prompt with a chord, optionally launch a shell, then type a command
+/// decoding the text from base64 into the clipboard. Base64 keeps the typed command to ASCII, whatever the text is.
+pub struct ClipboardTemplate {
+    /// Chord opening a prompt, see [crate::key::KeyPacket::parse], e.g. "GUI+r"
+    pub open: String,
+    /// Typed into the prompt and entered to start a shell, empty if the prompt is a shell
+    pub launch: String,
+    /// Command setting the clipboard and closing the shell, with [BASE64_PLACEHOLDER] in place of the text
+    pub command: String,
+}
+
+impl ClipboardTemplate {
+    /// Template for a host OS.
+    /// Windows opens PowerShell from the Run dialog and uses Set-Clipboard.
+    /// Linux opens a terminal with Ctrl+Alt+T and uses wl-copy on Wayland, xclip otherwise.
+    /// MacOS opens Terminal from Spotlight and uses pbcopy.
+    /// The commands start with a space to keep them out of shell history where that's ignored.
+    pub fn for_host(host: HostOS) -> ClipboardTemplate {
+        let (open, launch, command) = match host {
+            HostOS::Windows => (
+                "GUI+r",
+                "powershell -NoProfile",
+                " Set-Clipboard -Value ([Text.Encoding]::UTF8.GetString([Convert]::FromBase64String('{base64}'))); exit",
+            ),
+            HostOS::Linux => (
+                "CTRL+ALT+t",
+                "",
+                " printf %s '{base64}' | base64 -d | if [ -n \"$WAYLAND_DISPLAY\" ]; then wl-copy; else xclip -selection clipboard; fi; exit",
+            ),
+            HostOS::MacOS => (
+                "GUI+Spacebar",
+                "Terminal",
+                " printf %s '{base64}' | base64 -D | pbcopy; exit",
+            ),
+        };
+        ClipboardTemplate { open: open.to_string(), launch: launch.to_string(), command: command.to_string() }
+    }
+
+    /// Command setting the clipboard to text
+    pub fn command_for(&self, text: &str) -> String {
+        self.command.replace(BASE64_PLACEHOLDER, &base64_encode(text.as_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Sets a host's clipboard as a fast path for long text, which can then be pasted instead of typed key by key
+pub struct ClipboardBridge {
+    host: HostOS,
+    template: ClipboardTemplate,
+    open_wait: Duration,
+}
+
+impl ClipboardBridge {
+    /// New for a host OS with its [ClipboardTemplate::for_host] template
+    pub fn new(host: HostOS) -> ClipboardBridge {
+        ClipboardBridge { host, template: ClipboardTemplate::for_host(host), open_wait: Duration::from_millis(800) }
+    }
+
+    /// Set the template, e.g. for a desktop with other shortcuts or clipboard tools
+    pub fn set_template(&mut self, template: ClipboardTemplate) {
+        self.template = template;
+    }
+
+    /// Template
+    pub fn template(&self) -> &ClipboardTemplate {
+        &self.template
+    }
+
+    /// Set the wait for the prompt and shell to open before typing into them, on the keyboard's clock
+    pub fn set_open_wait(&mut self, open_wait: Duration) {
+        self.open_wait = open_wait;
+    }
+
+    /// Set the host clipboard to text by typing the template's command. Focus returns to the previous window when
+    /// the shell closes.
+    pub fn set_clipboard(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, text: &str) -> io::Result<()> {
+        keyboard.press_chord(&self.template.open)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid chord {:?}", self.template.open)))?;
+        keyboard.send(hid)?;
+        keyboard.clock().sleep(self.open_wait);
+        if !self.template.launch.is_empty() {
+            self.enter(keyboard, hid, &self.template.launch)?;
+            keyboard.clock().sleep(self.open_wait);
+        }
+        self.enter(keyboard, hid, &self.template.command_for(text))
+    }
+
+    /// Paste the host clipboard with Ctrl+V, Cmd+V on MacOS
+    pub fn paste(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend) -> io::Result<()> {
+        let chord = match self.host {
+            HostOS::MacOS => "GUI+v",
+            HostOS::Linux | HostOS::Windows => "CTRL+v",
+        };
+        keyboard.press_chord(chord);
+        keyboard.send(hid)
+    }
+
+    /// Set the host clipboard to text, then paste it into the previously focused window
+    pub fn paste_text(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, text: &str) -> io::Result<()> {
+        self.set_clipboard(keyboard, hid, text)?;
+        keyboard.clock().sleep(self.open_wait);
+        self.paste(keyboard, hid)
+    }
+
+    fn enter(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, line: &str) -> io::Result<()> {
+        let unmappable = keyboard.type_string(line);
+        if !unmappable.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("can't type {:?}", unmappable)));
+        }
+        keyboard.press_key(&BasicKey::Special(SpecialKey::ReturnEnter));
+        keyboard.send(hid)
+    }
+}
+
+/// Send text to a host agent setting the clipboard over a vendor interface, the fastest path when an agent is
+/// installed. Like [crate::echo::EchoSource] in reverse, each IN report starts with the number of UTF-8 bytes that
+/// follow, and an empty report ends the text.
+pub fn send_clipboard(vendor: &mut VendorHID, text: &str) -> io::Result<()> {
+    if vendor.report_len() < 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "report length must be at least 2"));
+    }
+    let chunk_len = (vendor.report_len() - 1).min(u8::MAX as usize);
+    for chunk in text.as_bytes().chunks(chunk_len) {
+        let mut report = vec![chunk.len() as u8];
+        report.extend_from_slice(chunk);
+        vendor.send_report(&report)?;
+    }
+    vendor.send_report(&[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClipboardTemplate;
+    use crate::{config::HostOS, key::KeyPacket};
+
+    #[test]
+    fn clipboard_templates() {
+        for host in [HostOS::Linux, HostOS::Windows, HostOS::MacOS] {
+            let template = ClipboardTemplate::for_host(host);
+            assert!(KeyPacket::parse(&template.open).is_some(), "{:?}", host);
+            let command = template.command_for("héllo");
+            assert!(command.contains("'aMOpbGxv'"), "{}", command);
+            assert!(command.is_ascii());
+        }
+    }
+}
@@ -0,0 +1,111 @@
+#![warn(missing_docs)]
+
+use std::{
+    fmt,
+    io,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::cancel::CancellationToken;
+
+/// Source of time for timed behaviors (schedules, pacing, dwell and mute windows).
+/// Swap [SystemClock] for a [VirtualClock] to test them deterministically without real sleeps.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current time
+    fn now(&self) -> Instant;
+
+    /// Wait for a duration
+    fn sleep(&self, duration: Duration);
+
+    /// Wait for a duration unless cancelled, failing with a [crate::cancel::Cancelled] error if cancelled
+    fn sleep_cancellable(&self, duration: Duration, cancel: &CancellationToken) -> io::Result<()> {
+        cancel.check()?;
+        self.sleep(duration);
+        cancel.check()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Real time, sleeping the thread
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if !duration.is_zero() {
+            thread::sleep(duration);
+        }
+    }
+
+    fn sleep_cancellable(&self, duration: Duration, cancel: &CancellationToken) -> io::Result<()> {
+        cancel.sleep(duration)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Virtual time that only moves when slept on or advanced, so timed behaviors run instantly and deterministically.
+/// Clones share the same time.
+pub struct VirtualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        VirtualClock::new()
+    }
+}
+
+impl VirtualClock {
+    /// New, starting at the current real time
+    pub fn new() -> VirtualClock {
+        VirtualClock { start: Instant::now(), elapsed: Arc::new(Mutex::new(Duration::ZERO)) }
+    }
+
+    /// Move time forward
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+
+    /// Time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, VirtualClock};
+    use crate::cancel::CancellationToken;
+
+    #[test]
+    fn virtual_time() {
+        let clock = VirtualClock::new();
+        let start = clock.now();
+        clock.sleep(Duration::from_secs(60));
+        clock.clone().advance(Duration::from_millis(5));
+        assert_eq!(clock.now() - start, Duration::from_millis(60_005));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(clock.sleep_cancellable(Duration::from_secs(1), &cancel).is_err());
+        assert_eq!(clock.elapsed(), Duration::from_millis(60_005));
+    }
+}
@@ -0,0 +1,316 @@
+#![warn(missing_docs)]
+
+use std::{io, thread, time::Duration};
+#[cfg(feature = "toml")]
+use std::{env, fs, path::PathBuf};
+
+use num_enum::{IntoPrimitive, FromPrimitive};
+use serde::{Serialize, Deserialize};
+
+use crate::{key::{BootOverflow, ReportProtocol}, lint::LintConfig, pacing::AdaptivePacing, report::ByteMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// Host Operating System
+pub enum HostOS {
+    /// Linux
+    #[default]
+    Linux,
+    /// Windows
+    Windows,
+    /// MacOS
+    MacOS,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// Policy for reopening HID devices that aren't available yet
+pub enum ReconnectPolicy {
+    /// Fail on the first error
+    #[default]
+    Never,
+    /// Retry a number of times, waiting between attempts
+    Retry {
+        /// Max number of retries after the first attempt
+        attempts: u32,
+        /// Wait between attempts in milliseconds
+        interval_ms: u64,
+    },
+}
+
+impl ReconnectPolicy {
+    /// Run an operation, retrying it according to the policy
+    pub fn run<T>(&self, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        match self {
+            ReconnectPolicy::Never => op(),
+            ReconnectPolicy::Retry { attempts, interval_ms } => {
+                let mut res = op();
+                for _ in 0..*attempts {
+                    if res.is_ok() {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(*interval_ms));
+                    res = op();
+                }
+                res
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// HID device paths
+pub struct DevicePaths {
+    /// Mouse device
+    pub mouse: String,
+    /// Keyboard device
+    pub keyboard: String,
+    /// Keyboard LED device
+    pub led: String,
+}
+
+impl Default for DevicePaths {
+    fn default() -> Self {
+        DevicePaths {
+            mouse: "/dev/hidg1".to_string(),
+            keyboard: "/dev/hidg0".to_string(),
+            led: "/dev/hidg0".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// Policy for retrying device writes that fail transiently (EAGAIN, EINTR, short writes).
+/// Waits double after each retry up to a maximum, plus random jitter. The default doesn't retry.
+pub struct WriteRetry {
+    /// Max number of retries after the first attempt
+    pub attempts: u32,
+    /// Wait before the first retry in milliseconds
+    pub backoff_ms: u64,
+    /// Max wait between retries in milliseconds
+    pub max_backoff_ms: u64,
+    /// Max random time added to each wait in milliseconds
+    pub jitter_ms: u64,
+}
+
+impl WriteRetry {
+    /// Check if an error is worth retrying
+    pub fn is_transient(err: &io::Error) -> bool {
+        matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::WriteZero)
+    }
+
+    /// Wait before a retry, not including jitter
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.backoff_ms.saturating_mul(1 << retry.min(32));
+        Duration::from_millis(backoff.min(self.max_backoff_ms.max(self.backoff_ms)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// Shared configuration for HID interfaces and virtual devices
+pub struct Config {
+    /// Device paths
+    pub devices: DevicePaths,
+    /// Keyboard layout key, see [crate::key::Keyboard::available_layouts]. None uses the basic keyboard table.
+    pub layout: Option<String>,
+    /// Host operating system
+    pub host_os: HostOS,
+    /// Wait between key packets in milliseconds
+    pub pacing_ms: u64,
+    /// Keyboard report protocol
+    pub report_protocol: ReportProtocol,
+    /// Boot protocol overflow policy
+    pub boot_overflow: BootOverflow,
+    /// Device reconnect policy
+    pub reconnect: ReconnectPolicy,
+    /// Wait for the host's first LED state report when connecting in milliseconds, 0 doesn't wait
+    pub led_sync_ms: u64,
+    /// Report ID preceding LED states in LED output reports, if the keyboard descriptor uses report IDs
+    pub led_report_id: Option<u8>,
+    /// Device write retry policy
+    pub write_retry: WriteRetry,
+    /// Longest a key can stay held before it's released automatically in milliseconds, 0 never releases
+    pub max_hold_ms: u64,
+    /// Keycode remaps from one keycode to another, see [crate::interceptor::Remap::from_config]
+    pub remaps: Vec<(u8, u8)>,
+    /// Seed of randomized behaviors such as write retry jitter, so runs can be reproduced. None seeds from the time.
+    pub seed: Option<u64>,
+    /// Rewrites key packets before they're written, for consumers expecting another report format
+    pub keyboard_transcoder: Option<ByteMap>,
+    /// Rewrites mouse packets before they're written, for consumers expecting another report format
+    pub mouse_transcoder: Option<ByteMap>,
+    /// Lint rules checked before sending key packets, see [crate::key::Keyboard::set_lint]. None doesn't check.
+    pub lint: Option<LintConfig>,
+    /// Pacing following the host's load, see [crate::key::Keyboard::set_adaptive_pacing]. None keeps pacing fixed.
+    pub adaptive_pacing: Option<AdaptivePacing>,
+}
+
+impl Config {
+    /// Wait between key packets
+    pub fn pacing(&self) -> Duration {
+        Duration::from_millis(self.pacing_ms)
+    }
+
+    /// Wait for the host's first LED state report when connecting
+    pub fn led_sync(&self) -> Duration {
+        Duration::from_millis(self.led_sync_ms)
+    }
+
+    /// Longest a key can stay held before it's released automatically, see [crate::key::Keyboard::set_max_hold]
+    pub fn max_hold(&self) -> Option<Duration> {
+        (self.max_hold_ms != 0).then(|| Duration::from_millis(self.max_hold_ms))
+    }
+
+    /// Parse config from a TOML string
+    #[cfg(feature = "toml")]
+    pub fn from_toml(str: &str) -> io::Result<Config> {
+        toml::from_str(str).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Serialize config to a TOML string
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> io::Result<String> {
+        toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Load config from a TOML file
+    #[cfg(feature = "toml")]
+    pub fn load(path: &str) -> io::Result<Config> {
+        Config::from_toml(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Named configs saved as TOML files in a directory, so a set of options can be picked by name (e.g. office-windows)
+#[cfg(feature = "toml")]
+#[derive(Debug, Clone)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+#[cfg(feature = "toml")]
+impl ProfileStore {
+    /// New, storing profiles in a directory
+    pub fn new(dir: impl Into<PathBuf>) -> ProfileStore {
+        ProfileStore { dir: dir.into() }
+    }
+
+    /// Profiles in $XDG_CONFIG_HOME/virt-hid/profiles, or ~/.config/virt-hid/profiles
+    pub fn user() -> io::Result<ProfileStore> {
+        let config_dir = match (env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) {
+            (Some(config_dir), _) => PathBuf::from(config_dir),
+            (None, Some(home)) => PathBuf::from(home).join(".config"),
+            (None, None) => return Err(io::Error::new(io::ErrorKind::NotFound, "no config directory")),
+        };
+        Ok(ProfileStore::new(config_dir.join("virt-hid").join("profiles")))
+    }
+
+    /// Path of a profile's file. Names may only contain ASCII letters, digits, '-' and '_'.
+    pub fn path(&self, name: &str) -> io::Result<PathBuf> {
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        match valid {
+            true => Ok(self.dir.join(format!("{}.toml", name))),
+            false => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid profile name {:?}", name))),
+        }
+    }
+
+    /// Save a profile, replacing any profile with the same name
+    pub fn save(&self, name: &str, config: &Config) -> io::Result<()> {
+        let path = self.path(name)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, config.to_toml()?)
+    }
+
+    /// Load a profile by name
+    pub fn load(&self, name: &str) -> io::Result<Config> {
+        Config::from_toml(&fs::read_to_string(self.path(name)?)?)
+    }
+
+    /// Delete a profile
+    pub fn remove(&self, name: &str) -> io::Result<()> {
+        fs::remove_file(self.path(name)?)
+    }
+
+    /// Names of the saved profiles, sorted
+    pub fn list(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "toml") {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "toml")]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{Config, HostOS, ProfileStore, ReconnectPolicy};
+    use crate::key::{Keyboard, ReportProtocol};
+
+    #[test]
+    fn loads_toml_config() {
+        let config = Config::from_toml(r#"
+            layout = "LAYOUT_GERMAN"
+            host_os = "Windows"
+            pacing_ms = 5
+            report_protocol = "Boot"
+            reconnect = { Retry = { attempts = 2, interval_ms = 0 } }
+
+            [devices]
+            keyboard = "/dev/hidg2"
+        "#).unwrap();
+        assert_eq!(config.layout.as_deref(), Some("LAYOUT_GERMAN"));
+        assert_eq!(config.host_os, HostOS::Windows);
+        assert_eq!(config.report_protocol, ReportProtocol::Boot);
+        assert_eq!(config.reconnect, ReconnectPolicy::Retry { attempts: 2, interval_ms: 0 });
+        assert_eq!(config.devices.keyboard, "/dev/hidg2");
+        // unset fields keep their defaults
+        assert_eq!(config.devices.mouse, "/dev/hidg1");
+        assert_eq!(Config::from_toml(&config.to_toml().unwrap()).unwrap(), config);
+        assert!(Config::from_toml("pacing_ms = \"fast\"").is_err());
+
+        let keyboard = Keyboard::from_config(&config);
+        assert_eq!(keyboard.pacing(), config.pacing());
+        assert_eq!(keyboard.type_options().layout, config.layout);
+
+        let mut tries = 0;
+        let res: std::io::Result<()> = config.reconnect.run(|| {
+            tries += 1;
+            Err(std::io::ErrorKind::NotFound.into())
+        });
+        assert!(res.is_err());
+        assert_eq!(tries, 3);
+    }
+
+    #[test]
+    fn saves_named_profiles() {
+        let dir = env::temp_dir().join(format!("virt-hid-profiles-{}", process::id()));
+        let store = ProfileStore::new(&dir);
+        assert_eq!(store.list().unwrap(), Vec::<String>::new());
+
+        let office = Config { layout: Some(String::from("LAYOUT_GERMAN")), host_os: HostOS::Windows, pacing_ms: 5, remaps: vec![(0x39, 0xE0)], ..Config::default() };
+        store.save("office-windows", &office).unwrap();
+        store.save("home", &Config::default()).unwrap();
+        assert_eq!(store.list().unwrap(), vec!["home", "office-windows"]);
+        assert_eq!(store.load("office-windows").unwrap(), office);
+        assert!(store.save("../escape", &office).is_err());
+
+        store.remove("home").unwrap();
+        assert_eq!(store.list().unwrap(), vec!["office-windows"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
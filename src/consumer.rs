@@ -0,0 +1,248 @@
+#![warn(missing_docs)]
+
+use std::{borrow::Cow, time::Duration};
+use std::{io, sync::Arc};
+
+use num_enum::{IntoPrimitive, FromPrimitive};
+use serde::{Serialize, Deserialize};
+
+use crate::{descriptor, device::VirtualDevice, mouse::MOUSE_DATA_WHEL_IDX};
+use crate::{HidBackend, clock::{Clock, SystemClock}};
+
+const CONSUMER_REPORT_LEN: usize = 2;
+/// Default wait between consumer reports, long enough for hosts to see each volume step
+pub const CONSUMER_PACING: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// Consumer page usages
+pub enum ConsumerKey {
+    /// Play/Pause
+    #[default]
+    PlayPause,
+    /// Scan Next Track
+    NextTrack,
+    /// Scan Previous Track
+    PreviousTrack,
+    /// Stop
+    Stop,
+    /// Mute
+    Mute,
+    /// Volume Increment
+    VolumeUp,
+    /// Volume Decrement
+    VolumeDown,
+}
+
+impl ConsumerKey {
+    /// Consumer page usage ID
+    pub fn usage(&self) -> u16 {
+        match self {
+            ConsumerKey::PlayPause => 0xCD,
+            ConsumerKey::NextTrack => 0xB5,
+            ConsumerKey::PreviousTrack => 0xB6,
+            ConsumerKey::Stop => 0xB7,
+            ConsumerKey::Mute => 0xE2,
+            ConsumerKey::VolumeUp => 0xE9,
+            ConsumerKey::VolumeDown => 0xEA,
+        }
+    }
+}
+
+/// Virtual consumer control (media keys), see [descriptor::CONSUMER]. Register its gadget interface with
+/// [crate::HID::register_device] under "consumer" and send it with [ConsumerControl::send].
+/// Every press is followed by a release report, so hosts count each volume step.
+pub struct ConsumerControl {
+    reports: Vec<[u8; CONSUMER_REPORT_LEN]>,
+    muted: bool,
+    pacing: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for ConsumerControl {
+    fn default() -> Self {
+        ConsumerControl::new()
+    }
+}
+
+impl ConsumerControl {
+    /// New, assuming the host isn't muted
+    pub fn new() -> ConsumerControl {
+        ConsumerControl {
+            reports: Vec::new(),
+            muted: false,
+            pacing: CONSUMER_PACING,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Set the wait between reports sent by [ConsumerControl::send]
+    pub fn set_pacing(&mut self, pacing: Duration) {
+        self.pacing = pacing;
+    }
+
+    /// Set the clock the pacing waits on, see [crate::clock::VirtualClock]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Press and release a usage
+    pub fn press(&mut self, key: ConsumerKey) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("press {:?}", key);
+        }
+        self.reports.push(key.usage().to_le_bytes());
+        self.reports.push([0; CONSUMER_REPORT_LEN]);
+    }
+
+    /// Raise the volume by a number of steps
+    pub fn volume_up_by(&mut self, steps: u32) {
+        for _ in 0..steps {
+            self.press(ConsumerKey::VolumeUp);
+        }
+    }
+
+    /// Lower the volume by a number of steps
+    pub fn volume_down_by(&mut self, steps: u32) {
+        for _ in 0..steps {
+            self.press(ConsumerKey::VolumeDown);
+        }
+    }
+
+    /// Change the volume by a number of steps, positive is up
+    pub fn volume_by(&mut self, steps: i32) {
+        match steps >= 0 {
+            true => self.volume_up_by(steps.unsigned_abs()),
+            false => self.volume_down_by(steps.unsigned_abs()),
+        }
+    }
+
+    /// Mute or unmute. Hosts treat Mute as a toggle, so it's only pressed if the mute state tracked by
+    /// this controller differs, see [ConsumerControl::sync_mute].
+    pub fn set_mute(&mut self, muted: bool) {
+        if self.muted != muted {
+            self.press(ConsumerKey::Mute);
+            self.muted = muted;
+        }
+    }
+
+    /// Mute state tracked by this controller
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Set the tracked mute state without pressing anything, e.g. after the host was muted by other means
+    pub fn sync_mute(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Take buffered events as raw reports
+    pub fn take_reports(&mut self) -> Vec<[u8; CONSUMER_REPORT_LEN]> {
+        std::mem::take(&mut self.reports)
+    }
+
+    /// Send buffered events to the interface registered under "consumer", waiting the pacing between reports
+    pub fn send(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+        for (i, report) in self.take_reports().iter().enumerate() {
+            if i != 0 && !self.pacing.is_zero() {
+                self.clock.sleep(self.pacing);
+            }
+            hid.send_device_report(self.interface_name(), report)?;
+        }
+        Ok(())
+    }
+}
+
+impl VirtualDevice for ConsumerControl {
+    fn descriptor(&self) -> Cow<'static, [u8]> {
+        Cow::Borrowed(descriptor::CONSUMER)
+    }
+
+    fn interface_name(&self) -> &str {
+        "consumer"
+    }
+
+    fn build_reports(&mut self) -> Vec<Vec<u8>> {
+        self.take_reports().iter().map(|report| report.to_vec()).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Binds a scroll wheel to volume, e.g. for a media controller knob. Wheel detents are converted into volume steps,
+/// carrying over detents that don't make up a whole step.
+pub struct WheelVolume {
+    detents_per_step: u32,
+    remainder: i32,
+}
+
+impl Default for WheelVolume {
+    fn default() -> Self {
+        WheelVolume::new(1)
+    }
+}
+
+impl WheelVolume {
+    /// New, changing the volume by one step every detents_per_step detents (at least 1)
+    pub fn new(detents_per_step: u32) -> WheelVolume {
+        WheelVolume { detents_per_step: detents_per_step.max(1), remainder: 0 }
+    }
+
+    /// Scroll the wheel by detents, positive is up, changing the volume of a consumer control
+    pub fn scroll(&mut self, consumer: &mut ConsumerControl, detents: i32) {
+        self.remainder = self.remainder.saturating_add(detents);
+        let steps = self.remainder / self.detents_per_step as i32;
+        self.remainder -= steps * self.detents_per_step as i32;
+        consumer.volume_by(steps);
+    }
+
+    /// Move the wheel of a raw mouse packet into volume steps, leaving the packet without wheel movement
+    pub fn scroll_packet(&mut self, consumer: &mut ConsumerControl, packet: &mut [u8; 5]) {
+        let detents = packet[MOUSE_DATA_WHEL_IDX] as i8;
+        packet[MOUSE_DATA_WHEL_IDX] = 0;
+        self.scroll(consumer, detents as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConsumerControl, ConsumerKey, WheelVolume};
+
+    #[test]
+    fn volume_and_mute_reports() {
+        let mut consumer = ConsumerControl::new();
+        consumer.volume_up_by(2);
+        consumer.set_mute(true);
+        consumer.set_mute(true);
+        let usages: Vec<u16> = consumer.take_reports().iter().map(|report| u16::from_le_bytes(*report)).collect();
+        let up = ConsumerKey::VolumeUp.usage();
+        assert_eq!(usages, vec![up, 0, up, 0, ConsumerKey::Mute.usage(), 0]);
+        assert!(consumer.is_muted());
+
+        let mut wheel = WheelVolume::new(2);
+        let mut packet = [0, 0, 0, (-5i8) as u8, 0];
+        wheel.scroll_packet(&mut consumer, &mut packet);
+        assert_eq!(packet, [0; 5]);
+        assert_eq!(consumer.take_reports().len(), 4);
+        wheel.scroll(&mut consumer, -1);
+        assert_eq!(consumer.take_reports()[0], ConsumerKey::VolumeDown.usage().to_le_bytes());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn paces_on_clock() {
+        use std::{sync::Arc, time::Duration};
+
+        use crate::{clock::VirtualClock, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        hid.register_device("consumer", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut consumer = ConsumerControl::new();
+        consumer.set_clock(Arc::new(clock.clone()));
+        consumer.volume_up_by(2);
+        consumer.send(&mut hid).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_millis(30));
+        assert_eq!(std::fs::read(hid.get_device_path("consumer").unwrap()).unwrap().len(), 8);
+    }
+}
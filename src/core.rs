@@ -0,0 +1,15 @@
+#![warn(missing_docs)]
+
+pub use crate::backend::HidBackend;
+pub use crate::{
+    absolute, accel, cancel, capture, clock, consumer, coverage, decode, descriptor, device, dial, dynamics, easing,
+    firmware, framing, history, ime, interceptor, key, keymap, latency, lint, logging, macros, matrix, mouse, pacing,
+    report, rng, schedule, session, simulate, transaction, typo,
+};
+#[cfg(feature = "layout-pack")]
+pub use crate::pack;
+
+/// Config Module: the options themselves. Profiles saved on disk are in [crate::io], see `ProfileStore`.
+pub mod config {
+    pub use crate::config::{Config, DevicePaths, HostOS, ReconnectPolicy, WriteRetry};
+}
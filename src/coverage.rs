@@ -0,0 +1,188 @@
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+
+use crate::key::Keyboard;
+
+/// Sample text for validating a layout
+pub struct LayoutFixture {
+    /// Locale of the sample
+    pub locale: &'static str,
+    /// Sample text
+    pub sample: &'static str,
+    /// XKB symbols of the locale's usual layout, see [crate::keymap::KeyTable::from_xkb_symbols]. The compiled layouts
+    /// only cover Latin scripts.
+    pub symbols: &'static str,
+}
+
+/// Validation fixtures for non-Latin scripts
+pub const FIXTURES: &[LayoutFixture] = &[
+    LayoutFixture {
+        locale: "ru_RU",
+        sample: "абвгдеёжзийклмнопрстуфхцчшщъыьэюяАБВГДЕЁЖЗИЙКЛМНОПРСТУФХЦЧШЩЪЫЬЭЮЯ",
+        symbols: "
+            key <TLDE> { [ ё, Ё ] };
+            key <AD01> { [ й, Й ] }; key <AD02> { [ ц, Ц ] }; key <AD03> { [ у, У ] }; key <AD04> { [ к, К ] };
+            key <AD05> { [ е, Е ] }; key <AD06> { [ н, Н ] }; key <AD07> { [ г, Г ] }; key <AD08> { [ ш, Ш ] };
+            key <AD09> { [ щ, Щ ] }; key <AD10> { [ з, З ] }; key <AD11> { [ х, Х ] }; key <AD12> { [ ъ, Ъ ] };
+            key <AC01> { [ ф, Ф ] }; key <AC02> { [ ы, Ы ] }; key <AC03> { [ в, В ] }; key <AC04> { [ а, А ] };
+            key <AC05> { [ п, П ] }; key <AC06> { [ р, Р ] }; key <AC07> { [ о, О ] }; key <AC08> { [ л, Л ] };
+            key <AC09> { [ д, Д ] }; key <AC10> { [ ж, Ж ] }; key <AC11> { [ э, Э ] };
+            key <AB01> { [ я, Я ] }; key <AB02> { [ ч, Ч ] }; key <AB03> { [ с, С ] }; key <AB04> { [ м, М ] };
+            key <AB05> { [ и, И ] }; key <AB06> { [ т, Т ] }; key <AB07> { [ ь, Ь ] }; key <AB08> { [ б, Б ] };
+            key <AB09> { [ ю, Ю ] };
+        ",
+    },
+    LayoutFixture {
+        locale: "el_GR",
+        sample: "αβγδεζηθικλμνξοπρσςτυφχψωΑΒΓΔΕΖΗΘΙΚΛΜΝΞΟΠΡΣΤΥΦΧΨΩ",
+        symbols: "
+            key <AD02> { [ ς ] }; key <AD03> { [ ε, Ε ] }; key <AD04> { [ ρ, Ρ ] }; key <AD05> { [ τ, Τ ] };
+            key <AD06> { [ υ, Υ ] }; key <AD07> { [ θ, Θ ] }; key <AD08> { [ ι, Ι ] }; key <AD09> { [ ο, Ο ] };
+            key <AD10> { [ π, Π ] };
+            key <AC01> { [ α, Α ] }; key <AC02> { [ σ, Σ ] }; key <AC03> { [ δ, Δ ] }; key <AC04> { [ φ, Φ ] };
+            key <AC05> { [ γ, Γ ] }; key <AC06> { [ η, Η ] }; key <AC07> { [ ξ, Ξ ] }; key <AC08> { [ κ, Κ ] };
+            key <AC09> { [ λ, Λ ] };
+            key <AB01> { [ ζ, Ζ ] }; key <AB02> { [ χ, Χ ] }; key <AB03> { [ ψ, Ψ ] }; key <AB04> { [ ω, Ω ] };
+            key <AB05> { [ β, Β ] }; key <AB06> { [ ν, Ν ] }; key <AB07> { [ μ, Μ ] };
+        ",
+    },
+    LayoutFixture {
+        locale: "he_IL",
+        sample: "אבגדהוזחטיכךלמםנןסעפףצץקרשת",
+        symbols: "
+            key <AD03> { [ ק ] }; key <AD04> { [ ר ] }; key <AD05> { [ א ] }; key <AD06> { [ ט ] };
+            key <AD07> { [ ו ] }; key <AD08> { [ ן ] }; key <AD09> { [ ם ] }; key <AD10> { [ פ ] };
+            key <AC01> { [ ש ] }; key <AC02> { [ ד ] }; key <AC03> { [ ג ] }; key <AC04> { [ כ ] };
+            key <AC05> { [ ע ] }; key <AC06> { [ י ] }; key <AC07> { [ ח ] }; key <AC08> { [ ל ] };
+            key <AC09> { [ ך ] }; key <AC10> { [ ף ] };
+            key <AB01> { [ ז ] }; key <AB02> { [ ס ] }; key <AB03> { [ ב ] }; key <AB04> { [ ה ] };
+            key <AB05> { [ נ ] }; key <AB06> { [ מ ] }; key <AB07> { [ צ ] }; key <AB08> { [ ת ] };
+            key <AB09> { [ ץ ] };
+        ",
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Which chars of a text a layout can and can't type
+pub struct LayoutCoverage {
+    /// Layout key
+    pub layout: &'static str,
+    /// Chars that can be typed
+    pub supported: Vec<char>,
+    /// Chars that can't be typed
+    pub unsupported: Vec<char>,
+    /// Pairs of chars that type the same keystrokes and so can't round trip
+    pub collisions: Vec<(char, char)>,
+}
+
+impl LayoutCoverage {
+    /// Check a layouts coverage of the chars in a text
+    pub fn new(layout_key: &str, text: &str) -> Option<LayoutCoverage> {
+        let layout = Keyboard::available_layouts()
+            .into_iter()
+            .find(|layout| *layout == layout_key)?;
+        let mut coverage = LayoutCoverage {
+            layout,
+            supported: Vec::new(),
+            unsupported: Vec::new(),
+            collisions: Vec::new(),
+        };
+        let mut typed: HashMap<Vec<Vec<u8>>, char> = HashMap::new();
+        for c in text.chars() {
+            if coverage.supported.contains(&c) || coverage.unsupported.contains(&c) {
+                continue;
+            }
+            let mut keyboard = Keyboard::new();
+            if keyboard.press(layout_key, c).is_none() {
+                coverage.unsupported.push(c);
+                continue;
+            }
+            let packets = keyboard.take_packets()
+                .iter()
+                .map(|packet| packet.as_bytes().to_vec())
+                .collect();
+            match typed.get(&packets) {
+                Some(other) => coverage.collisions.push((*other, c)),
+                None => {
+                    typed.insert(packets, c);
+                },
+            }
+            coverage.supported.push(c);
+        }
+        Some(coverage)
+    }
+
+    /// Check every available layouts coverage of the chars in a text
+    pub fn report(text: &str) -> Vec<LayoutCoverage> {
+        let mut layouts = Keyboard::available_layouts();
+        layouts.sort_unstable();
+        layouts.into_iter()
+            .filter_map(|layout| LayoutCoverage::new(layout, text))
+            .collect()
+    }
+
+    /// True if every char can be typed and round trips
+    pub fn is_complete(&self) -> bool {
+        self.unsupported.is_empty() && self.collisions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LayoutCoverage, FIXTURES};
+
+    #[test]
+    fn fixtures_round_trip() {
+        for fixture in FIXTURES {
+            for coverage in LayoutCoverage::report(fixture.sample) {
+                assert!(coverage.collisions.is_empty(), "{} {}: {:?}", fixture.locale, coverage.layout, coverage.collisions);
+                assert_eq!(
+                    coverage.supported.len() + coverage.unsupported.len(),
+                    fixture.sample.chars().count(),
+                    "{} {}", fixture.locale, coverage.layout
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fixtures_type_their_scripts() {
+        use crate::{key::{KeyPacket, Keyboard}, keymap::KeyTable};
+
+        let typed = |locale: &str, text: &str| {
+            let fixture = FIXTURES.iter().find(|fixture| fixture.locale == locale).unwrap();
+            let mut keyboard = Keyboard::new();
+            keyboard.set_key_table(Some(KeyTable::from_xkb_symbols(fixture.symbols)));
+            assert!(keyboard.type_string(fixture.sample).is_empty(), "{}", locale);
+            keyboard.take_packets();
+            assert!(keyboard.type_string(text).is_empty(), "{}", locale);
+            keyboard.take_packets()
+        };
+        let packets = |chords: &[&str]| {
+            let mut packets: Vec<KeyPacket> = chords.iter()
+                .flat_map(|chord| [KeyPacket::parse(chord).unwrap(), KeyPacket::new()])
+                .collect();
+            packets.push(KeyPacket::new());
+            packets
+        };
+
+        assert_eq!(typed("ru_RU", "Мир"), packets(&["LSHIFT+v", "b", "h"]));
+        assert_eq!(typed("el_GR", "Γει"), packets(&["LSHIFT+g", "e", "i"]));
+        assert_eq!(typed("he_IL", "שלום"), packets(&["a", "k", "u", "o"]));
+    }
+
+    #[test]
+    fn us_english_ascii() {
+        let ascii: String = (' '..='~').collect();
+        let coverage = LayoutCoverage::new("LAYOUT_US_ENGLISH", &ascii).unwrap();
+        assert!(coverage.is_complete(), "{:?}", coverage);
+    }
+
+    #[test]
+    fn astral_chars_unsupported() {
+        let coverage = LayoutCoverage::new("LAYOUT_US_ENGLISH", "a😀").unwrap();
+        assert_eq!(coverage.supported, vec!['a']);
+        assert_eq!(coverage.unsupported, vec!['😀']);
+    }
+}
@@ -0,0 +1,168 @@
+#![warn(missing_docs)]
+
+use std::{env, fmt};
+
+use crate::key::{KeyOrigin, KeyPacket, Modifier, SpecialKey, ToKBytes};
+
+/// Get a readable name for a keycode using the reverse of the translation tables,
+/// e.g. KeyA, Digit1, Spacebar or '/'. Unknown keycodes are named by their hex value.
+pub fn keycode_name(keycode: u8) -> String {
+    let special = (0..=u8::MAX as u32)
+        .map(SpecialKey::from)
+        .find(|special| special.to_kbyte() == keycode);
+    if let Some(special) = special {
+        return format!("{:?}", special);
+    }
+
+    let c = (' '..='~').find(|c| c.to_kbytes(&KeyOrigin::Keyboard) == Some([0x00, keycode]));
+    match c {
+        Some(c) if c.is_ascii_alphabetic() => format!("Key{}", c.to_ascii_uppercase()),
+        Some(c) if c.is_ascii_digit() => format!("Digit{}", c),
+        Some(c) => format!("{:?}", c),
+        None => format!("0x{:02X}", keycode),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Key event action
+pub enum KeyAction {
+    /// Keys went down
+    Press,
+    /// Keys went up
+    Release,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Key event decoded from a change between packets
+pub struct DecodedEvent {
+    /// Action
+    pub action: KeyAction,
+    /// Modifiers pressed or released
+    pub modifiers: Vec<Modifier>,
+    /// Names of the keys pressed or released, see [keycode_name]
+    pub keys: Vec<String>,
+}
+
+impl DecodedEvent {
+    fn from_packet(action: KeyAction, packet: &KeyPacket) -> Option<DecodedEvent> {
+        if packet.is_empty() {
+            return None;
+        }
+        Some(DecodedEvent {
+            action,
+            modifiers: packet.modifiers(),
+            keys: packet.usages().map(keycode_name).collect(),
+        })
+    }
+}
+
+impl fmt::Display for DecodedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            KeyAction::Press => "press",
+            KeyAction::Release => "release",
+        };
+        let names: Vec<String> = self.modifiers.iter()
+            .map(|modifier| format!("{:?}", modifier))
+            .chain(self.keys.iter().cloned())
+            .collect();
+        write!(f, "{} {}", action, names.join("+"))
+    }
+}
+
+/// Decodes a stream of key packets into press and release events
+pub struct KeyDecoder {
+    previous: KeyPacket,
+}
+
+impl Default for KeyDecoder {
+    fn default() -> Self {
+        KeyDecoder::new()
+    }
+}
+
+impl KeyDecoder {
+    /// New, starting with no keys held
+    pub fn new() -> KeyDecoder {
+        KeyDecoder { previous: KeyPacket::new() }
+    }
+
+    /// Decode the next packet into the keys released and pressed since the previous packet
+    pub fn decode(&mut self, packet: KeyPacket) -> Vec<DecodedEvent> {
+        let released = DecodedEvent::from_packet(KeyAction::Release, &(&self.previous - &packet));
+        let pressed = DecodedEvent::from_packet(KeyAction::Press, &(&packet - &self.previous));
+        self.previous = packet;
+        released.into_iter().chain(pressed).collect()
+    }
+}
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// Readable description of a packet, e.g. "LeftShift+KeyA (mods 0x02, keys [04])"
+fn describe_packet(packet: &KeyPacket) -> String {
+    let names: Vec<String> = packet.modifiers().iter()
+        .map(|modifier| format!("{:?}", modifier))
+        .chain(packet.usages().map(keycode_name))
+        .collect();
+    let keys: Vec<String> = packet.usages().map(|keycode| format!("{:02X}", keycode)).collect();
+    let names = if names.is_empty() { String::from("(empty)") } else { names.join("+") };
+    format!("{} (mods 0x{:02X}, keys [{}])", names, packet.as_bytes()[0], keys.join(" "))
+}
+
+/// Decoded diff of two packet sequences, listing only the packets that differ. None if they're equal.
+/// Colorized with ANSI escapes unless NO_COLOR is set.
+pub fn packets_diff(actual: &[KeyPacket], expected: &[KeyPacket]) -> Option<String> {
+    if actual == expected {
+        return None;
+    }
+    let (red, green, reset) = match env::var_os("NO_COLOR") {
+        Some(_) => ("", "", ""),
+        None => (RED, GREEN, RESET),
+    };
+    let describe = |packet: Option<&KeyPacket>| packet.map(describe_packet).unwrap_or_else(|| String::from("(missing)"));
+    let mut diff = format!("packet sequences differ ({} actual, {} expected)\n", actual.len(), expected.len());
+    for i in 0..actual.len().max(expected.len()) {
+        let (actual, expected) = (actual.get(i), expected.get(i));
+        if actual != expected {
+            diff.push_str(&format!("packet {}:\n", i));
+            diff.push_str(&format!("{}  - expected: {}{}\n", red, describe(expected), reset));
+            diff.push_str(&format!("{}  + actual:   {}{}\n", green, describe(actual), reset));
+        }
+    }
+    Some(diff)
+}
+
+/// Assert two packet sequences are equal, panicking with a decoded diff (see [packets_diff]) if they aren't
+#[track_caller]
+pub fn assert_packets_eq(actual: &[KeyPacket], expected: &[KeyPacket]) {
+    if let Some(diff) = packets_diff(actual, expected) {
+        panic!("{}", diff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyDecoder, packets_diff};
+    use crate::key::{KeyOrigin, KeyPacket};
+
+    #[test]
+    fn decode_press_release() {
+        let mut decoder = KeyDecoder::new();
+        let events = decoder.decode(KeyPacket::from_char(&'A', &KeyOrigin::Keyboard).unwrap());
+        assert_eq!(events.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec!["press LeftShift+KeyA"]);
+        let events = decoder.decode(KeyPacket::new());
+        assert_eq!(events.iter().map(|e| e.to_string()).collect::<Vec<_>>(), vec!["release LeftShift+KeyA"]);
+    }
+
+    #[test]
+    fn diff_lists_mismatches() {
+        let expected = [KeyPacket::parse("LSHIFT+a").unwrap(), KeyPacket::new()];
+        assert_eq!(packets_diff(&expected, &expected), None);
+        let diff = packets_diff(&[KeyPacket::parse("a").unwrap()], &expected).unwrap();
+        assert!(diff.contains("LeftShift+KeyA (mods 0x02, keys [04])"));
+        assert!(diff.contains("packet 1:"));
+        assert!(diff.contains("(missing)"));
+    }
+}
@@ -0,0 +1,319 @@
+#![warn(missing_docs)]
+
+use std::{borrow::Cow, io};
+
+use crate::{mouse::MouseFormat, report::ReportLayout};
+
+const PAGE_GENERIC_DESKTOP: u32 = 0x01;
+const PAGE_BUTTONS: u32 = 0x09;
+const PAGE_CONSUMER: u32 = 0x0C;
+
+/// Keyboard report descriptor matching [crate::key::KeyPacket].
+/// Input: 1 modifier byte then a 256 bit key bitmap. Output: 5 LED bits, see [crate::key::LEDState].
+pub const KEYBOARD: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x06,       // Usage (Keyboard)
+    0xA1, 0x01,       // Collection (Application)
+    0x05, 0x07,       //   Usage Page (Key Codes)
+    0x19, 0xE0,       //   Usage Minimum (224)
+    0x29, 0xE7,       //   Usage Maximum (231)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x25, 0x01,       //   Logical Maximum (1)
+    0x75, 0x01,       //   Report Size (1)
+    0x95, 0x08,       //   Report Count (8)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute) Modifiers
+    0x05, 0x08,       //   Usage Page (LEDs)
+    0x19, 0x01,       //   Usage Minimum (1)
+    0x29, 0x05,       //   Usage Maximum (5)
+    0x95, 0x05,       //   Report Count (5)
+    0x91, 0x02,       //   Output (Data, Variable, Absolute) LEDs
+    0x75, 0x03,       //   Report Size (3)
+    0x95, 0x01,       //   Report Count (1)
+    0x91, 0x03,       //   Output (Constant) LED padding
+    0x05, 0x07,       //   Usage Page (Key Codes)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2A, 0xFF, 0x00, //   Usage Maximum (255)
+    0x75, 0x01,       //   Report Size (1)
+    0x96, 0x00, 0x01, //   Report Count (256)
+    0x81, 0x02,       //   Input (Data, Variable, Absolute) Key bitmap
+    0xC0,             // End Collection
+];
+
+/// Mouse report descriptor matching [crate::mouse::Mouse].
+/// Input: 3 button bits, then X, Y, wheel and horizontal pan as signed bytes.
+pub const MOUSE: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x02,       // Usage (Mouse)
+    0xA1, 0x01,       // Collection (Application)
+    0x09, 0x01,       //   Usage (Pointer)
+    0xA1, 0x00,       //   Collection (Physical)
+    0x05, 0x09,       //     Usage Page (Buttons)
+    0x19, 0x01,       //     Usage Minimum (1)
+    0x29, 0x03,       //     Usage Maximum (3)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x25, 0x01,       //     Logical Maximum (1)
+    0x75, 0x01,       //     Report Size (1)
+    0x95, 0x03,       //     Report Count (3)
+    0x81, 0x02,       //     Input (Data, Variable, Absolute) Buttons
+    0x75, 0x05,       //     Report Size (5)
+    0x95, 0x01,       //     Report Count (1)
+    0x81, 0x03,       //     Input (Constant) Button padding
+    0x05, 0x01,       //     Usage Page (Generic Desktop)
+    0x09, 0x30,       //     Usage (X)
+    0x09, 0x31,       //     Usage (Y)
+    0x09, 0x38,       //     Usage (Wheel)
+    0x15, 0x81,       //     Logical Minimum (-127)
+    0x25, 0x7F,       //     Logical Maximum (127)
+    0x75, 0x08,       //     Report Size (8)
+    0x95, 0x03,       //     Report Count (3)
+    0x81, 0x06,       //     Input (Data, Variable, Relative) X, Y, Wheel
+    0x05, 0x0C,       //     Usage Page (Consumer)
+    0x0A, 0x38, 0x02, //     Usage (AC Pan)
+    0x95, 0x01,       //     Report Count (1)
+    0x81, 0x06,       //     Input (Data, Variable, Relative) Pan
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+];
+
+/// Absolute pointer report descriptor matching [crate::absolute::AbsolutePointer].
+/// Input: 3 button bits, then X and Y as 16 bit positions from 0 to 32767. Declared as a mouse rather than a
+/// digitizer, so hosts map it across every monitor instead of a single screen.
+pub const ABSOLUTE_MOUSE: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x02,       // Usage (Mouse)
+    0xA1, 0x01,       // Collection (Application)
+    0x09, 0x01,       //   Usage (Pointer)
+    0xA1, 0x00,       //   Collection (Physical)
+    0x05, 0x09,       //     Usage Page (Buttons)
+    0x19, 0x01,       //     Usage Minimum (1)
+    0x29, 0x03,       //     Usage Maximum (3)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x25, 0x01,       //     Logical Maximum (1)
+    0x75, 0x01,       //     Report Size (1)
+    0x95, 0x03,       //     Report Count (3)
+    0x81, 0x02,       //     Input (Data, Variable, Absolute) Buttons
+    0x75, 0x05,       //     Report Size (5)
+    0x95, 0x01,       //     Report Count (1)
+    0x81, 0x03,       //     Input (Constant) Button padding
+    0x05, 0x01,       //     Usage Page (Generic Desktop)
+    0x09, 0x30,       //     Usage (X)
+    0x09, 0x31,       //     Usage (Y)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x26, 0xFF, 0x7F, //     Logical Maximum (32767)
+    0x75, 0x10,       //     Report Size (16)
+    0x95, 0x02,       //     Report Count (2)
+    0x81, 0x02,       //     Input (Data, Variable, Absolute) X, Y
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+];
+
+/// Radial controller (e.g. Surface Dial) report descriptor matching [crate::dial::Dial].
+/// Input: 1 button bit then a 15 bit signed rotation in tenths of a degree.
+pub const DIAL: &[u8] = &[
+    0x05, 0x01,       // Usage Page (Generic Desktop)
+    0x09, 0x0E,       // Usage (System Multi-Axis Controller)
+    0xA1, 0x01,       // Collection (Application)
+    0x05, 0x0D,       //   Usage Page (Digitizers)
+    0x09, 0x21,       //   Usage (Puck)
+    0xA1, 0x00,       //   Collection (Physical)
+    0x05, 0x09,       //     Usage Page (Buttons)
+    0x09, 0x01,       //     Usage (Button 1)
+    0x15, 0x00,       //     Logical Minimum (0)
+    0x25, 0x01,       //     Logical Maximum (1)
+    0x75, 0x01,       //     Report Size (1)
+    0x95, 0x01,       //     Report Count (1)
+    0x81, 0x02,       //     Input (Data, Variable, Absolute) Button
+    0x05, 0x01,       //     Usage Page (Generic Desktop)
+    0x09, 0x37,       //     Usage (Dial)
+    0x55, 0x0F,       //     Unit Exponent (-1)
+    0x65, 0x14,       //     Unit (Degrees)
+    0x36, 0xF0, 0xF1, //     Physical Minimum (-3600)
+    0x46, 0x10, 0x0E, //     Physical Maximum (3600)
+    0x16, 0xF0, 0xF1, //     Logical Minimum (-3600)
+    0x26, 0x10, 0x0E, //     Logical Maximum (3600)
+    0x75, 0x0F,       //     Report Size (15)
+    0x95, 0x01,       //     Report Count (1)
+    0x81, 0x06,       //     Input (Data, Variable, Relative) Rotation
+    0xC0,             //   End Collection
+    0xC0,             // End Collection
+];
+
+/// Consumer control report descriptor matching [crate::consumer::ConsumerControl].
+/// Input: one 16 bit consumer usage, 0 when nothing is pressed.
+pub const CONSUMER: &[u8] = &[
+    0x05, 0x0C,       // Usage Page (Consumer)
+    0x09, 0x01,       // Usage (Consumer Control)
+    0xA1, 0x01,       // Collection (Application)
+    0x15, 0x00,       //   Logical Minimum (0)
+    0x26, 0xFF, 0x03, //   Logical Maximum (1023)
+    0x19, 0x00,       //   Usage Minimum (0)
+    0x2A, 0xFF, 0x03, //   Usage Maximum (1023)
+    0x75, 0x10,       //   Report Size (16)
+    0x95, 0x01,       //   Report Count (1)
+    0x81, 0x00,       //   Input (Data, Array, Absolute) Usage
+    0xC0,             // End Collection
+];
+
+/// Vendor defined (usage page 0xFF00) report descriptor with IN and OUT reports of report_len bytes,
+/// for exchanging data with a custom host agent, see [crate::vendor::VendorHID].
+pub fn vendor(report_len: u16) -> Vec<u8> {
+    let [len_lo, len_hi] = report_len.to_le_bytes();
+    vec![
+        0x06, 0x00, 0xFF,       // Usage Page (Vendor Defined 0xFF00)
+        0x09, 0x01,             // Usage (1)
+        0xA1, 0x01,             // Collection (Application)
+        0x15, 0x00,             //   Logical Minimum (0)
+        0x26, 0xFF, 0x00,       //   Logical Maximum (255)
+        0x75, 0x08,             //   Report Size (8)
+        0x96, len_lo, len_hi,   //   Report Count (report_len)
+        0x09, 0x02,             //   Usage (2)
+        0x81, 0x02,             //   Input (Data, Variable, Absolute) IN report
+        0x96, len_lo, len_hi,   //   Report Count (report_len)
+        0x09, 0x03,             //   Usage (3)
+        0x91, 0x02,             //   Output (Data, Variable, Absolute) OUT report
+        0xC0,                   // End Collection
+    ]
+}
+
+/// Usage page and usage of the first application collection in a report descriptor, e.g. (0x01, 0x06) for
+/// [KEYBOARD]. None if there is none or the descriptor is truncated.
+pub fn application_usage(descriptor: &[u8]) -> Option<(u32, u32)> {
+    let (mut page, mut usage) = (0u32, None);
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+        if prefix == 0xFE {
+            // long item
+            i += 3 + *descriptor.get(i + 1)? as usize;
+            continue;
+        }
+        let len = match prefix & 0x03 { 3 => 4, len => len as usize };
+        let data = descriptor.get(i + 1..i + 1 + len)?;
+        let unsigned = data.iter().rev().fold(0u32, |value, byte| value << 8 | *byte as u32);
+        match prefix & 0xFC {
+            // Usage Page
+            0x04 => page = unsigned,
+            // Usage, extended usages carry their page in the high 16 bits
+            0x08 => usage = Some(if len == 4 { (unsigned >> 16, unsigned & 0xFFFF) } else { (page, unsigned) }),
+            // Collection (Application)
+            0xA0 if unsigned == 0x01 => return usage,
+            // other main items end the usages before them
+            0x80 | 0x90 | 0xA0 | 0xB0 | 0xC0 => usage = None,
+            _ => (),
+        }
+        i += 1 + len;
+    }
+    None
+}
+
+/// Field name of a mouse usage, see [crate::report::ReportLayout::mouse]
+fn mouse_field(page: u32, usage: u32) -> Option<&'static str> {
+    match (page, usage) {
+        (PAGE_GENERIC_DESKTOP, 0x30) => Some("x"),
+        (PAGE_GENERIC_DESKTOP, 0x31) => Some("y"),
+        (PAGE_GENERIC_DESKTOP, 0x38) => Some("wheel"),
+        (PAGE_CONSUMER, 0x238) => Some("pan"),
+        _ => None,
+    }
+}
+
+/// Read the input report format of a mouse from its report descriptor: report length, optional report ID,
+/// button bits and the width and signedness of the X, Y, wheel and pan axes. Descriptors with several report IDs,
+/// or without buttons or X and Y axes, are unsupported.
+pub fn mouse_format(descriptor: &[u8]) -> io::Result<MouseFormat> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut fields: Vec<(&str, usize, usize, bool)> = Vec::new();
+    let (mut page, mut logical_min, mut size, mut count) = (0u32, 0i64, 0usize, 0usize);
+    let mut report_id: Option<u8> = None;
+    let mut usages: Vec<u32> = Vec::new();
+    let (mut usage_min, mut usage_max) = (None, None);
+    let mut offset = 0;
+    let mut i = 0;
+    while i < descriptor.len() {
+        let prefix = descriptor[i];
+        if prefix == 0xFE {
+            // long item
+            let len = *descriptor.get(i + 1).ok_or_else(|| invalid("truncated report descriptor"))? as usize;
+            i += 3 + len;
+            continue;
+        }
+        let len = match prefix & 0x03 { 3 => 4, len => len as usize };
+        let data = descriptor.get(i + 1..i + 1 + len).ok_or_else(|| invalid("truncated report descriptor"))?;
+        let unsigned = data.iter().rev().fold(0u32, |value, byte| value << 8 | *byte as u32);
+        let signed = match len {
+            1 => data[0] as i8 as i64,
+            2 => i16::from_le_bytes([data[0], data[1]]) as i64,
+            4 => unsigned as i32 as i64,
+            _ => 0,
+        };
+        // extended usages carry their page in the high 16 bits
+        let usage = |page: u32| if len == 4 { (unsigned >> 16, unsigned & 0xFFFF) } else { (page, unsigned) };
+        match (prefix >> 2) & 0x03 {
+            // main
+            0 => {
+                if prefix >> 4 == 0x8 {
+                    let constant = unsigned & 0x01 != 0;
+                    let variable = unsigned & 0x02 != 0;
+                    if !constant && variable && page == PAGE_BUTTONS {
+                        fields.push(("buttons", offset, size * count, false));
+                    } else if !constant && variable {
+                        for n in 0..count {
+                            let usage = usages.get(n).or(usages.last()).copied()
+                                .or(usage_min.map(|min: u32| min + n as u32).filter(|usage| usage_max.is_none_or(|max| *usage <= max)));
+                            if let Some(name) = usage.and_then(|usage| mouse_field(page, usage)) {
+                                fields.push((name, offset + n * size, size, logical_min < 0));
+                            }
+                        }
+                    }
+                    offset += size * count;
+                }
+                usages.clear();
+                usage_min = None;
+                usage_max = None;
+            },
+            // global
+            1 => match prefix >> 4 {
+                0x0 => page = unsigned,
+                0x1 => logical_min = signed,
+                0x7 => size = unsigned as usize,
+                0x8 => match report_id {
+                    Some(id) if id as u32 != unsigned => {
+                        return Err(io::Error::new(io::ErrorKind::Unsupported, "several report IDs"));
+                    },
+                    _ => report_id = Some(unsigned as u8),
+                },
+                0x9 => count = unsigned as usize,
+                _ => (),
+            },
+            // local
+            2 => match prefix >> 4 {
+                0x0 => {
+                    let (usage_page, usage) = usage(page);
+                    if usage_page == page {
+                        usages.push(usage);
+                    }
+                },
+                0x1 => usage_min = Some(usage(page).1),
+                0x2 => usage_max = Some(usage(page).1),
+                _ => (),
+            },
+            _ => (),
+        }
+        i += 1 + len;
+    }
+
+    let id_len = report_id.map_or(0, |_| 8);
+    let mut layout = ReportLayout::new((offset + id_len).div_ceil(8));
+    if report_id.is_some() {
+        layout = layout.field("report_id", 0, 8, false);
+    }
+    for (name, offset, width, signed) in fields {
+        if layout.get(name).is_none() {
+            layout = layout.field(name, offset + id_len, width, signed);
+        }
+    }
+    if ["buttons", "x", "y"].iter().any(|name| layout.get(name).is_none()) {
+        return Err(invalid("mouse report descriptor without buttons, X and Y"));
+    }
+    Ok(MouseFormat { layout, report_id, descriptor: Cow::Owned(descriptor.to_vec()) })
+}
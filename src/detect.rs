@@ -0,0 +1,128 @@
+#![warn(missing_docs)]
+
+use std::{collections::HashMap, io};
+
+use crate::{key::{KeyPacket, Keyboard}, HID};
+
+/// Sample typed to detect a layout, covering every letter and digit
+pub const PANGRAM: &str = "The quick brown fox jumps over the lazy dog 0123456789";
+/// Char perceived for keystrokes a layout doesn't type
+pub const UNKNOWN_CHAR: char = '\u{FFFD}';
+
+/// Decodes key packets into the text a host would perceive using a layout, the reverse of typing with it.
+/// Covers printable ASCII, Latin-1 and any extra chars given, including chars typed with dead keys.
+pub struct LayoutDecoder {
+    layout: &'static str,
+    // press packets of a char's keystrokes
+    chars: HashMap<Vec<Vec<u8>>, char>,
+}
+
+impl LayoutDecoder {
+    /// New for a layout key, see [Keyboard::available_layouts]. None if the layout isn't available.
+    pub fn new(layout_key: &str, extra: &str) -> Option<LayoutDecoder> {
+        let layout = Keyboard::available_layouts()
+            .into_iter()
+            .find(|layout| *layout == layout_key)?;
+        let mut chars = HashMap::new();
+        for c in (' '..='~').chain('\u{A0}'..='\u{FF}').chain(extra.chars()) {
+            let mut keyboard = Keyboard::new();
+            if keyboard.press(layout, c).is_none() {
+                continue;
+            }
+            let presses = press_packets(&keyboard.take_packets());
+            chars.entry(presses).or_insert(c);
+        }
+        Some(LayoutDecoder { layout, chars })
+    }
+
+    /// Layout key
+    pub fn layout(&self) -> &'static str {
+        self.layout
+    }
+
+    /// Text perceived from key packets. Keystrokes the layout doesn't type are perceived as [UNKNOWN_CHAR].
+    pub fn decode(&self, packets: &[KeyPacket]) -> String {
+        let presses = press_packets(packets);
+        let mut text = String::new();
+        let mut i = 0;
+        while i < presses.len() {
+            // dead key sequences are two presses
+            let (c, len) = match presses.get(i..i + 2).and_then(|sequence| self.chars.get(sequence)) {
+                Some(c) => (*c, 2),
+                None => (self.chars.get(&presses[i..i + 1]).copied().unwrap_or(UNKNOWN_CHAR), 1),
+            };
+            text.push(c);
+            i += len;
+        }
+        text
+    }
+}
+
+/// Bytes of the packets pressing keys, skipping releases
+fn press_packets(packets: &[KeyPacket]) -> Vec<Vec<u8>> {
+    packets.iter()
+        .filter(|packet| !packet.is_empty())
+        .map(|packet| packet.as_bytes().to_vec())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// How well a layout explains typed key packets
+pub struct LayoutMatch {
+    /// Layout key
+    pub layout: &'static str,
+    /// Text perceived with the layout
+    pub perceived: String,
+    /// Fraction of the sample's chars perceived correctly, from 0 to 1
+    pub score: f64,
+}
+
+/// Score layouts by how well the text a host would perceive with each matches a sample, best first.
+/// Candidates that aren't available layouts are skipped.
+pub fn rank_layouts(packets: &[KeyPacket], sample: &str, candidates: &[&str]) -> Vec<LayoutMatch> {
+    let expected: Vec<char> = sample.chars().collect();
+    let mut matches: Vec<LayoutMatch> = candidates.iter()
+        .filter_map(|candidate| LayoutDecoder::new(candidate, sample))
+        .map(|decoder| {
+            let perceived = decoder.decode(packets);
+            let correct = perceived.chars().zip(&expected).filter(|(perceived, expected)| perceived == *expected).count();
+            let len = expected.len().max(perceived.chars().count()).max(1);
+            LayoutMatch { layout: decoder.layout(), perceived, score: correct as f64 / len as f64 }
+        })
+        .collect();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.layout.cmp(b.layout)));
+    matches
+}
+
+/// Type a sample (e.g. [PANGRAM]) with a layout, None for the basic keyboard table, then rank every available layout
+/// by how well the text a host would perceive with it matches, best first. Useful for validating layout tables, where
+/// a table that doesn't rank itself first has conflicting entries.
+pub fn detect_layout(hid: &mut HID, typed_with: Option<&str>, sample: &str) -> io::Result<Vec<LayoutMatch>> {
+    hid.clear_key_packets();
+    let mut keyboard = Keyboard::new();
+    keyboard.set_layout(typed_with);
+    keyboard.type_string(sample);
+    keyboard.flush(hid)?;
+    let mut candidates = Keyboard::available_layouts();
+    candidates.sort_unstable();
+    Ok(rank_layouts(hid.key_packets(), sample, &candidates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_layout, LayoutDecoder, PANGRAM, UNKNOWN_CHAR};
+    use crate::{key::KeyPacket, HID};
+
+    #[test]
+    fn detects_typed_layout() {
+        let mut hid = HID::new("", "", "").unwrap();
+        let matches = detect_layout(&mut hid, Some("LAYOUT_US_ENGLISH"), PANGRAM).unwrap();
+        assert_eq!(matches[0].layout, "LAYOUT_US_ENGLISH");
+        assert_eq!(matches[0].perceived, PANGRAM);
+        assert_eq!(matches[0].score, 1.0);
+
+        let decoder = LayoutDecoder::new("LAYOUT_US_ENGLISH", "").unwrap();
+        assert_eq!(decoder.decode(&[KeyPacket::parse("0xE7").unwrap(), KeyPacket::new()]), UNKNOWN_CHAR.to_string());
+        assert!(LayoutDecoder::new("LAYOUT_NONE", "").is_none());
+    }
+}
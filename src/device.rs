@@ -0,0 +1,142 @@
+#![warn(missing_docs)]
+
+use std::borrow::Cow;
+
+use crate::{descriptor, key::Keyboard, mouse::Mouse};
+
+/// Virtual HID device type. Implement it to add devices (e.g. a dial) without patching virt-hid, then register the
+/// device's gadget interface with [crate::HID::register_device] or create it with [crate::uhid::UHIDDevice::from_device].
+pub trait VirtualDevice {
+    /// Report descriptor of the device's interface
+    fn descriptor(&self) -> Cow<'static, [u8]>;
+
+    /// Name the device's interface is registered under
+    fn interface_name(&self) -> &str;
+
+    /// Take the buffered events as raw reports
+    fn build_reports(&mut self) -> Vec<Vec<u8>>;
+}
+
+impl VirtualDevice for Keyboard {
+    fn descriptor(&self) -> Cow<'static, [u8]> {
+        Cow::Borrowed(descriptor::KEYBOARD)
+    }
+
+    fn interface_name(&self) -> &str {
+        "keyboard"
+    }
+
+    fn build_reports(&mut self) -> Vec<Vec<u8>> {
+        self.take_packets().iter().map(|packet| packet.as_bytes().to_vec()).collect()
+    }
+}
+
+impl VirtualDevice for Mouse {
+    fn descriptor(&self) -> Cow<'static, [u8]> {
+        self.format().descriptor.clone()
+    }
+
+    fn interface_name(&self) -> &str {
+        "mouse"
+    }
+
+    fn build_reports(&mut self) -> Vec<Vec<u8>> {
+        self.take_reports()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Output report sent by the host to a device interface, see [crate::HID::receive_device_event]. Reports are parsed
+/// in the Xbox 360 controller's format, which hosts commonly use for gamepads.
+pub enum OutputEvent {
+    /// Rumble motor speeds, from 0 (off) to 255
+    Rumble {
+        /// Speed of the low frequency (large) motor
+        strong: u8,
+        /// Speed of the high frequency (small) motor
+        weak: u8,
+    },
+    /// Player LED ring pattern
+    PlayerLeds(u8),
+    /// Any other report, as received
+    Raw(Vec<u8>),
+}
+
+impl OutputEvent {
+    /// Parse an output report: `[0x00, 0x08, 0x00, strong, weak, ..]` is rumble, `[0x01, 0x03, pattern]` sets the
+    /// player LEDs and anything else is raw
+    pub fn parse(report: &[u8]) -> OutputEvent {
+        match report {
+            [0x00, 0x08, _, strong, weak, ..] => OutputEvent::Rumble { strong: *strong, weak: *weak },
+            [0x01, 0x03, pattern, ..] => OutputEvent::PlayerLeds(*pattern),
+            _ => OutputEvent::Raw(report.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn send_registered_device() {
+        use std::borrow::Cow;
+
+        use super::{OutputEvent, VirtualDevice};
+        use crate::{HID, key::{Keyboard, ReportProtocol}};
+
+        struct Counter(u8);
+
+        impl VirtualDevice for Counter {
+            fn descriptor(&self) -> Cow<'static, [u8]> {
+                Cow::Owned(crate::descriptor::vendor(1))
+            }
+
+            fn interface_name(&self) -> &str {
+                "counter"
+            }
+
+            fn build_reports(&mut self) -> Vec<Vec<u8>> {
+                self.0 += 1;
+                vec![vec![self.0]]
+            }
+        }
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut counter = Counter(0);
+        assert!(hid.send_device(&mut counter).is_err());
+        hid.register_device("counter", "/dev/hidg2").unwrap();
+        hid.send_device(&mut counter).unwrap();
+        hid.send_device(&mut counter).unwrap();
+        assert_eq!(std::fs::read(hid.get_device_path("counter").unwrap()).unwrap(), vec![1, 2]);
+        assert_eq!(hid.receive_device_report("counter", std::time::Duration::ZERO).unwrap(), None);
+        assert!(hid.receive_device_report("missing", std::time::Duration::ZERO).is_err());
+        hid.inject_device_report("counter", &[0x00, 0x08, 0x00, 0x80, 0x20]).unwrap();
+        hid.inject_device_report("counter", &[0x02]).unwrap();
+        assert_eq!(hid.receive_device_event("counter", std::time::Duration::ZERO).unwrap(), Some(OutputEvent::Rumble { strong: 0x80, weak: 0x20 }));
+        assert_eq!(hid.receive_device_report("counter", std::time::Duration::ZERO).unwrap(), Some(vec![0x02]));
+        assert_eq!(hid.receive_device_event("counter", std::time::Duration::ZERO).unwrap(), None);
+        assert!(hid.inject_device_report("missing", &[0]).is_err());
+
+        // unregistered keyboards are sent in the keyboard interface's protocol
+        hid.set_keyboard_protocol(ReportProtocol::Boot);
+        let mut keyboard = Keyboard::new();
+        keyboard.press_basic_string_checked("a");
+        hid.send_device(&mut keyboard).unwrap();
+        assert_eq!(hid.events().len(), 2);
+        assert_eq!(std::fs::read(hid.get_keyboard_path()).unwrap(), [
+            [0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00],
+            [0x00; 8],
+            [0x00; 8],
+        ].concat());
+    }
+
+    #[test]
+    fn parses_output_reports() {
+        use super::OutputEvent;
+
+        assert_eq!(OutputEvent::parse(&[0x00, 0x08, 0x00, 0xFF, 0x40, 0x00, 0x00, 0x00]), OutputEvent::Rumble { strong: 0xFF, weak: 0x40 });
+        assert_eq!(OutputEvent::parse(&[0x01, 0x03, 0x06]), OutputEvent::PlayerLeds(0x06));
+        assert_eq!(OutputEvent::parse(&[0x00, 0x08, 0x00]), OutputEvent::Raw(vec![0x00, 0x08, 0x00]));
+        assert_eq!(OutputEvent::parse(&[]), OutputEvent::Raw(Vec::new()));
+    }
+}
@@ -0,0 +1,120 @@
+#![warn(missing_docs)]
+
+use std::borrow::Cow;
+
+use crate::{descriptor, device::VirtualDevice};
+
+const DIAL_REPORT_LEN: usize = 2;
+const DIAL_BUTTON: u16 = 0x01;
+/// Max rotation in a report, in tenths of a degree
+const DIAL_MAX_ROTATION: i16 = 3600;
+
+/// Virtual radial controller (e.g. Surface Dial), see [descriptor::DIAL]. Register its gadget interface with
+/// [crate::HID::register_device] under "dial" and send it with [crate::HID::send_device].
+pub struct Dial {
+    rotation: i16,
+    pressed: bool,
+    hold: bool,
+}
+
+impl Default for Dial {
+    fn default() -> Self {
+        Dial::new()
+    }
+}
+
+impl Dial {
+    /// New
+    pub fn new() -> Dial {
+        Dial { rotation: 0, pressed: false, hold: false }
+    }
+
+    /// Rotate the dial by degrees, positive is clockwise. Rotations in the same report add up to at most a full turn either way.
+    pub fn rotate(&mut self, degrees: f32) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("rotate {:?}", degrees);
+        }
+        let tenths = (degrees * 10.0).round().clamp(-DIAL_MAX_ROTATION as f32, DIAL_MAX_ROTATION as f32) as i16;
+        self.rotation = (self.rotation + tenths).clamp(-DIAL_MAX_ROTATION, DIAL_MAX_ROTATION);
+    }
+
+    /// Click the dial
+    pub fn press(&mut self) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("press dial");
+        }
+        self.pressed = true;
+    }
+
+    /// Hold the dial down
+    pub fn hold(&mut self) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("hold dial");
+        }
+        self.hold = true;
+    }
+
+    /// Release the held dial
+    pub fn release(&mut self) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("release dial");
+        }
+        self.hold = false;
+    }
+
+    fn report(rotation: i16, pressed: bool) -> [u8; DIAL_REPORT_LEN] {
+        let mut data = (rotation as u16) << 1;
+        if pressed {
+            data |= DIAL_BUTTON;
+        }
+        data.to_le_bytes()
+    }
+
+    /// Take buffered dial events as raw reports, followed by a report restoring the held state
+    pub fn take_reports(&mut self) -> Vec<[u8; DIAL_REPORT_LEN]> {
+        let report = Dial::report(self.rotation, self.pressed || self.hold);
+        self.rotation = 0;
+        self.pressed = false;
+        vec![report, Dial::report(0, self.hold)]
+    }
+}
+
+impl VirtualDevice for Dial {
+    fn descriptor(&self) -> Cow<'static, [u8]> {
+        Cow::Borrowed(descriptor::DIAL)
+    }
+
+    fn interface_name(&self) -> &str {
+        "dial"
+    }
+
+    fn build_reports(&mut self) -> Vec<Vec<u8>> {
+        self.take_reports().iter().map(|report| report.to_vec()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dial;
+
+    #[test]
+    fn rotation_reports() {
+        let mut dial = Dial::new();
+        dial.rotate(-1.5);
+        dial.press();
+        let reports = dial.take_reports();
+        let data = u16::from_le_bytes(reports[0]);
+        assert_eq!(data & 0x01, 1);
+        assert_eq!((data as i16) >> 1, -15);
+        assert_eq!(reports[1], [0, 0]);
+
+        dial.hold();
+        dial.rotate(720.0);
+        let data = u16::from_le_bytes(dial.take_reports()[0]);
+        assert_eq!(((data as i16) >> 1, data & 0x01), (3600, 1));
+    }
+}
@@ -0,0 +1,145 @@
+#![warn(missing_docs)]
+
+use std::{collections::HashMap, time::Duration};
+
+use serde::{Serialize, Deserialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{key::{KeyOrigin, KeyPacket}, schedule::{Report, Scheduler}};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// Keystroke dynamics timing model, e.g. exported from a keystroke capture dataset.
+/// Dwell is how long a key is held, down-down is the time from pressing one key to pressing the next.
+/// A down-down shorter than the previous dwell makes the keys overlap, like fast typists do.
+/// A repeated key is released when it's pressed again, as it can't overlap itself.
+pub struct DynamicsProfile {
+    /// Dwell of keys without their own entry
+    pub default_dwell: Duration,
+    /// Down-down of digraphs without their own entry
+    pub default_down_down: Duration,
+    /// Dwell per character
+    pub dwell: HashMap<char, Duration>,
+    /// Down-down per digraph, keyed by the two characters, e.g. "th"
+    pub down_down: HashMap<String, Duration>,
+}
+
+impl Default for DynamicsProfile {
+    fn default() -> Self {
+        DynamicsProfile {
+            default_dwell: Duration::from_millis(90),
+            default_down_down: Duration::from_millis(180),
+            dwell: HashMap::new(),
+            down_down: HashMap::new(),
+        }
+    }
+}
+
+impl DynamicsProfile {
+    /// New with default timings for every key
+    pub fn new(default_dwell: Duration, default_down_down: Duration) -> DynamicsProfile {
+        DynamicsProfile { default_dwell, default_down_down, ..DynamicsProfile::default() }
+    }
+
+    /// Set dwell of a character
+    pub fn set_dwell(&mut self, c: char, dwell: Duration) {
+        self.dwell.insert(c, dwell);
+    }
+
+    /// Set down-down of a digraph
+    pub fn set_down_down(&mut self, first: char, second: char, down_down: Duration) {
+        self.down_down.insert([first, second].iter().collect(), down_down);
+    }
+
+    /// Dwell of a character
+    pub fn dwell_of(&self, c: char) -> Duration {
+        self.dwell.get(&c).copied().unwrap_or(self.default_dwell)
+    }
+
+    /// Down-down of a digraph
+    pub fn down_down_of(&self, first: char, second: char) -> Duration {
+        let digraph: String = [first, second].iter().collect();
+        self.down_down.get(&digraph).copied().unwrap_or(self.default_down_down)
+    }
+
+    /// Schedule typing a string with the basic keyboard table after the last scheduled report, following the profile's timings.
+    /// Returns the grapheme clusters that couldn't be typed, which are skipped without affecting the timing of the rest.
+    pub fn schedule(&self, scheduler: &mut Scheduler, str: &str) -> Vec<String> {
+        let mut unmappable = Vec::new();
+        // (time, key index, pressed)
+        let mut edges = Vec::new();
+        let mut keys: Vec<KeyPacket> = Vec::new();
+        // index of each key's release edge
+        let mut releases: Vec<usize> = Vec::new();
+        let mut at = scheduler.end();
+        let mut previous = None;
+        for grapheme in str.graphemes(true) {
+            let mut chars = grapheme.chars();
+            let packet = match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyPacket::from_char(&c, &KeyOrigin::Keyboard).map(|packet| (c, packet)),
+                _ => None,
+            };
+            let Some((c, packet)) = packet else {
+                unmappable.push(grapheme.to_string());
+                continue;
+            };
+            if let Some(previous) = previous {
+                at += self.down_down_of(previous, c);
+            }
+            // a key still held can't be pressed again, release it first so the repeat is seen
+            for (held, release) in keys.iter().zip(&releases) {
+                let (released_at, _, _) = &mut edges[*release];
+                if *released_at > at && packet.contains_any(held) {
+                    *released_at = at;
+                }
+            }
+            edges.push((at, keys.len(), true));
+            releases.push(edges.len());
+            edges.push((at + self.dwell_of(c), keys.len(), false));
+            keys.push(packet);
+            previous = Some(c);
+        }
+
+        edges.sort_by_key(|(at, _, pressed)| (*at, *pressed));
+        let mut down = vec![false; keys.len()];
+        for (at, key, pressed) in edges {
+            down[key] = pressed;
+            let state = keys.iter()
+                .zip(&down)
+                .filter(|(_, down)| **down)
+                .fold(KeyPacket::new(), |state, (packet, _)| &state | packet);
+            scheduler.push(at, Report::Keyboard(state));
+        }
+        unmappable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{key::{KeyOrigin, KeyPacket}, schedule::{Report, Scheduler}};
+
+    use super::DynamicsProfile;
+
+    #[test]
+    fn releases_repeated_key_between_presses() {
+        let profile = DynamicsProfile::new(Duration::from_millis(100), Duration::from_millis(40));
+        let mut scheduler = Scheduler::new();
+        assert!(profile.schedule(&mut scheduler, "ll").is_empty());
+
+        let l = KeyPacket::from_char(&'l', &KeyOrigin::Keyboard).unwrap();
+        let reports: Vec<_> = scheduler.reports().iter()
+            .map(|scheduled| match &scheduled.report {
+                Report::Keyboard(packet) => (scheduled.at, packet.contains_any(&l)),
+                Report::Mouse(_) => panic!("unexpected mouse report"),
+            })
+            .collect();
+        assert_eq!(reports, vec![
+            (Duration::ZERO, true),
+            (Duration::from_millis(40), false),
+            (Duration::from_millis(40), true),
+            (Duration::from_millis(140), false),
+        ]);
+    }
+}
@@ -0,0 +1,99 @@
+#![warn(missing_docs)]
+
+use serde::{Serialize, Deserialize};
+
+/// Default overshoot of [Easing::Overshoot], about 10% past the target
+pub const OVERSHOOT: f32 = 1.70158;
+const BEZIER_ITERATIONS: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+/// Easing curve mapping the fraction of a movement's time to the fraction of its distance travelled
+pub enum Easing {
+    /// Constant speed
+    #[default]
+    Linear,
+    /// Start slow, cubic
+    EaseIn,
+    /// End slow, cubic
+    EaseOut,
+    /// Start and end slow, cubic
+    EaseInOut,
+    /// Cubic bezier through (0, 0), the two control points and (1, 1), like CSS cubic-bezier(x1, y1, x2, y2).
+    /// x is time and y distance, x values are clamped to 0..=1.
+    Bezier {
+        /// First control point time
+        x1: f32,
+        /// First control point distance
+        y1: f32,
+        /// Second control point time
+        x2: f32,
+        /// Second control point distance
+        y2: f32,
+    },
+    /// End slow after moving past the target and settling back, by an amount (see [OVERSHOOT])
+    Overshoot(f32),
+}
+
+/// Value of a 1D cubic bezier from 0 to 1 with control values a and b
+fn bezier(a: f32, b: f32, s: f32) -> f32 {
+    let r = 1.0 - s;
+    3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+}
+
+impl Easing {
+    /// Fraction of the distance travelled after a fraction of the time, from 0 at t = 0 to 1 at t = 1.
+    /// t is clamped to 0..=1, the result can leave 0..=1 for curves moving past their ends.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+            Easing::Bezier { x1, y1, x2, y2 } => {
+                let (x1, x2) = (x1.clamp(0.0, 1.0), x2.clamp(0.0, 1.0));
+                // x is monotonic in s when the control times are within 0..=1
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..BEZIER_ITERATIONS {
+                    let mid = (low + high) / 2.0;
+                    match bezier(x1, x2, mid) < t {
+                        true => low = mid,
+                        false => high = mid,
+                    }
+                }
+                bezier(y1, y2, (low + high) / 2.0)
+            },
+            Easing::Overshoot(amount) => {
+                let t = t - 1.0;
+                1.0 + (amount + 1.0) * t * t * t + amount * t * t
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Easing, OVERSHOOT};
+
+    #[test]
+    fn curve_shapes() {
+        let curves = [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+            Easing::Bezier { x1: 0.25, y1: 0.1, x2: 0.25, y2: 1.0 },
+            Easing::Overshoot(OVERSHOOT),
+        ];
+        for curve in curves {
+            assert!(curve.apply(0.0).abs() < 1e-3, "{:?}", curve);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-3, "{:?}", curve);
+        }
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert!((Easing::EaseInOut.apply(0.5) - 0.5).abs() < 1e-6);
+        assert!((Easing::Bezier { x1: 0.0, y1: 0.0, x2: 1.0, y2: 1.0 }.apply(0.3) - 0.3).abs() < 1e-3);
+        assert!(Easing::Overshoot(OVERSHOOT).apply(0.8) > 1.0);
+    }
+}
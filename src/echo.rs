@@ -0,0 +1,187 @@
+#![warn(missing_docs)]
+
+use std::{
+    io::{self, Read},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use crate::{key::Keyboard, vendor::VendorHID, HidBackend};
+
+/// Default time to wait for more echoed text before giving up
+pub const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Channel a host agent echoes received text over. The agent is a small program on the target reading the keystrokes
+/// it receives (e.g. from a focused text field) and echoing the text back as UTF-8. Over the vendor interface each OUT
+/// report starts with the number of UTF-8 bytes that follow, at most the report length minus one, and a char may be
+/// split across reports. Over a network stream the UTF-8 bytes are sent as they are.
+pub trait EchoSource {
+    /// Read the next echoed bytes, None if nothing arrived within the timeout
+    fn read_echo(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// UTF-8 bytes of a vendor echo report, after its length byte
+fn echo_payload(report: &[u8]) -> io::Result<Vec<u8>> {
+    let Some((len, payload)) = report.split_first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "empty echo report"));
+    };
+    payload.get(..*len as usize)
+        .map(|payload| payload.to_vec())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "echo length exceeds report"))
+}
+
+impl EchoSource for VendorHID {
+    fn read_echo(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        self.receive_report(timeout)?.map(|report| echo_payload(&report)).transpose()
+    }
+}
+
+impl EchoSource for TcpStream {
+    fn read_echo(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        self.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut buf = [0; 256];
+        match self.read(&mut buf) {
+            Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "host agent disconnected")),
+            Ok(len) => Ok(Some(buf[..len].to_vec())),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Typed text compared with the text the host agent echoed
+pub struct EchoReport {
+    /// Text typed
+    pub expected: String,
+    /// Text echoed by the host agent
+    pub received: String,
+}
+
+impl EchoReport {
+    /// Check if the host received exactly the typed text
+    pub fn is_match(&self) -> bool {
+        self.expected == self.received
+    }
+
+    /// Index of the first char that differs, None if the texts match
+    pub fn first_mismatch(&self) -> Option<usize> {
+        if self.is_match() {
+            return None;
+        }
+        let same = self.expected.chars().zip(self.received.chars()).take_while(|(expected, received)| expected == received).count();
+        Some(same)
+    }
+
+    /// Fail with [io::ErrorKind::InvalidData] describing the first difference if the texts don't match
+    pub fn check(&self) -> io::Result<()> {
+        match self.first_mismatch() {
+            None => Ok(()),
+            Some(i) => Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "host received {:?}, expected {:?} (first difference at char {})", self.received, self.expected, i,
+            ))),
+        }
+    }
+}
+
+/// Cross-checks typed text against the text a host agent echoes back, for end to end verified typing
+pub struct EchoVerifier<S: EchoSource> {
+    source: S,
+    timeout: Duration,
+    pending: Vec<u8>,
+}
+
+impl<S: EchoSource> EchoVerifier<S> {
+    /// New, reading echoes from a source
+    pub fn new(source: S) -> EchoVerifier<S> {
+        EchoVerifier { source, timeout: ECHO_TIMEOUT, pending: Vec::new() }
+    }
+
+    /// Set how long to wait for more echoed text before giving up
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the echo source
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Discard echoes that arrived before now, e.g. before typing
+    pub fn drain(&mut self) -> io::Result<()> {
+        while self.source.read_echo(Duration::ZERO)?.is_some() {}
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Read echoed text until it has as many chars as expected, or no more arrives within the timeout
+    pub fn verify(&mut self, expected: &str) -> io::Result<EchoReport> {
+        let expected_len = expected.chars().count();
+        let mut received = String::new();
+        let mut deadline = Instant::now() + self.timeout;
+        while received.chars().count() < expected_len {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let bytes = match self.source.read_echo(wait)? {
+                Some(bytes) => bytes,
+                None => break,
+            };
+            deadline = Instant::now() + self.timeout;
+            self.pending.extend_from_slice(&bytes);
+            let valid = match std::str::from_utf8(&self.pending) {
+                Ok(str) => str.len(),
+                // keep a char split across reports for the next read
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            };
+            let text: Vec<u8> = self.pending.drain(..valid).collect();
+            received.push_str(std::str::from_utf8(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+        }
+        Ok(EchoReport { expected: expected.to_string(), received })
+    }
+
+    /// Type text with a keyboard, then verify the host agent echoes it. Earlier echoes are discarded first.
+    pub fn type_verified(&mut self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, text: &str) -> io::Result<EchoReport> {
+        self.drain()?;
+        keyboard.type_string(text);
+        keyboard.flush(hid)?;
+        self.verify(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, io, time::Duration};
+
+    use super::{echo_payload, EchoSource, EchoVerifier};
+
+    struct Echoes(VecDeque<Vec<u8>>);
+
+    impl EchoSource for Echoes {
+        fn read_echo(&mut self, _timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.pop_front())
+        }
+    }
+
+    #[test]
+    fn cross_checks_echoes() {
+        let echoed = "héllo".as_bytes();
+        let mut verifier = EchoVerifier::new(Echoes(VecDeque::from([echoed[..2].to_vec(), echoed[2..].to_vec()])));
+        let report = verifier.verify("héllo").unwrap();
+        assert!(report.is_match());
+        assert!(report.check().is_ok());
+
+        verifier.source_mut().0.push_back(b"helo".to_vec());
+        let report = verifier.verify("hello").unwrap();
+        assert_eq!(report.received, "helo");
+        assert_eq!(report.first_mismatch(), Some(3));
+        assert!(report.check().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_vendor_echoes() {
+        assert_eq!(echo_payload(&[2, b'h', b'i', 0]).unwrap(), b"hi");
+        assert_eq!(echo_payload(&[0, 0]).unwrap(), b"");
+        assert_eq!(echo_payload(&[]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(echo_payload(&[3, b'h']).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
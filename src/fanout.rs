@@ -0,0 +1,272 @@
+#![warn(missing_docs)]
+
+use std::{io::{self, Write}, time::Duration};
+
+use crate::{HidBackend, key::KeyPacket};
+
+/// Tag written before key packets to a writer child
+pub const KEY_TAG: u8 = b'K';
+/// Tag written before mouse packets to a writer child
+pub const MOUSE_TAG: u8 = b'M';
+
+/// Backend a [FanoutHID] duplicates packets to
+pub enum FanoutChild {
+    /// Backend, e.g. a HID interface. Reports sent through [HidBackend::send_key_report] and
+    /// [HidBackend::send_mouse_report] are encoded in its own protocol and format.
+    Backend(Box<dyn HidBackend + Send>),
+    /// Writer, e.g. a tap log file or a network mirror. Each packet is written as [KEY_TAG] or [MOUSE_TAG] followed by its bytes.
+    Writer(Box<dyn Write + Send>),
+}
+
+impl FanoutChild {
+    fn send(&mut self, tag: u8, data: &[u8]) -> io::Result<()> {
+        match (self, tag) {
+            (FanoutChild::Backend(backend), KEY_TAG) => backend.send_key_packet(data),
+            (FanoutChild::Backend(backend), _) => backend.send_mouse_packet(data),
+            (FanoutChild::Writer(writer), _) => {
+                let mut tagged = Vec::with_capacity(data.len() + 1);
+                tagged.push(tag);
+                tagged.extend_from_slice(data);
+                writer.write_all(&tagged)?;
+                writer.flush()
+            },
+        }
+    }
+
+    fn send_key_report(&mut self, packet: &KeyPacket) -> io::Result<()> {
+        match self {
+            FanoutChild::Backend(backend) => backend.send_key_report(packet),
+            FanoutChild::Writer(_) => self.send(KEY_TAG, packet.as_bytes()),
+        }
+    }
+
+    fn send_mouse_report(&mut self, packet: &[u8; 5]) -> io::Result<()> {
+        match self {
+            FanoutChild::Backend(backend) => backend.send_mouse_report(packet),
+            FanoutChild::Writer(_) => self.send(MOUSE_TAG, packet),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What a child's send errors do
+pub enum ErrorIsolation {
+    /// Fail the send, after the other children have been sent to
+    #[default]
+    Propagate,
+    /// Count the error and carry on
+    Ignore,
+    /// Count the error and stop sending to the child
+    Detach,
+}
+
+struct Child {
+    child: FanoutChild,
+    isolation: ErrorIsolation,
+    errors: u64,
+    detached: bool,
+}
+
+#[derive(Default)]
+/// Duplicates each packet to several children, e.g. a gadget, a tap log and a network mirror. LED states are received
+/// from the first backend child.
+pub struct FanoutHID {
+    children: Vec<Child>,
+}
+
+impl FanoutHID {
+    /// New, without children
+    pub fn new() -> FanoutHID {
+        FanoutHID::default()
+    }
+
+    /// Add a child, returning its index
+    pub fn add(&mut self, child: FanoutChild, isolation: ErrorIsolation) -> usize {
+        self.children.push(Child { child, isolation, errors: 0, detached: false });
+        self.children.len() - 1
+    }
+
+    /// Number of children
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    /// Check if there are no children
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Child
+    pub fn child(&mut self, index: usize) -> Option<&mut FanoutChild> {
+        self.children.get_mut(index).map(|child| &mut child.child)
+    }
+
+    /// Number of send errors of a child
+    pub fn errors(&self, index: usize) -> Option<u64> {
+        self.children.get(index).map(|child| child.errors)
+    }
+
+    /// Check if a child was detached after an error
+    pub fn is_detached(&self, index: usize) -> Option<bool> {
+        self.children.get(index).map(|child| child.detached)
+    }
+
+    fn send(&mut self, mut send: impl FnMut(&mut FanoutChild) -> io::Result<()>) -> io::Result<()> {
+        let mut res = Ok(());
+        for child in self.children.iter_mut().filter(|child| !child.detached) {
+            if let Err(e) = send(&mut child.child) {
+                child.errors += 1;
+                match child.isolation {
+                    ErrorIsolation::Propagate if res.is_ok() => res = Err(e),
+                    ErrorIsolation::Detach => child.detached = true,
+                    _ => (),
+                }
+            }
+        }
+        res
+    }
+
+    /// First backend child that isn't detached
+    fn primary(&mut self) -> Option<&mut Box<dyn HidBackend + Send>> {
+        self.children.iter_mut()
+            .filter(|child| !child.detached)
+            .find_map(|child| match &mut child.child {
+                FanoutChild::Backend(backend) => Some(backend),
+                FanoutChild::Writer(_) => None,
+            })
+    }
+}
+
+impl HidBackend for FanoutHID {
+    fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        self.send(|child| child.send(KEY_TAG, data))
+    }
+
+    fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        self.send(|child| child.send(MOUSE_TAG, data))
+    }
+
+    fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        match self.primary() {
+            Some(backend) => backend.receive_states_packet(timeout),
+            None => Ok(None),
+        }
+    }
+
+    fn send_key_report(&mut self, packet: &KeyPacket) -> io::Result<()> {
+        self.send(|child| child.send_key_report(packet))
+    }
+
+    fn send_mouse_report(&mut self, packet: &[u8; 5]) -> io::Result<()> {
+        self.send(|child| child.send_mouse_report(packet))
+    }
+
+    fn last_led_state(&self) -> Option<u8> {
+        self.children.iter()
+            .filter(|child| !child.detached)
+            .find_map(|child| match &child.child {
+                FanoutChild::Backend(backend) => Some(backend.last_led_state()),
+                FanoutChild::Writer(_) => None,
+            })
+            .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{self, Write}, sync::{Arc, Mutex}};
+
+    use super::{ErrorIsolation, FanoutChild, FanoutHID, KEY_TAG, MOUSE_TAG};
+    use crate::HidBackend;
+
+    struct Tap(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Tap {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "mirror gone"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn isolates_child_errors() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut fanout = FanoutHID::new();
+        let broken = fanout.add(FanoutChild::Writer(Box::new(Broken)), ErrorIsolation::Detach);
+        fanout.add(FanoutChild::Writer(Box::new(Tap(log.clone()))), ErrorIsolation::Propagate);
+
+        fanout.send_mouse_packet(&[1, 2, 3, 4, 5]).unwrap();
+        fanout.send_mouse_packet(&[0; 5]).unwrap();
+        assert_eq!(fanout.errors(broken), Some(1));
+        assert_eq!(fanout.is_detached(broken), Some(true));
+        assert_eq!(log.lock().unwrap().len(), 12);
+        assert_eq!(log.lock().unwrap()[..6], [MOUSE_TAG, 1, 2, 3, 4, 5]);
+
+        fanout.add(FanoutChild::Writer(Box::new(Broken)), ErrorIsolation::Propagate);
+        assert!(fanout.send_key_packet(&[0; 33]).is_err());
+        assert_eq!(log.lock().unwrap().len(), 46);
+    }
+    #[test]
+    fn keyboards_flush_to_every_child() {
+        use std::time::Duration;
+
+        use crate::key::{KeyPacket, Keyboard, ReportProtocol};
+
+        /// Boot protocol gadget recording its key reports
+        struct Boot(Arc<Mutex<Vec<Vec<u8>>>>);
+
+        impl HidBackend for Boot {
+            fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                self.0.lock().unwrap().push(data.to_vec());
+                Ok(())
+            }
+
+            fn send_mouse_packet(&mut self, _data: &[u8]) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+                Ok(Some(0x02))
+            }
+
+            fn keyboard_protocol(&self) -> ReportProtocol {
+                ReportProtocol::Boot
+            }
+        }
+
+        let (reports, log) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        let mut fanout = FanoutHID::new();
+        fanout.add(FanoutChild::Writer(Box::new(Tap(log.clone()))), ErrorIsolation::Propagate);
+        fanout.add(FanoutChild::Backend(Box::new(Boot(reports.clone()))), ErrorIsolation::Propagate);
+        assert_eq!(fanout.receive_states_packet(Duration::ZERO).unwrap(), Some(0x02));
+
+        let mut keyboard = Keyboard::new();
+        keyboard.press_basic_string_checked("a");
+        keyboard.flush(&mut fanout).unwrap();
+        assert_eq!(reports.lock().unwrap().len(), 3);
+        assert_eq!(log.lock().unwrap().len(), 3 * 34);
+
+        // reports are encoded for each child
+        fanout.release_all().unwrap();
+        assert_eq!(reports.lock().unwrap().last().unwrap(), &vec![0; 8]);
+        let log = log.lock().unwrap();
+        assert_eq!(log[log.len() - 40..log.len() - 6], [&[KEY_TAG][..], KeyPacket::new().as_bytes()].concat());
+        assert_eq!(log[log.len() - 6..], [MOUSE_TAG, 0, 0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,207 @@
+#![warn(missing_docs)]
+
+use std::{ffi::CStr, os::raw::{c_char, c_int}, ptr};
+
+use crate::{HID, key::Keyboard, mouse::{Delta, Mouse, MouseButton, MouseDir}};
+
+/// Opaque handle bundling a HID interface with a virtual keyboard and mouse
+pub struct VirtHID {
+    hid: HID,
+    keyboard: Keyboard,
+    mouse: Mouse,
+}
+
+const FFI_OK: c_int = 0;
+const FFI_ERR: c_int = -1;
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+unsafe fn to_handle<'a>(handle: *mut VirtHID) -> Option<&'a mut VirtHID> {
+    handle.as_mut()
+}
+
+fn to_code<T, E>(res: Result<T, E>) -> c_int {
+    match res {
+        Ok(_) => FFI_OK,
+        Err(_) => FFI_ERR,
+    }
+}
+
+/// Create a new HID handle from the mouse, keyboard and LED device paths.
+/// Returns null on failure.
+///
+/// # Safety
+/// All paths must be valid null terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_new(mouse: *const c_char, keyboard: *const c_char, led: *const c_char) -> *mut VirtHID {
+    let (mouse, keyboard, led) = match (to_str(mouse), to_str(keyboard), to_str(led)) {
+        (Some(mouse), Some(keyboard), Some(led)) => (mouse, keyboard, led),
+        _ => return ptr::null_mut(),
+    };
+    match HID::new(mouse, keyboard, led) {
+        Ok(hid) => Box::into_raw(Box::new(VirtHID {
+            hid,
+            keyboard: Keyboard::new(),
+            mouse: Mouse::new(),
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Destroy a HID handle created by [virt_hid_new]. Null is ignored.
+///
+/// # Safety
+/// The handle must have been created by [virt_hid_new] and not already destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_free(handle: *mut VirtHID) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Type a string. If layout is null the basic US keyboard table is used,
+/// otherwise the named layout from [Keyboard::available_layouts].
+/// Returns 0 on success and -1 on failure. Nothing is typed if the layout is unknown or can't type all of the text.
+///
+/// # Safety
+/// The handle must be valid, the text must be a valid null terminated string and the layout must be null or a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_type_string(handle: *mut VirtHID, layout: *const c_char, text: *const c_char) -> c_int {
+    let (handle, text) = match (to_handle(handle), to_str(text)) {
+        (Some(handle), Some(text)) => (handle, text),
+        _ => return FFI_ERR,
+    };
+    let unmappable = match (layout.is_null(), to_str(layout)) {
+        (true, _) => handle.keyboard.press_basic_string_checked(text),
+        (false, Some(layout)) => handle.keyboard.press_string_checked(layout, text),
+        (false, None) => return FFI_ERR,
+    };
+    if !unmappable.is_empty() {
+        handle.keyboard.take_packets();
+        return FFI_ERR;
+    }
+    to_code(handle.keyboard.send(&mut handle.hid))
+}
+
+/// Hold a key down by keycode until [virt_hid_key_up] is called.
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+/// The handle must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_key_down(handle: *mut VirtHID, keycode: u8) -> c_int {
+    let handle = match to_handle(handle) {
+        Some(handle) => handle,
+        None => return FFI_ERR,
+    };
+    handle.keyboard.hold_keycode(keycode);
+    to_code(handle.keyboard.send(&mut handle.hid))
+}
+
+/// Release a key held by [virt_hid_key_down].
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+/// The handle must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_key_up(handle: *mut VirtHID, keycode: u8) -> c_int {
+    let handle = match to_handle(handle) {
+        Some(handle) => handle,
+        None => return FFI_ERR,
+    };
+    handle.keyboard.release_keycode(keycode);
+    to_code(handle.keyboard.send(&mut handle.hid))
+}
+
+/// Move the mouse by a relative amount.
+/// Returns 0 on success and -1 on failure.
+///
+/// # Safety
+/// The handle must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_mouse_move(handle: *mut VirtHID, x: i8, y: i8) -> c_int {
+    let handle = match to_handle(handle) {
+        Some(handle) => handle,
+        None => return FFI_ERR,
+    };
+    handle.mouse.move_mouse(Delta::saturating(x as i32), MouseDir::X);
+    handle.mouse.move_mouse(Delta::saturating(y as i32), MouseDir::Y);
+    to_code(handle.mouse.send(&mut handle.hid))
+}
+
+/// Click a mouse button (0 = left, 1 = right, 2 = middle).
+/// Returns 0 on success and -1 on failure, including any other button.
+///
+/// # Safety
+/// The handle must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn virt_hid_mouse_click(handle: *mut VirtHID, button: u32) -> c_int {
+    let handle = match to_handle(handle) {
+        Some(handle) => handle,
+        None => return FFI_ERR,
+    };
+    let button = match button {
+        0..=2 => MouseButton::from(button),
+        _ => return FFI_ERR,
+    };
+    handle.mouse.press_button(button);
+    to_code(handle.mouse.send(&mut handle.hid))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, ptr};
+
+    use super::{virt_hid_key_down, virt_hid_mouse_click, virt_hid_mouse_move, virt_hid_type_string, FFI_ERR};
+
+    #[test]
+    fn null_handle_fails() {
+        unsafe {
+            assert_eq!(virt_hid_mouse_click(ptr::null_mut(), 0), FFI_ERR);
+            assert_eq!(virt_hid_mouse_move(ptr::null_mut(), 1, 1), FFI_ERR);
+            assert_eq!(virt_hid_key_down(ptr::null_mut(), 4), FFI_ERR);
+            let text = CString::new("a").unwrap();
+            assert_eq!(virt_hid_type_string(ptr::null_mut(), ptr::null(), text.as_ptr()), FFI_ERR);
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn invalid_button_fails() {
+        use super::{virt_hid_free, virt_hid_new, FFI_OK};
+
+        unsafe {
+            let path = CString::new("").unwrap();
+            let handle = virt_hid_new(path.as_ptr(), path.as_ptr(), path.as_ptr());
+            assert!(!handle.is_null());
+            assert_eq!(virt_hid_mouse_click(handle, 3), FFI_ERR);
+            assert_eq!(virt_hid_mouse_click(handle, u32::MAX), FFI_ERR);
+            assert_eq!((*handle).mouse.take_packets(), vec![[0; 5]; 2]);
+            assert_eq!(virt_hid_mouse_click(handle, 2), FFI_OK);
+            virt_hid_free(handle);
+        }
+    }
+    #[cfg(feature = "debug")]
+    #[test]
+    fn untypable_text_fails() {
+        use super::{virt_hid_free, virt_hid_new, FFI_OK};
+
+        unsafe {
+            let path = CString::new("").unwrap();
+            let handle = virt_hid_new(path.as_ptr(), path.as_ptr(), path.as_ptr());
+            let (text, emoji) = (CString::new("a").unwrap(), CString::new("a\u{1F600}").unwrap());
+            let unknown = CString::new("LAYOUT_UNKNOWN").unwrap();
+            assert_eq!(virt_hid_type_string(handle, ptr::null(), emoji.as_ptr()), FFI_ERR);
+            assert_eq!(virt_hid_type_string(handle, unknown.as_ptr(), text.as_ptr()), FFI_ERR);
+            assert!((*handle).hid.key_packets().is_empty());
+            assert_eq!(virt_hid_type_string(handle, ptr::null(), text.as_ptr()), FFI_OK);
+            assert_eq!((*handle).hid.key_packets().len(), 3);
+            virt_hid_free(handle);
+        }
+    }
+}
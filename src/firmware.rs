@@ -0,0 +1,234 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+
+use crate::{
+    key::{encode_string, KeyPacket, Modifier},
+    macros::{Macro, MacroAction},
+};
+
+/// Keycode names in QMK (without the X_ prefix of SEND_STRING) and ZMK, by usage
+const KEY_NAMES: &[(u8, &str, &str)] = &[
+    (0x04, "A", "A"), (0x05, "B", "B"), (0x06, "C", "C"), (0x07, "D", "D"), (0x08, "E", "E"), (0x09, "F", "F"),
+    (0x0A, "G", "G"), (0x0B, "H", "H"), (0x0C, "I", "I"), (0x0D, "J", "J"), (0x0E, "K", "K"), (0x0F, "L", "L"),
+    (0x10, "M", "M"), (0x11, "N", "N"), (0x12, "O", "O"), (0x13, "P", "P"), (0x14, "Q", "Q"), (0x15, "R", "R"),
+    (0x16, "S", "S"), (0x17, "T", "T"), (0x18, "U", "U"), (0x19, "V", "V"), (0x1A, "W", "W"), (0x1B, "X", "X"),
+    (0x1C, "Y", "Y"), (0x1D, "Z", "Z"),
+    (0x1E, "1", "N1"), (0x1F, "2", "N2"), (0x20, "3", "N3"), (0x21, "4", "N4"), (0x22, "5", "N5"),
+    (0x23, "6", "N6"), (0x24, "7", "N7"), (0x25, "8", "N8"), (0x26, "9", "N9"), (0x27, "0", "N0"),
+    (0x28, "ENT", "RET"), (0x29, "ESC", "ESC"), (0x2A, "BSPC", "BSPC"), (0x2B, "TAB", "TAB"), (0x2C, "SPC", "SPACE"),
+    (0x2D, "MINS", "MINUS"), (0x2E, "EQL", "EQUAL"), (0x2F, "LBRC", "LBKT"), (0x30, "RBRC", "RBKT"),
+    (0x31, "BSLS", "BSLH"), (0x32, "NUHS", "NON_US_HASH"), (0x33, "SCLN", "SEMI"), (0x34, "QUOT", "SQT"),
+    (0x35, "GRV", "GRAVE"), (0x36, "COMM", "COMMA"), (0x37, "DOT", "DOT"), (0x38, "SLSH", "FSLH"),
+    (0x39, "CAPS", "CAPS"),
+    (0x3A, "F1", "F1"), (0x3B, "F2", "F2"), (0x3C, "F3", "F3"), (0x3D, "F4", "F4"), (0x3E, "F5", "F5"),
+    (0x3F, "F6", "F6"), (0x40, "F7", "F7"), (0x41, "F8", "F8"), (0x42, "F9", "F9"), (0x43, "F10", "F10"),
+    (0x44, "F11", "F11"), (0x45, "F12", "F12"),
+    (0x46, "PSCR", "PSCRN"), (0x47, "SCRL", "SLCK"), (0x48, "PAUS", "PAUSE_BREAK"), (0x49, "INS", "INS"),
+    (0x4A, "HOME", "HOME"), (0x4B, "PGUP", "PG_UP"), (0x4C, "DEL", "DEL"), (0x4D, "END", "END"),
+    (0x4E, "PGDN", "PG_DN"), (0x4F, "RGHT", "RIGHT"), (0x50, "LEFT", "LEFT"), (0x51, "DOWN", "DOWN"),
+    (0x52, "UP", "UP"), (0x53, "NUM", "KP_NUM"),
+    (0x54, "PSLS", "KP_SLASH"), (0x55, "PAST", "KP_MULTIPLY"), (0x56, "PMNS", "KP_MINUS"), (0x57, "PPLS", "KP_PLUS"),
+    (0x58, "PENT", "KP_ENTER"), (0x59, "P1", "KP_N1"), (0x5A, "P2", "KP_N2"), (0x5B, "P3", "KP_N3"),
+    (0x5C, "P4", "KP_N4"), (0x5D, "P5", "KP_N5"), (0x5E, "P6", "KP_N6"), (0x5F, "P7", "KP_N7"),
+    (0x60, "P8", "KP_N8"), (0x61, "P9", "KP_N9"), (0x62, "P0", "KP_N0"), (0x63, "PDOT", "KP_DOT"),
+    (0x64, "NUBS", "NON_US_BSLH"), (0x65, "APP", "K_APP"),
+];
+
+/// Modifier names in QMK and ZMK
+fn modifier_names(modifier: Modifier) -> (&'static str, &'static str) {
+    match modifier {
+        Modifier::LeftControl => ("LCTL", "LCTRL"),
+        Modifier::LeftShift => ("LSFT", "LSHFT"),
+        Modifier::LeftAlt => ("LALT", "LALT"),
+        Modifier::LeftMeta => ("LGUI", "LGUI"),
+        Modifier::RightControl => ("RCTL", "RCTRL"),
+        Modifier::RightShift => ("RSFT", "RSHFT"),
+        Modifier::RightAlt => ("RALT", "RALT"),
+        Modifier::RightMeta => ("RGUI", "RGUI"),
+    }
+}
+
+fn unsupported(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, what)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Modifier(Modifier),
+    Usage(u8),
+}
+
+impl Key {
+    /// Name in QMK (false) or ZMK (true)
+    fn name(&self, zmk: bool) -> io::Result<&'static str> {
+        match self {
+            Key::Modifier(modifier) => {
+                let (qmk_name, zmk_name) = modifier_names(*modifier);
+                Ok(if zmk { zmk_name } else { qmk_name })
+            },
+            Key::Usage(usage) => KEY_NAMES.iter()
+                .find(|(key_usage, _, _)| key_usage == usage)
+                .map(|(_, qmk_name, zmk_name)| if zmk { *zmk_name } else { *qmk_name })
+                .ok_or_else(|| unsupported(format!("no firmware keycode for usage 0x{:02X}", usage))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Text(String),
+    Delay(Duration),
+    Down(Key),
+    Up(Key),
+    Tap(Key),
+}
+
+/// Key downs and ups between two packets, releasing keys before modifiers and pressing modifiers before keys
+fn packet_steps(previous: &KeyPacket, packet: &KeyPacket, steps: &mut Vec<Step>) {
+    let (released, pressed) = (previous - packet, packet - previous);
+    steps.extend(released.usages().map(|usage| Step::Up(Key::Usage(usage))));
+    steps.extend(released.modifiers().into_iter().map(|modifier| Step::Up(Key::Modifier(modifier))));
+    steps.extend(pressed.modifiers().into_iter().map(|modifier| Step::Down(Key::Modifier(modifier))));
+    steps.extend(pressed.usages().map(|usage| Step::Down(Key::Usage(usage))));
+}
+
+/// Convert a macro into firmware steps, keeping text as text if keep_text is set and otherwise typing it with the
+/// basic keyboard table. A key down directly followed by its up becomes a tap.
+fn macro_steps(recorded: &Macro, keep_text: bool) -> io::Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut held = KeyPacket::new();
+    for event in recorded.events() {
+        if !event.delay.is_zero() {
+            steps.push(Step::Delay(event.delay));
+        }
+        match &event.action {
+            MacroAction::Type(text) => {
+                if let Some(c) = text.chars().find(|c| !c.is_ascii() || (c.is_ascii_control() && !matches!(c, '\n' | '\t'))) {
+                    return Err(unsupported(format!("firmware can't type {:?}", c)));
+                }
+                if keep_text {
+                    packet_steps(&held, &KeyPacket::new(), &mut steps);
+                    held = KeyPacket::new();
+                    steps.push(Step::Text(text.clone()));
+                } else {
+                    for packet in encode_string(None, text) {
+                        packet_steps(&held, &packet, &mut steps);
+                        held = packet;
+                    }
+                }
+            },
+            MacroAction::Key(packet) => {
+                packet_steps(&held, packet, &mut steps);
+                held = packet.clone();
+            },
+            MacroAction::Mouse(_) => return Err(unsupported(String::from("firmware macros can't send mouse reports"))),
+        }
+    }
+
+    let mut taps: Vec<Step> = Vec::with_capacity(steps.len());
+    for step in steps {
+        match (taps.last(), &step) {
+            (Some(Step::Down(down)), Step::Up(up)) if down == up => {
+                let key = *up;
+                taps.pop();
+                taps.push(Step::Tap(key));
+            },
+            _ => taps.push(step),
+        }
+    }
+    Ok(taps)
+}
+
+fn qmk_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Export a macro as a QMK SEND_STRING statement, e.g. `SEND_STRING(SS_LCTL("c") SS_DELAY(100) "hello");`.
+/// Text is sent as a string literal, typed by QMK with the host layout it's built for.
+/// Fails with [io::ErrorKind::Unsupported] for mouse events, non ASCII text and keys without a QMK keycode.
+pub fn to_qmk(recorded: &Macro) -> io::Result<String> {
+    let mut parts = Vec::new();
+    for step in macro_steps(recorded, true)? {
+        parts.push(match step {
+            Step::Text(text) => qmk_string(&text),
+            Step::Delay(delay) => format!("SS_DELAY({})", delay.as_millis()),
+            Step::Down(key) => format!("SS_DOWN(X_{})", key.name(false)?),
+            Step::Up(key) => format!("SS_UP(X_{})", key.name(false)?),
+            Step::Tap(key) => format!("SS_TAP(X_{})", key.name(false)?),
+        });
+    }
+    Ok(format!("SEND_STRING({});", parts.join(" ")))
+}
+
+/// Export a macro as a ZMK macro behavior node, to be placed in the `macros` node of a keymap and bound as `&name`.
+/// Text is typed as key taps from the basic keyboard table, see [encode_string].
+/// Fails with [io::ErrorKind::Unsupported] for mouse events, non ASCII text and keys without a ZMK keycode.
+pub fn to_zmk(recorded: &Macro, name: &str) -> io::Result<String> {
+    let mut bindings = Vec::new();
+    for step in macro_steps(recorded, false)? {
+        match step {
+            Step::Text(_) => unreachable!("text is typed as keys"),
+            Step::Delay(delay) => {
+                bindings.push(format!("<&macro_wait_time {}>", delay.as_millis()));
+                bindings.push(String::from("<&macro_tap &none>"));
+                bindings.push(String::from("<&macro_wait_time 0>"));
+            },
+            Step::Down(key) => bindings.push(format!("<&macro_press &kp {}>", key.name(true)?)),
+            Step::Up(key) => bindings.push(format!("<&macro_release &kp {}>", key.name(true)?)),
+            Step::Tap(key) => bindings.push(format!("<&macro_tap &kp {}>", key.name(true)?)),
+        }
+    }
+    let mut node = format!("{name}: {name} {{\n", name = name);
+    node.push_str("    compatible = \"zmk,behavior-macro\";\n");
+    node.push_str("    #binding-cells = <0>;\n");
+    node.push_str("    wait-ms = <0>;\n");
+    node.push_str("    bindings\n");
+    for (i, binding) in bindings.iter().enumerate() {
+        node.push_str(&format!("        {} {}\n", if i == 0 { "=" } else { "," }, binding));
+    }
+    node.push_str("        ;\n};\n");
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{to_qmk, to_zmk};
+    use crate::{key::KeyPacket, macros::{Macro, MacroAction, MacroEvent}};
+
+    #[test]
+    fn exports_macros() {
+        let mut recorded = Macro::new();
+        recorded.push(MacroEvent::new(Duration::ZERO, MacroAction::Key(KeyPacket::parse("LCTRL+c").unwrap())));
+        recorded.push(MacroEvent::new(Duration::ZERO, MacroAction::Key(KeyPacket::new())));
+        recorded.push(MacroEvent::new(Duration::from_millis(100), MacroAction::Type(String::from("Hi \"x\"\n"))));
+        assert_eq!(
+            to_qmk(&recorded).unwrap(),
+            "SEND_STRING(SS_DOWN(X_LCTL) SS_TAP(X_C) SS_UP(X_LCTL) SS_DELAY(100) \"Hi \\\"x\\\"\\n\");",
+        );
+
+        let node = to_zmk(&recorded, "copy_hi").unwrap();
+        assert!(node.starts_with("copy_hi: copy_hi {\n    compatible = \"zmk,behavior-macro\";\n"));
+        assert!(node.contains("        = <&macro_press &kp LCTRL>\n        , <&macro_tap &kp C>\n"));
+        assert!(node.contains("<&macro_wait_time 100>"));
+        assert!(node.contains("        , <&macro_press &kp LSHFT>\n        , <&macro_tap &kp H>\n"));
+        assert!(node.ends_with("<&macro_tap &kp RET>\n        ;\n};\n"));
+
+        recorded.push(MacroEvent::new(Duration::ZERO, MacroAction::Mouse([1, 0, 0, 0, 0])));
+        assert!(to_qmk(&recorded).is_err());
+        assert!(to_zmk(&recorded, "m").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+
+use crate::{HidBackend, key::{BasicKey, Keyboard, LEDState, Modifier}, mouse::{Delta, Mouse, MouseButton, MouseDir}};
+
+#[derive(Debug, Clone)]
+/// Action performed when entering a state
+pub enum Action {
+    /// Type a string with the keyboard's configured layout
+    Type(String),
+    /// Press a key
+    Press(BasicKey),
+    /// Press a shortcut
+    Shortcut(Vec<Modifier>, BasicKey),
+    /// Click a mouse button
+    Click(MouseButton),
+    /// Move the mouse by a relative amount
+    Move(Delta, Delta),
+}
+
+#[derive(Debug, Clone)]
+/// Event that moves the flow to another state
+pub enum Trigger {
+    /// Time since entering the state
+    After(Duration),
+    /// LED turning on (true) or off (false)
+    Led(LEDState, bool),
+}
+
+#[derive(Debug, Clone)]
+/// Transition to another state
+pub struct Transition {
+    /// Trigger
+    pub trigger: Trigger,
+    /// Name of the next state
+    pub to: String,
+}
+
+#[derive(Debug, Clone)]
+/// Flow state. A state without transitions ends the flow.
+pub struct State {
+    name: String,
+    actions: Vec<Action>,
+    transitions: Vec<Transition>,
+}
+
+impl State {
+    /// New
+    pub fn new(name: &str) -> State {
+        State { name: name.to_string(), actions: Vec::new(), transitions: Vec::new() }
+    }
+
+    /// Add an action performed when entering the state
+    pub fn push_action(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    /// Add a transition. Transitions are checked in the order they're added.
+    pub fn push_transition(&mut self, trigger: Trigger, to: &str) {
+        self.transitions.push(Transition { trigger, to: to.to_string() });
+    }
+
+    /// Name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug, Clone)]
+/// State machine for long running interactive flows
+pub struct Flow {
+    start: String,
+    states: Vec<State>,
+}
+
+impl Flow {
+    /// New flow beginning at the start state
+    pub fn new(start: &str) -> Flow {
+        Flow { start: start.to_string(), states: Vec::new() }
+    }
+
+    /// Add a state, replacing any state with the same name
+    pub fn add_state(&mut self, state: State) {
+        self.states.retain(|s| s.name != state.name);
+        self.states.push(state);
+    }
+
+    fn get_state(&self, name: &str) -> io::Result<&State> {
+        self.states.iter()
+            .find(|state| state.name == name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no flow state named {:?}", name)))
+    }
+
+    fn enter(state: &State, hid: &mut dyn HidBackend, keyboard: &mut Keyboard, mouse: &mut Mouse) -> io::Result<()> {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("enter {:?}", state.name);
+        }
+        for action in &state.actions {
+            match action {
+                Action::Type(text) => {
+                    keyboard.type_string(text);
+                    keyboard.send(hid)?;
+                },
+                Action::Press(key) => {
+                    keyboard.press_key(key);
+                    keyboard.send(hid)?;
+                },
+                Action::Shortcut(modifiers, key) => {
+                    keyboard.press_shortcut(modifiers, key);
+                    keyboard.send(hid)?;
+                },
+                Action::Click(button) => {
+                    mouse.press_button(*button);
+                    mouse.send(hid)?;
+                },
+                Action::Move(x, y) => {
+                    mouse.move_mouse(*x, MouseDir::X);
+                    mouse.move_mouse(*y, MouseDir::Y);
+                    mouse.send(hid)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the flow until it reaches a state without transitions, polling LED states every poll interval. Time based
+    /// triggers and polls without LED triggers go by the keyboard's clock.
+    /// Returns the names of the states visited in order.
+    pub fn run(&self, hid: &mut dyn HidBackend, keyboard: &mut Keyboard, mouse: &mut Mouse, poll: Duration) -> io::Result<Vec<String>> {
+        let mut visited = Vec::new();
+        let mut state = self.get_state(&self.start)?;
+        loop {
+            visited.push(state.name.clone());
+            Flow::enter(state, hid, keyboard, mouse)?;
+            if state.transitions.is_empty() {
+                return Ok(visited);
+            }
+
+            let entered = keyboard.clock().now();
+            let watches_leds = state.transitions.iter().any(|t| matches!(t.trigger, Trigger::Led(..)));
+            let next = loop {
+                if watches_leds {
+                    keyboard.update_led_state(hid, poll)?;
+                } else {
+                    keyboard.clock().sleep(poll);
+                }
+                let fired = state.transitions.iter().find(|transition| match &transition.trigger {
+                    Trigger::After(duration) => keyboard.clock().now().saturating_duration_since(entered) >= *duration,
+                    Trigger::Led(led, on) => keyboard.led_state(led) == *on,
+                });
+                if let Some(transition) = fired {
+                    break &transition.to;
+                }
+            };
+            state = self.get_state(next)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn runs_led_and_timer_transitions() {
+        use std::{io::Write, sync::Arc, time::Duration};
+
+        use super::{Action, Flow, State, Trigger};
+        use crate::{clock::VirtualClock, key::{BasicKey, Keyboard, LEDState, SpecialKey}, mouse::Mouse, HID};
+
+        let mut leds = tempfile::NamedTempFile::new().unwrap();
+        leds.write_all(&[0x01]).unwrap();
+        let mut hid = HID::new("", "", "").unwrap();
+        hid.set_state_data(leds.path().to_str().unwrap()).unwrap();
+        let clock = VirtualClock::new();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_clock(Arc::new(clock.clone()));
+
+        let mut flow = Flow::new("boot");
+        let mut boot = State::new("boot");
+        boot.push_action(Action::Press(BasicKey::Special(SpecialKey::ReturnEnter)));
+        boot.push_transition(Trigger::Led(LEDState::NumLock, true), "ready");
+        flow.add_state(boot);
+        let mut ready = State::new("ready");
+        ready.push_action(Action::Type(String::from("ok")));
+        ready.push_transition(Trigger::After(Duration::from_secs(5)), "done");
+        flow.add_state(ready);
+        flow.add_state(State::new("done"));
+
+        let visited = flow.run(&mut hid, &mut keyboard, &mut Mouse::new(), Duration::from_secs(1)).unwrap();
+        assert_eq!(visited, vec!["boot", "ready", "done"]);
+        assert_eq!(clock.elapsed(), Duration::from_secs(5));
+        assert!(Flow::new("missing").run(&mut hid, &mut keyboard, &mut Mouse::new(), Duration::ZERO).is_err());
+    }
+}
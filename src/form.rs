@@ -0,0 +1,143 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{HidBackend, key::{Keyboard, KeyPacket}};
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// Value typed into a form field by [fill_form]
+pub struct FieldValue {
+    /// Text typed into the field, empty leaves the field as it is
+    pub value: String,
+    /// Wait after typing the field, None uses [FormOptions::delay]
+    pub delay: Option<Duration>,
+}
+
+impl FieldValue {
+    /// New, waiting the form's delay
+    pub fn new(value: &str) -> FieldValue {
+        FieldValue { value: value.to_string(), delay: None }
+    }
+
+    /// New, waiting a delay after typing, e.g. for fields that load suggestions
+    pub fn with_delay(value: &str, delay: Duration) -> FieldValue {
+        FieldValue { value: value.to_string(), delay: Some(delay) }
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::new(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// How [fill_form] moves through a form
+pub struct FormOptions {
+    /// Chords pressed in order to move to the next field, see [KeyPacket::parse]
+    pub navigation: Vec<String>,
+    /// Wait after typing each field
+    pub delay: Duration,
+    /// Chord pressed after the last field to submit the form, None doesn't submit
+    pub submit: Option<String>,
+}
+
+impl Default for FormOptions {
+    fn default() -> Self {
+        FormOptions { navigation: vec!["Tab".to_string()], delay: Duration::ZERO, submit: None }
+    }
+}
+
+/// Type values into consecutive form fields, starting in the focused field and moving to the next with the navigation
+/// chords. Each field is sent before its delay, waited on the keyboard's clock. Returns the grapheme clusters that
+/// couldn't be typed.
+pub fn fill_form(keyboard: &mut Keyboard, hid: &mut dyn HidBackend, fields: &[FieldValue], options: FormOptions) -> io::Result<Vec<String>> {
+    fill_form_verified(keyboard, hid, fields, options, |_, _, _| Ok(true))
+}
+
+/// Like [fill_form], calling verify with each field's index after its delay, e.g. to check a field with
+/// [crate::echo] or a screenshot. Stops with an InvalidData error at the first field verify rejects.
+pub fn fill_form_verified(
+    keyboard: &mut Keyboard,
+    hid: &mut dyn HidBackend,
+    fields: &[FieldValue],
+    options: FormOptions,
+    mut verify: impl FnMut(usize, &FieldValue, &mut dyn HidBackend) -> io::Result<bool>,
+) -> io::Result<Vec<String>> {
+    // parse every chord before typing anything, so a typo doesn't leave the form half filled
+    if let Some(chord) = options.navigation.iter().chain(&options.submit).find(|chord| KeyPacket::parse(chord).is_none()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unrecognised chord {:?}", chord)));
+    }
+
+    let mut unmappable = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("fill field {}", i);
+        }
+        if i > 0 {
+            for chord in &options.navigation {
+                keyboard.press_chord(chord);
+            }
+        }
+        unmappable.extend(keyboard.type_string(&field.value));
+        keyboard.send(hid)?;
+        keyboard.clock().sleep(field.delay.unwrap_or(options.delay));
+        if !verify(i, field, hid)? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("field {} failed verification", i)));
+        }
+    }
+    if let Some(submit) = &options.submit {
+        keyboard.press_chord(submit);
+        keyboard.send(hid)?;
+    }
+    Ok(unmappable)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn fills_fields_in_order() {
+        use std::{io, sync::Arc, time::Duration};
+
+        use super::{fill_form, fill_form_verified, FieldValue, FormOptions};
+        use crate::{HID, clock::VirtualClock, key::{Keyboard, KeyPacket}};
+
+        let chords = |chords: &[&str]| chords.iter().map(|chord| KeyPacket::parse(chord).unwrap()).collect::<Vec<_>>();
+        let written = |hid: &HID| std::fs::read(hid.get_keyboard_path()).unwrap()
+            .chunks(KeyPacket::new().as_bytes().len())
+            .map(|data| KeyPacket::from_bytes(data).unwrap())
+            .filter(|packet| !packet.is_empty())
+            .collect::<Vec<_>>();
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_clock(Arc::new(clock.clone()));
+        let options = FormOptions { submit: Some("ReturnEnter".to_string()), delay: Duration::from_secs(1), ..FormOptions::default() };
+        let fields = ["a".into(), FieldValue::new(""), FieldValue::with_delay("b", Duration::from_secs(5))];
+        fill_form(&mut keyboard, &mut hid, &fields, options).unwrap();
+        assert_eq!(written(&hid), chords(&["a", "Tab", "Tab", "b", "ReturnEnter"]));
+        assert_eq!(clock.elapsed(), Duration::from_secs(7));
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let options = FormOptions { navigation: vec!["nope".to_string()], ..FormOptions::default() };
+        let error = fill_form(&mut keyboard, &mut hid, &["a".into()], options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(written(&hid).is_empty());
+
+        let mut verified = Vec::new();
+        let error = fill_form_verified(&mut keyboard, &mut hid, &["a".into(), "b".into(), "c".into()], FormOptions::default(), |i, field, _| {
+            verified.push(field.value.clone());
+            Ok(i == 0)
+        }).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(verified, vec!["a", "b"]);
+        assert_eq!(written(&hid), chords(&["a", "Tab", "b"]));
+    }
+}
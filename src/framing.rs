@@ -0,0 +1,272 @@
+#![warn(missing_docs)]
+
+use std::io::{self, Read, Write};
+
+/// Ends every frame on the wire, COBS encoding removes it from the frame contents
+const DELIMITER: u8 = 0x00;
+/// Frame kind, sequence number and CRC
+const FRAME_OVERHEAD: usize = 4;
+/// Default number of retransmits before a send fails
+pub const FRAME_RETRIES: u32 = 3;
+
+/// Encode data with Consistent Overhead Byte Stuffing, so it contains no zero bytes
+pub fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_idx = 0;
+    encoded.push(0);
+    for byte in data {
+        if *byte != 0 {
+            encoded.push(*byte);
+        }
+        let code = encoded.len() - code_idx;
+        if *byte == 0 || code == 0xFF {
+            encoded[code_idx] = code as u8;
+            code_idx = encoded.len();
+            encoded.push(0);
+        }
+    }
+    encoded[code_idx] = (encoded.len() - code_idx) as u8;
+    encoded
+}
+
+/// Decode COBS encoded data, see [cobs_encode]. None if the data is malformed.
+pub fn cobs_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let code = encoded[i] as usize;
+        if code == 0 || i + code > encoded.len() {
+            return None;
+        }
+        data.extend_from_slice(&encoded[i + 1..i + code]);
+        i += code;
+        if code != 0xFF && i < encoded.len() {
+            data.push(0);
+        }
+    }
+    Some(data)
+}
+
+/// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ (*byte as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => crc << 1 ^ 0x1021,
+        })
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Frame kind
+pub enum FrameKind {
+    /// Payload to deliver
+    Data,
+    /// Acknowledges a data frame with the same sequence number
+    Ack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Frame of a [FramedLink]
+pub struct Frame {
+    /// Kind
+    pub kind: FrameKind,
+    /// Sequence number
+    pub seq: u8,
+    /// Payload, empty for acks
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Encode for the wire: kind, sequence number, payload and big endian CRC16, COBS encoded and delimited
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.payload.len() + FRAME_OVERHEAD);
+        data.push(match self.kind {
+            FrameKind::Data => 0,
+            FrameKind::Ack => 1,
+        });
+        data.push(self.seq);
+        data.extend_from_slice(&self.payload);
+        data.extend_from_slice(&crc16(&data).to_be_bytes());
+        let mut encoded = cobs_encode(&data);
+        encoded.push(DELIMITER);
+        encoded
+    }
+
+    /// Decode a frame from the wire, without its delimiter. Fails with [io::ErrorKind::InvalidData] if it's corrupt.
+    pub fn decode(encoded: &[u8]) -> io::Result<Frame> {
+        let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("corrupt frame: {}", what));
+        let data = cobs_decode(encoded).ok_or_else(|| invalid("bad encoding"))?;
+        if data.len() < FRAME_OVERHEAD {
+            return Err(invalid("too short"));
+        }
+        let (data, crc) = data.split_at(data.len() - 2);
+        if crc16(data).to_be_bytes() != crc {
+            return Err(invalid("bad checksum"));
+        }
+        let kind = match data[0] {
+            0 => FrameKind::Data,
+            1 => FrameKind::Ack,
+            _ => return Err(invalid("unknown kind")),
+        };
+        Ok(Frame { kind, seq: data[1], payload: data[2..].to_vec() })
+    }
+}
+
+/// Check if a read error means no data arrived before the link's read timeout
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(err.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+/// Reliable packets over a noisy byte stream such as a UART to a microcontroller. Packets are sent as COBS encoded
+/// frames with a CRC16, acknowledged by the receiver and retransmitted if the ack doesn't arrive. Corrupt frames are
+/// dropped. The stream needs a read timeout (e.g. a serial port's), which is how long a send waits for each ack.
+pub struct FramedLink<T: Read + Write> {
+    io: T,
+    seq: u8,
+    last_received: Option<u8>,
+    retries: u32,
+}
+
+impl<T: Read + Write> FramedLink<T> {
+    /// New over a stream
+    pub fn new(io: T) -> FramedLink<T> {
+        FramedLink { io, seq: 0, last_received: None, retries: FRAME_RETRIES }
+    }
+
+    /// Set the number of retransmits before a send fails
+    pub fn set_retries(&mut self, retries: u32) {
+        self.retries = retries;
+    }
+
+    /// Get the underlying stream
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Get the underlying stream
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Read the next frame, skipping corrupt frames. None if the read timed out.
+    fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut encoded = Vec::new();
+        let mut byte = [0; 1];
+        loop {
+            match self.io.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) if byte[0] != DELIMITER => encoded.push(byte[0]),
+                Ok(_) => match Frame::decode(&encoded) {
+                    Ok(frame) => return Ok(Some(frame)),
+                    Err(_e) => {
+                        #[cfg(feature = "debug")]
+                        {
+                            crate::logging::log_line!("dropped frame: {}", _e);
+                        }
+                        encoded.clear();
+                    },
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) if is_timeout(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Send a packet, retransmitting it until it's acknowledged.
+    /// Fails with [io::ErrorKind::TimedOut] if it isn't acknowledged after the retries.
+    pub fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        let frame = Frame { kind: FrameKind::Data, seq: self.seq, payload: payload.to_vec() }.encode();
+        for _ in 0..=self.retries {
+            self.io.write_all(&frame)?;
+            self.io.flush()?;
+            while let Some(reply) = self.read_frame()? {
+                if reply.kind == FrameKind::Ack && reply.seq == self.seq {
+                    self.seq = self.seq.wrapping_add(1);
+                    return Ok(());
+                }
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::TimedOut, "frame not acknowledged"))
+    }
+
+    /// Receive the next packet, acknowledging it. Retransmitted packets are acknowledged again but only returned once.
+    /// Fails with [io::ErrorKind::TimedOut] if nothing arrives before the read timeout.
+    pub fn receive(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            let frame = match self.read_frame()? {
+                Some(frame) => frame,
+                None => return Err(io::Error::new(io::ErrorKind::TimedOut, "no frame received")),
+            };
+            if frame.kind != FrameKind::Data {
+                continue;
+            }
+            self.io.write_all(&Frame { kind: FrameKind::Ack, seq: frame.seq, payload: Vec::new() }.encode())?;
+            self.io.flush()?;
+            if self.last_received != Some(frame.seq) {
+                self.last_received = Some(frame.seq);
+                return Ok(frame.payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use super::{cobs_decode, cobs_encode, crc16, Frame, FrameKind, FramedLink};
+
+    /// Stream reading from prepared input, where running out of input is a timeout, and recording output
+    struct Wire {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Wire {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(kind: FrameKind, seq: u8, payload: &[u8]) -> Vec<u8> {
+        Frame { kind, seq, payload: payload.to_vec() }.encode()
+    }
+
+    #[test]
+    fn frames_survive_noise() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        for data in [vec![], vec![0], vec![0x11, 0x00, 0x00, 0x22], vec![0xAB; 600]] {
+            let encoded = cobs_encode(&data);
+            assert!(!encoded.contains(&0));
+            assert_eq!(cobs_decode(&encoded), Some(data));
+        }
+
+        let data = frame(FrameKind::Data, 0, &[0x02, 0x00, 0x04]);
+        let mut corrupt = data.clone();
+        corrupt[3] ^= 0x10;
+        let input = [corrupt, data.clone(), data].concat();
+        let mut receiver = FramedLink::new(Wire { input: Cursor::new(input), output: Vec::new() });
+        assert_eq!(receiver.receive().unwrap(), vec![0x02, 0x00, 0x04]);
+        assert_eq!(receiver.receive().unwrap_err().kind(), io::ErrorKind::TimedOut);
+        let ack = frame(FrameKind::Ack, 0, &[]);
+        assert_eq!(receiver.into_inner().output, [ack.clone(), ack.clone()].concat());
+
+        let mut sender = FramedLink::new(Wire { input: Cursor::new(ack), output: Vec::new() });
+        sender.send(b"a").unwrap();
+        sender.set_retries(2);
+        assert_eq!(sender.send(b"b").unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(sender.get_ref().output, [frame(FrameKind::Data, 0, b"a"), frame(FrameKind::Data, 1, b"b").repeat(3)].concat());
+    }
+}
@@ -1,105 +1,789 @@
 #![warn(missing_docs)]
 
-use std::{io::{self, Read}, fs::File, time::Duration, os::unix::prelude::AsRawFd};
+use std::{io::{self, Read, Write}, fs::{self, File}, path::{Path, PathBuf}, thread, time::{Duration, Instant}, os::{fd::{AsFd, BorrowedFd}, unix::prelude::AsRawFd}};
 
 pub use hid::HID;
+use crate::{backend::{HidBackend, LockKeys}, config::{DevicePaths, WriteRetry}, descriptor, device::{OutputEvent, VirtualDevice}, mouse::MouseFormat, key::{KeyPacket, LEDState, LEDStatePacket, ReportProtocol, SpecialKey}, rng::RngCore};
 use nix::{poll::{ppoll, PollFd, PollFlags}, sys::time::TimeSpec};
 
-fn read_timeout(file: &mut File, timeout: Duration) -> io::Result<Option<u8>> {
+pub(crate) fn wait_readable(file: &File, timeout: Duration) -> io::Result<bool> {
     let mut poll_fd = [PollFd::new(file.as_raw_fd(), PollFlags::POLLIN)];
     if ppoll(&mut poll_fd, Some(TimeSpec::from_duration(timeout)), None)? == 1 {
         if let Some(flags) = poll_fd[0].revents() {
-            if flags.contains(PollFlags::POLLIN) {
-                let mut buf = [0;1];
-        
-                if file.read(&mut buf)? == 1 {
-                    return Ok(Some(buf[0]))
-                }
-            }
+            return Ok(flags.contains(PollFlags::POLLIN));
+        }
+    }
+    Ok(false)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Device write metrics
+pub struct WriteStats {
+    /// Successful writes
+    pub writes: u64,
+    /// Retried writes
+    pub retries: u64,
+    /// Failed writes
+    pub failures: u64,
+}
+
+/// Write a whole report in one write, retrying transient failures according to a policy
+fn write_report(
+    writer: &mut impl Write,
+    data: &[u8],
+    policy: &WriteRetry,
+    stats: &mut WriteStats,
+    rng: &mut dyn RngCore,
+) -> io::Result<()> {
+    let mut retry = 0;
+    loop {
+        let res = match writer.write(data) {
+            Ok(len) if len == data.len() => Ok(()),
+            Ok(_) => Err(io::Error::new(io::ErrorKind::WriteZero, "short report write")),
+            Err(e) => Err(e),
+        };
+        match res {
+            Ok(()) => {
+                stats.writes += 1;
+                return Ok(());
+            },
+            Err(e) if retry < policy.attempts && WriteRetry::is_transient(&e) => {
+                stats.retries += 1;
+                let jitter = match policy.jitter_ms {
+                    0 => 0,
+                    jitter_ms => rng.next_u64() % (jitter_ms + 1),
+                };
+                thread::sleep(policy.backoff(retry) + Duration::from_millis(jitter));
+                retry += 1;
+            },
+            Err(e) => {
+                stats.failures += 1;
+                return Err(e);
+            },
+        }
+    }
+}
+
+/// LED state reader split off a [HID] interface, so LEDs can be watched on one thread while another sends
+pub struct LEDReader {
+    file: Option<File>,
+    last_led_state: Option<u8>,
+    led_report_id: Option<u8>,
+}
+
+impl LEDReader {
+    /// Receive raw LED states packet with a timeout
+    pub fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        let Some(file) = &mut self.file else {
+            return Ok(None);
+        };
+        let data = read_timeout(file, timeout, self.led_report_id)?;
+        self.last_led_state = data.or(self.last_led_state);
+        Ok(data)
+    }
+
+    /// Receive LED states with a timeout
+    pub fn receive_led_states(&mut self, timeout: Duration) -> io::Result<Option<LEDStatePacket>> {
+        Ok(self.receive_states_packet(timeout)?.map(LEDStatePacket::from_raw))
+    }
+
+    /// LED interface file descriptor, for registering with an external event loop. Once readable,
+    /// receive with a zero timeout.
+    pub fn led_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.file.as_ref().map(|file| file.as_fd())
+    }
+
+    /// Last LED states packet received, including any the HID interface received before the split
+    pub fn last_led_state(&self) -> Option<u8> {
+        self.last_led_state
+    }
+}
+
+/// LED state of an LED output report, which starts with the report ID if the keyboard descriptor uses report IDs.
+/// None if the report has a different ID or is too short.
+fn parse_led_report(data: &[u8], led_report_id: Option<u8>) -> Option<u8> {
+    match led_report_id {
+        Some(id) => (data.len() == 2 && data[0] == id).then(|| data[1]),
+        None => data.first().copied(),
+    }
+}
+
+/// Read an LED state with a timeout, skipping reports with other report IDs
+fn read_timeout(file: &mut File, timeout: Duration, led_report_id: Option<u8>) -> io::Result<Option<u8>> {
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0; 2];
+    let len = 1 + led_report_id.is_some() as usize;
+    while wait_readable(file, deadline.saturating_duration_since(Instant::now()))? {
+        let read = file.read(&mut buf[..len])?;
+        if read == 0 {
+            break;
+        }
+        if let Some(data) = parse_led_report(&buf[..read], led_report_id) {
+            return Ok(Some(data));
         }
     }
     Ok(None)
 }
 
+const DEV_PATH: &str = "/dev";
+const HIDG_CLASS_PATH: &str = "/sys/class/hidg";
+const GADGET_CONFIGFS_PATH: &str = "/sys/kernel/config/usb_gadget";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GadgetKind {
+    Keyboard,
+    Mouse,
+}
+
+/// Kind of a configfs HID function from its report descriptor, or its boot protocol if the descriptor doesn't say
+fn function_kind(function: &Path) -> Option<GadgetKind> {
+    let usage = fs::read(function.join("report_desc")).ok().and_then(|report_desc| descriptor::application_usage(&report_desc));
+    match usage {
+        Some((0x01, 0x06)) => Some(GadgetKind::Keyboard),
+        Some((0x01, 0x02)) => Some(GadgetKind::Mouse),
+        Some(_) => None,
+        None => match fs::read_to_string(function.join("protocol")).ok()?.trim() {
+            "1" => Some(GadgetKind::Keyboard),
+            "2" => Some(GadgetKind::Mouse),
+            _ => None,
+        },
+    }
+}
+
+/// Entries of a directory, sorted, empty if it can't be read
+fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir).into_iter().flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    entries.sort();
+    entries
+}
+
+/// Find the keyboard and mouse nodes in dev, matching each hidg node in the sysfs class with the configfs HID function
+/// of the same device number
+fn discover_paths(dev: &Path, hidg_class: &Path, configfs: &Path) -> io::Result<DevicePaths> {
+    let read_dev = |path: &Path| fs::read_to_string(path.join("dev")).ok().map(|dev| dev.trim().to_string());
+    let functions: Vec<(String, GadgetKind)> = sorted_entries(configfs).iter()
+        .flat_map(|gadget| sorted_entries(&gadget.join("functions")))
+        .filter(|function| function.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("hid.")))
+        .filter_map(|function| Some((read_dev(&function)?, function_kind(&function)?)))
+        .collect();
+    let find = |kind: GadgetKind| sorted_entries(hidg_class).into_iter()
+        .find(|node| read_dev(node).is_some_and(|number| functions.contains(&(number, kind))))
+        .and_then(|node| Some(dev.join(node.file_name()?).to_string_lossy().into_owned()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no {:?} hidg node found", kind)));
+    let keyboard = find(GadgetKind::Keyboard)?;
+    Ok(DevicePaths { mouse: find(GadgetKind::Mouse)?, led: keyboard.clone(), keyboard })
+}
+
+/// Echo round trip below which a host echoing every lock key is assumed to be firmware, see [HostProbe::stage]
+pub const FIRMWARE_RTT: Duration = Duration::from_millis(8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Host boot stage estimated by [HID::probe_host_stage]
+pub enum HostStage {
+    /// No lock key was echoed, e.g. the host is off or ignores keyboard LEDs
+    Unresponsive,
+    /// BIOS or UEFI, answering every lock key quickly
+    Firmware,
+    /// Console or login screen, answering every lock key
+    Login,
+    /// Desktop session, leaving ScrollLock unmanaged
+    Desktop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Echo round trip of each lock key LED, None if the host didn't echo it
+pub struct HostProbe {
+    /// NumLock round trip
+    pub num_lock: Option<Duration>,
+    /// CapsLock round trip
+    pub caps_lock: Option<Duration>,
+    /// ScrollLock round trip
+    pub scroll_lock: Option<Duration>,
+}
+
+impl HostProbe {
+    /// Estimate the host stage. Experimental: hosts vary, so treat it as a hint for sequencing, not a fact.
+    pub fn stage(&self) -> HostStage {
+        let echoes = [self.num_lock, self.caps_lock, self.scroll_lock];
+        if echoes.iter().all(Option::is_none) {
+            return HostStage::Unresponsive;
+        }
+        if echoes.iter().all(|rtt| rtt.is_some_and(|rtt| rtt < FIRMWARE_RTT)) {
+            return HostStage::Firmware;
+        }
+        match self.scroll_lock {
+            Some(_) => HostStage::Login,
+            None => HostStage::Desktop,
+        }
+    }
+}
+
+impl HID {
+    /// Find the keyboard and mouse /dev/hidg* nodes, which can change between boots, by matching each node with its
+    /// gadget function in configfs and reading the function's report descriptor (or boot protocol). The keyboard node
+    /// also receives the LED states.
+    pub fn discover_paths() -> io::Result<DevicePaths> {
+        discover_paths(Path::new(DEV_PATH), Path::new(HIDG_CLASS_PATH), Path::new(GADGET_CONFIGFS_PATH))
+    }
+
+    /// Open the keyboard and mouse found by [HID::discover_paths]
+    pub fn discover() -> io::Result<HID> {
+        let paths = HID::discover_paths()?;
+        HID::new(&paths.mouse, &paths.keyboard, &paths.led)
+    }
+
+    /// Input oracle. Toggle NumLock and wait for the host to echo it in its LED state, confirming the host input stack is
+    /// responsive, then toggle it back. Returns the round trip time of the first echo, or a TimedOut error.
+    /// Sends its own key packets, so call it while no keys are held.
+    pub fn handshake(&mut self, timeout: Duration) -> io::Result<Duration> {
+        LockKeys::handshake(self, timeout)
+    }
+
+    /// Toggle each lock key, waiting up to timeout for the host to echo it, then toggle it back.
+    /// Sends its own key packets, so call it while no keys are held.
+    pub fn probe_leds(&mut self, timeout: Duration) -> io::Result<HostProbe> {
+        let mut probe = HostProbe::default();
+        let locks = [
+            (SpecialKey::NumLockAndClear, LEDState::NumLock, &mut probe.num_lock),
+            (SpecialKey::CapsLock, LEDState::CapsLock, &mut probe.caps_lock),
+            (SpecialKey::ScrollLock, LEDState::ScrollLock, &mut probe.scroll_lock),
+        ];
+        for (key, led, rtt) in locks {
+            *rtt = self.lock_round_trip(&key, &led, timeout)?;
+        }
+        Ok(probe)
+    }
+
+    /// Heartbeat timing how fast the host answers. Toggle ScrollLock, which desktops rarely act on, waiting up to
+    /// timeout for the host to echo it, then toggle it back. Returns the round trip time, None if the host didn't echo it.
+    /// Sends its own key packets, so call it while no keys are held.
+    pub fn heartbeat(&mut self, timeout: Duration) -> io::Result<Option<Duration>> {
+        LockKeys::heartbeat(self, timeout)
+    }
+
+    /// Estimate whether the host is in firmware, at a login prompt or on a desktop from which lock key LEDs it echoes
+    /// and how fast, see [HID::probe_leds] and [HostProbe::stage]. Experimental and imperfect, but useful for sequencing
+    /// unattended installs.
+    pub fn probe_host_stage(&mut self, timeout: Duration) -> io::Result<HostStage> {
+        Ok(self.probe_leds(timeout)?.stage())
+    }
+
+    /// Receive an output report from the host with a timeout like [HID::receive_device_report], parsed as an event,
+    /// e.g. to forward rumble to a real controller
+    pub fn receive_device_event(&mut self, name: &str, timeout: Duration) -> io::Result<Option<OutputEvent>> {
+        Ok(self.receive_device_report(name, timeout)?.map(|report| OutputEvent::parse(&report)))
+    }
+
+    /// Send the buffered reports of a device to the interface registered under its name, see [HID::register_device].
+    /// Keyboards and mice without a registered interface use the built-in keyboard and mouse interfaces, keyboards in
+    /// the interface's [HID::keyboard_protocol].
+    pub fn send_device(&mut self, device: &mut dyn VirtualDevice) -> io::Result<()> {
+        let name = device.interface_name().to_string();
+        let registered = self.has_device(&name);
+        if !registered && name != "keyboard" && name != "mouse" {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no interface registered for {}", name)));
+        }
+        for report in device.build_reports() {
+            match name.as_str() {
+                _ if registered => self.send_device_report(&name, &report)?,
+                "keyboard" => {
+                    let packet = KeyPacket::from_bytes(&report)
+                        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed key report"))?;
+                    HidBackend::send_key_report(self, &packet)?
+                },
+                _ => self.send_mouse_packet(&report)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HidBackend for HID {
+    fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        HID::send_key_packet(self, data)
+    }
+
+    fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        HID::send_mouse_packet(self, data)
+    }
+
+    fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        HID::receive_states_packet(self, timeout)
+    }
+
+    fn send_device_report(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        HID::send_device_report(self, name, data)
+    }
+
+    fn keyboard_protocol(&self) -> ReportProtocol {
+        HID::keyboard_protocol(self)
+    }
+
+    fn mouse_format(&self) -> Option<&MouseFormat> {
+        HID::mouse_format(self)
+    }
+
+    fn last_led_state(&self) -> Option<u8> {
+        HID::last_led_state(self)
+    }
+
+    fn wait_led_state(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        HID::wait_led_state(self, timeout)
+    }
+}
+
+
 #[cfg(not(feature = "debug"))]
 mod hid {
-    use std::{fs::{OpenOptions, File}, io::{Write, self}, time::Duration};
+    use std::{collections::HashMap, fs::{OpenOptions, File}, io::{self, Read}, os::fd::{AsFd, BorrowedFd}, time::Duration};
+
+    use crate::{
+        config::{Config, WriteRetry},
+        key::{KeyPacket, ReportProtocol},
+        mouse::MouseFormat,
+        report::{transcode, ReportTranscoder},
+        rng::{BoxedRng, RngCore, SplitMix64},
+        stop,
+    };
+    use super::{read_timeout, wait_readable, write_report, LEDReader, WriteStats};
 
-    use super::read_timeout;
+    /// Longest output report read from a registered interface, the full speed interrupt transfer limit
+    const MAX_OUTPUT_REPORT_LEN: usize = 64;
     /// HID interface
     pub struct HID {
         mouse_hid: File,
         keyboard_hid: File,
-        led_state: File,
+        led_state: Option<File>,
+        devices: HashMap<String, File>,
+        last_led_state: Option<u8>,
+        led_report_id: Option<u8>,
+        write_retry: WriteRetry,
+        write_stats: WriteStats,
+        rng: BoxedRng,
+        keyboard_transcoder: Option<Box<dyn ReportTranscoder>>,
+        mouse_transcoder: Option<Box<dyn ReportTranscoder>>,
+        mouse_format: Option<MouseFormat>,
+        keyboard_protocol: ReportProtocol,
+        stop_id: usize,
     }
     
     impl HID {
         /// Create new HID interface
         pub fn new(mouse: &str, keyboard: &str, led: &str) -> io::Result<HID>{
+            let mouse_hid = OpenOptions::new()
+                .read(false)
+                .write(true)
+                .open(mouse)?;
+            let keyboard_hid = OpenOptions::new()
+                .read(false)
+                .write(true)
+                .open(keyboard)?;
+            let led_state = OpenOptions::new()
+                .read(true)
+                .write(false)
+                .open(led)?;
+            // register last, so a failed open doesn't leave the interfaces registered
             Ok(HID {
-                mouse_hid: OpenOptions::new()
-                    .read(false)
-                    .write(true)
-                    .open(mouse)?, 
-                keyboard_hid: OpenOptions::new()
-                    .read(false)
-                    .write(true)
-                    .open(keyboard)?,
-                led_state: OpenOptions::new()
-                    .read(true)
-                    .write(false)
-                    .open(led)?,
+                stop_id: stop::register(&keyboard_hid, &mouse_hid, KeyPacket::new().as_bytes().to_vec(), vec![0; 5])?,
+                mouse_hid,
+                keyboard_hid,
+                led_state: Some(led_state),
+                devices: HashMap::new(),
+                last_led_state: None,
+                led_report_id: None,
+                write_retry: WriteRetry::default(),
+                write_stats: WriteStats::default(),
+                rng: Box::new(SplitMix64::from_entropy()),
+                keyboard_transcoder: None,
+                mouse_transcoder: None,
+                mouse_format: None,
+                keyboard_protocol: ReportProtocol::Bitmap,
             })
         }
 
+        /// Create new HID interface from config, retrying according to its reconnect policy,
+        /// then wait for the host's first LED state report if LED sync is configured
+        pub fn from_config(config: &Config) -> io::Result<HID> {
+            let mut hid = config.reconnect.run(|| HID::new(&config.devices.mouse, &config.devices.keyboard, &config.devices.led))?;
+            hid.set_write_retry(config.write_retry);
+            hid.set_led_report_id(config.led_report_id);
+            hid.set_keyboard_protocol(config.report_protocol);
+            if let Some(seed) = config.seed {
+                hid.set_rng(SplitMix64::new(seed));
+            }
+            if let Some(transcoder) = &config.keyboard_transcoder {
+                hid.set_keyboard_transcoder(Some(Box::new(transcoder.clone())));
+            }
+            if let Some(transcoder) = &config.mouse_transcoder {
+                hid.set_mouse_transcoder(Some(Box::new(transcoder.clone())));
+            }
+            if !config.led_sync().is_zero() {
+                hid.wait_led_state(config.led_sync())?;
+            }
+            Ok(hid)
+        }
+
+        /// Set the report ID preceding LED states in LED output reports, if the keyboard descriptor uses report IDs
+        pub fn set_led_report_id(&mut self, led_report_id: Option<u8>) {
+            self.led_report_id = led_report_id;
+        }
+
+        /// Set the policy for retrying transient write failures
+        pub fn set_write_retry(&mut self, write_retry: WriteRetry) {
+            self.write_retry = write_retry;
+        }
+
+        /// Set the random number generator of write retry jitter, seeded from the current time by default
+        pub fn set_rng(&mut self, rng: impl RngCore + Send + 'static) {
+            self.rng = Box::new(rng);
+        }
+
+        /// Set the transcoder applied to key packets just before they're written, None writes them unchanged
+        pub fn set_keyboard_transcoder(&mut self, transcoder: Option<Box<dyn ReportTranscoder>>) {
+            self.keyboard_transcoder = transcoder;
+            self.update_stop_releases();
+        }
+
+        /// Set the transcoder applied to mouse packets just before they're written, None writes them unchanged
+        pub fn set_mouse_transcoder(&mut self, transcoder: Option<Box<dyn ReportTranscoder>>) {
+            self.mouse_transcoder = transcoder;
+            self.update_stop_releases();
+        }
+
+        /// Set the format of the mouse interface, see [super::HidBackend::mouse_format]. None sends 5 byte packets.
+        pub fn set_mouse_format(&mut self, format: Option<MouseFormat>) {
+            self.mouse_format = format;
+            self.update_stop_releases();
+        }
+
+        /// Format of the mouse interface, see [super::HidBackend::mouse_format]
+        pub fn mouse_format(&self) -> Option<&MouseFormat> {
+            self.mouse_format.as_ref()
+        }
+
+        /// Set the report protocol of the keyboard interface, see [super::HidBackend::keyboard_protocol]. Keep it in
+        /// step with [crate::key::Keyboard::set_protocol], both are set from the config's report protocol.
+        pub fn set_keyboard_protocol(&mut self, protocol: ReportProtocol) {
+            self.keyboard_protocol = protocol;
+            self.update_stop_releases();
+        }
+
+        /// Report protocol of the keyboard interface, see [super::HidBackend::keyboard_protocol]
+        pub fn keyboard_protocol(&self) -> ReportProtocol {
+            self.keyboard_protocol
+        }
+
+        /// Write metrics, including retries
+        pub fn write_stats(&self) -> WriteStats {
+            self.write_stats
+        }
+
+        /// Last LED states packet received from the host, if any
+        pub fn last_led_state(&self) -> Option<u8> {
+            self.last_led_state
+        }
+
+        /// Get the last LED states packet received from the host, waiting for the first one with a timeout if none has been received yet
+        pub fn wait_led_state(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+            match self.last_led_state {
+                Some(data) => Ok(Some(data)),
+                None => self.receive_states_packet(timeout),
+            }
+        }
+
         
         /// Receive raw LED states packet from HID interface with a timeout. [crate::key::LEDStatePacket] provides an abstraction for raw state packets.
         pub fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>>{
-            read_timeout(&mut self.led_state, timeout)
+            let Some(led_state) = &mut self.led_state else {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "LED reader was split off"));
+            };
+            let data = read_timeout(led_state, timeout, self.led_report_id)?;
+            self.last_led_state = data.or(self.last_led_state);
+            Ok(data)
+        }
+
+        /// LED interface file descriptor, for registering with an external event loop. Once readable,
+        /// receive with a zero timeout. None if the LED reader was split off.
+        pub fn led_fd(&self) -> Option<BorrowedFd<'_>> {
+            self.led_state.as_ref().map(|file| file.as_fd())
+        }
+
+        /// Keyboard interface file descriptor
+        pub fn keyboard_fd(&self) -> BorrowedFd<'_> {
+            self.keyboard_hid.as_fd()
+        }
+
+        /// Mouse interface file descriptor
+        pub fn mouse_fd(&self) -> BorrowedFd<'_> {
+            self.mouse_hid.as_fd()
+        }
+
+        /// Split off the LED interface into its own handle. Receiving LED states from the HID interface fails afterwards.
+        /// Returns None if it was already split off.
+        pub fn split_led_reader(&mut self) -> Option<LEDReader> {
+            Some(LEDReader {
+                file: Some(self.led_state.take()?),
+                last_led_state: self.last_led_state,
+                led_report_id: self.led_report_id,
+            })
         }
 
         /// Send raw key pack to HID interface. [crate::key::Keyboard] and [crate::key::KeyPacket] provides an abstractions for raw key packets.
         pub fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
-            self.keyboard_hid.write_all(data)?;
+            stop::check()?;
+            let data = transcode(&self.keyboard_transcoder, data);
+            write_report(&mut self.keyboard_hid, &data, &self.write_retry, &mut self.write_stats, self.rng.as_mut())?;
             self.keyboard_hid.sync_all()
         }
     
         /// Send raw mouse packet to HID interface. [crate::mouse::Mouse] provides an abstractions for raw mouse packets.
         pub fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
-            self.mouse_hid.write_all(data)?;
+            stop::check()?;
+            let data = transcode(&self.mouse_transcoder, data);
+            write_report(&mut self.mouse_hid, &data, &self.write_retry, &mut self.write_stats, self.rng.as_mut())?;
             self.mouse_hid.sync_all()
         }
+
+        /// Register the interface of a [crate::device::VirtualDevice] under a name, e.g. /dev/hidg2
+        pub fn register_device(&mut self, name: &str, path: &str) -> io::Result<()> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)?;
+            self.devices.insert(name.to_string(), file);
+            Ok(())
+        }
+
+        /// Receive an output report from the host for an interface registered with [HID::register_device] with a
+        /// timeout, like LED states for the keyboard, e.g. rumble or player LED commands for a gamepad. Reports
+        /// start with the report ID if the interface's descriptor uses report IDs.
+        pub fn receive_device_report(&mut self, name: &str, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+            let file = self.devices.get_mut(name)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no interface registered for {}", name)))?;
+            if !wait_readable(file, timeout)? {
+                return Ok(None);
+            }
+            let mut report = vec![0; MAX_OUTPUT_REPORT_LEN];
+            let len = file.read(&mut report)?;
+            report.truncate(len);
+            Ok(Some(report))
+        }
+
+        /// Update the reports an emergency stop releases the interfaces with
+        fn update_stop_releases(&self) {
+            let key_release = match self.keyboard_protocol {
+                ReportProtocol::Bitmap => KeyPacket::new().as_bytes().to_vec(),
+                ReportProtocol::Boot => KeyPacket::new().to_boot_report().to_vec(),
+            };
+            let mouse_release = self.mouse_format.as_ref().map_or_else(|| vec![0; 5], |format| format.encode(&[0; 5]));
+            stop::set_releases(
+                self.stop_id,
+                transcode(&self.keyboard_transcoder, &key_release).into_owned(),
+                transcode(&self.mouse_transcoder, &mouse_release).into_owned(),
+            );
+        }
+
+        pub(super) fn has_device(&self, name: &str) -> bool {
+            self.devices.contains_key(name)
+        }
+
+        /// Send raw report to an interface registered with [HID::register_device]
+        pub fn send_device_report(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+            stop::check()?;
+            let file = self.devices.get_mut(name)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no interface registered for {}", name)))?;
+            write_report(file, data, &self.write_retry, &mut self.write_stats, self.rng.as_mut())?;
+            file.sync_all()
+        }
+    }
+
+    impl Drop for HID {
+        fn drop(&mut self) {
+            stop::unregister(self.stop_id);
+        }
     }
     
 }
 #[cfg(feature = "debug")]
 mod hid {
-    use std::{io, time::Duration, fs::File, io::{Write}, path::{Path}};
+    use std::{collections::{HashMap, VecDeque}, io, time::Duration, fs::File, os::fd::{AsFd, BorrowedFd}, path::{Path}};
 
     use tempfile::NamedTempFile;
 
-    use super::read_timeout;
+    use crate::{
+        config::{Config, WriteRetry},
+        decode::{DecodedEvent, KeyDecoder},
+        key::{KeyPacket, ReportProtocol},
+        mouse::MouseFormat,
+        report::{transcode, ReportTranscoder},
+        rng::{BoxedRng, RngCore, SplitMix64},
+        stop,
+    };
+    use super::{read_timeout, write_report, LEDReader, WriteStats};
 
     /// HID interface
     pub struct HID {
         mouse_file: NamedTempFile,
         keyboard_file: NamedTempFile,
         state_file: Option<File>,
+        led_split: bool,
+        devices: HashMap<String, NamedTempFile>,
+        output_reports: HashMap<String, VecDeque<Vec<u8>>>,
+        decoder: KeyDecoder,
+        events: Vec<DecodedEvent>,
+        key_packets: Vec<KeyPacket>,
+        last_led_state: Option<u8>,
+        led_report_id: Option<u8>,
+        write_retry: WriteRetry,
+        write_stats: WriteStats,
+        rng: BoxedRng,
+        keyboard_transcoder: Option<Box<dyn ReportTranscoder>>,
+        mouse_transcoder: Option<Box<dyn ReportTranscoder>>,
+        mouse_format: Option<MouseFormat>,
+        keyboard_protocol: ReportProtocol,
+        stop_id: usize,
     }
     
     impl HID {
         /// Create new HID interface
-        pub fn new(_mouse: &str, _keyboard: &str) -> io::Result<HID>{
+        pub fn new(_mouse: &str, _keyboard: &str, _led: &str) -> io::Result<HID>{
+            let mouse_file = NamedTempFile::new()?;
+            let keyboard_file = NamedTempFile::new()?;
             Ok(HID {
-                mouse_file: NamedTempFile::new()?,
-                keyboard_file: NamedTempFile::new()?,
+                stop_id: stop::register(keyboard_file.as_file(), mouse_file.as_file(), KeyPacket::new().as_bytes().to_vec(), vec![0; 5])?,
+                mouse_file,
+                keyboard_file,
                 state_file: None,
+                led_split: false,
+                devices: HashMap::new(),
+                output_reports: HashMap::new(),
+                decoder: KeyDecoder::new(),
+                events: Vec::new(),
+                key_packets: Vec::new(),
+                last_led_state: None,
+                led_report_id: None,
+                write_retry: WriteRetry::default(),
+                write_stats: WriteStats::default(),
+                rng: Box::new(SplitMix64::from_entropy()),
+                keyboard_transcoder: None,
+                mouse_transcoder: None,
+                mouse_format: None,
+                keyboard_protocol: ReportProtocol::Bitmap,
             })
         }
 
-        /// Set file to read states from for debugging
+        /// Create new HID interface from config
+        pub fn from_config(config: &Config) -> io::Result<HID> {
+            let mut hid = HID::new(&config.devices.mouse, &config.devices.keyboard, &config.devices.led)?;
+            hid.set_led_report_id(config.led_report_id);
+            hid.set_keyboard_protocol(config.report_protocol);
+            if let Some(seed) = config.seed {
+                hid.set_rng(SplitMix64::new(seed));
+            }
+            if let Some(transcoder) = &config.keyboard_transcoder {
+                hid.set_keyboard_transcoder(Some(Box::new(transcoder.clone())));
+            }
+            if let Some(transcoder) = &config.mouse_transcoder {
+                hid.set_mouse_transcoder(Some(Box::new(transcoder.clone())));
+            }
+            Ok(hid)
+        }
+
+        /// Set the report ID preceding LED states in LED output reports, if the keyboard descriptor uses report IDs
+        pub fn set_led_report_id(&mut self, led_report_id: Option<u8>) {
+            self.led_report_id = led_report_id;
+        }
+
+        /// Set the policy for retrying transient write failures
+        pub fn set_write_retry(&mut self, write_retry: WriteRetry) {
+            self.write_retry = write_retry;
+        }
+
+        /// Set the random number generator of write retry jitter, seeded from the current time by default
+        pub fn set_rng(&mut self, rng: impl RngCore + Send + 'static) {
+            self.rng = Box::new(rng);
+        }
+
+        /// Set the transcoder applied to key packets just before they're written, None writes them unchanged
+        pub fn set_keyboard_transcoder(&mut self, transcoder: Option<Box<dyn ReportTranscoder>>) {
+            self.keyboard_transcoder = transcoder;
+            self.update_stop_releases();
+        }
+
+        /// Set the transcoder applied to mouse packets just before they're written, None writes them unchanged
+        pub fn set_mouse_transcoder(&mut self, transcoder: Option<Box<dyn ReportTranscoder>>) {
+            self.mouse_transcoder = transcoder;
+            self.update_stop_releases();
+        }
+
+        /// Set the format of the mouse interface, see [super::HidBackend::mouse_format]. None sends 5 byte packets.
+        pub fn set_mouse_format(&mut self, format: Option<MouseFormat>) {
+            self.mouse_format = format;
+            self.update_stop_releases();
+        }
+
+        /// Format of the mouse interface, see [super::HidBackend::mouse_format]
+        pub fn mouse_format(&self) -> Option<&MouseFormat> {
+            self.mouse_format.as_ref()
+        }
+
+        /// Set the report protocol of the keyboard interface, see [super::HidBackend::keyboard_protocol]. Keep it in
+        /// step with [crate::key::Keyboard::set_protocol], both are set from the config's report protocol.
+        pub fn set_keyboard_protocol(&mut self, protocol: ReportProtocol) {
+            self.keyboard_protocol = protocol;
+            self.update_stop_releases();
+        }
+
+        /// Report protocol of the keyboard interface, see [super::HidBackend::keyboard_protocol]
+        pub fn keyboard_protocol(&self) -> ReportProtocol {
+            self.keyboard_protocol
+        }
+
+        /// Write metrics, including retries
+        pub fn write_stats(&self) -> WriteStats {
+            self.write_stats
+        }
+
+        /// Last LED states packet received from the state file, if any
+        pub fn last_led_state(&self) -> Option<u8> {
+            self.last_led_state
+        }
+
+        /// Get the last LED states packet received, waiting for the first one with a timeout if none has been received yet
+        pub fn wait_led_state(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+            match self.last_led_state {
+                Some(data) => Ok(Some(data)),
+                None => self.receive_states_packet(timeout),
+            }
+        }
+
+        /// Get key events decoded from the key packets sent so far
+        pub fn events(&self) -> &[DecodedEvent] {
+            &self.events
+        }
+
+        /// Clear decoded key events
+        pub fn clear_events(&mut self) {
+            self.events.clear();
+        }
+
+        /// Get the key packets sent so far
+        pub fn key_packets(&self) -> &[KeyPacket] {
+            &self.key_packets
+        }
+
+        /// Clear sent key packets
+        pub fn clear_key_packets(&mut self) {
+            self.key_packets.clear();
+        }
+
+        /// Set file to read states from for debugging, also after the LED reader was split off
         pub fn set_state_data(&mut self, path: &str) -> io::Result<()> {
             self.state_file = Some(File::open(path)?);
+            self.led_split = false;
             Ok(())
         }
 
@@ -112,23 +796,336 @@ mod hid {
         pub fn get_mouse_path(&self) -> &Path {
             self.mouse_file.path()
         }
+
+        /// Get path of temp file a registered device's reports are being written too
+        pub fn get_device_path(&self, name: &str) -> Option<&Path> {
+            self.devices.get(name).map(|file| file.path())
+        }
         
         /// Receive raw LED states packet from HID interface with a timeout. [crate::key::LEDStatePacket] provides an abstraction for raw state packets.
         pub fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>>{
+            if self.led_split {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "LED reader was split off"));
+            }
             if let Some(file) = &mut self.state_file {
-                return read_timeout(file, timeout)
+                let data = read_timeout(file, timeout, self.led_report_id)?;
+                self.last_led_state = data.or(self.last_led_state);
+                return Ok(data)
             }
             Ok(None)
         }
 
+        /// State file descriptor, if a state file is set
+        pub fn led_fd(&self) -> Option<BorrowedFd<'_>> {
+            self.state_file.as_ref().map(|file| file.as_fd())
+        }
+
+        /// Keyboard temp file descriptor
+        pub fn keyboard_fd(&self) -> BorrowedFd<'_> {
+            self.keyboard_file.as_file().as_fd()
+        }
+
+        /// Mouse temp file descriptor
+        pub fn mouse_fd(&self) -> BorrowedFd<'_> {
+            self.mouse_file.as_file().as_fd()
+        }
+
+        /// Split off the state file into its own LED reader. Receiving LED states from the HID interface fails
+        /// afterwards. None if it was already split off.
+        pub fn split_led_reader(&mut self) -> Option<LEDReader> {
+            if self.led_split {
+                return None;
+            }
+            self.led_split = true;
+            Some(LEDReader {
+                file: self.state_file.take(),
+                last_led_state: self.last_led_state,
+                led_report_id: self.led_report_id,
+            })
+        }
+
         /// Send raw key pack to HID interface. [crate::key::Keyboard] and [crate::key::KeyPacket] provides an abstractions for raw key packets.
-        pub fn send_key_packet(&mut self, data: &[u8]) -> io::Result<usize> {
-            self.keyboard_file.write(data)
+        pub fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+            stop::check()?;
+            if let Some(packet) = KeyPacket::from_bytes(data) {
+                for event in self.decoder.decode(packet.clone()) {
+                    crate::logging::log_line!("{}", event);
+                    self.events.push(event);
+                }
+                self.key_packets.push(packet);
+            }
+            let data = transcode(&self.keyboard_transcoder, data);
+            write_report(&mut self.keyboard_file, &data, &self.write_retry, &mut self.write_stats, self.rng.as_mut())
         }
     
         /// Send raw mouse packet to HID interface. [crate::mouse::Mouse] provides an abstractions for raw mouse packets.
-        pub fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<usize> {
-            self.mouse_file.write(data)
+        pub fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+            stop::check()?;
+            let data = transcode(&self.mouse_transcoder, data);
+            write_report(&mut self.mouse_file, &data, &self.write_retry, &mut self.write_stats, self.rng.as_mut())
+        }
+
+        /// Register the interface of a [crate::device::VirtualDevice] under a name. Reports are written to a temp file.
+        pub fn register_device(&mut self, name: &str, _path: &str) -> io::Result<()> {
+            self.devices.insert(name.to_string(), NamedTempFile::new()?);
+            Ok(())
+        }
+
+        /// Receive an output report from the host for an interface registered with [HID::register_device]. Only
+        /// reports injected with [HID::inject_device_report] arrive, without any the timeout is waited out.
+        pub fn receive_device_report(&mut self, name: &str, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+            if !self.has_device(name) {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("no interface registered for {}", name)));
+            }
+            if let Some(report) = self.output_reports.get_mut(name).and_then(|reports| reports.pop_front()) {
+                return Ok(Some(report));
+            }
+            std::thread::sleep(timeout);
+            Ok(None)
+        }
+
+        /// Queue an output report as if the host sent it to an interface registered with [HID::register_device]
+        pub fn inject_device_report(&mut self, name: &str, report: &[u8]) -> io::Result<()> {
+            if !self.has_device(name) {
+                return Err(io::Error::new(io::ErrorKind::NotFound, format!("no interface registered for {}", name)));
+            }
+            self.output_reports.entry(name.to_string()).or_default().push_back(report.to_vec());
+            Ok(())
+        }
+
+        #[cfg(test)]
+        pub(crate) fn stop_id(&self) -> usize {
+            self.stop_id
         }
+
+        /// Update the reports an emergency stop releases the interfaces with
+        fn update_stop_releases(&self) {
+            let key_release = match self.keyboard_protocol {
+                ReportProtocol::Bitmap => KeyPacket::new().as_bytes().to_vec(),
+                ReportProtocol::Boot => KeyPacket::new().to_boot_report().to_vec(),
+            };
+            let mouse_release = self.mouse_format.as_ref().map_or_else(|| vec![0; 5], |format| format.encode(&[0; 5]));
+            stop::set_releases(
+                self.stop_id,
+                transcode(&self.keyboard_transcoder, &key_release).into_owned(),
+                transcode(&self.mouse_transcoder, &mouse_release).into_owned(),
+            );
+        }
+
+        pub(super) fn has_device(&self, name: &str) -> bool {
+            self.devices.contains_key(name)
+        }
+
+        /// Send raw report to an interface registered with [HID::register_device]
+        pub fn send_device_report(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+            stop::check()?;
+            let file = self.devices.get_mut(name)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no interface registered for {}", name)))?;
+            write_report(file, data, &self.write_retry, &mut self.write_stats, self.rng.as_mut())
+        }
+    }
+
+    impl Drop for HID {
+        fn drop(&mut self) {
+            stop::unregister(self.stop_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{self, Write}, time::Duration};
+
+    use super::{discover_paths, parse_led_report, write_report, HidBackend, HostProbe, HostStage, LockKeys, WriteStats, FIRMWARE_RTT};
+    use crate::{config::{DevicePaths, WriteRetry}, descriptor, key::{Keyboard, KeyPacket, SpecialKey}, mouse::{Mouse, MouseButton}, rng::SplitMix64};
+
+    /// Writer failing with WouldBlock a number of times before accepting writes
+    struct FlakyWriter {
+        failures: u32,
+        written: Vec<u8>,
+    }
+
+    impl Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            self.written.extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retries_transient_writes() {
+        let policy = WriteRetry { attempts: 2, backoff_ms: 1, max_backoff_ms: 2, jitter_ms: 1 };
+        let mut stats = WriteStats::default();
+        let mut writer = FlakyWriter { failures: 2, written: Vec::new() };
+        write_report(&mut writer, &[1, 2, 3], &policy, &mut stats, &mut SplitMix64::new(0)).unwrap();
+        assert_eq!(writer.written, vec![1, 2, 3]);
+        assert_eq!(stats, WriteStats { writes: 1, retries: 2, failures: 0 });
+
+        let mut writer = FlakyWriter { failures: 3, written: Vec::new() };
+        assert!(write_report(&mut writer, &[1], &policy, &mut stats, &mut SplitMix64::new(0)).is_err());
+        assert_eq!(stats, WriteStats { writes: 1, retries: 4, failures: 1 });
+    }
+
+    #[test]
+    fn host_stage_heuristic() {
+        let fast = Some(Duration::from_millis(2));
+        let slow = Some(FIRMWARE_RTT * 4);
+        assert_eq!(HostProbe::default().stage(), HostStage::Unresponsive);
+        assert_eq!(HostProbe { num_lock: fast, caps_lock: fast, scroll_lock: fast }.stage(), HostStage::Firmware);
+        assert_eq!(HostProbe { num_lock: slow, caps_lock: fast, scroll_lock: fast }.stage(), HostStage::Login);
+        assert_eq!(HostProbe { num_lock: fast, caps_lock: fast, scroll_lock: None }.stage(), HostStage::Desktop);
+    }
+
+    #[test]
+    fn led_report_ids() {
+        assert_eq!(parse_led_report(&[0x02], None), Some(0x02));
+        assert_eq!(parse_led_report(&[0x01, 0x02], Some(1)), Some(0x02));
+        assert_eq!(parse_led_report(&[0x03, 0x02], Some(1)), None);
+        assert_eq!(parse_led_report(&[0x01], Some(1)), None);
+    }
+
+    /// Backend keeping sent packets, with the host echoing every lock key
+    #[derive(Default)]
+    struct Loopback {
+        keys: Vec<Vec<u8>>,
+        mouse: Vec<Vec<u8>>,
+        leds: u8,
+    }
+
+    impl HidBackend for Loopback {
+        fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+            self.keys.push(data.to_vec());
+            Ok(())
+        }
+
+        fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+            self.mouse.push(data.to_vec());
+            Ok(())
+        }
+
+        fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+            self.leds ^= 0x04;
+            Ok(Some(self.leds))
+        }
+    }
+
+    #[test]
+    fn custom_backend() {
+        let mut backend = Loopback::default();
+        let mut keyboard = Keyboard::new();
+        keyboard.press_basic_string_checked("a");
+        keyboard.flush(&mut backend).unwrap();
+        assert_eq!(backend.keys[0], KeyPacket::parse("a").unwrap().as_bytes());
+        assert_eq!(backend.keys.last().unwrap(), KeyPacket::new().as_bytes());
+
+        let mut mouse = Mouse::new();
+        mouse.press_button(MouseButton::Left);
+        mouse.send(&mut backend).unwrap();
+        assert_eq!(backend.mouse.first().map(|packet| packet[0]), Some(1));
+
+        assert_eq!(backend.wait_led_state(Duration::ZERO).unwrap(), Some(0x04));
+    }
+
+    /// Backend for a host toggling its lock LEDs when a lock key is pressed
+    #[derive(Default)]
+    struct LockHost {
+        pressed: bool,
+        leds: u8,
+        taps: u32,
+    }
+
+    impl HidBackend for LockHost {
+        fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+            let pressed = KeyPacket::from_bytes(data).unwrap().contains_any(&KeyPacket::from_special(&SpecialKey::NumLockAndClear));
+            if pressed && !self.pressed {
+                self.leds ^= 0x01;
+                self.taps += 1;
+            }
+            self.pressed = pressed;
+            Ok(())
+        }
+
+        fn send_mouse_packet(&mut self, _data: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+            Ok(Some(self.leds))
+        }
+
+        fn last_led_state(&self) -> Option<u8> {
+            Some(self.leds)
+        }
+    }
+
+    #[test]
+    fn numlock_handshake() {
+        let mut host = LockHost::default();
+        assert!(LockKeys::handshake(&mut host, Duration::from_secs(1)).is_ok());
+        // toggled and toggled back
+        assert_eq!((host.taps, host.leds), (2, 0));
+
+        // a host that never echoes NumLock times out, with NumLock tapped back
+        let mut backend = Loopback::default();
+        assert_eq!(LockKeys::handshake(&mut backend, Duration::from_millis(10)).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        let numlock = KeyPacket::from_special(&SpecialKey::NumLockAndClear).as_bytes().to_vec();
+        let released = KeyPacket::new().as_bytes().to_vec();
+        assert_eq!(backend.keys, vec![numlock.clone(), released.clone(), numlock, released]);
+    }
+
+    #[test]
+    fn discovers_nodes() {
+        use std::{env, fs, process};
+
+        let root = env::temp_dir().join(format!("virt-hid-discover-{}", process::id()));
+        let (dev, class, configfs) = (root.join("dev"), root.join("class"), root.join("configfs"));
+        let function = |name: &str, number: &str, report_desc: &[u8]| {
+            let path = configfs.join("g1/functions").join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("dev"), format!("{}\n", number)).unwrap();
+            fs::write(path.join("report_desc"), report_desc).unwrap();
+        };
+        function("hid.usb0", "236:0", &descriptor::vendor(64));
+        function("hid.usb1", "236:1", descriptor::MOUSE);
+        function("hid.usb2", "236:2", descriptor::KEYBOARD);
+        for (node, number) in [("hidg0", "236:0"), ("hidg1", "236:1"), ("hidg2", "236:2")] {
+            fs::create_dir_all(class.join(node)).unwrap();
+            fs::write(class.join(node).join("dev"), format!("{}\n", number)).unwrap();
+        }
+
+        let path = |node: &str| dev.join(node).to_string_lossy().into_owned();
+        assert_eq!(discover_paths(&dev, &class, &configfs).unwrap(), DevicePaths {
+            mouse: path("hidg1"),
+            keyboard: path("hidg2"),
+            led: path("hidg2"),
+        });
+        assert_eq!(descriptor::application_usage(&descriptor::vendor(64)), Some((0xFF00, 0x01)));
+
+        fs::remove_dir_all(configfs.join("g1/functions/hid.usb1")).unwrap();
+        assert_eq!(discover_paths(&dev, &class, &configfs).unwrap_err().kind(), io::ErrorKind::NotFound);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn split_led_reader_once() {
+        use std::io::ErrorKind;
+
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        assert_eq!(hid.receive_states_packet(Duration::ZERO).unwrap(), None);
+        let mut reader = hid.split_led_reader().unwrap();
+        assert!(hid.split_led_reader().is_none());
+        assert_eq!(hid.receive_states_packet(Duration::ZERO).unwrap_err().kind(), ErrorKind::NotConnected);
+        assert_eq!(reader.receive_states_packet(Duration::ZERO).unwrap(), None);
     }
 }
@@ -0,0 +1,118 @@
+#![warn(missing_docs)]
+
+use std::{collections::VecDeque, time::Instant};
+
+use crate::{decode::{DecodedEvent, KeyAction}, key::KeyPacket};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Key event in a [KeyHistory]
+pub struct HistoryEvent {
+    /// When the event was sent
+    pub at: Instant,
+    /// Action
+    pub action: KeyAction,
+    /// Keys and modifiers pressed or released
+    pub keys: KeyPacket,
+}
+
+impl HistoryEvent {
+    /// Readable form of the event
+    pub fn decoded(&self) -> DecodedEvent {
+        DecodedEvent {
+            action: self.action,
+            modifiers: self.keys.modifiers(),
+            keys: self.keys.usages().map(crate::decode::keycode_name).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Bounded history of the most recent key events sent, oldest first. Older events are dropped once it's full.
+pub struct KeyHistory {
+    capacity: usize,
+    events: VecDeque<HistoryEvent>,
+    previous: KeyPacket,
+}
+
+impl KeyHistory {
+    /// New, keeping at most capacity events
+    pub fn new(capacity: usize) -> KeyHistory {
+        KeyHistory { capacity, events: VecDeque::with_capacity(capacity), previous: KeyPacket::new() }
+    }
+
+    /// Most events kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of events kept
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Check if no events are kept
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Forget every event
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Record a sent packet as the keys released and pressed since the previous one
+    pub fn record(&mut self, packet: &KeyPacket, at: Instant) {
+        let released = &self.previous - packet;
+        let pressed = packet - &self.previous;
+        for (action, keys) in [(KeyAction::Release, released), (KeyAction::Press, pressed)] {
+            if keys.is_empty() || self.capacity == 0 {
+                continue;
+            }
+            if self.events.len() == self.capacity {
+                self.events.pop_front();
+            }
+            self.events.push_back(HistoryEvent { at, action, keys });
+        }
+        self.previous = packet.clone();
+    }
+
+    /// Up to the n most recent events, oldest first
+    pub fn recent(&self, n: usize) -> impl Iterator<Item = &HistoryEvent> {
+        self.events.iter().skip(self.events.len().saturating_sub(n))
+    }
+
+    /// Keys pressed by the n most recent events that are still held
+    pub fn unreleased(&self, n: usize) -> KeyPacket {
+        let pressed = self.recent(n).fold(KeyPacket::new(), |pressed, event| match event.action {
+            KeyAction::Press => &pressed | &event.keys,
+            KeyAction::Release => &pressed - &event.keys,
+        });
+        &pressed & &self.previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::KeyHistory;
+    use crate::{decode::KeyAction, key::KeyPacket};
+
+    #[test]
+    fn bounded_history() {
+        let mut history = KeyHistory::new(3);
+        let now = Instant::now();
+        for chord in ["LSHIFT", "LSHIFT+a", "LSHIFT", "LSHIFT+b"] {
+            history.record(&KeyPacket::parse(chord).unwrap(), now);
+        }
+        assert_eq!(history.len(), 3);
+        let recent: Vec<String> = history.recent(2).map(|event| event.decoded().to_string()).collect();
+        assert_eq!(recent, vec!["release KeyA", "press KeyB"]);
+        assert_eq!(history.recent(1).next().unwrap().action, KeyAction::Press);
+        assert_eq!(history.unreleased(1), KeyPacket::parse("b").unwrap());
+        assert_eq!(history.unreleased(10), KeyPacket::parse("b").unwrap());
+
+        history.record(&KeyPacket::new(), now);
+        assert!(history.unreleased(3).is_empty());
+    }
+}
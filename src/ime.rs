@@ -0,0 +1,160 @@
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::key::{BasicKey, SpecialKey};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Keystrokes for entering text through a host IME
+pub struct ImeInput {
+    /// Romanization or reading typed into the IME
+    pub reading: String,
+    /// Keys pressed after the reading to select and commit a candidate
+    pub selection: Vec<BasicKey>,
+}
+
+impl ImeInput {
+    /// New
+    pub fn new(reading: &str, selection: Vec<BasicKey>) -> ImeInput {
+        ImeInput { reading: reading.to_string(), selection }
+    }
+
+    /// Select the nth candidate (0 being the first) by converting with Spacebar, moving through
+    /// the candidate list with Spacebar and committing with Return
+    pub fn nth_candidate(reading: &str, n: usize) -> ImeInput {
+        let mut selection = vec![BasicKey::Special(SpecialKey::Spacebar); n + 1];
+        selection.push(BasicKey::Special(SpecialKey::ReturnEnter));
+        ImeInput::new(reading, selection)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Part of a string to be typed
+pub enum ImeSegment {
+    /// Text typed directly with the keyboard layout
+    Direct(String),
+    /// Text entered through the IME
+    Ime(ImeInput),
+}
+
+/// Strategy for splitting text into directly typed text and IME input
+pub trait ImeStrategy {
+    /// Split text into segments
+    fn segments(&self, text: &str) -> Vec<ImeSegment>;
+}
+
+/// Treat all text as an IME reading, committing it with the selection keys
+pub struct PassThrough {
+    /// Keys pressed after the reading
+    pub selection: Vec<BasicKey>,
+}
+
+impl Default for PassThrough {
+    fn default() -> Self {
+        PassThrough { selection: ImeInput::nth_candidate("", 0).selection }
+    }
+}
+
+impl ImeStrategy for PassThrough {
+    fn segments(&self, text: &str) -> Vec<ImeSegment> {
+        vec![ImeSegment::Ime(ImeInput::new(text, self.selection.clone()))]
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// User supplied table converting text to IME input. Text without an entry is typed directly.
+pub struct ConversionTable {
+    entries: HashMap<String, ImeInput>,
+    max_len: usize,
+}
+
+impl ConversionTable {
+    /// New
+    pub fn new() -> ConversionTable {
+        ConversionTable::default()
+    }
+
+    /// Add a conversion for a piece of text
+    pub fn insert(&mut self, text: &str, input: ImeInput) {
+        self.max_len = self.max_len.max(text.chars().count());
+        self.entries.insert(text.to_string(), input);
+    }
+
+    /// Get the conversion for a piece of text
+    pub fn get(&self, text: &str) -> Option<&ImeInput> {
+        self.entries.get(text)
+    }
+
+    fn longest_match<'a>(&self, text: &'a str) -> Option<(&'a str, &ImeInput)> {
+        let bounds: Vec<usize> = text.char_indices()
+            .map(|(i, _)| i)
+            .skip(1)
+            .chain([text.len()])
+            .take(self.max_len)
+            .collect();
+        bounds.iter().rev().find_map(|end| {
+            let prefix = &text[..*end];
+            self.entries.get(prefix).map(|input| (prefix, input))
+        })
+    }
+}
+
+impl ImeStrategy for ConversionTable {
+    fn segments(&self, text: &str) -> Vec<ImeSegment> {
+        let mut segments = Vec::new();
+        let mut direct = String::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            match self.longest_match(rest) {
+                Some((matched, input)) => {
+                    if !direct.is_empty() {
+                        segments.push(ImeSegment::Direct(std::mem::take(&mut direct)));
+                    }
+                    segments.push(ImeSegment::Ime(input.clone()));
+                    rest = &rest[matched.len()..];
+                },
+                None => {
+                    direct.push(c);
+                    rest = &rest[c.len_utf8()..];
+                },
+            }
+        }
+        if !direct.is_empty() {
+            segments.push(ImeSegment::Direct(direct));
+        }
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConversionTable, ImeInput, ImeSegment, ImeStrategy, PassThrough};
+    use crate::key::{BasicKey, Keyboard, SpecialKey};
+
+    #[test]
+    fn segments_and_types_readings() {
+        let mut table = ConversionTable::new();
+        table.insert("日", ImeInput::nth_candidate("ni", 1));
+        table.insert("日本", ImeInput::nth_candidate("nihon", 0));
+        assert_eq!(table.segments("a日本b日"), vec![
+            ImeSegment::Direct(String::from("a")),
+            ImeSegment::Ime(ImeInput::nth_candidate("nihon", 0)),
+            ImeSegment::Direct(String::from("b")),
+            ImeSegment::Ime(ImeInput::nth_candidate("ni", 1)),
+        ]);
+        let (space, enter) = (BasicKey::Special(SpecialKey::Spacebar), BasicKey::Special(SpecialKey::ReturnEnter));
+        assert_eq!(ImeInput::nth_candidate("ni", 1).selection, vec![space, space, enter]);
+        assert_eq!(PassThrough::default().segments("kanji"), vec![ImeSegment::Ime(ImeInput::new("kanji", vec![space, enter]))]);
+
+        let mut keyboard = Keyboard::new();
+        assert!(keyboard.type_ime_string("日", &table).is_empty());
+        let mut expected = Keyboard::new();
+        expected.type_string("ni");
+        for key in [&space, &space, &enter] {
+            expected.press_key(key);
+        }
+        assert_eq!(keyboard.take_packets(), expected.take_packets());
+    }
+}
@@ -0,0 +1,429 @@
+#![warn(missing_docs)]
+
+use std::{collections::HashMap, io::Write};
+use std::{collections::VecDeque, io, time::{Duration, Instant}};
+
+use crate::{config::Config, decode::KeyDecoder, key::KeyPacket};
+use crate::HidBackend;
+
+const MODIFIER_KEYCODE_MIN: u8 = 0xE0;
+const MODIFIER_KEYCODE_MAX: u8 = 0xE7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Input report passing through an [Interceptor]
+pub enum InputEvent {
+    /// Keyboard report
+    Key(KeyPacket),
+    /// Raw mouse report
+    Mouse([u8; 5]),
+}
+
+/// Filter in an [Interceptor] pipeline
+pub trait Filter {
+    /// Pass an event on, possibly changed, or drop it by returning None
+    fn filter(&mut self, event: InputEvent) -> Option<InputEvent>;
+}
+
+/// Keycodes of the keys and modifiers held in a packet, modifiers as LeftControl (0xE0) to RightGUI (0xE7)
+fn held_keycodes(packet: &KeyPacket) -> Vec<u8> {
+    let modifiers = packet.as_bytes()[0];
+    (0..8)
+        .filter(|bit| modifiers & (1 << bit) != 0)
+        .map(|bit| MODIFIER_KEYCODE_MIN + bit)
+        .chain(packet.usages().filter(|keycode| !(MODIFIER_KEYCODE_MIN..=MODIFIER_KEYCODE_MAX).contains(keycode)))
+        .collect()
+}
+
+/// Packet holding keycodes, see [held_keycodes]
+fn packet_from_keycodes(keycodes: &[u8]) -> KeyPacket {
+    let is_modifier = |keycode: &u8| (MODIFIER_KEYCODE_MIN..=MODIFIER_KEYCODE_MAX).contains(keycode);
+    let modifiers = keycodes.iter()
+        .filter(|keycode| is_modifier(keycode))
+        .fold(0, |modifiers, keycode| modifiers | 1 << (keycode - MODIFIER_KEYCODE_MIN));
+    KeyPacket::from_usages(modifiers, keycodes.iter().copied().filter(|keycode| !is_modifier(keycode)))
+}
+
+#[derive(Debug, Clone, Default)]
+/// Remap keycodes. Modifiers are remapped by their keycodes, LeftControl (0xE0) to RightGUI (0xE7).
+pub struct Remap {
+    keys: HashMap<u8, u8>,
+}
+
+impl Remap {
+    /// New, remapping nothing
+    pub fn new() -> Remap {
+        Remap::default()
+    }
+
+    /// Remap a keycode
+    pub fn key(mut self, from: u8, to: u8) -> Remap {
+        self.keys.insert(from, to);
+        self
+    }
+
+    /// New from the remaps of a config
+    pub fn from_config(config: &Config) -> Remap {
+        Remap { keys: config.remaps.iter().copied().collect() }
+    }
+}
+
+impl Filter for Remap {
+    fn filter(&mut self, event: InputEvent) -> Option<InputEvent> {
+        match event {
+            InputEvent::Key(packet) => {
+                let keycodes: Vec<u8> = held_keycodes(&packet).into_iter()
+                    .map(|keycode| *self.keys.get(&keycode).unwrap_or(&keycode))
+                    .collect();
+                Some(InputEvent::Key(packet_from_keycodes(&keycodes)))
+            },
+            event => Some(event),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Block keys, or keys pressed with exact modifiers (e.g. Ctrl+Alt+Delete), from reaching the host
+pub struct Blocklist {
+    keys: Vec<u8>,
+    chords: Vec<(u8, u8)>,
+}
+
+impl Blocklist {
+    /// New, blocking nothing
+    pub fn new() -> Blocklist {
+        Blocklist::default()
+    }
+
+    /// Block a keycode
+    pub fn key(mut self, keycode: u8) -> Blocklist {
+        self.keys.push(keycode);
+        self
+    }
+
+    /// Block a keycode while exactly the modifiers in the modifier byte are held
+    pub fn chord(mut self, modifiers: u8, keycode: u8) -> Blocklist {
+        self.chords.push((modifiers, keycode));
+        self
+    }
+}
+
+impl Filter for Blocklist {
+    fn filter(&mut self, event: InputEvent) -> Option<InputEvent> {
+        match event {
+            InputEvent::Key(packet) => {
+                let modifiers = packet.as_bytes()[0];
+                let keycodes: Vec<u8> = held_keycodes(&packet).into_iter()
+                    .filter(|keycode| !self.keys.contains(keycode) && !self.chords.contains(&(modifiers, *keycode)))
+                    .collect();
+                Some(InputEvent::Key(packet_from_keycodes(&keycodes)))
+            },
+            event => Some(event),
+        }
+    }
+}
+
+/// Limit the rate of reports pressing keys or buttons. Reports over the limit are dropped, reports only releasing
+/// keys always pass so nothing gets stuck.
+pub struct RateLimit {
+    max: usize,
+    per: Duration,
+    passed: VecDeque<Instant>,
+    last_key: KeyPacket,
+    last_buttons: u8,
+}
+
+impl RateLimit {
+    /// Allow at most max reports pressing keys or buttons per window of time
+    pub fn new(max: usize, per: Duration) -> RateLimit {
+        RateLimit { max, per, passed: VecDeque::new(), last_key: KeyPacket::new(), last_buttons: 0 }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        while self.passed.front().is_some_and(|passed| now.duration_since(*passed) >= self.per) {
+            self.passed.pop_front();
+        }
+        if self.passed.len() >= self.max {
+            return false;
+        }
+        self.passed.push_back(now);
+        true
+    }
+}
+
+impl Filter for RateLimit {
+    fn filter(&mut self, event: InputEvent) -> Option<InputEvent> {
+        match &event {
+            InputEvent::Key(packet) => {
+                let presses = !(packet - &self.last_key).is_empty();
+                if presses && !self.allow() {
+                    return None;
+                }
+                self.last_key = packet.clone();
+            },
+            InputEvent::Mouse(packet) => {
+                let presses = packet[0] & !self.last_buttons != 0 || packet[1..].iter().any(|byte| *byte != 0);
+                if presses && !self.allow() {
+                    return None;
+                }
+                self.last_buttons = packet[0];
+            },
+        }
+        Some(event)
+    }
+}
+
+/// Log decoded key events and mouse reports to a writer, passing everything on
+pub struct Logger {
+    writer: Box<dyn Write + Send>,
+    decoder: KeyDecoder,
+}
+
+impl Logger {
+    /// New, logging to a writer, e.g. [std::io::stderr]
+    pub fn new(writer: impl Write + Send + 'static) -> Logger {
+        Logger { writer: Box::new(writer), decoder: KeyDecoder::new() }
+    }
+}
+
+impl Filter for Logger {
+    fn filter(&mut self, event: InputEvent) -> Option<InputEvent> {
+        match &event {
+            InputEvent::Key(packet) => {
+                for decoded in self.decoder.decode(packet.clone()) {
+                    let _ = writeln!(self.writer, "{}", decoded);
+                }
+            },
+            InputEvent::Mouse(packet) => {
+                let _ = writeln!(self.writer, "mouse {:?}", packet);
+            },
+        }
+        Some(event)
+    }
+}
+
+/// Chord of modifiers and a key, e.g. (0, ScrollLock)
+pub type Chord = (u8, u8);
+
+/// Hotkey triggering a local action
+struct Hotkey {
+    sequence: Vec<Chord>,
+    action: Box<dyn FnMut() + Send>,
+    progress: usize,
+}
+
+/// Reserve hotkeys (sequences of chords, e.g. ScrollLock ScrollLock) for local actions such as switching targets or
+/// stopping forwarding. Keys pressed as part of a hotkey are held back from the host until released, and dropped if the
+/// hotkey isn't completed.
+pub struct Hotkeys {
+    hotkeys: Vec<Hotkey>,
+    timeout: Duration,
+    last: KeyPacket,
+    last_press: Option<Instant>,
+    held_back: Vec<u8>,
+}
+
+impl Hotkeys {
+    /// New, with a max time between the chords of a hotkey
+    pub fn new(timeout: Duration) -> Hotkeys {
+        Hotkeys { hotkeys: Vec::new(), timeout, last: KeyPacket::new(), last_press: None, held_back: Vec::new() }
+    }
+
+    /// Reserve a sequence of chords, running an action when it's pressed
+    pub fn hotkey(mut self, sequence: &[Chord], action: impl FnMut() + Send + 'static) -> Hotkeys {
+        if !sequence.is_empty() {
+            self.hotkeys.push(Hotkey { sequence: sequence.to_vec(), action: Box::new(action), progress: 0 });
+        }
+        self
+    }
+
+    /// Advance hotkeys by a chord. Returns true if the chord is part of a hotkey.
+    fn press(&mut self, chord: Chord) -> bool {
+        let now = Instant::now();
+        if self.last_press.is_some_and(|last_press| now.duration_since(last_press) > self.timeout) {
+            self.hotkeys.iter_mut().for_each(|hotkey| hotkey.progress = 0);
+        }
+        self.last_press = Some(now);
+
+        let mut matched = false;
+        let mut completed = None;
+        for (i, hotkey) in self.hotkeys.iter_mut().enumerate() {
+            hotkey.progress = if hotkey.sequence[hotkey.progress] == chord {
+                hotkey.progress + 1
+            } else if hotkey.sequence[0] == chord {
+                1
+            } else {
+                0
+            };
+            matched |= hotkey.progress > 0;
+            if hotkey.progress == hotkey.sequence.len() && completed.is_none() {
+                completed = Some(i);
+            }
+        }
+        if let Some(i) = completed {
+            self.hotkeys.iter_mut().for_each(|hotkey| hotkey.progress = 0);
+            (self.hotkeys[i].action)();
+        }
+        matched
+    }
+}
+
+impl Filter for Hotkeys {
+    fn filter(&mut self, event: InputEvent) -> Option<InputEvent> {
+        let InputEvent::Key(packet) = event else {
+            return Some(event);
+        };
+        let modifiers = packet.as_bytes()[0];
+        let held = held_keycodes(&packet);
+        let previous = held_keycodes(&self.last);
+        self.held_back.retain(|keycode| held.contains(keycode));
+        for keycode in held.iter().filter(|keycode| !previous.contains(keycode) && !(MODIFIER_KEYCODE_MIN..=MODIFIER_KEYCODE_MAX).contains(keycode)) {
+            if self.press((modifiers, *keycode)) {
+                self.held_back.push(*keycode);
+            }
+        }
+        self.last = packet;
+
+        let forwarded: Vec<u8> = held.into_iter().filter(|keycode| !self.held_back.contains(keycode)).collect();
+        Some(InputEvent::Key(packet_from_keycodes(&forwarded)))
+    }
+}
+
+#[derive(Default)]
+/// Pipeline of filters events pass through, in order, before reaching the HID interface
+pub struct Interceptor {
+    filters: Vec<Box<dyn Filter + Send>>,
+}
+
+impl Interceptor {
+    /// New, passing everything through
+    pub fn new() -> Interceptor {
+        Interceptor::default()
+    }
+
+    /// Add a filter at the end of the pipeline
+    pub fn with(mut self, filter: impl Filter + Send + 'static) -> Interceptor {
+        self.push(filter);
+        self
+    }
+
+    /// Add a filter at the end of the pipeline
+    pub fn push(&mut self, filter: impl Filter + Send + 'static) {
+        self.filters.push(Box::new(filter));
+    }
+
+    /// Pass an event through the filters. Returns None if a filter dropped it.
+    pub fn process(&mut self, event: InputEvent) -> Option<InputEvent> {
+        self.filters.iter_mut().try_fold(event, |event, filter| filter.filter(event))
+    }
+
+    /// Pass an event through the filters and send it to the HID interface unless it was dropped
+    pub fn forward(&mut self, hid: &mut dyn HidBackend, event: InputEvent) -> io::Result<()> {
+        match self.process(event) {
+            Some(InputEvent::Key(packet)) => hid.send_key_packet(packet.as_bytes()),
+            Some(InputEvent::Mouse(packet)) => hid.send_mouse_report(&packet),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Blocklist, InputEvent, Interceptor, Remap};
+    use crate::key::{KeyPacket, Modifier};
+
+    fn process(interceptor: &mut Interceptor, packet: KeyPacket) -> (u8, Vec<u8>) {
+        match interceptor.process(InputEvent::Key(packet)) {
+            Some(InputEvent::Key(packet)) => (packet.as_bytes()[0], packet.usages().collect()),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[test]
+    fn remap_and_block() {
+        // CapsLock to LeftControl, then block Ctrl+Q
+        let mut interceptor = Interceptor::new()
+            .with(Remap::new().key(0x39, 0xE0))
+            .with(Blocklist::new().chord(Modifier::LeftControl.to_mkbyte(), 0x14));
+        let ctrl = Modifier::LeftControl.to_mkbyte();
+        let mut packet = KeyPacket::from_keycodes(0, 0x39);
+        assert_eq!(process(&mut interceptor, packet.clone()), (ctrl, vec![]));
+        packet.push_key_keycode(0x14);
+        assert_eq!(process(&mut interceptor, packet.clone()), (ctrl, vec![]));
+        packet.push_key_keycode(0x04);
+        assert_eq!(process(&mut interceptor, packet), (ctrl, vec![0x04]));
+    }
+
+    #[test]
+    fn hotkey_is_held_back() {
+        use std::{sync::{Arc, atomic::{AtomicUsize, Ordering}}, time::Duration};
+
+        use super::Hotkeys;
+
+        const SCROLL_LOCK: u8 = 0x47;
+        let switches = Arc::new(AtomicUsize::new(0));
+        let counter = switches.clone();
+        let mut interceptor = Interceptor::new()
+            .with(Hotkeys::new(Duration::from_secs(1)).hotkey(&[(0, SCROLL_LOCK), (0, SCROLL_LOCK)], move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }));
+
+        let scroll_lock = KeyPacket::from_keycodes(0, SCROLL_LOCK);
+        for _ in 0..2 {
+            assert_eq!(process(&mut interceptor, scroll_lock.clone()), (0, vec![]));
+            assert_eq!(process(&mut interceptor, KeyPacket::new()), (0, vec![]));
+        }
+        assert_eq!(switches.load(Ordering::SeqCst), 1);
+        assert_eq!(process(&mut interceptor, KeyPacket::from_keycodes(0, 0x04)), (0, vec![0x04]));
+    }
+
+    #[test]
+    fn rate_limit_drops_presses() {
+        use std::time::Duration;
+
+        use super::RateLimit;
+
+        let mut interceptor = Interceptor::new().with(RateLimit::new(2, Duration::from_secs(3600)));
+        for keycode in [0x04, 0x05] {
+            assert_eq!(process(&mut interceptor, KeyPacket::from_keycodes(0, keycode)), (0, vec![keycode]));
+            assert_eq!(process(&mut interceptor, KeyPacket::new()), (0, vec![]));
+        }
+        assert_eq!(interceptor.process(InputEvent::Key(KeyPacket::from_keycodes(0, 0x06))), None);
+        // releases and held buttons pass over the limit
+        assert_eq!(process(&mut interceptor, KeyPacket::new()), (0, vec![]));
+        assert_eq!(interceptor.process(InputEvent::Mouse([1, 0, 0, 0, 0])), None);
+        assert_eq!(interceptor.process(InputEvent::Mouse([0, 0, 0, 0, 0])), Some(InputEvent::Mouse([0, 0, 0, 0, 0])));
+        assert_eq!(interceptor.process(InputEvent::Mouse([0, 5, 0, 0, 0])), None);
+    }
+
+    #[test]
+    fn logger_passes_everything() {
+        use std::{io::{self, Write}, sync::{Arc, Mutex}};
+
+        use super::Logger;
+
+        struct Capture(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Capture {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let mut interceptor = Interceptor::new().with(Logger::new(Capture(captured.clone())));
+        let shift_a = KeyPacket::from_keycodes(Modifier::LeftShift.to_mkbyte(), 0x04);
+        assert_eq!(process(&mut interceptor, shift_a), (Modifier::LeftShift.to_mkbyte(), vec![0x04]));
+        assert_eq!(process(&mut interceptor, KeyPacket::new()), (0, vec![]));
+        let mouse = InputEvent::Mouse([1, 2, 0xFE, 0, 0]);
+        assert_eq!(interceptor.process(mouse.clone()), Some(mouse));
+        assert_eq!(
+            String::from_utf8_lossy(&captured.lock().unwrap()),
+            "press LeftShift+KeyA\nrelease LeftShift+KeyA\nmouse [1, 2, 254, 0, 0]\n"
+        );
+    }
+}
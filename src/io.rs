@@ -0,0 +1,14 @@
+#![warn(missing_docs)]
+
+pub use crate::hid::{HID, HostProbe, HostStage, FIRMWARE_RTT};
+pub use crate::stop::{emergency_stop, reset_emergency_stop};
+pub use crate::{
+    chaos, choreography, clipboard, echo, fanout, flow, form, power, recorder, stop, switcher, terminal, transfer,
+    vendor,
+};
+#[cfg(feature = "toml")]
+pub use crate::config::ProfileStore;
+#[cfg(feature = "debug")]
+pub use crate::detect;
+#[cfg(feature = "uhid")]
+pub use crate::uhid;
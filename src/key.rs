@@ -1,23 +1,260 @@
 #![warn(missing_docs)]
 
+use std::{borrow::Cow, collections::HashMap, error::Error, fmt, io, ops::{BitAnd, BitOr, Deref, DerefMut, Sub}, str::FromStr, sync::OnceLock, time::Duration};
 use std::{
-    io::{self},
-    str::FromStr,
-    time::Duration,
+    sync::Arc,
+    time::Instant,
 };
 
 use gen_layouts_sys::*;
 use keyboard_layouts::{keycode_for_unicode, Keycode, deadkey_for_keycode, key_for_keycode, modifier_for_keycode};
-use num_enum::IntoPrimitive;
-use serde::{Serialize, Deserialize};
+use num_enum::{IntoPrimitive, FromPrimitive};
+use serde::{de, Deserializer, Serialize, Serializer, Deserialize};
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub use crate::translate::*;
-use crate::HID;
+use crate::accel::Accel;
+use crate::config::{Config, HostOS};
+use crate::history::{HistoryEvent, KeyHistory};
+use crate::ime::{ImeSegment, ImeStrategy};
+use crate::keymap::KeyTable;
+use crate::lint::{self, LintConfig, LintWarning};
+use crate::matrix::KeyMatrix;
+use crate::pacing::AdaptivePacing;
+use crate::{HidBackend, cancel::{self, CancellationToken}, clock::{Clock, SystemClock}, latency::LatencyLog};
+#[cfg(feature = "hid")]
+use crate::{power::{SuspendPolicy, Udc, RESUME_TIMEOUT}, stop};
 
 const KEY_PACKET_KEY_LEN: usize = 32;
 const KEY_PACKET_LEN: usize = KEY_PACKET_KEY_IDX + KEY_PACKET_KEY_LEN;
 const KEY_PACKET_MOD_IDX: usize = 0;
 const KEY_PACKET_KEY_IDX: usize = 1;
+const BOOT_REPORT_LEN: usize = 8;
+const BOOT_REPORT_KEY_IDX: usize = 2;
+pub(crate) const BOOT_REPORT_MAX_KEYS: usize = 6;
+const BOOT_REPORT_ROLLOVER: u8 = 0x01;
+const LAYOUT_CACHE_CAPACITY: usize = 1024;
+/// Longest [Keyboard::self_test] waits for an LED state from the host
+pub const SELF_TEST_LED_TIMEOUT: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// Keyboard Report Protocol
+pub enum ReportProtocol {
+    /// 33 byte modifier + key bitmap report
+    #[default]
+    Bitmap,
+    /// 8 byte boot protocol report, max 6 keys
+    Boot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// What to do when a packet holds more keys than a boot protocol report can carry
+pub enum BootOverflow {
+    /// Send the phantom state (all keys set to ErrorRollOver)
+    #[default]
+    Rollover,
+    /// Split the keys, in keycode order, across sequential reports of up to 6 keys each
+    Split,
+    /// Fail with an InvalidInput [RolloverExceeded] error before anything is sent
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned when a packet holds more keys than a boot protocol report can carry, see [BootOverflow::Error]
+pub struct RolloverExceeded {
+    /// Keys held
+    pub keys: usize,
+}
+
+impl fmt::Display for RolloverExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} keys held, boot reports carry at most {}", self.keys, BOOT_REPORT_MAX_KEYS)
+    }
+}
+
+impl Error for RolloverExceeded {}
+
+/// Check if an error was caused by exceeding the boot protocol rollover, see [RolloverExceeded]
+pub fn is_rollover_exceeded_error(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<RolloverExceeded>())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// What happens to buffered keystrokes sent while a keyboard is muted
+pub enum MutePolicy {
+    /// Discard them, keeping a release of the keys no longer held for the first send after the keyboard is unmuted
+    #[default]
+    Drop,
+    /// Keep them buffered until a send after the keyboard is unmuted
+    Defer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// Order bidirectional (e.g. Hebrew or Arabic) text is typed in
+pub enum BidiOrder {
+    /// Type in logical (memory) order, for hosts that apply the bidi algorithm themselves
+    #[default]
+    Logical,
+    /// Type each line in visual (display) order, for hosts that display text in the order it's typed
+    Visual,
+}
+
+impl BidiOrder {
+    /// Reorder text for typing
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            BidiOrder::Logical => Cow::Borrowed(text),
+            BidiOrder::Visual => Cow::Owned(text.split('\n')
+                .map(|line| {
+                    let info = BidiInfo::new(line, None);
+                    info.paragraphs.iter()
+                        .map(|para| info.reorder_line(para, para.range.clone()))
+                        .collect::<String>()
+                })
+                .collect::<Vec<String>>()
+                .join("\n")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// Key pressed for a newline when typing strings
+pub enum NewlineKey {
+    /// Main Return key
+    #[default]
+    Return,
+    /// Keypad Enter, which some applications treat differently from Return
+    KeypadEnter,
+}
+
+impl NewlineKey {
+   /// Key pressed for a newline
+   pub fn key(&self) -> SpecialKey {
+      match self {
+         NewlineKey::Return => SpecialKey::ReturnEnter,
+         NewlineKey::KeypadEnter => SpecialKey::Enter,
+      }
+   }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// Keystrokes typed for a newline in multi-line strings, for hosts where Enter sends instead of breaking the line
+pub enum Newline {
+    /// The newline key, see [NewlineKey]
+    #[default]
+    Enter,
+    /// Shift and the newline key, a line break in most chat applications
+    ShiftEnter,
+    /// Ctrl and the newline key
+    CtrlEnter,
+    /// Chords pressed in order, see [KeyPacket::parse]
+    Custom(Vec<String>),
+}
+
+impl Newline {
+   /// Packets pressed in order for a newline, with key as the newline key. None if a custom chord isn't recognised.
+   pub fn packets(&self, key: NewlineKey) -> Option<Vec<KeyPacket>> {
+      let with_modifier = |modifier: Option<Modifier>| {
+         let mut packet = KeyPacket::new();
+         if let Some(modifier) = modifier {
+            packet.push_modifier(&modifier);
+         }
+         packet.push_special(&key.key());
+         packet
+      };
+      match self {
+         Newline::Enter => Some(vec![with_modifier(None)]),
+         Newline::ShiftEnter => Some(vec![with_modifier(Some(Modifier::LeftShift))]),
+         Newline::CtrlEnter => Some(vec![with_modifier(Some(Modifier::LeftControl))]),
+         Newline::Custom(chords) => chords.iter().map(|chord| KeyPacket::parse(chord)).collect(),
+      }
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// What happens when a string has grapheme clusters that can't be typed
+pub enum TypeErrorPolicy {
+    /// Skip them and type the rest
+    #[default]
+    Skip,
+    /// Type nothing
+    Abort,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// Options for typing strings, see [Keyboard::type_with]
+pub struct TypeOptions {
+    /// Layout, None uses the basic keyboard table
+    pub layout: Option<String>,
+    /// Wait between sent key packets
+    pub delay: Duration,
+    /// Enter chars the layout can't type through the unicode hex input of a host OS
+    /// (Ctrl+Shift+U on Linux, Alt+Keypad Plus on Windows with EnableHexNumpad, Option on MacOS with Unicode Hex Input)
+    pub unicode_fallback: Option<HostOS>,
+    /// Key pressed for newlines
+    pub newline: NewlineKey,
+    /// Keystrokes typed for newlines
+    pub newline_policy: Newline,
+    /// Invert Shift for letters while the host has CapsLock on, see [Keyboard::set_led_states]
+    pub caps_compensation: bool,
+    /// What happens when some of the string can't be typed
+    pub error_policy: TypeErrorPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// Decimal separator used by [Keyboard::type_number]
+pub enum NumberLocale {
+    /// Dot, e.g. 1234.5
+    #[default]
+    Dot,
+    /// Comma, e.g. 1234,5
+    Comma,
+    /// DecimalSeparator usage, letting the host pick the separator for its locale
+    Host,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Calculator operator
+pub enum Operator {
+    /// +
+    Add,
+    /// -
+    Subtract,
+    /// *
+    Multiply,
+    /// /
+    Divide,
+    /// =
+    Equals,
+    /// Enter
+    Enter,
+}
+
+impl Operator {
+    /// Key typing the operator from a key origin
+    pub fn to_key(&self, origin: &KeyOrigin) -> BasicKey {
+        let c = match self {
+            Operator::Add => '+',
+            Operator::Subtract => '-',
+            Operator::Multiply => '*',
+            Operator::Divide => '/',
+            Operator::Equals => '=',
+            Operator::Enter => return match origin {
+                KeyOrigin::Keypad => BasicKey::KeypadEnter,
+                _ => BasicKey::Special(SpecialKey::ReturnEnter),
+            },
+        };
+        BasicKey::Char(c, *origin)
+    }
+}
 
 #[derive(Debug, Clone, IntoPrimitive)]
 #[repr(usize)]
@@ -59,8 +296,18 @@ impl LEDStatePacket {
         LEDStatePacket { data: 0x00 }
     }
 
+    /// Create from a raw LED states packet
+    pub fn from_raw(data: u8) -> LEDStatePacket {
+        LEDStatePacket { data }
+    }
+
+    /// Raw LED states packet
+    pub fn raw(&self) -> u8 {
+        self.data
+    }
+
     /// Create a new LED State Packet from an incoming raw packet.
-    pub fn new_from_packet(hid: &mut HID, timeout: Duration) -> io::Result<LEDStatePacket> {
+    pub fn new_from_packet(hid: &mut dyn HidBackend, timeout: Duration) -> io::Result<LEDStatePacket> {
         Ok(LEDStatePacket {
             data: hid.receive_states_packet(timeout)?.unwrap_or(0),
         })
@@ -74,7 +321,7 @@ impl LEDStatePacket {
     }
 
     /// Update LED States with an incoming raw packet with a timeout.
-    pub fn update(&mut self, hid: &mut HID, timeout: Duration) -> io::Result<()> {
+    pub fn update(&mut self, hid: &mut dyn HidBackend, timeout: Duration) -> io::Result<()> {
         match hid.receive_states_packet(timeout)? {
             Some(data) => self.data = data,
             None => (),
@@ -89,6 +336,48 @@ impl From<&LEDStatePacket> for u8 {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+/// Warning that the stuck modifier watchdog released held modifiers
+pub struct StuckModifiers {
+    /// Modifiers released
+    pub modifiers: Vec<Modifier>,
+    /// Time since the last send
+    pub idle: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Health of an interface checked by [Keyboard::self_test]
+pub enum InterfaceHealth {
+    /// Working
+    Ok,
+    /// Nothing received, e.g. there's no LED interface or the host hasn't sent LED states yet
+    NoResponse,
+    /// Failed with an error
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Health report of [Keyboard::self_test]
+pub struct SelfTestReport {
+    /// Writing an all-zero key packet
+    pub keyboard: InterfaceHealth,
+    /// Writing a zero movement mouse packet
+    pub mouse: InterfaceHealth,
+    /// Receiving LED states from the host
+    pub led: InterfaceHealth,
+    /// LED states received, if any
+    pub led_state: Option<u8>,
+    /// Time the test took
+    pub duration: Duration,
+}
+
+impl SelfTestReport {
+    /// Check if the keyboard and mouse interfaces work. LED states are optional, as hosts may not send them.
+    pub fn is_healthy(&self) -> bool {
+        self.keyboard == InterfaceHealth::Ok && self.mouse == InterfaceHealth::Ok
+    }
+}
+
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, Serialize, Deserialize)]
 /// Basic Key Press
 pub enum BasicKey {
@@ -96,6 +385,44 @@ pub enum BasicKey {
     Char(char, KeyOrigin),
     /// Special Key
     Special(SpecialKey),
+    /// Keypad Enter, which some applications treat differently from Return
+    KeypadEnter,
+}
+
+#[derive(Debug, Clone)]
+/// Keys typing a char in a layout
+enum LayoutKeys {
+    /// Modifier and keys pressed together
+    Sequence(u8, Vec<u8>),
+    /// Key, after a dead key if needed
+    Key { dead: Option<(u8, u8)>, modifier: u8, key: u8 },
+}
+
+impl LayoutKeys {
+   fn lookup(layout_key: &str, c: char) -> Option<LayoutKeys> {
+      let layout = Keyboard::get_layout(layout_key)?;
+      let unicode = u16::try_from(c as u32).ok()?;
+      match keycode_for_unicode(layout, unicode) {
+         Keycode::ModifierKeySequence(modifier, sequence) => Some(LayoutKeys::Sequence(
+            modifier as u8,
+            sequence.into_iter().map(|keycode| keycode as u8).collect(),
+         )),
+         Keycode::RegularKey(keycode) => Some(LayoutKeys::Key {
+            dead: deadkey_for_keycode(layout, keycode)
+               .map(|dead_keycode| (modifier_for_keycode(layout, dead_keycode), key_for_keycode(layout, dead_keycode))),
+            modifier: modifier_for_keycode(layout, keycode),
+            key: key_for_keycode(layout, keycode),
+         }),
+         _ => None,
+      }
+   }
+}
+
+#[derive(Debug, Default)]
+/// Layout lookups of the last layout used
+struct LayoutCache {
+    layout: String,
+    keys: HashMap<char, Option<LayoutKeys>>,
 }
 
 /// Virtual Keyboard
@@ -103,6 +430,39 @@ pub struct Keyboard {
     packets: Vec<KeyPacket>,
     holding: KeyPacket,
     led_states: LEDStatePacket,
+    layout: Option<String>,
+    pacing: Duration,
+    protocol: ReportProtocol,
+    boot_overflow: BootOverflow,
+    matrix: Option<KeyMatrix>,
+    key_table: Option<KeyTable>,
+    newline: NewlineKey,
+    basic_newline: Option<NewlineKey>,
+    newline_policy: Newline,
+    unicode_fallback: Option<HostOS>,
+    caps_compensation: bool,
+    error_policy: TypeErrorPolicy,
+    bidi_order: BidiOrder,
+    stuck_modifier_timeout: Option<Duration>,
+    max_hold: Option<Duration>,
+    layout_cache: LayoutCache,
+    layout_cache_capacity: usize,
+    history: Option<KeyHistory>,
+    lint: Option<LintConfig>,
+    adaptive_pacing: Option<AdaptivePacing>,
+    restore_pacing: Option<Duration>,
+    last_send: Option<Instant>,
+    last_heartbeat: Option<Instant>,
+    latency: Option<LatencyLog>,
+    muted: bool,
+    muted_until: Option<Instant>,
+    mute_policy: MutePolicy,
+    #[cfg(feature = "hid")]
+    power: Option<(Udc, SuspendPolicy)>,
+    clock: Arc<dyn Clock>,
+    held_since: HashMap<u8, Instant>,
+    #[cfg(feature = "hid")]
+    stop_generation: usize,
 }
 
 impl FromStr for Keyboard {
@@ -110,7 +470,7 @@ impl FromStr for Keyboard {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut keyboard = Keyboard::new();
-        keyboard.press_basic_string(s);
+        keyboard.press_basic_string_checked(s);
         Ok(keyboard)
     }
 }
@@ -122,9 +482,296 @@ impl Keyboard {
          packets: Vec::new(),
          holding: KeyPacket::new(),
          led_states: LEDStatePacket::new(),
+         layout: None,
+         pacing: Duration::ZERO,
+         protocol: ReportProtocol::Bitmap,
+         boot_overflow: BootOverflow::Rollover,
+         matrix: None,
+         key_table: None,
+         newline: NewlineKey::Return,
+         basic_newline: None,
+         newline_policy: Newline::Enter,
+         unicode_fallback: None,
+         caps_compensation: false,
+         error_policy: TypeErrorPolicy::Skip,
+         bidi_order: BidiOrder::Logical,
+         stuck_modifier_timeout: None,
+         max_hold: None,
+         layout_cache: LayoutCache::default(),
+         layout_cache_capacity: LAYOUT_CACHE_CAPACITY,
+         history: None,
+         lint: None,
+         adaptive_pacing: None,
+         restore_pacing: None,
+         last_send: None,
+         last_heartbeat: None,
+         latency: None,
+         muted: false,
+         muted_until: None,
+         mute_policy: MutePolicy::Drop,
+         #[cfg(feature = "hid")]
+         power: None,
+         clock: Arc::new(SystemClock),
+         held_since: HashMap::new(),
+         #[cfg(feature = "hid")]
+         stop_generation: stop::generation(),
+      }
+   }
+
+   /// New from config
+   pub fn from_config(config: &Config) -> Keyboard {
+      let mut keyboard = Keyboard::new();
+      keyboard.set_layout(config.layout.as_deref());
+      keyboard.set_pacing(config.pacing());
+      keyboard.set_protocol(config.report_protocol);
+      keyboard.set_boot_overflow(config.boot_overflow);
+      keyboard.set_max_hold(config.max_hold());
+      keyboard.set_lint(config.lint);
+      keyboard.set_adaptive_pacing(config.adaptive_pacing);
+      keyboard
+   }
+
+   /// Set the layout used by [Keyboard::type_string]. None uses the basic keyboard table.
+   pub fn set_layout(&mut self, layout_key: Option<&str>) {
+      self.layout = layout_key.map(|layout_key| layout_key.to_string());
+   }
+
+   /// Set the wait between sent key packets
+   pub fn set_pacing(&mut self, pacing: Duration) {
+      self.pacing = pacing;
+      self.restore_pacing = None;
+   }
+
+   /// Wait between sent key packets
+   pub fn pacing(&self) -> Duration {
+      self.pacing
+   }
+
+   /// Adapt the wait between sent key packets to the host's load while sending, starting from the pacing set, see
+   /// [AdaptivePacing]. Heartbeats go between keystrokes while no keys are held. None keeps pacing fixed (the default).
+   pub fn set_adaptive_pacing(&mut self, adaptive_pacing: Option<AdaptivePacing>) {
+      self.adaptive_pacing = adaptive_pacing;
+   }
+
+   /// Set the clock used for pacing, dwell, mute windows and the stuck modifier watchdog, see [crate::clock::VirtualClock]
+   pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+      self.clock = clock;
+   }
+
+   /// Clock used for pacing, also by helpers that wait between typing, e.g. [crate::form::fill_form]
+   pub fn clock(&self) -> &Arc<dyn Clock> {
+      &self.clock
+   }
+
+   /// Set the report protocol used when sending
+   pub fn set_protocol(&mut self, protocol: ReportProtocol) {
+      self.protocol = protocol;
+   }
+
+   /// Set what happens when a packet holds more keys than a boot protocol report can carry, sending the phantom state
+   /// by default
+   pub fn set_boot_overflow(&mut self, boot_overflow: BootOverflow) {
+      self.boot_overflow = boot_overflow;
+   }
+
+   /// Set a table generated from a console keymap or XKB symbols, used by [Keyboard::type_string] before the layout.
+   /// Chars missing from the table fall back to the layout.
+   pub fn set_key_table(&mut self, key_table: Option<KeyTable>) {
+      self.key_table = key_table;
+   }
+
+   /// Set a key matrix to emulate the ghosting of a physical keyboard when sending. None sends packets as they are.
+   pub fn set_matrix(&mut self, matrix: Option<KeyMatrix>) {
+      self.matrix = matrix;
+   }
+
+   /// Set the key pressed for newlines in typed strings
+   pub fn set_newline_key(&mut self, newline: NewlineKey) {
+      self.newline = newline;
+   }
+
+   /// Set the key pressed for newlines by [Keyboard::press_basic_string_checked], separately from layout-driven typing.
+   /// None follows [Keyboard::set_newline_key].
+   pub fn set_basic_newline_key(&mut self, newline: Option<NewlineKey>) {
+      self.basic_newline = newline;
+   }
+
+   /// Set the keystrokes typed for newlines in strings, e.g. [Newline::ShiftEnter] for chat applications
+   pub fn set_newline_policy(&mut self, newline_policy: Newline) {
+      self.newline_policy = newline_policy;
+   }
+
+   /// Keystrokes typed for newlines in strings
+   pub fn newline_policy(&self) -> &Newline {
+      &self.newline_policy
+   }
+
+   /// Get the options used by [Keyboard::type_string]
+   pub fn type_options(&self) -> TypeOptions {
+      TypeOptions {
+         layout: self.layout.clone(),
+         delay: self.pacing,
+         unicode_fallback: self.unicode_fallback,
+         newline: self.newline,
+         newline_policy: self.newline_policy.clone(),
+         caps_compensation: self.caps_compensation,
+         error_policy: self.error_policy,
+      }
+   }
+
+   /// Set the options used by [Keyboard::type_string]
+   pub fn set_type_options(&mut self, options: TypeOptions) {
+      self.set_pacing(options.delay);
+      self.layout = options.layout;
+      self.unicode_fallback = options.unicode_fallback;
+      self.newline = options.newline;
+      self.newline_policy = options.newline_policy;
+      self.caps_compensation = options.caps_compensation;
+      self.error_policy = options.error_policy;
+   }
+
+   /// Set the order bidirectional text is typed in by [Keyboard::type_string]
+   pub fn set_bidi_order(&mut self, bidi_order: BidiOrder) {
+      self.bidi_order = bidi_order;
+   }
+
+   /// Set how long modifiers can stay held without a send before [Keyboard::release_stuck_modifiers] releases them. None disables the watchdog.
+   pub fn set_stuck_modifier_timeout(&mut self, timeout: Option<Duration>) {
+      self.stuck_modifier_timeout = timeout;
+   }
+
+   /// Set the longest a key or modifier can stay held on the host before it's released automatically, guarding
+   /// relays against dropped key ups. Holds only expire when flushing or calling [Keyboard::release_expired_holds], so
+   /// a caller that stops sending must poll it, e.g. at [Keyboard::next_hold_deadline]. None disables auto release (the
+   /// default).
+   pub fn set_max_hold(&mut self, max_hold: Option<Duration>) {
+      self.max_hold = max_hold;
+   }
+
+   /// Keep a history of the last capacity key events sent, see [Keyboard::recent_events]. None stops keeping one (the
+   /// default) and forgets it.
+   pub fn set_history_capacity(&mut self, capacity: Option<usize>) {
+      self.history = capacity.map(KeyHistory::new);
+   }
+
+   /// History of key events sent, if kept
+   pub fn history(&self) -> Option<&KeyHistory> {
+      self.history.as_ref()
+   }
+
+   /// Up to the n most recent key events sent, oldest first. Empty if no history is kept.
+   pub fn recent_events(&self, n: usize) -> Vec<HistoryEvent> {
+      self.history.iter().flat_map(|history| history.recent(n).cloned()).collect()
+   }
+
+   /// Set lint rules checked before flushing, see [crate::lint::lint]. Warnings are logged, or refused as an
+   /// InvalidInput error without sending if the config denies them. None doesn't check (the default).
+   pub fn set_lint(&mut self, lint: Option<LintConfig>) {
+      self.lint = lint;
+   }
+
+   /// Check the buffered packets, ending with the release packet a flush would send, against the lint rules set,
+   /// or the default rules if none are
+   pub fn lint(&self) -> Vec<LintWarning> {
+      self.lint_with(&self.lint.unwrap_or_default())
+   }
+
+   fn lint_with(&self, config: &LintConfig) -> Vec<LintWarning> {
+      let mut packets = self.packets.clone();
+      if !packets.is_empty() {
+         packets.push(self.create_release_packet());
+      }
+      lint::lint(&packets, self.pacing, config)
+   }
+
+   /// Enable or disable latency measurement mode. When enabled each packet is timestamped when queued and when written.
+   /// Enabling starts a new log, with packets already buffered timestamped as queued now.
+   pub fn set_latency_mode(&mut self, enabled: bool) {
+      self.latency = enabled.then(|| {
+         let mut latency = LatencyLog::new();
+         let now = Instant::now();
+         for _ in &self.packets {
+            latency.mark_queued_at(now);
+         }
+         latency
+      });
+   }
+
+   /// Get the packet timestamps recorded in latency measurement mode
+   pub fn latency(&self) -> Option<&LatencyLog> {
+      self.latency.as_ref()
+   }
+
+   /// Get the packet timestamps recorded in latency measurement mode, e.g. to mark packets received by a loopback host
+   pub fn latency_mut(&mut self) -> Option<&mut LatencyLog> {
+      self.latency.as_mut()
+   }
+
+   /// Mute or unmute sending, e.g. while an operator takes manual control of the host. See [Keyboard::set_mute_policy].
+   pub fn set_muted(&mut self, muted: bool) {
+      self.muted = muted;
+      if !muted {
+         self.muted_until = None;
+      }
+   }
+
+   /// Mute sending for a window of time from now
+   pub fn mute(&mut self, window: Duration) {
+      self.muted_until = Some(self.clock.now() + window);
+   }
+
+   /// Check if sending is muted
+   pub fn is_muted(&self) -> bool {
+      self.muted || self.muted_until.is_some_and(|until| self.clock.now() < until)
+   }
+
+   /// Set what happens to buffered keystrokes sent while muted
+   pub fn set_mute_policy(&mut self, mute_policy: MutePolicy) {
+      self.mute_policy = mute_policy;
+   }
+
+   /// Watch the USB device controller for host suspend, handling keystrokes sent while suspended according to the
+   /// policy. None stops watching.
+   #[cfg(feature = "hid")]
+   pub fn set_power_management(&mut self, udc: Option<Udc>, policy: SuspendPolicy) {
+      self.power = udc.map(|udc| (udc, policy));
+   }
+
+   /// Wait with a timeout for the host to resume, then flush. Returns false, keeping the buffer, if it's still suspended.
+   #[cfg(feature = "hid")]
+   pub fn flush_on_resume(&mut self, hid: &mut dyn HidBackend, timeout: Duration) -> io::Result<bool> {
+      if let Some((udc, _)) = &self.power {
+         if !udc.wait_resumed(timeout)? {
+            return Ok(false);
+         }
+      }
+      self.flush(hid)?;
+      Ok(true)
+   }
+
+   /// Check if the host is suspended, waking it up if the policy says to
+   #[cfg(feature = "hid")]
+   fn host_suspended(&self) -> io::Result<bool> {
+      let Some((udc, policy)) = &self.power else {
+         return Ok(false);
+      };
+      if !udc.is_suspended()? {
+         return Ok(false);
+      }
+      match policy {
+         SuspendPolicy::Queue => Ok(true),
+         SuspendPolicy::Wakeup => {
+            udc.wakeup()?;
+            Ok(!udc.wait_resumed(RESUME_TIMEOUT)?)
+         },
       }
    }
 
+   #[cfg(not(feature = "hid"))]
+   fn host_suspended(&self) -> io::Result<bool> {
+      Ok(false)
+   }
+
    /// Get a list of the supported keyboard layouts
    pub fn available_layouts() -> Vec<&'static str> {
       LAYOUT_MAP.keys().map(|k| *k).collect()
@@ -141,57 +788,144 @@ impl Keyboard {
       self.led_states.get_state(state)
    }
 
+   /// Boot time diagnostics for appliances. Write harmless reports to each interface (an all-zero key packet and a zero
+   /// movement mouse packet), then check for LED states from the host, waiting up to [SELF_TEST_LED_TIMEOUT] if none have
+   /// been received. Received LED states are synced like [Keyboard::attach]. The all-zero key packet releases keys held
+   /// on the host, so call it while no keys are held.
+   pub fn self_test(&mut self, hid: &mut dyn HidBackend) -> SelfTestReport {
+      let start = self.clock.now();
+      let health = |result: io::Result<()>| match result {
+         Ok(()) => InterfaceHealth::Ok,
+         Err(e) => InterfaceHealth::Failed(e.to_string()),
+      };
+      let keyboard = health(self.send_release_all(hid));
+      let mouse = health(hid.send_mouse_report(&[0; 5]));
+      let (led, led_state) = match hid.wait_led_state(SELF_TEST_LED_TIMEOUT) {
+         Ok(Some(data)) => {
+            self.led_states = LEDStatePacket::from_raw(data);
+            (InterfaceHealth::Ok, Some(data))
+         },
+         Ok(None) => (InterfaceHealth::NoResponse, None),
+         Err(e) => (InterfaceHealth::Failed(e.to_string()), None),
+      };
+      SelfTestReport { keyboard, mouse, led, led_state, duration: self.clock.now() - start }
+   }
+
+   /// Initial LED state sync. Take the last LED states the HID interface received, waiting for the host's
+   /// first report with a timeout if there hasn't been one. Returns false if the LED states are still unknown.
+   pub fn attach(&mut self, hid: &mut dyn HidBackend, timeout: Duration) -> io::Result<bool> {
+      match hid.wait_led_state(timeout)? {
+         Some(data) => {
+            self.led_states = LEDStatePacket::from_raw(data);
+            Ok(true)
+         },
+         None => Ok(false),
+      }
+   }
+
+   /// Set LED states, e.g. received by a [crate::LEDReader] on another thread
+   pub fn set_led_states(&mut self, led_states: LEDStatePacket) {
+      self.led_states = led_states;
+   }
+
    /// update LED states from incoming led state packets
-   pub fn update_led_state(&mut self, hid: &mut HID, timeout: Duration) -> io::Result<()> {
+   pub fn update_led_state(&mut self, hid: &mut dyn HidBackend, timeout: Duration) -> io::Result<()> {
       self.led_states.update(hid, timeout)
    }
 
+   fn queue(&mut self, packet: KeyPacket) {
+      self.forget_stale_holds();
+      if let Some(latency) = &mut self.latency {
+         latency.mark_queued();
+      }
+      self.packets.push(packet);
+   }
+
    fn add_buffer(&mut self, packet: &KeyPacket) {
       if let Some(last) = self.packets.last() {
          if last.contains_any(packet) {
-               self.packets.push(self.create_release_packet())
+               self.queue(self.create_release_packet())
          }
       }
    }
 
-   /// Hold key down
-   pub fn hold_key(&mut self, key: &BasicKey) -> Option<u8> {
+   fn key_kbytes(key: &BasicKey) -> Option<[u8; 2]> {
+      match key {
+         BasicKey::Char(c, key_origin) => c.to_kbytes(key_origin),
+         BasicKey::Special(special) => Some([0, special.to_kbyte()]),
+         BasicKey::KeypadEnter => Some([0, SpecialKey::Enter.to_kbyte()]),
+      }
+   }
+
+   /// Hold key down until the returned guard is dropped, which queues the release. The guard dereferences to the
+   /// keyboard, so it can type and flush while the key is held. [HeldKey::forget] keeps the key held.
+   /// None if the key isn't in the basic keyboard table.
+   #[must_use = "dropping the guard releases the key straight away, use HeldKey::forget to keep it held"]
+   pub fn hold_key(&mut self, key: &BasicKey) -> Option<HeldKey<'_>> {
       #[cfg(feature = "debug")]
       {
-         println!("hold {:?}", key);
+         crate::logging::log_line!("hold {:?}", key);
       }
-      let kbytes = match key {
-         BasicKey::Char(c, key_origin) => c.to_kbytes(key_origin)?,
-         BasicKey::Special(special) => [0, special.to_kbyte()],
-      };
+      self.forget_stale_holds();
+      let kbytes = Keyboard::key_kbytes(key)?;
       self.holding.add_key(&kbytes);
-      self.packets.push(self.create_release_packet());
-      Some(kbytes[1])
+      self.queue(self.create_release_packet());
+      Some(HeldKey { keyboard: self, key: *key, keycode: kbytes[1], forgotten: false })
    }
 
    /// Release Key
    pub fn release_key(&mut self, key: &BasicKey) {
       #[cfg(feature = "debug")]
       {
-         println!("release {:?}", key);
+         crate::logging::log_line!("release {:?}", key);
       }
-      let kbytes = match key {
-         BasicKey::Char(c, key_origin) => match c.to_kbytes(key_origin) {
-               Some(kbytes) => kbytes,
-               None => return,
-         },
-         BasicKey::Special(special) => [0, special.to_kbyte()],
+      let kbytes = match Keyboard::key_kbytes(key) {
+         Some(kbytes) => kbytes,
+         None => return,
       };
       self.holding.remove_key(&kbytes);
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
+   }
+
+   /// Hold a shortcut down, keeping the modifiers and key held across sends until [Keyboard::release_shortcut]
+   pub fn hold_shortcut(&mut self, modifiers: &[Modifier], key: &BasicKey) -> Option<()> {
+      #[cfg(feature = "debug")]
+      {
+         crate::logging::log_line!("hold {:?} {:?}", modifiers, key);
+      }
+      self.forget_stale_holds();
+      let kbytes = Keyboard::key_kbytes(key)?;
+      for modifier in modifiers {
+         self.holding.push_modifier(modifier);
+      }
+      self.holding.add_key(&kbytes);
+      self.queue(self.create_release_packet());
+      Some(())
+   }
+
+   /// Release a shortcut held by [Keyboard::hold_shortcut], releasing the key before the modifiers
+   pub fn release_shortcut(&mut self, modifiers: &[Modifier], key: &BasicKey) {
+      #[cfg(feature = "debug")]
+      {
+         crate::logging::log_line!("release {:?} {:?}", modifiers, key);
+      }
+      if let Some(kbytes) = Keyboard::key_kbytes(key) {
+         self.holding.remove_key(&kbytes);
+         self.queue(self.create_release_packet());
+      }
+      for modifier in modifiers {
+         self.holding.remove_mod(modifier);
+      }
+      self.queue(self.create_release_packet());
    }
 
    /// Hold all keys in string
    pub fn hold_string(&mut self, str: &str) {
       #[cfg(feature = "debug")]
       {
-         println!("hold {:?}", str);
+         crate::logging::log_line!("hold {:?}", str);
       }
+      self.forget_stale_holds();
       for c in str.chars() {
          let kbytes = match c.to_kbytes(&KeyOrigin::Keyboard) {
                Some(packet) => packet,
@@ -199,14 +933,14 @@ impl Keyboard {
          };
          self.holding.add_key(&kbytes);
       }
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
    }
 
    /// Release all keys in string
    pub fn release_string(&mut self, str: &str) {
       #[cfg(feature = "debug")]
       {
-         println!("release {:?}", str);
+         crate::logging::log_line!("release {:?}", str);
       }
       for c in str.chars() {
          let kbytes = match c.to_kbytes(&KeyOrigin::Keyboard) {
@@ -215,137 +949,227 @@ impl Keyboard {
          };
          self.holding.remove_key(&kbytes);
       }
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
    }
 
    /// Hold key with keycode
    pub fn hold_keycode(&mut self, key: u8) {
       #[cfg(feature = "debug")]
       {
-         println!("hold {:08b}", key);
+         crate::logging::log_line!("hold {}", crate::decode::keycode_name(key));
       }
+      self.forget_stale_holds();
       self.holding.add_key(&[0, key]);
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
    }
 
    /// Release key with keycode
    pub fn release_keycode(&mut self, key: u8) {
       #[cfg(feature = "debug")]
       {
-         println!("release {:08b}", key);
+         crate::logging::log_line!("release {}", crate::decode::keycode_name(key));
       }
       self.holding.remove_key(&[0, key]);
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
    }
 
    /// Hold modifier key
    pub fn hold_mod(&mut self, modifier: &Modifier) {
       #[cfg(feature = "debug")]
       {
-         println!("hold {:?}", modifier);
+         crate::logging::log_line!("hold {:?}", modifier);
       }
+      self.forget_stale_holds();
       self.holding.push_modifier(modifier);
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
+   }
+
+   /// Keys and modifiers held
+   pub fn held(&self) -> &KeyPacket {
+      static RELEASED: OnceLock<KeyPacket> = OnceLock::new();
+      match self.holds_stale() {
+         true => RELEASED.get_or_init(KeyPacket::new),
+         false => &self.holding,
+      }
+   }
+
+   /// Whether an emergency stop released the held keys on the host after they were pressed, see [crate::stop]
+   #[cfg(feature = "hid")]
+   fn holds_stale(&self) -> bool {
+      self.stop_generation != stop::generation()
+   }
+
+   #[cfg(not(feature = "hid"))]
+   fn holds_stale(&self) -> bool {
+      false
+   }
+
+   /// Forget the held keys and drop the buffer if an emergency stop released everything on the host since they were
+   /// pressed and queued
+   fn forget_stale_holds(&mut self) {
+      #[cfg(feature = "hid")]
+      if self.holds_stale() {
+         self.stop_generation = stop::generation();
+         self.holding = KeyPacket::new();
+         self.held_since.clear();
+         self.packets.clear();
+         if let Some(latency) = &mut self.latency {
+            latency.discard_queued();
+         }
+      }
    }
 
    /// Release modifier key
    pub fn release_mod(&mut self, modifier: &Modifier) {
       #[cfg(feature = "debug")]
       {
-         println!("release {:?}", modifier);
+         crate::logging::log_line!("release {:?}", modifier);
       }
       self.holding.remove_mod(modifier);
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
    }
 
-   fn add_held_keys(&mut self, packet: &mut KeyPacket) {
-      let mut i = 0;
-      for byte in &mut self.holding.data {
-         *byte |= packet.data[i];
-         i += 1;
+   fn add_held_keys(&self, packet: &mut KeyPacket) {
+      if self.holds_stale() {
+         return;
+      }
+      for (byte, held) in packet.data.iter_mut().zip(self.holding.data.iter()) {
+         *byte |= held;
       }
    }
 
    fn create_release_packet(&self) -> KeyPacket {
-      self.holding.clone()
+      self.held().clone()
+   }
+
+   /// Keycode bytes of a char in the basic keyboard table, with newlines pressing a newline key
+   fn char_kbytes(c: char, newline: NewlineKey) -> Option<[u8; 2]> {
+      match c {
+         '\n' => Some([0, newline.key().to_kbyte()]),
+         c => c.to_kbytes(&KeyOrigin::Keyboard),
+      }
    }
 
    /// Press key with layout support
    pub fn press(&mut self, layout_key: &str, c: char) -> Option<()> {
-      let layout = Keyboard::get_layout(layout_key)?;
-      match keycode_for_unicode(layout, c as u16) {
-            Keycode::ModifierKeySequence(modifier, sequence) => {
-               let mut packet = KeyPacket::from_mod_keycode(modifier as  u8);
+      if c == '\n' {
+         return match self.newline_policy {
+            Newline::Enter => {
+               self.press_special(&self.newline.key());
+               Some(())
+            },
+            _ => self.press_newline(self.newline),
+         };
+      }
+      match self.layout_keys(layout_key, c)? {
+            LayoutKeys::Sequence(modifier, sequence) => {
+               let mut packet = KeyPacket::from_mod_keycode(modifier);
                for keycode in sequence {
-                  packet.push_key_keycode(keycode as u8);
+                  packet.push_key_keycode(keycode);
                }
                self.add_buffer(&packet);
                self.add_held_keys(&mut packet);
-               self.packets.push(packet);
-               self.packets.push(self.create_release_packet());
+               self.queue(packet);
+               self.queue(self.create_release_packet());
             },
-            Keycode::RegularKey(keycode) => {
-               if let Some(dead_keycode) = deadkey_for_keycode(layout, keycode) {
-                  let key = key_for_keycode(layout, dead_keycode);
-                  let modifier = modifier_for_keycode(layout, dead_keycode);
-
-                  let mut packet = KeyPacket::from_keycodes(modifier, key);
+            LayoutKeys::Key { dead, modifier, key } => {
+               if let Some((dead_modifier, dead_key)) = dead {
+                  let mut packet = KeyPacket::from_keycodes(dead_modifier, dead_key);
                   self.add_buffer(&packet);
                   self.add_held_keys(&mut packet);
-                  self.packets.push(packet);
+                  self.queue(packet);
 
-                  self.packets.push(self.create_release_packet());
+                  self.queue(self.create_release_packet());
                }
-               let key = key_for_keycode(layout, keycode);
-               let modifier = modifier_for_keycode(layout, keycode);
-
                let mut packet = KeyPacket::from_keycodes(modifier, key);
                self.add_held_keys(&mut packet);
-               self.packets.push(packet);
+               self.queue(packet);
 
-               self.packets.push(self.create_release_packet());
+               self.queue(self.create_release_packet());
             }
-            _ => return None,
       }
       #[cfg(feature = "debug")]
       {
-         println!("press {:?}", c);
+         crate::logging::log_line!("press {:?}", c);
       }
       Some(())
    }
 
+   /// Look up the keys typing a char in a layout, through the layout cache
+   fn layout_keys(&mut self, layout_key: &str, c: char) -> Option<LayoutKeys> {
+      if self.layout_cache_capacity == 0 {
+         return LayoutKeys::lookup(layout_key, c);
+      }
+      if self.layout_cache.layout != layout_key || self.layout_cache.keys.len() >= self.layout_cache_capacity {
+         self.layout_cache.layout.clear();
+         self.layout_cache.layout.push_str(layout_key);
+         self.layout_cache.keys.clear();
+      }
+      self.layout_cache.keys
+         .entry(c)
+         .or_insert_with(|| LayoutKeys::lookup(layout_key, c))
+         .clone()
+   }
+
+   /// Set how many chars the layout lookup cache holds before it's cleared. 0 disables the cache.
+   pub fn set_layout_cache_capacity(&mut self, capacity: usize) {
+      self.layout_cache_capacity = capacity;
+      self.layout_cache.keys.clear();
+   }
+
    /// Send keystroke in packet
    pub fn press_packet(&mut self, mut packet: KeyPacket) {
       self.add_held_keys(&mut packet);
-      self.packets.push(packet)
+      self.queue(packet)
+   }
+
+   /// Send keystroke of a readable chord, see [KeyPacket::parse]. None if a part of the chord isn't recognised.
+   pub fn press_chord(&mut self, chord: &str) -> Option<()> {
+      self.press_packet(KeyPacket::parse(chord)?);
+      self.queue(self.create_release_packet());
+      Some(())
+   }
+
+   /// Send the keystrokes of a common shortcut on a host OS, see [Accel::chords]. None if the host has no shortcut for it.
+   pub fn press_accel(&mut self, accel: Accel, host: HostOS) -> Option<()> {
+      #[cfg(feature = "debug")]
+      {
+         crate::logging::log_line!("press {:?}", accel);
+      }
+      let packets = accel.chords(host)?.iter().map(|chord| KeyPacket::parse(chord)).collect::<Option<Vec<_>>>()?;
+      for packet in packets {
+         self.press_packet(packet);
+         self.queue(self.create_release_packet());
+      }
+      Some(())
    }
 
    /// Send modifier keystroke
    pub fn press_modifier(&mut self, modifier: &Modifier) {
       #[cfg(feature = "debug")]
       {
-         println!("press {:?}", modifier);
+         crate::logging::log_line!("press {:?}", modifier);
       }
       let mut packet = self.create_release_packet();
       packet.push_modifier(modifier);
-      self.packets.push(packet);
-      self.packets.push(self.create_release_packet());
+      self.queue(packet);
+      self.queue(self.create_release_packet());
    }
 
    /// Send shortcut keystroke
    pub fn press_shortcut(&mut self, modifiers: &[Modifier], key: &BasicKey) -> Option<()> {
       #[cfg(feature = "debug")]
       {
-         println!("press {:?} {:?}", modifiers, key);
+         crate::logging::log_line!("press {:?} {:?}", modifiers, key);
       }
       let mut packet = self.create_release_packet();
       for modifier in modifiers {
          packet.push_modifier(modifier);
       }
       packet.push_key(key);
-      self.packets.push(self.create_release_packet());
-      self.packets.push(packet);
-      self.packets.push(self.create_release_packet());
+      self.queue(self.create_release_packet());
+      self.queue(packet);
+      self.queue(self.create_release_packet());
 
       Some(())
    }
@@ -353,23 +1177,23 @@ impl Keyboard {
    fn press_special(&mut self, special: &SpecialKey) {
       #[cfg(feature = "debug")]
       {
-         println!("press {:?}", special);
+         crate::logging::log_line!("press {:?}", special);
       }
       let mut packet = self.create_release_packet();
       packet.push_special(special);
       self.add_buffer(&packet);
-      self.packets.push(packet);
+      self.queue(packet);
    }
 
    fn press_char(&mut self, c: &char, key_origin: &KeyOrigin) -> Option<()> {
       #[cfg(feature = "debug")]
       {
-         println!("press {:?} {:?}", c, key_origin);
+         crate::logging::log_line!("press {:?} {:?}", c, key_origin);
       }
       let mut packet = self.create_release_packet();
       packet.push_char(c, key_origin);
       self.add_buffer(&packet);
-      self.packets.push(packet);
+      self.queue(packet);
       Some(())
    }
 
@@ -378,6 +1202,39 @@ impl Keyboard {
       match key {
          BasicKey::Char(c, key_origin) => self.press_char(c, key_origin)?,
          BasicKey::Special(special) => self.press_special(special),
+         BasicKey::KeypadEnter => self.press_special(&SpecialKey::Enter),
+      }
+      Some(())
+   }
+
+   /// Send keystrokes typing a number, e.g. into a spreadsheet or calculator. Keypad origin types digits, sign and
+   /// separator on the keypad (so NumLock must be on), other origins use the main keyboard.
+   /// Returns None without typing anything if the number isn't finite or a key isn't available from the origin.
+   pub fn type_number(&mut self, n: f64, locale: NumberLocale, origin: KeyOrigin) -> Option<()> {
+      if !n.is_finite() {
+         return None;
+      }
+      let keys = n.to_string()
+         .chars()
+         .map(|c| number_key(c, locale, origin))
+         .collect::<Option<Vec<BasicKey>>>()?;
+      for key in &keys {
+         self.press_key(key)?;
+      }
+      Some(())
+   }
+
+   /// Send keystroke of a calculator operator
+   pub fn press_operator(&mut self, operator: Operator, origin: KeyOrigin) -> Option<()> {
+      self.press_key(&operator.to_key(&origin))
+   }
+
+   /// Send keystrokes of a calculation, typing each number followed by its operator,
+   /// e.g. [(12.5, Add), (3.0, Equals)]
+   pub fn type_calculation(&mut self, tape: &[(f64, Operator)], locale: NumberLocale, origin: KeyOrigin) -> Option<()> {
+      for (n, operator) in tape {
+         self.type_number(*n, locale, origin)?;
+         self.press_operator(*operator, origin)?;
       }
       Some(())
    }
@@ -386,75 +1243,903 @@ impl Keyboard {
    pub fn press_keycode(&mut self, key: u8) {
       #[cfg(feature = "debug")]
       {
-         println!("press {:08b}", key);
+         crate::logging::log_line!("press {}", crate::decode::keycode_name(key));
       }
       let mut packet = KeyPacket::new();
       packet.add_key(&[0, key]);
       self.add_buffer(&packet);
-      self.packets.push(packet);
+      self.queue(packet);
    }
 
-   /// Send keystrokes of keys in string
+   /// Send keystrokes of keys in string from the basic keyboard table, see [Keyboard::press_basic_string_checked]
+   #[deprecated(since = "0.1.0", note = "use press_basic_string_checked, which returns the clusters that couldn't be typed")]
    pub fn press_basic_string(&mut self, str: &str) {
-      #[cfg(feature = "debug")]
-      {
-         println!("press {:?}", str);
-      }
-      for c in str.chars() {
-         let mut packet = self.create_release_packet();
-         let kbytes = match c.to_kbytes(&KeyOrigin::Keyboard) {
-               Some(packet) => packet,
-               None => continue,
-         };
-         packet.add_key(&kbytes);
-         let needs_space = packet.get_key(&kbytes);
-         self.packets.push(packet);
+      self.press_basic_string_checked(str);
+   }
 
-         if needs_space {
-               self.packets.push(self.create_release_packet())
-         }
+   /// Send keystrokes of keys in string from the basic keyboard table, with the other typing options set (see
+   /// [Keyboard::set_type_options]) and the basic newline key if one is set.
+   /// Returns the grapheme clusters that couldn't be typed.
+   pub fn press_basic_string_checked(&mut self, str: &str) -> Vec<String> {
+      let options = TypeOptions {
+         layout: None,
+         newline: self.basic_newline.unwrap_or(self.newline),
+         ..self.type_options()
+      };
+      self.press_string_with(str, &options)
+   }
+
+   /// Press a char from the basic keyboard table
+   fn press_basic_char(&mut self, c: char, newline: NewlineKey) -> Option<()> {
+      if c == '\n' && self.newline_policy != Newline::Enter {
+         return self.press_newline(newline);
+      }
+      let kbytes = Keyboard::char_kbytes(c, newline)?;
+      self.press_kbytes(kbytes);
+      Some(())
+   }
+
+   /// Press the keystrokes of the newline policy, with key as the newline key
+   fn press_newline(&mut self, key: NewlineKey) -> Option<()> {
+      for packet in self.newline_policy.packets(key)? {
+         self.press_packet(packet);
+         self.queue(self.create_release_packet());
+      }
+      Some(())
+   }
+
+   /// Press and release a modifier byte and keycode
+   fn press_kbytes(&mut self, kbytes: [u8; 2]) {
+      let mut packet = self.create_release_packet();
+      packet.add_key(&kbytes);
+      let needs_space = packet.get_key(&kbytes);
+      self.queue(packet);
+
+      if needs_space {
+            self.queue(self.create_release_packet())
       }
    }
 
-   /// Send keystrokes of keys in string with layout support
+   /// Send keystrokes of keys in string with a layout, see [Keyboard::press_string_checked]
+   #[deprecated(since = "0.1.0", note = "use press_string_checked, which returns the clusters that couldn't be typed")]
    pub fn press_string(&mut self, layout_key: &str, str: &str) {
+      self.press_string_checked(layout_key, str);
+   }
+
+   /// Send keystrokes of keys in string with a layout, with the other typing options set (see
+   /// [Keyboard::set_type_options]). Returns the grapheme clusters that couldn't be typed.
+   pub fn press_string_checked(&mut self, layout_key: &str, str: &str) -> Vec<String> {
+      let options = TypeOptions { layout: Some(layout_key.to_string()), ..self.type_options() };
+      self.press_string_with(str, &options)
+   }
+
+   /// Send keystrokes of keys in string like [Keyboard::type_with], without the key table or bidi reordering
+   fn press_string_with(&mut self, str: &str, options: &TypeOptions) -> Vec<String> {
       #[cfg(feature = "debug")]
       {
-         println!("press {:?}", str);
+         crate::logging::log_line!("press {:?}", str);
       }
-      for c in str.chars() {
-         self.press(layout_key, c);
+      let key_table = self.key_table.take();
+      let unmappable = self.with_type_options(options, |keyboard| keyboard.type_checked(str));
+      self.key_table = key_table;
+      unmappable
+   }
+
+   /// Send keystrokes of keys in string using the configured layout and bidi order, see [Keyboard::set_type_options].
+   /// Returns the grapheme clusters that couldn't be typed.
+   pub fn type_string(&mut self, str: &str) -> Vec<String> {
+      let str = self.bidi_order.apply(str);
+      self.type_checked(&str)
+   }
+
+   /// Send keystrokes of keys in string with typing options, see [Keyboard::type_string]. The keyboard's own options
+   /// are restored afterwards, except the delay, which paces the next send before the keyboard's pacing is restored.
+   pub fn type_with(&mut self, str: &str, options: &TypeOptions) -> Vec<String> {
+      self.with_type_options(options, |keyboard| keyboard.type_string(str))
+   }
+
+   /// Type graphemes, typing nothing if any can't be typed and the error policy aborts
+   fn type_checked(&mut self, str: &str) -> Vec<String> {
+      if self.error_policy == TypeErrorPolicy::Abort {
+         let mut dry_run = Keyboard::new();
+         dry_run.key_table = self.key_table.clone();
+         dry_run.set_type_options(self.type_options());
+         let unmappable = dry_run.type_graphemes(str);
+         if !unmappable.is_empty() {
+            return unmappable;
+         }
+      }
+      self.type_graphemes(str)
+   }
+
+   /// Run f with typing options set, then restore the keyboard's own. The delay paces the next send.
+   fn with_type_options<R>(&mut self, options: &TypeOptions, f: impl FnOnce(&mut Keyboard) -> R) -> R {
+      let saved = self.type_options();
+      let restore_pacing = self.restore_pacing.unwrap_or(saved.delay);
+      self.set_type_options(options.clone());
+      let res = f(self);
+      let saved = TypeOptions { delay: options.delay, ..saved };
+      self.set_type_options(saved);
+      if options.delay != restore_pacing {
+         self.restore_pacing = Some(restore_pacing);
+      }
+      res
+   }
+
+   fn type_graphemes(&mut self, str: &str) -> Vec<String> {
+      #[cfg(feature = "debug")]
+      {
+         crate::logging::log_line!("type {:?}", str);
+      }
+      let mut unmappable = Vec::new();
+      for grapheme in str.graphemes(true) {
+         if self.type_grapheme(grapheme).is_none() {
+            unmappable.push(grapheme.to_string());
+         }
+      }
+      unmappable
+   }
+
+   /// Type a grapheme cluster with the key table or layout, falling back to the host's unicode input
+   fn type_grapheme(&mut self, grapheme: &str) -> Option<()> {
+      let start = self.packets.len();
+      let typed = grapheme_char(grapheme).and_then(|c| {
+         let table_kbytes = self.key_table.as_ref().and_then(|key_table| key_table.get(c));
+         let typed = match (table_kbytes, self.layout.take()) {
+            (Some(kbytes), layout) => {
+               self.layout = layout;
+               self.press_kbytes(kbytes);
+               Some(())
+            },
+            (None, Some(layout_key)) => {
+               let typed = self.press(&layout_key, c);
+               self.layout = Some(layout_key);
+               typed
+            },
+            (None, None) => self.press_basic_char(c, self.newline),
+         };
+         if typed.is_some() && self.caps_compensation && c.is_lowercase() != c.is_uppercase()
+            && self.led_states.get_state(&LEDState::CapsLock) {
+            self.compensate_caps(start);
+         }
+         typed
+      });
+      match (typed, self.unicode_fallback) {
+         (Some(()), _) => Some(()),
+         (None, Some(host)) => grapheme.chars().try_for_each(|c| self.press_unicode(host, c)),
+         (None, None) => None,
+      }
+   }
+
+   /// Invert Shift in the last key packet queued since start
+   fn compensate_caps(&mut self, start: usize) {
+      let release = self.create_release_packet();
+      let shift = Modifier::LeftShift.to_mkbyte() | Modifier::RightShift.to_mkbyte();
+      if let Some(packet) = self.packets[start..].iter_mut().rev().find(|packet| **packet != release) {
+         let modifiers = &mut packet.data[KEY_PACKET_MOD_IDX];
+         if *modifiers & shift != 0 {
+            *modifiers &= !shift;
+         } else {
+            *modifiers |= Modifier::LeftShift.to_mkbyte();
+         }
+      }
+   }
+
+   /// Enter a char through the unicode hex input of a host OS. Returns None if the host can't enter it.
+   fn press_unicode(&mut self, host: HostOS, c: char) -> Option<()> {
+      #[cfg(feature = "debug")]
+      {
+         crate::logging::log_line!("unicode {:?}", c);
+      }
+      match host {
+         HostOS::Linux => {
+            self.press_shortcut(&[Modifier::LeftControl, Modifier::LeftShift], &BasicKey::Char('u', KeyOrigin::Keyboard))?;
+            self.press_basic_string_checked(&format!("{:x}", c as u32));
+            self.press_special(&SpecialKey::Spacebar);
+         },
+         HostOS::Windows => {
+            let unit = u16::try_from(c as u32).ok()?;
+            self.hold_mod(&Modifier::LeftAlt);
+            self.press_key(&BasicKey::Char('+', KeyOrigin::Keypad))?;
+            self.press_basic_string_checked(&format!("{:x}", unit));
+            self.release_mod(&Modifier::LeftAlt);
+         },
+         HostOS::MacOS => {
+            self.hold_mod(&Modifier::LeftAlt);
+            for unit in c.encode_utf16(&mut [0; 2]) {
+               self.press_basic_string_checked(&format!("{:04x}", unit));
+            }
+            self.release_mod(&Modifier::LeftAlt);
+         },
+      }
+      self.queue(self.create_release_packet());
+      Some(())
+   }
+
+   /// Send keystrokes of keys in string using the configured layout, entering parts of it through a host IME as
+   /// decided by the IME strategy. Returns the grapheme clusters that couldn't be typed.
+   pub fn type_ime_string(&mut self, str: &str, ime: &dyn ImeStrategy) -> Vec<String> {
+      let mut unmappable = Vec::new();
+      for segment in ime.segments(str) {
+         match segment {
+            ImeSegment::Direct(text) => unmappable.extend(self.type_string(&text)),
+            ImeSegment::Ime(input) => {
+               unmappable.extend(self.type_string(&input.reading));
+               for key in &input.selection {
+                  self.press_key(key);
+               }
+            },
+         }
+      }
+      unmappable
+   }
+
+   /// Send keystrokes of keys in string like [Keyboard::type_ime_string] with typing options, see [Keyboard::type_with]
+   pub fn type_ime_with(&mut self, str: &str, ime: &dyn ImeStrategy, options: &TypeOptions) -> Vec<String> {
+      self.with_type_options(options, |keyboard| keyboard.type_ime_string(str, ime))
+   }
+
+   /// Take buffered keystrokes as packets, ending with a release packet, without sending them to a HID interface
+   pub fn take_packets(&mut self) -> Vec<KeyPacket> {
+      if self.packets.is_empty() {
+         return Vec::new();
+      }
+
+      self.queue(self.create_release_packet());
+      if let Some(latency) = &mut self.latency {
+         latency.discard_queued();
+      }
+      std::mem::take(&mut self.packets)
+   }
+
+   /// Flush Buffered keystrokes to HID interface, same as [Keyboard::flush]
+   pub fn send(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+      self.flush(hid)
+   }
+
+   /// Send buffered keystrokes to HID interface followed by the held keys, and empty the buffer.
+   ///
+   /// ```text
+   /// buffer [p1, p2], holding H  --flush-->  host sees p1, p2, H   buffer []
+   /// buffer [],       holding H  --flush-->  nothing sent          buffer []
+   /// ```
+   ///
+   /// Nothing is sent while muted or while the host is suspended, see [Keyboard::set_mute_policy] and [Keyboard::set_power_management].
+   pub fn flush(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+      self.flush_with(hid, None)
+   }
+
+   /// Flush like [Keyboard::flush], returning the lint warnings found before sending, see [Keyboard::set_lint]
+   pub fn flush_checked(&mut self, hid: &mut dyn HidBackend) -> io::Result<Vec<LintWarning>> {
+      let warnings = self.check_lint(&self.lint.unwrap_or_default())?;
+      self.send_buffered(hid, None)?;
+      Ok(warnings)
+   }
+
+   /// Lint the buffer, failing with the warnings if the config denies them
+   fn check_lint(&self, config: &LintConfig) -> io::Result<Vec<LintWarning>> {
+      let warnings = self.lint_with(config);
+      #[cfg(feature = "debug")]
+      for warning in &warnings {
+         crate::logging::log_line!("lint: {}", warning);
       }
+      if config.deny && !warnings.is_empty() {
+         let messages: Vec<String> = warnings.iter().map(|warning| warning.to_string()).collect();
+         return Err(io::Error::new(io::ErrorKind::InvalidInput, messages.join("; ")));
+      }
+      Ok(warnings)
+   }
+
+   /// Flush like [Keyboard::flush], checking a cancellation token between packets. If cancelled, every key is
+   /// released on the host, the buffer and held keys are cleared and a [cancel::Cancelled] error is returned.
+   pub fn flush_cancellable(&mut self, hid: &mut dyn HidBackend, cancel: &CancellationToken) -> io::Result<()> {
+      self.flush_with(hid, Some(cancel))
    }
 
-   /// Flush Buffered keystrokes to HID interface
-   pub fn send(&mut self, hid: &mut HID) -> io::Result<()> {
+   fn flush_with(&mut self, hid: &mut dyn HidBackend, cancel: Option<&CancellationToken>) -> io::Result<()> {
+      if let Some(config) = self.lint {
+         self.check_lint(&config)?;
+      }
+      self.send_buffered(hid, cancel)
+   }
+
+   fn send_buffered(&mut self, hid: &mut dyn HidBackend, cancel: Option<&CancellationToken>) -> io::Result<()> {
+      self.forget_stale_holds();
       if self.packets.len() == 0 {
          return Ok(());
       }
+      if self.is_muted() {
+         if self.mute_policy == MutePolicy::Drop {
+            // keep a release, so keys released while muted are released on the host once unmuted
+            self.packets.clear();
+            if let Some(latency) = &mut self.latency {
+               latency.discard_queued();
+            }
+            self.queue(self.create_release_packet());
+         }
+         return Ok(());
+      }
+      if self.host_suspended()? {
+         return Ok(());
+      }
 
-      self.packets.push(self.create_release_packet());
-      KeyPacket::send_all(&self.packets, hid)?;
+      self.expire_holds();
+      // refuse overflowing packets before queueing their release, so a refused flush leaves the buffer as it was
+      for packet in &self.packets {
+         self.encode_packet(packet)?;
+      }
+      self.queue(self.create_release_packet());
+      let written = match self.send_paced(hid, cancel) {
+         Err(e) if cancel::is_cancelled_error(&e) => {
+            self.packets.clear();
+            self.holding = KeyPacket::new();
+            if let Some(latency) = &mut self.latency {
+               latency.discard_queued();
+            }
+            return Err(e);
+         },
+         res => res?,
+      };
       self.packets.clear();
+      if let Some(latency) = &mut self.latency {
+         latency.record_written(&written);
+      }
+      self.last_send = Some(self.clock.now());
+      if let Some(pacing) = self.restore_pacing.take() {
+         self.pacing = pacing;
+      }
       Ok(())
    }
 
-   /// Send Buffered keystrokes to HID interface and keep buffered keystrokes
-   pub fn send_keep(&self, hid: &mut HID) -> io::Result<()> {
-      if self.packets.len() == 0 {
+   /// Send the buffered packets, with heartbeats adjusting the pacing if adaptive pacing is set
+   fn send_paced(&mut self, hid: &mut dyn HidBackend, cancel: Option<&CancellationToken>) -> io::Result<Vec<Instant>> {
+      let Some(adaptive) = self.adaptive_pacing else {
+         let packets = std::mem::take(&mut self.packets);
+         let written = self.send_packets(&packets, hid, cancel);
+         self.packets = packets;
+         return written;
+      };
+      let packets = std::mem::take(&mut self.packets);
+      let mut written = Vec::new();
+      match self.send_adaptive(&packets, adaptive, hid, cancel, &mut written) {
+         Ok(()) => {
+            self.packets = packets;
+            Ok(written)
+         },
+         Err(e) => {
+            // only the packets left to send stay buffered, with their queue times
+            self.packets = packets[written.len()..].to_vec();
+            if let Some(latency) = &mut self.latency {
+               latency.record_written(&written);
+            }
+            Err(e)
+         },
+      }
+   }
+
+   /// Send packets with heartbeats in between, collecting when each packet sent was written
+   fn send_adaptive(
+      &mut self,
+      packets: &[KeyPacket],
+      adaptive: AdaptivePacing,
+      hid: &mut dyn HidBackend,
+      cancel: Option<&CancellationToken>,
+      written: &mut Vec<Instant>,
+   ) -> io::Result<()> {
+      // heartbeats tap ScrollLock, so they only go after packets releasing every key
+      for i in (0..packets.len()).filter(|&i| packets[i].is_empty()) {
+         written.extend(self.send_packets(&packets[written.len()..=i], hid, cancel)?);
+         let now = self.clock.now();
+         if self.last_heartbeat.is_some_and(|last| now.saturating_duration_since(last) < adaptive.interval) {
+            continue;
+         }
+         let rtt = self.heartbeat(hid, adaptive.timeout)?;
+         self.last_heartbeat = Some(self.clock.now());
+         self.pacing = adaptive.adjust(self.pacing, rtt);
+         #[cfg(feature = "debug")]
+         {
+            crate::logging::log_line!("heartbeat {:?}, pacing {:?}", rtt, self.pacing);
+         }
+      }
+      written.extend(self.send_packets(&packets[written.len()..], hid, cancel)?);
+      Ok(())
+   }
+
+   /// Heartbeat like [crate::HID::heartbeat], timed on the keyboard's clock and tapping ScrollLock in its report
+   /// protocol. The LED states received on the way are kept, see [Keyboard::set_led_states].
+   fn heartbeat(&mut self, hid: &mut dyn HidBackend, timeout: Duration) -> io::Result<Option<Duration>> {
+      let before = hid.last_led_state();
+      let start = self.clock.now();
+      self.tap_scroll_lock(hid)?;
+      let Some(echoed) = self.wait_scroll_lock(hid, before, start + timeout)? else {
+         // the host may have toggled it without echoing, so tap it again to leave it as it was
+         self.tap_scroll_lock(hid)?;
+         return Ok(None);
+      };
+      let rtt = self.clock.now().saturating_duration_since(start);
+      self.tap_scroll_lock(hid)?;
+      self.wait_scroll_lock(hid, Some(echoed), self.clock.now() + timeout)?;
+      Ok(Some(rtt))
+   }
+
+   /// Tap ScrollLock and release it, in the keyboard's report protocol
+   fn tap_scroll_lock(&self, hid: &mut dyn HidBackend) -> io::Result<()> {
+      let tap = self.encode_packet(&KeyPacket::from_special(&SpecialKey::ScrollLock))?;
+      for report in tap.iter().chain(&self.release_reports()?) {
+         hid.send_key_packet(report)?;
+      }
+      Ok(())
+   }
+
+   /// Wait for an LED state with ScrollLock changed from before (or any LED state if before is unknown), keeping every
+   /// LED state received. None if none arrived by the deadline.
+   fn wait_scroll_lock(&mut self, hid: &mut dyn HidBackend, before: Option<u8>, deadline: Instant) -> io::Result<Option<u8>> {
+      let led = LEDState::ScrollLock;
+      loop {
+         let remaining = deadline.saturating_duration_since(self.clock.now());
+         let Some(data) = hid.receive_states_packet(remaining)? else {
+            return Ok(None);
+         };
+         self.led_states = LEDStatePacket::from_raw(data);
+         if before.is_none_or(|before| led.get_state(before) != led.get_state(data)) {
+            return Ok(Some(data));
+         }
+         if remaining.is_zero() {
+            return Ok(None);
+         }
+      }
+   }
+
+   /// Keycodes of the held keys, modifiers as LeftControl (0xE0) to RightMeta (0xE7)
+   fn held_keycodes(&self) -> Vec<u8> {
+      let modifiers = self.holding.data[KEY_PACKET_MOD_IDX];
+      (0..8)
+         .filter(|bit| modifiers & (1 << bit) != 0)
+         .map(|bit| SpecialKey::LeftControl.to_kbyte() + bit)
+         .chain(self.holding.usages())
+         .collect()
+   }
+
+   /// Track when each held key was first sent and stop holding keys held longer than the max hold.
+   /// Returns the keycodes released.
+   fn expire_holds(&mut self) -> Vec<u8> {
+      let max_hold = match self.max_hold {
+         Some(max_hold) => max_hold,
+         None => return Vec::new(),
+      };
+      let now = self.clock.now();
+      let held = self.held_keycodes();
+      self.held_since.retain(|keycode, _| held.contains(keycode));
+      let mut expired = Vec::new();
+      for keycode in held {
+         let since = *self.held_since.entry(keycode).or_insert(now);
+         if now.saturating_duration_since(since) >= max_hold {
+            expired.push(keycode);
+         }
+      }
+      for keycode in &expired {
+         let modifier = SpecialKey::LeftControl.to_kbyte();
+         match keycode.checked_sub(modifier) {
+            Some(bit) if bit < 8 => self.holding.data[KEY_PACKET_MOD_IDX] &= !(1 << bit),
+            _ => self.holding.remove_key(&[0, *keycode]),
+         }
+         self.held_since.remove(keycode);
+      }
+      #[cfg(feature = "debug")]
+      if !expired.is_empty() {
+         crate::logging::log_line!("warning: auto releasing {:02X?} after {:?}", expired, max_hold);
+      }
+      expired
+   }
+
+   /// When the first of the held keys and modifiers sent expires, None without a max hold or holds. Nothing releases
+   /// them unless [Keyboard::release_expired_holds] is called at or after it, or the keyboard is flushed.
+   pub fn next_hold_deadline(&self) -> Option<Instant> {
+      let max_hold = self.max_hold?;
+      let held = self.held_keycodes();
+      self.held_since.iter()
+         .filter(|(keycode, _)| held.contains(keycode))
+         .map(|(_, since)| *since + max_hold)
+         .min()
+   }
+
+   /// Release keys and modifiers held longer than the max hold (see [Keyboard::set_max_hold]) and send the release.
+   /// Call it periodically, e.g. whenever a relay's input read times out or at [Keyboard::next_hold_deadline].
+   /// Returns the keycodes released.
+   pub fn release_expired_holds(&mut self, hid: &mut dyn HidBackend) -> io::Result<Vec<u8>> {
+      let expired = self.expire_holds();
+      if !expired.is_empty() {
+         self.queue(self.create_release_packet());
+         self.send(hid)?;
+      }
+      Ok(expired)
+   }
+
+   /// Undo the n most recent key events, e.g. of a macro that was interrupted, by releasing the keys they pressed that
+   /// are still held. Returns the keys released. Needs a history, see [Keyboard::set_history_capacity].
+   pub fn undo_recent(&mut self, hid: &mut dyn HidBackend, n: usize) -> io::Result<KeyPacket> {
+      let unreleased = match &self.history {
+         Some(history) => history.unreleased(n),
+         None => return Err(io::Error::new(io::ErrorKind::Unsupported, "no key history kept")),
+      };
+      if !unreleased.is_empty() {
+         self.holding = &self.holding - &unreleased;
+         self.queue(self.create_release_packet());
+         self.send(hid)?;
+      }
+      Ok(unreleased)
+   }
+
+   /// Stuck modifier watchdog. If modifiers are held and nothing has been sent for longer than the
+   /// stuck modifier timeout, release them and return a warning listing the released modifiers.
+   pub fn release_stuck_modifiers(&mut self, hid: &mut dyn HidBackend) -> io::Result<Option<StuckModifiers>> {
+      let (timeout, last_send) = match (self.stuck_modifier_timeout, self.last_send) {
+         (Some(timeout), Some(last_send)) => (timeout, last_send),
+         _ => return Ok(None),
+      };
+      let idle = self.clock.now().saturating_duration_since(last_send);
+      let modifiers = Modifier::from_byte(self.holding.data[KEY_PACKET_MOD_IDX]);
+      if idle < timeout || modifiers.is_empty() {
+         return Ok(None);
+      }
+
+      #[cfg(feature = "debug")]
+      {
+         crate::logging::log_line!("warning: releasing stuck {:?} after {:?}", modifiers, idle);
+      }
+      for modifier in &modifiers {
+         self.holding.remove_mod(modifier);
+      }
+      self.queue(self.create_release_packet());
+      self.send(hid)?;
+      Ok(Some(StuckModifiers { modifiers, idle }))
+   }
+
+   /// Send a packet with only the held keys, leaving the buffer untouched. Restores the host's view of held keys,
+   /// e.g. after another writer or a dropped report.
+   ///
+   /// ```text
+   /// buffer [p1, p2], holding H  --reassert_holds-->  host sees H   buffer [p1, p2]
+   /// ```
+   pub fn reassert_holds(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+      if self.is_muted() {
          return Ok(());
       }
+      self.send_packets(&[self.create_release_packet()], hid, None)?;
+      Ok(())
+   }
 
-      KeyPacket::send_all(&self.packets, hid)?;
-      hid.send_key_packet(&self.create_release_packet().data)
+   /// Send Buffered keystrokes to HID interface and keep buffered keystrokes.
+   /// Prefer [Keyboard::flush] and [Keyboard::reassert_holds].
+   ///
+   /// ```text
+   /// buffer [p1, p2], holding H  --send_keep-->  host sees p1, p2, H   buffer [p1, p2]
+   /// ```
+   pub fn send_keep(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+      if self.packets.len() == 0 || self.is_muted() {
+         return Ok(());
+      }
+
+      let mut packets = self.packets.clone();
+      packets.push(self.create_release_packet());
+      self.send_packets(&packets, hid, None)?;
+      Ok(())
+   }
+
+   /// Flush buffered keystrokes, then press a key n times, sending each press and waiting dwell after it
+   pub fn tap_key(&mut self, hid: &mut dyn HidBackend, key: &BasicKey, n: usize, dwell: Duration) -> io::Result<()> {
+      self.send(hid)?;
+      for _ in 0..n {
+         self.press_key(key);
+         self.send(hid)?;
+         self.clock.sleep(dwell);
+      }
+      Ok(())
+   }
+
+   /// Switch tasks by holding Alt, tapping Tab n times with dwell after each tap so the host's
+   /// switcher can render, then releasing Alt. Alt is released even if sending a tap fails.
+   pub fn alt_tab(&mut self, hid: &mut dyn HidBackend, n: usize, dwell: Duration) -> io::Result<()> {
+      self.hold_mod(&Modifier::LeftAlt);
+      let res = self.tap_key(hid, &BasicKey::Special(SpecialKey::Tab), n, dwell);
+      self.release_mod(&Modifier::LeftAlt);
+      let release = self.send(hid);
+      res.and(release)
+   }
+
+   /// Send packets, recording them in the history and returning when each was written. A packet the key matrix
+   /// splits is written with its last part. If cancelled between packets, sends a release of every key and fails.
+   /// Every keystroke the keyboard sends goes through here or [Keyboard::send_release_all], heartbeat taps aside.
+   fn send_packets(&mut self, packets: &[KeyPacket], hid: &mut dyn HidBackend, cancel: Option<&CancellationToken>) -> io::Result<Vec<Instant>> {
+      let indexed = match &self.matrix {
+         Some(matrix) => matrix.apply_indexed(packets),
+         None => packets.iter().cloned().enumerate().collect(),
+      };
+      let reports = indexed.iter()
+         .map(|(i, packet)| self.encode_packet(packet).map(|reports| (*i, reports)))
+         .collect::<io::Result<Vec<_>>>()?;
+      let mut written = Vec::new();
+      for (n, (i, reports)) in reports.iter().enumerate() {
+         if let Some(Err(e)) = cancel.map(CancellationToken::check) {
+            self.send_release_all(hid)?;
+            return Err(e);
+         }
+         for report in reports {
+            hid.send_key_packet(report)?;
+         }
+         if indexed.get(n + 1).map(|(next, _)| *next) != Some(*i) {
+            written.push(Instant::now());
+            if let Some(history) = &mut self.history {
+               history.record(&packets[*i], self.clock.now());
+            }
+         }
+         match cancel {
+            // wakes early if cancelled, the next packet is then skipped
+            Some(cancel) => { let _ = self.clock.sleep_cancellable(self.pacing, cancel); },
+            None => self.clock.sleep(self.pacing),
+         }
+      }
+      Ok(written)
+   }
+
+   /// Send a packet releasing every key, in the keyboard's report protocol, recording it in the history
+   fn send_release_all(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+      for report in self.release_reports()? {
+         hid.send_key_packet(&report)?;
+      }
+      if let Some(history) = &mut self.history {
+         history.record(&KeyPacket::new(), self.clock.now());
+      }
+      Ok(())
+   }
+
+   /// Reports a packet is sent as in the keyboard's report protocol
+   fn encode_packet(&self, packet: &KeyPacket) -> io::Result<Vec<Vec<u8>>> {
+      match self.protocol {
+         ReportProtocol::Bitmap => Ok(vec![packet.as_bytes().to_vec()]),
+         ReportProtocol::Boot => Ok(packet.to_boot_reports(self.boot_overflow)?.iter().map(|report| report.to_vec()).collect()),
+      }
+   }
+
+   /// Take buffered keystrokes like [Keyboard::take_packets], checked and encoded the way [Keyboard::flush] sends them:
+   /// linted, rewritten by the key matrix and converted to the report protocol. Each packet comes with its reports.
+   /// Nothing is taken if a check fails.
+   pub(crate) fn take_encoded(&mut self) -> io::Result<Vec<(KeyPacket, Vec<Vec<u8>>)>> {
+      if let Some(config) = self.lint {
+         self.check_lint(&config)?;
+      }
+      let mut packets = self.packets.clone();
+      if !packets.is_empty() {
+         packets.push(self.create_release_packet());
+      }
+      if let Some(matrix) = &self.matrix {
+         packets = matrix.apply(&packets);
+      }
+      let encoded = packets.into_iter()
+         .map(|packet| self.encode_packet(&packet).map(|reports| (packet, reports)))
+         .collect::<io::Result<Vec<_>>>()?;
+      self.take_packets();
+      Ok(encoded)
+   }
+
+   /// Reports releasing every key in the keyboard's report protocol
+   pub(crate) fn release_reports(&self) -> io::Result<Vec<Vec<u8>>> {
+      self.encode_packet(&KeyPacket::new())
+   }
+}
+
+/// Key held by [Keyboard::hold_key], released when dropped. Dereferences to the keyboard holding it.
+pub struct HeldKey<'a> {
+    keyboard: &'a mut Keyboard,
+    key: BasicKey,
+    keycode: u8,
+    forgotten: bool,
+}
+
+impl HeldKey<'_> {
+   /// Held key
+   pub fn key(&self) -> BasicKey {
+      self.key
+   }
+
+   /// Keycode of the held key
+   pub fn keycode(&self) -> u8 {
+      self.keycode
+   }
+
+   /// Queue the release now, same as dropping the guard
+   pub fn release(self) {}
+
+   /// Keep the key held after the guard is gone, e.g. for a hold released later with [Keyboard::release_key].
+   /// Returns the keycode.
+   pub fn forget(mut self) -> u8 {
+      self.forgotten = true;
+      self.keycode
+   }
+}
+
+impl Deref for HeldKey<'_> {
+   type Target = Keyboard;
+
+   fn deref(&self) -> &Keyboard {
+      self.keyboard
    }
 }
 
+impl DerefMut for HeldKey<'_> {
+   fn deref_mut(&mut self) -> &mut Keyboard {
+      self.keyboard
+   }
+}
+
+impl Drop for HeldKey<'_> {
+   fn drop(&mut self) {
+      if !self.forgotten {
+         self.keyboard.release_key(&self.key);
+      }
+   }
+}
+
+/// Get the key typing a char of a formatted number
+fn number_key(c: char, locale: NumberLocale, origin: KeyOrigin) -> Option<BasicKey> {
+   let key = match (c, origin) {
+      ('.', _) if locale == NumberLocale::Host => BasicKey::Special(SpecialKey::DecimalSeparator),
+      ('.', KeyOrigin::Keypad) if locale == NumberLocale::Comma => BasicKey::Special(SpecialKey::Comma),
+      ('.', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_DotAndDelete),
+      ('.', _) if locale == NumberLocale::Comma => BasicKey::Char(',', origin),
+      ('0', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_0AndInsert),
+      ('1', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_1AndEnd),
+      ('2', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_2AndDownArrow),
+      ('3', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_3AndPageDn),
+      ('4', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_4AndLeftArrow),
+      ('5', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_5),
+      ('6', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_6AndRightArrow),
+      ('7', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_7AndHome),
+      ('8', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_8AndUpArrow),
+      ('9', KeyOrigin::Keypad) => BasicKey::Special(SpecialKey::_9AndPageUp),
+      (c, origin) => BasicKey::Char(c, origin),
+   };
+   match key {
+      BasicKey::Char(c, origin) => c.to_kbytes(&origin).map(|_| key),
+      BasicKey::Special(_) | BasicKey::KeypadEnter => Some(key),
+   }
+}
+
+/// Encode a string as the key packets typing it, ending with a release packet, without a [Keyboard] or HID interface.
+/// None uses the basic keyboard table. Grapheme clusters that can't be typed are skipped.
+pub fn encode_string(layout_key: Option<&str>, str: &str) -> Vec<KeyPacket> {
+   let mut keyboard = Keyboard::new();
+   keyboard.set_layout(layout_key);
+   keyboard.type_string(str);
+   keyboard.take_packets()
+}
+
+/// Modifier by name, see [KeyPacket::parse]
+fn modifier_from_name(name: &str) -> Option<Modifier> {
+   let modifier = match name.to_ascii_uppercase().as_str() {
+      "LCTRL" | "CTRL" | "LEFTCONTROL" => Modifier::LeftControl,
+      "LSHIFT" | "SHIFT" | "LEFTSHIFT" => Modifier::LeftShift,
+      "LALT" | "ALT" | "LEFTALT" => Modifier::LeftAlt,
+      "LGUI" | "GUI" | "LMETA" | "META" | "LEFTMETA" => Modifier::LeftMeta,
+      "RCTRL" | "RIGHTCONTROL" => Modifier::RightControl,
+      "RSHIFT" | "RIGHTSHIFT" => Modifier::RightShift,
+      "RALT" | "RIGHTALT" => Modifier::RightAlt,
+      "RGUI" | "RMETA" | "RIGHTMETA" => Modifier::RightMeta,
+      _ => return None,
+   };
+   Some(modifier)
+}
+
+/// Get the char a grapheme cluster can be typed as. Multi codepoint clusters can't be typed
+/// key by key, except CRLF which is typed as a newline.
+fn grapheme_char(grapheme: &str) -> Option<char> {
+   if grapheme == "\r\n" {
+      return Some('\n');
+   }
+   let mut chars = grapheme.chars();
+   match (chars.next(), chars.next()) {
+      (Some(c), None) => Some(c),
+      _ => None,
+   }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// Key Packet abstraction
 pub struct KeyPacket {
     data: [u8; KEY_PACKET_LEN],
 }
 
+impl FromIterator<BasicKey> for KeyPacket {
+    fn from_iter<I: IntoIterator<Item = BasicKey>>(iter: I) -> Self {
+        KeyPacket::from_keys(iter)
+    }
+}
+
+impl BitOr for &KeyPacket {
+    type Output = KeyPacket;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl BitAnd for &KeyPacket {
+    type Output = KeyPacket;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.intersection(rhs)
+    }
+}
+
+impl Sub for &KeyPacket {
+    type Output = KeyPacket;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.difference(rhs)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// Human readable form of a key packet: the modifier byte and the set usages
+struct CompactKeyPacket {
+    modifiers: u8,
+    usages: Vec<u8>,
+}
+
+/// Serializes as the modifier byte and list of set usages in human readable formats (e.g. TOML),
+/// and as the raw packet bytes in binary formats.
+impl Serialize for KeyPacket {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            CompactKeyPacket {
+                modifiers: self.data[KEY_PACKET_MOD_IDX],
+                usages: self.keycodes(),
+            }.serialize(serializer)
+        } else {
+            serializer.serialize_bytes(&self.data)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyPacket {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let compact = CompactKeyPacket::deserialize(deserializer)?;
+            let mut packet = KeyPacket::new();
+            packet.data[KEY_PACKET_MOD_IDX] = compact.modifiers;
+            for usage in compact.usages {
+                packet.push_key_keycode(usage);
+            }
+            Ok(packet)
+        } else {
+            deserializer.deserialize_bytes(KeyPacketBytesVisitor)
+        }
+    }
+}
+
+struct KeyPacketBytesVisitor;
+
+impl<'de> de::Visitor<'de> for KeyPacketBytesVisitor {
+    type Value = KeyPacket;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} key packet bytes", KEY_PACKET_LEN)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<KeyPacket, E> {
+        let data = bytes.try_into().map_err(|_| E::invalid_length(bytes.len(), &self))?;
+        Ok(KeyPacket { data })
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<KeyPacket, A::Error> {
+        let mut data = [0; KEY_PACKET_LEN];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        Ok(KeyPacket { data })
+    }
+}
+
 impl KeyPacket {
    /// New
    pub fn new() -> KeyPacket {
@@ -531,6 +2216,105 @@ impl KeyPacket {
       packet
    }
 
+   /// Create from a readable chord for tests and fixtures, e.g. "LSHIFT+a", "LeftControl+ReturnEnter" or "CTRL++".
+   /// Parts are joined by '+' and can be modifiers (LCTRL, LSHIFT, LALT, LGUI, their R variants, CTRL, SHIFT, ALT, GUI
+   /// or [Modifier] names), [SpecialKey] names, single chars typed on the main keyboard or hex keycodes (0x04).
+   /// Names are case insensitive. Returns None if a part isn't recognised.
+   pub fn parse(chord: &str) -> Option<KeyPacket> {
+      let mut parts: Vec<&str> = chord.split('+').collect();
+      if chord.ends_with("++") || chord == "+" {
+         parts.truncate(parts.len() - 2);
+         parts.push("+");
+      }
+      let mut packet = KeyPacket::new();
+      for part in parts {
+         let part = part.trim();
+         let mut chars = part.chars();
+         if let (Some(c), None) = (chars.next(), chars.next()) {
+            packet.add_key(&c.to_kbytes(&KeyOrigin::Keyboard)?);
+         } else if let Some(modifier) = modifier_from_name(part) {
+            packet.data[KEY_PACKET_MOD_IDX] |= modifier.to_mkbyte();
+         } else if let Some(hex) = part.strip_prefix("0x").or_else(|| part.strip_prefix("0X")) {
+            packet.push_key_keycode(u8::from_str_radix(hex, 16).ok()?);
+         } else {
+            let special = (0..=u8::MAX as u32)
+               .map(SpecialKey::from)
+               .find(|special| format!("{:?}", special).eq_ignore_ascii_case(part))?;
+            packet.push_special(&special);
+         }
+      }
+      Some(packet)
+   }
+
+   /// Create from raw bytes of a packet or an 8 byte boot protocol report
+   pub fn from_bytes(data: &[u8]) -> Option<KeyPacket> {
+      let mut packet = KeyPacket::new();
+      match data.len() {
+         KEY_PACKET_LEN => packet.data.copy_from_slice(data),
+         BOOT_REPORT_LEN => {
+            packet.data[KEY_PACKET_MOD_IDX] = data[0];
+            for key in &data[BOOT_REPORT_KEY_IDX..] {
+               if *key != 0x00 {
+                  packet.push_key_keycode(*key);
+               }
+            }
+         },
+         _ => return None,
+      }
+      Some(packet)
+   }
+
+   /// Create from keys, e.g. a HashSet of keys. Chars that can't be typed are skipped.
+   pub fn from_keys<I: IntoIterator<Item = BasicKey>>(keys: I) -> KeyPacket {
+      let mut packet = KeyPacket::new();
+      for key in keys {
+         packet.push_key(&key);
+      }
+      packet
+   }
+
+   fn combine(&self, other: &KeyPacket, op: impl Fn(u8, u8) -> u8) -> KeyPacket {
+      let mut packet = KeyPacket::new();
+      for (i, byte) in packet.data.iter_mut().enumerate() {
+         *byte = op(self.data[i], other.data[i]);
+      }
+      packet
+   }
+
+   /// Keys and modifiers in either packet
+   pub fn union(&self, other: &KeyPacket) -> KeyPacket {
+      self.combine(other, |a, b| a | b)
+   }
+
+   /// Keys and modifiers in both packets
+   pub fn intersection(&self, other: &KeyPacket) -> KeyPacket {
+      self.combine(other, |a, b| a & b)
+   }
+
+   /// Keys and modifiers in this packet but not the other
+   pub fn difference(&self, other: &KeyPacket) -> KeyPacket {
+      self.combine(other, |a, b| a & !b)
+   }
+
+   /// Check if the packet has no keys or modifiers set
+   pub fn is_empty(&self) -> bool {
+      self.data.iter().all(|byte| *byte == 0)
+   }
+
+   /// Modifiers set in the packet
+   pub fn modifiers(&self) -> Vec<Modifier> {
+      Modifier::from_byte(self.data[KEY_PACKET_MOD_IDX])
+   }
+
+   /// Iterate over the keycodes (usages) of all keys set in the packet
+   pub fn usages(&self) -> impl Iterator<Item = u8> + '_ {
+      self.data[KEY_PACKET_KEY_IDX..].iter()
+         .enumerate()
+         .flat_map(|(i, byte)| (0..8)
+            .filter(move |bit| byte & (1 << bit) != 0)
+            .map(move |bit| (i * 8 + bit) as u8))
+   }
+
    /// Check if packet contains the keystroke for a char
    pub fn contains_char(&self, key: char, key_origin: &KeyOrigin) -> bool {
       let kbyte = match key.to_kbytes(key_origin) {
@@ -586,11 +2370,22 @@ impl KeyPacket {
       self.add_key(&[modifier, key]);
    }
 
+   /// New from a modifier byte and key keycodes
+   pub fn from_usages(modifiers: u8, usages: impl IntoIterator<Item = u8>) -> KeyPacket {
+      let mut packet = KeyPacket::new();
+      for usage in usages {
+         packet.push_key_keycode(usage);
+      }
+      packet.data[KEY_PACKET_MOD_IDX] = modifiers;
+      packet
+   }
+
    /// Add key to packet
    pub fn push_key(&mut self, key: &BasicKey) -> Option<u8> {
       match key {
          BasicKey::Char(c, key_origin) => self.push_char(c, key_origin),
          BasicKey::Special(special) => self.push_special(special),
+         BasicKey::KeypadEnter => self.push_special(&SpecialKey::Enter),
       }
    }
 
@@ -608,13 +2403,57 @@ impl KeyPacket {
       Some(kbytes)
    }
 
+   /// Raw packet bytes
+   pub fn as_bytes(&self) -> &[u8] {
+      &self.data
+   }
+
+   /// Convert to an 8 byte boot protocol report.
+   /// More than 6 keys produces the phantom state (all keys set to ErrorRollOver).
+   pub fn to_boot_report(&self) -> [u8; BOOT_REPORT_LEN] {
+      let mut report = [0x00; BOOT_REPORT_LEN];
+      report[0] = self.data[KEY_PACKET_MOD_IDX];
+      let keycodes = self.keycodes();
+      if keycodes.len() > BOOT_REPORT_MAX_KEYS {
+         report[BOOT_REPORT_KEY_IDX..].fill(BOOT_REPORT_ROLLOVER);
+      } else {
+         report[BOOT_REPORT_KEY_IDX..BOOT_REPORT_KEY_IDX + keycodes.len()].copy_from_slice(&keycodes);
+      }
+      report
+   }
+
+   /// Convert to 8 byte boot protocol reports, handling more than 6 keys with an overflow policy
+   pub fn to_boot_reports(&self, overflow: BootOverflow) -> io::Result<Vec<[u8; BOOT_REPORT_LEN]>> {
+      let keycodes = self.keycodes();
+      if keycodes.len() <= BOOT_REPORT_MAX_KEYS {
+         return Ok(vec![self.to_boot_report()]);
+      }
+      match overflow {
+         BootOverflow::Rollover => Ok(vec![self.to_boot_report()]),
+         BootOverflow::Split => Ok(keycodes.chunks(BOOT_REPORT_MAX_KEYS)
+            .map(|chunk| {
+               let mut report = [0x00; BOOT_REPORT_LEN];
+               report[0] = self.data[KEY_PACKET_MOD_IDX];
+               report[BOOT_REPORT_KEY_IDX..BOOT_REPORT_KEY_IDX + chunk.len()].copy_from_slice(chunk);
+               report
+            })
+            .collect()),
+         BootOverflow::Error => Err(io::Error::new(io::ErrorKind::InvalidInput, RolloverExceeded { keys: keycodes.len() })),
+      }
+   }
+
+   /// Keycodes of all keys set in the packet
+   pub fn keycodes(&self) -> Vec<u8> {
+      self.usages().collect()
+   }
+
    /// Send packet to hid interface
-   pub fn send(&self, hid: &mut HID) -> io::Result<()> {
+   pub fn send(&self, hid: &mut dyn HidBackend) -> io::Result<()> {
       hid.send_key_packet(&self.data)
    }
 
    /// Send a list of packets to hid interface
-   pub fn send_all(packets: &Vec<KeyPacket>, hid: &mut HID) -> io::Result<()> {
+   pub fn send_all(packets: &Vec<KeyPacket>, hid: &mut dyn HidBackend) -> io::Result<()> {
       for packet in packets {
          packet.send(hid)?;
       }
@@ -622,35 +2461,836 @@ impl KeyPacket {
       Ok(())
    }
 
-   /// Print packet data
+   /// Packet data as hex
+   pub fn to_hex(&self) -> String {
+      hex(&self.data)
+   }
+
+   /// Print packet data, see [crate::logging]
    pub fn print_data(data: &[u8]) {
-      for data in data {
-         print!("{:02x}", data);
-      }
-      println!();
+      crate::logging::log_line!("{}", hex(data));
    }
 
-   /// Print packet
+   /// Print packet, see [crate::logging]
    pub fn print_packet(packet: &KeyPacket) {
-      for data in packet.data {
-         print!("{:02x}", data);
-      }
-      println!();
+      crate::logging::log_line!("{}", packet.to_hex());
    }
 
-   /// Print packets
+   /// Print packets, see [crate::logging]
    pub fn print_packets(packets: &Vec<KeyPacket>) {
       for packet in packets {
-         for data in packet.data {
-               print!("{:02x}", data);
-         }
-         println!();
+         crate::logging::log_line!("{}", packet.to_hex());
       }
    }
+}
 
-   fn clone(&self) -> KeyPacket {
-      KeyPacket {
-         data: self.data.clone(),
-      }
-   }
+fn hex(data: &[u8]) -> String {
+   data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn type_options() {
+        use super::{Keyboard, LEDStatePacket, Modifier, TypeErrorPolicy, TypeOptions};
+        use crate::config::HostOS;
+
+        let mut keyboard = Keyboard::new();
+        let mut options = TypeOptions { error_policy: TypeErrorPolicy::Abort, ..TypeOptions::default() };
+        assert_eq!(keyboard.type_with("a\u{e9}", &options), vec!["\u{e9}"]);
+        assert!(keyboard.take_packets().is_empty());
+        // the options only last for the call
+        assert_eq!(keyboard.type_options(), TypeOptions::default());
+        assert_eq!(keyboard.press_basic_string_checked("a\u{e9}"), vec!["\u{e9}"]);
+        assert!(!keyboard.take_packets().is_empty());
+        keyboard.set_type_options(options.clone());
+        assert_eq!(keyboard.press_basic_string_checked("a\u{e9}"), vec!["\u{e9}"]);
+        assert!(keyboard.take_packets().is_empty());
+        keyboard.set_type_options(TypeOptions::default());
+
+        options.unicode_fallback = Some(HostOS::Linux);
+        assert!(keyboard.type_with("a\u{e9}", &options).is_empty());
+        let packets = keyboard.take_packets();
+        let ctrl_shift = Modifier::all_to_byte(&[Modifier::LeftControl, Modifier::LeftShift]);
+        assert!(packets.iter().any(|packet| packet.as_bytes()[0] == ctrl_shift));
+
+        options.caps_compensation = true;
+        keyboard.set_led_states(LEDStatePacket::from_raw(0x02));
+        keyboard.type_with("aA1", &options);
+        let modifiers: Vec<u8> = keyboard.take_packets().iter()
+            .filter(|packet| !packet.is_empty())
+            .map(|packet| packet.as_bytes()[0])
+            .collect();
+        assert_eq!(modifiers, vec![Modifier::LeftShift.to_mkbyte(), 0, 0]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn grapheme_clusters() {
+        use super::Keyboard;
+
+        let mut keyboard = Keyboard::new();
+        assert_eq!(keyboard.press_basic_string_checked("ae\u{301}b"), vec!["e\u{301}"]);
+        let checked = keyboard.take_packets();
+        // the deprecated entry point types the same, dropping the unmappable clusters
+        keyboard.press_basic_string("ae\u{301}b");
+        assert_eq!(keyboard.take_packets(), checked);
+        keyboard.press_string("LAYOUT_US_ENGLISH", "ab");
+        let layout = keyboard.take_packets();
+        assert!(keyboard.press_string_checked("LAYOUT_US_ENGLISH", "ab").is_empty());
+        assert_eq!(keyboard.take_packets(), layout);
+    }
+
+    #[test]
+    fn bidi_order() {
+        use super::{BidiOrder, Keyboard};
+
+        assert_eq!(BidiOrder::Logical.apply("ab \u{5d0}\u{5d1}\u{5d2}"), "ab \u{5d0}\u{5d1}\u{5d2}");
+        assert_eq!(BidiOrder::Visual.apply("ab \u{5d0}\u{5d1}\u{5d2}\n\u{5d3}\u{5d4} 12"), "ab \u{5d2}\u{5d1}\u{5d0}\n12 \u{5d4}\u{5d3}");
+
+        let mut keyboard = Keyboard::new();
+        keyboard.set_bidi_order(BidiOrder::Visual);
+        // hebrew isn't in the basic table, the skipped letters show the order they were typed in
+        assert_eq!(keyboard.type_string("a \u{5d0}\u{5d1}"), vec!["\u{5d1}", "\u{5d0}"]);
+    }
+
+    #[test]
+    fn hold_shortcut() {
+        use super::{BasicKey, KeyOrigin, KeyPacket, Keyboard, Modifier, SpecialKey};
+
+        let mut keyboard = Keyboard::new();
+        let tab = BasicKey::Special(SpecialKey::Tab);
+        keyboard.hold_shortcut(&[Modifier::LeftAlt], &tab).unwrap();
+        let alt_tab = KeyPacket::parse("LALT+Tab").unwrap();
+        // take_packets ends with the held state
+        assert_eq!(keyboard.take_packets(), vec![alt_tab.clone(); 2]);
+
+        // pressed keys are added to the held chord without staying held
+        keyboard.press_key(&BasicKey::Char('a', KeyOrigin::Keyboard)).unwrap();
+        assert_eq!(keyboard.take_packets(), vec![&alt_tab | &KeyPacket::parse("a").unwrap(), alt_tab]);
+
+        keyboard.release_shortcut(&[Modifier::LeftAlt], &tab);
+        assert_eq!(keyboard.take_packets(), vec![KeyPacket::parse("LALT").unwrap(), KeyPacket::new(), KeyPacket::new()]);
+    }
+
+    #[test]
+    fn packet_set_operations() {
+        use std::collections::HashSet;
+
+        use super::{BasicKey, KeyOrigin, KeyPacket, Modifier, SpecialKey};
+
+        let keys: HashSet<BasicKey> = [
+            BasicKey::Char('a', KeyOrigin::Keyboard),
+            BasicKey::Char('A', KeyOrigin::Keyboard),
+            BasicKey::Special(SpecialKey::Tab),
+        ].into_iter().collect();
+        let ab_tab = KeyPacket::from_keys(keys.clone());
+        assert_eq!(ab_tab, keys.into_iter().collect());
+        assert_eq!(ab_tab, KeyPacket::parse("LSHIFT+a+Tab").unwrap());
+        assert_eq!(ab_tab.modifiers(), vec![Modifier::LeftShift]);
+        assert_eq!(ab_tab.usages().collect::<Vec<u8>>(), vec![0x04, 0x2B]);
+
+        let b = KeyPacket::parse("b").unwrap();
+        let union = ab_tab.union(&b);
+        assert_eq!(union, &ab_tab | &b);
+        assert_eq!(union.usages().count(), 3);
+        assert_eq!(union.intersection(&b), b);
+        assert_eq!(&union & &b, b);
+        assert_eq!(union.difference(&b), ab_tab);
+        assert_eq!(&union - &b, ab_tab);
+        assert!(ab_tab.intersection(&b).is_empty());
+        assert!(!ab_tab.is_empty());
+    }
+
+    #[test]
+    fn boot_report_splitting() {
+        use super::{BootOverflow, KeyPacket};
+
+        let six = KeyPacket::parse("LCTRL+a+b+c+d+e+f").unwrap();
+        for overflow in [BootOverflow::Rollover, BootOverflow::Split, BootOverflow::Error] {
+            assert_eq!(six.to_boot_reports(overflow).unwrap(), vec![[0x01, 0, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09]]);
+        }
+
+        // keys are split in keycode order, whatever order they were added in, with the modifiers in every report
+        let thirteen = KeyPacket::parse("LCTRL+m+l+k+j+i+h+g+f+e+d+c+b+a").unwrap();
+        assert_eq!(thirteen.to_boot_reports(BootOverflow::Split).unwrap(), vec![
+            [0x01, 0, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09],
+            [0x01, 0, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F],
+            [0x01, 0, 0x10, 0, 0, 0, 0, 0],
+        ]);
+        assert_eq!(thirteen.to_boot_reports(BootOverflow::Error).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn keypad_numbers() {
+        use super::{BasicKey, KeyOrigin, Keyboard, NumberLocale, Operator, SpecialKey};
+
+        let pressed = |keys: &[BasicKey]| {
+            let mut keyboard = Keyboard::new();
+            for key in keys {
+                keyboard.press_key(key).unwrap();
+            }
+            keyboard.take_packets()
+        };
+        let mut keyboard = Keyboard::new();
+        keyboard.type_number(-12.5, NumberLocale::Comma, KeyOrigin::Keypad).unwrap();
+        assert_eq!(keyboard.take_packets(), pressed(&[
+            BasicKey::Char('-', KeyOrigin::Keypad),
+            BasicKey::Special(SpecialKey::_1AndEnd),
+            BasicKey::Special(SpecialKey::_2AndDownArrow),
+            BasicKey::Special(SpecialKey::Comma),
+            BasicKey::Special(SpecialKey::_5),
+        ]));
+        keyboard.type_number(0.5, NumberLocale::Host, KeyOrigin::Keyboard).unwrap();
+        assert_eq!(keyboard.take_packets(), pressed(&[
+            BasicKey::Char('0', KeyOrigin::Keyboard),
+            BasicKey::Special(SpecialKey::DecimalSeparator),
+            BasicKey::Char('5', KeyOrigin::Keyboard),
+        ]));
+        assert!(keyboard.type_number(f64::NAN, NumberLocale::Dot, KeyOrigin::Keypad).is_none());
+        assert!(keyboard.take_packets().is_empty());
+
+        keyboard.type_calculation(&[(4.0, Operator::Add), (2.0, Operator::Enter)], NumberLocale::Dot, KeyOrigin::Keypad).unwrap();
+        assert_eq!(keyboard.take_packets(), pressed(&[
+            BasicKey::Special(SpecialKey::_4AndLeftArrow),
+            BasicKey::Char('+', KeyOrigin::Keypad),
+            BasicKey::Special(SpecialKey::_2AndDownArrow),
+            BasicKey::Special(SpecialKey::Enter),
+        ]));
+    }
+
+    #[test]
+    fn encodes_without_keyboard() {
+        use super::{encode_string, KeyPacket};
+
+        let packets = encode_string(None, "aA");
+        assert_eq!(packets, encode_string(None, "aA"));
+        assert_eq!(packets.last(), Some(&KeyPacket::new()));
+        let pressed: Vec<KeyPacket> = packets.into_iter().filter(|packet| !packet.is_empty()).collect();
+        assert_eq!(pressed, vec![KeyPacket::parse("a").unwrap(), KeyPacket::parse("LSHIFT+a").unwrap()]);
+        // clusters that can't be typed are skipped
+        assert_eq!(encode_string(None, "a\u{e9}A"), encode_string(None, "aA"));
+        assert!(encode_string(None, "").is_empty());
+    }
+
+    #[test]
+    fn layout_cache() {
+        use super::Keyboard;
+
+        let typed = |capacity: usize, layout: &str| {
+            let mut keyboard = Keyboard::new();
+            keyboard.set_layout_cache_capacity(capacity);
+            let unmappable = keyboard.press_string_checked(layout, "hello, world\u{e9}");
+            assert!(keyboard.layout_cache.keys.len() <= capacity);
+            (keyboard.take_packets(), unmappable)
+        };
+        let uncached = typed(0, "LAYOUT_US_ENGLISH");
+        assert!(!uncached.0.is_empty());
+        assert_eq!(typed(1024, "LAYOUT_US_ENGLISH"), uncached);
+        // a full cache is cleared rather than growing
+        assert_eq!(typed(3, "LAYOUT_US_ENGLISH"), uncached);
+
+        let mut keyboard = Keyboard::new();
+        assert!(keyboard.press_string_checked("LAYOUT_US_ENGLISH", "a").is_empty());
+        // lookups aren't shared between layouts
+        assert_eq!(keyboard.press_string_checked("nope", "a"), vec!["a"]);
+    }
+
+    #[test]
+    fn parse_chords() {
+        use super::{KeyOrigin, KeyPacket, Modifier, SpecialKey};
+
+        assert_eq!(KeyPacket::parse("LSHIFT+a"), KeyPacket::from_char(&'A', &KeyOrigin::Keyboard));
+        let mut packet = KeyPacket::from_special(&SpecialKey::ReturnEnter);
+        packet.push_modifier(&Modifier::LeftControl);
+        assert_eq!(KeyPacket::parse("ctrl+returnenter").unwrap().as_bytes()[0], packet.as_bytes()[0]);
+        assert_eq!(KeyPacket::parse("CTRL++").unwrap().usages().count(), 1);
+        assert_eq!(KeyPacket::parse("0x04"), KeyPacket::parse("a"));
+        assert_eq!(KeyPacket::parse("LSHIFT+nope"), None);
+    }
+
+    #[test]
+    fn newline_key() {
+        use super::{Keyboard, NewlineKey, SpecialKey, encode_string};
+
+        let usages = |packets: Vec<super::KeyPacket>| packets.iter().flat_map(|packet| packet.usages().collect::<Vec<_>>()).collect::<Vec<_>>();
+        assert_eq!(usages(encode_string(None, "\n")), vec![SpecialKey::ReturnEnter.to_kbyte()]);
+
+        let mut keyboard = Keyboard::new();
+        keyboard.set_newline_key(NewlineKey::KeypadEnter);
+        keyboard.press_basic_string_checked("\r\n");
+        assert_eq!(usages(keyboard.take_packets()), vec![SpecialKey::Enter.to_kbyte()]);
+    }
+
+    #[test]
+    fn keypad_enter_routing() {
+        use super::{BasicKey, KeyOrigin, KeyPacket, Keyboard, NewlineKey, Operator, SpecialKey};
+
+        let (enter, ret) = (SpecialKey::Enter.to_kbyte(), SpecialKey::ReturnEnter.to_kbyte());
+        assert_eq!((enter, ret), (0x58, 0x28));
+        let mut packet = KeyPacket::new();
+        assert_eq!(packet.push_key(&BasicKey::KeypadEnter), Some(enter));
+        assert_eq!(Operator::Enter.to_key(&KeyOrigin::Keypad), BasicKey::KeypadEnter);
+        assert_eq!(Operator::Enter.to_key(&KeyOrigin::Keyboard), BasicKey::Special(SpecialKey::ReturnEnter));
+
+        let usages = |keyboard: &mut Keyboard| keyboard.take_packets().iter().flat_map(|packet| packet.usages().collect::<Vec<_>>()).collect::<Vec<_>>();
+        let mut keyboard = Keyboard::new();
+        keyboard.press_key(&BasicKey::KeypadEnter);
+        keyboard.set_basic_newline_key(Some(NewlineKey::KeypadEnter));
+        keyboard.press_basic_string_checked("\n");
+        keyboard.type_string("\n");
+        assert_eq!(usages(&mut keyboard), vec![enter, enter, ret]);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn compact_serialization() {
+        use serde::{Serialize, Deserialize};
+
+        use super::{KeyOrigin, KeyPacket};
+
+        #[derive(Serialize, Deserialize)]
+        struct Macro {
+            packets: Vec<KeyPacket>,
+        }
+
+        let packets = vec![KeyPacket::from_char(&'A', &KeyOrigin::Keyboard).unwrap(), KeyPacket::new()];
+        let str = toml::to_string(&Macro { packets: packets.clone() }).unwrap();
+        assert_eq!(str, "[[packets]]\nmodifiers = 2\nusages = [4]\n\n[[packets]]\nmodifiers = 0\nusages = []\n");
+        assert_eq!(toml::from_str::<Macro>(&str).unwrap().packets, packets);
+    }
+
+    /// Packets written to the debug HID's keyboard file
+    #[cfg(feature = "debug")]
+    fn written_packets(hid: &crate::HID) -> Vec<super::KeyPacket> {
+        std::fs::read(hid.get_keyboard_path()).unwrap()
+            .chunks(super::KEY_PACKET_LEN)
+            .map(|data| super::KeyPacket::from_bytes(data).unwrap())
+            .collect()
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn flush_and_reassert_holds() {
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket, Modifier};
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        let a = KeyPacket::from_char(&'a', &KeyOrigin::Keyboard).unwrap();
+        let mut shift = KeyPacket::new();
+        shift.push_modifier(&Modifier::LeftShift);
+
+        keyboard.hold_mod(&Modifier::LeftShift);
+        keyboard.press_key(&BasicKey::Char('a', KeyOrigin::Keyboard));
+        keyboard.flush(&mut hid).unwrap();
+        let flushed = written_packets(&hid);
+        assert_eq!(flushed.last(), Some(&shift));
+        assert!(flushed.contains(&(&a | &shift)));
+
+        // an empty buffer sends nothing
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(written_packets(&hid).len(), flushed.len());
+
+        // reasserting sends only the held keys and keeps the buffer
+        keyboard.press_key(&BasicKey::Char('b', KeyOrigin::Keyboard));
+        keyboard.reassert_holds(&mut hid).unwrap();
+        let reasserted = written_packets(&hid);
+        assert_eq!(reasserted.len(), flushed.len() + 1);
+        assert_eq!(reasserted.last(), Some(&shift));
+        assert!(!keyboard.take_packets().is_empty());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn mute_drops_or_defers() {
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket, MutePolicy};
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.hold_key(&BasicKey::Char('a', KeyOrigin::Keyboard)).unwrap().forget();
+        keyboard.flush(&mut hid).unwrap();
+        let mut held = written_packets(&hid);
+        keyboard.set_muted(true);
+        keyboard.release_key(&BasicKey::Char('a', KeyOrigin::Keyboard));
+        keyboard.press_key(&BasicKey::Char('b', KeyOrigin::Keyboard));
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(written_packets(&hid), held);
+        // only the release survives the mute
+        keyboard.set_muted(false);
+        keyboard.flush(&mut hid).unwrap();
+        held.extend([KeyPacket::new(), KeyPacket::new()]);
+        assert_eq!(written_packets(&hid), held);
+
+        let mut hid = HID::new("", "", "").unwrap();
+        keyboard.set_muted(true);
+        keyboard.set_mute_policy(MutePolicy::Defer);
+        keyboard.press_key(&BasicKey::Char('a', KeyOrigin::Keyboard));
+        keyboard.flush(&mut hid).unwrap();
+        assert!(written_packets(&hid).is_empty());
+        keyboard.set_muted(false);
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(written_packets(&hid).len(), 2);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn cancelled_flush_releases() {
+        use super::{KeyOrigin, Keyboard, KeyPacket, Modifier};
+        use crate::{cancel::{is_cancelled_error, CancellationToken}, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        let cancel = CancellationToken::new();
+        keyboard.hold_mod(&Modifier::LeftShift);
+        keyboard.press_basic_string_checked("abc");
+        keyboard.flush_cancellable(&mut hid, &cancel).unwrap();
+        let flushed = written_packets(&hid).len();
+
+        keyboard.press_basic_string_checked("abc");
+        cancel.cancel();
+        let err = keyboard.flush_cancellable(&mut hid, &cancel).unwrap_err();
+        assert!(is_cancelled_error(&err));
+        assert_eq!(written_packets(&hid)[flushed..], [KeyPacket::new()]);
+        assert!(keyboard.take_packets().is_empty());
+        keyboard.press_key(&super::BasicKey::Char('a', KeyOrigin::Keyboard));
+        assert_eq!(keyboard.take_packets().last(), Some(&KeyPacket::new()));
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn watchdog_on_virtual_clock() {
+        use std::{sync::Arc, time::Duration};
+
+        use super::{Keyboard, Modifier};
+        use crate::{clock::VirtualClock, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_clock(Arc::new(clock.clone()));
+        keyboard.set_pacing(Duration::from_secs(1));
+        keyboard.set_stuck_modifier_timeout(Some(Duration::from_secs(30)));
+        keyboard.mute(Duration::from_secs(10));
+        assert!(keyboard.is_muted());
+        clock.advance(Duration::from_secs(10));
+        assert!(!keyboard.is_muted());
+
+        keyboard.hold_mod(&Modifier::LeftShift);
+        keyboard.press_basic_string_checked("ab");
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(10) + Duration::from_secs(written_packets(&hid).len() as u64));
+        assert!(keyboard.release_stuck_modifiers(&mut hid).unwrap().is_none());
+        clock.advance(Duration::from_secs(30));
+        let stuck = keyboard.release_stuck_modifiers(&mut hid).unwrap().unwrap();
+        assert_eq!(stuck.modifiers, vec![Modifier::LeftShift]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn auto_release_holds() {
+        use std::{sync::Arc, time::Duration};
+
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket, Modifier};
+        use crate::{clock::{Clock, VirtualClock}, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_clock(Arc::new(clock.clone()));
+        keyboard.set_max_hold(Some(Duration::from_secs(5)));
+        keyboard.hold_mod(&Modifier::LeftShift);
+        keyboard.flush(&mut hid).unwrap();
+        clock.advance(Duration::from_secs(3));
+        keyboard.hold_key(&BasicKey::Char('a', KeyOrigin::Keyboard)).unwrap().forget();
+        keyboard.flush(&mut hid).unwrap();
+        assert!(keyboard.release_expired_holds(&mut hid).unwrap().is_empty());
+        assert_eq!(keyboard.next_hold_deadline(), Some(clock.now() + Duration::from_secs(2)));
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(keyboard.release_expired_holds(&mut hid).unwrap(), vec![0xE1]);
+        assert_eq!(written_packets(&hid).last(), KeyPacket::parse("a").as_ref());
+        assert_eq!(keyboard.next_hold_deadline(), Some(clock.now() + Duration::from_secs(3)));
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(keyboard.release_expired_holds(&mut hid).unwrap(), vec![0x04]);
+        assert_eq!(written_packets(&hid).last(), Some(&KeyPacket::new()));
+        assert_eq!(keyboard.next_hold_deadline(), None);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn self_test_reports_health() {
+        use super::{InterfaceHealth, Keyboard, KeyPacket};
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let report = Keyboard::new().self_test(&mut hid);
+        assert_eq!(report.keyboard, InterfaceHealth::Ok);
+        assert_eq!(report.mouse, InterfaceHealth::Ok);
+        assert_eq!(report.led, InterfaceHealth::NoResponse);
+        assert!(report.is_healthy());
+        assert_eq!(written_packets(&hid), vec![KeyPacket::new()]);
+        assert_eq!(std::fs::read(hid.get_mouse_path()).unwrap(), vec![0; 5]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn undo_recent_events() {
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket, Modifier};
+        use crate::{decode::KeyAction, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        assert!(keyboard.undo_recent(&mut hid, 1).is_err());
+        keyboard.set_history_capacity(Some(16));
+        keyboard.press_key(&BasicKey::Char('x', KeyOrigin::Keyboard));
+        keyboard.hold_mod(&Modifier::LeftControl);
+        keyboard.hold_key(&BasicKey::Char('c', KeyOrigin::Keyboard)).unwrap().forget();
+        keyboard.flush(&mut hid).unwrap();
+        let recent = keyboard.recent_events(2);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[1].action, KeyAction::Press);
+
+        assert_eq!(keyboard.undo_recent(&mut hid, 2).unwrap(), KeyPacket::parse("CTRL+c").unwrap());
+        assert_eq!(written_packets(&hid).last(), Some(&KeyPacket::new()));
+        assert!(keyboard.undo_recent(&mut hid, 2).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn lint_before_flush() {
+        use super::{Keyboard, Modifier};
+        use crate::{lint::{LintConfig, LintRule}, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.press_modifier(&Modifier::LeftMeta);
+        let warnings = keyboard.flush_checked(&mut hid).unwrap();
+        assert_eq!(warnings.iter().map(|warning| warning.rule).collect::<Vec<_>>(), vec![LintRule::ModifierOnly]);
+        assert_eq!(written_packets(&hid).len(), 3);
+
+        keyboard.set_lint(Some(LintConfig { deny: true, ..LintConfig::default() }));
+        keyboard.press_modifier(&Modifier::LeftMeta);
+        let err = keyboard.flush(&mut hid).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(keyboard.lint().len(), 1);
+        assert_eq!(written_packets(&hid).len(), 3);
+        assert_eq!(keyboard.flush(&mut hid).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+        keyboard.set_lint(Some(LintConfig { deny: true, modifier_only: false, ..LintConfig::default() }));
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(written_packets(&hid).len(), 6);
+    }
+
+    #[test]
+    fn held_key_guard() {
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket};
+
+        let mut keyboard = Keyboard::new();
+        {
+            let mut held = keyboard.hold_key(&BasicKey::Char('w', KeyOrigin::Keyboard)).unwrap();
+            held.type_string("x");
+            assert_eq!(held.held(), &KeyPacket::parse("w").unwrap());
+        }
+        assert!(keyboard.held().is_empty());
+        let packets = keyboard.take_packets();
+        assert_eq!(packets.first(), KeyPacket::parse("w").as_ref());
+        assert_eq!(packets.last(), Some(&KeyPacket::new()));
+
+        let keycode = keyboard.hold_key(&BasicKey::Char('w', KeyOrigin::Keyboard)).unwrap().forget();
+        assert_eq!(keyboard.held().keycodes(), vec![keycode]);
+        keyboard.hold_key(&BasicKey::Char('s', KeyOrigin::Keyboard)).unwrap().release();
+        assert_eq!(keyboard.held(), &KeyPacket::parse("w").unwrap());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn boot_overflow_policies() {
+        use super::{is_rollover_exceeded_error, BootOverflow, Keyboard, KeyPacket, ReportProtocol};
+        use crate::HID;
+
+        let seven = KeyPacket::parse("LSHIFT+a+b+c+d+e+f+g").unwrap();
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_protocol(ReportProtocol::Boot);
+        keyboard.press_packet(seven.clone());
+        keyboard.flush(&mut hid).unwrap();
+        let written = std::fs::read(hid.get_keyboard_path()).unwrap();
+        assert_eq!(written.chunks(8).collect::<Vec<_>>(), vec![&[0x02, 0, 1, 1, 1, 1, 1, 1][..], &[0; 8][..]]);
+
+        let mut hid = HID::new("", "", "").unwrap();
+        keyboard.set_boot_overflow(BootOverflow::Error);
+        keyboard.press_packet(seven.clone());
+        let err = keyboard.flush(&mut hid).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(is_rollover_exceeded_error(&err));
+        assert!(std::fs::read(hid.get_keyboard_path()).unwrap().is_empty());
+
+        keyboard.set_boot_overflow(BootOverflow::Split);
+        keyboard.flush(&mut hid).unwrap();
+        let written = std::fs::read(hid.get_keyboard_path()).unwrap();
+        assert_eq!(written.chunks(8).collect::<Vec<_>>(), vec![
+            &[0x02, 0, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09][..],
+            &[0x02, 0, 0x0A, 0, 0, 0, 0, 0][..],
+            &[0; 8][..],
+        ]);
+
+        assert_eq!(seven.to_boot_reports(BootOverflow::Rollover).unwrap(), vec![[0x02, 0, 1, 1, 1, 1, 1, 1]]);
+    }
+
+    #[test]
+    fn newline_policy() {
+        use super::{Keyboard, KeyPacket, Newline, NewlineKey};
+
+        let typed = |keyboard: &mut Keyboard| keyboard.take_packets().into_iter().filter(|packet| !packet.is_empty()).collect::<Vec<_>>();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_newline_policy(Newline::ShiftEnter);
+        keyboard.type_string("a\nb");
+        assert_eq!(typed(&mut keyboard), vec![
+            KeyPacket::parse("a").unwrap(),
+            KeyPacket::parse("LSHIFT+ReturnEnter").unwrap(),
+            KeyPacket::parse("b").unwrap(),
+        ]);
+
+        keyboard.set_newline_key(NewlineKey::KeypadEnter);
+        keyboard.set_newline_policy(Newline::CtrlEnter);
+        keyboard.press_basic_string_checked("\r\n");
+        assert_eq!(typed(&mut keyboard), vec![Newline::CtrlEnter.packets(NewlineKey::KeypadEnter).unwrap()[0].clone()]);
+
+        keyboard.set_newline_policy(Newline::Custom(vec!["End".to_string(), "LSHIFT+ReturnEnter".to_string()]));
+        assert_eq!(keyboard.type_options().newline_policy, *keyboard.newline_policy());
+        keyboard.type_string("\n");
+        assert_eq!(typed(&mut keyboard), vec![KeyPacket::parse("End").unwrap(), KeyPacket::parse("LSHIFT+ReturnEnter").unwrap()]);
+
+        keyboard.set_newline_policy(Newline::Custom(vec!["nope".to_string()]));
+        assert_eq!(keyboard.type_string("\n"), vec!["\n".to_string()]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn adaptive_pacing_heartbeats() {
+        use std::time::Duration;
+
+        use super::{Keyboard, KeyPacket, SpecialKey};
+        use crate::{pacing::AdaptivePacing, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        let adaptive = AdaptivePacing { max: Duration::from_millis(1), interval: Duration::from_secs(60), ..AdaptivePacing::default() };
+        keyboard.set_adaptive_pacing(Some(adaptive));
+        keyboard.press_basic_string_checked("ab");
+        keyboard.flush(&mut hid).unwrap();
+        // the unconnected host never echoes, so the first heartbeat slows down to the most and the next isn't due yet
+        assert_eq!(keyboard.pacing(), Duration::from_millis(1));
+        let scroll_lock = KeyPacket::from_special(&SpecialKey::ScrollLock);
+        let packets = written_packets(&hid);
+        assert_eq!(packets.iter().filter(|packet| **packet == scroll_lock).count(), 2);
+        assert_eq!(packets[1..4], [KeyPacket::new(), scroll_lock, KeyPacket::new()]);
+    }
+
+    #[test]
+    fn adaptive_pacing_heartbeats_in_protocol() {
+        use std::{collections::VecDeque, io, sync::Arc, time::Duration};
+
+        use super::{Keyboard, KeyPacket, LEDState, ReportProtocol, SpecialKey};
+        use crate::{clock::VirtualClock, pacing::AdaptivePacing, HidBackend};
+
+        /// Echoes queued LED states, failing ScrollLock taps while unplugged
+        struct Host {
+            keys: Vec<Vec<u8>>,
+            echoes: VecDeque<u8>,
+            unplugged: bool,
+        }
+
+        impl HidBackend for Host {
+            fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                if self.unplugged && data == KeyPacket::from_special(&SpecialKey::ScrollLock).to_boot_report() {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "unplugged"));
+                }
+                self.keys.push(data.to_vec());
+                Ok(())
+            }
+
+            fn send_mouse_packet(&mut self, _data: &[u8]) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+                Ok(self.echoes.pop_front())
+            }
+        }
+
+        let mut keyboard = Keyboard::new();
+        keyboard.set_protocol(ReportProtocol::Boot);
+        keyboard.set_clock(Arc::new(VirtualClock::new()));
+        keyboard.set_adaptive_pacing(Some(AdaptivePacing { interval: Duration::from_secs(60), ..AdaptivePacing::default() }));
+        keyboard.press_basic_string_checked("ab");
+
+        let mut host = Host { keys: Vec::new(), echoes: VecDeque::new(), unplugged: true };
+        assert_eq!(keyboard.flush(&mut host).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        let sent = host.keys.len();
+        assert!(sent > 0);
+
+        // CapsLock stays on in both echoes
+        host.unplugged = false;
+        host.echoes.extend([0x06, 0x02]);
+        keyboard.flush(&mut host).unwrap();
+        assert!(host.keys.iter().all(|report| report.len() == 8));
+        let a = KeyPacket::parse("a").unwrap().to_boot_report().to_vec();
+        assert_eq!(host.keys.iter().filter(|report| **report == a).count(), 1);
+        assert_eq!(host.keys[..sent].iter().filter(|report| **report == a).count(), 1);
+        assert!(keyboard.led_state(&LEDState::CapsLock));
+        assert!(!keyboard.led_state(&LEDState::ScrollLock));
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn latency_per_packet() {
+        use super::{Keyboard, KeyPacket};
+        use crate::{matrix::{GhostPolicy, KeyMatrix}, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.press_basic_string_checked("ab");
+        keyboard.set_latency_mode(true);
+        keyboard.press_basic_string_checked("c");
+        keyboard.flush(&mut hid).unwrap();
+        let latency = keyboard.latency().unwrap();
+        assert_eq!(latency.timings().len(), written_packets(&hid).len());
+        assert_eq!(latency.queued(), 0);
+
+        // the matrix splits the chord in two, which is timed as one packet
+        let mut hid = HID::new("", "", "").unwrap();
+        keyboard.set_matrix(Some(KeyMatrix::from_rows(&[&[0x04, 0x05], &[0x06, 0x07]], GhostPolicy::Reorder)));
+        keyboard.set_latency_mode(true);
+        keyboard.press_packet(KeyPacket::parse("a+b+c").unwrap());
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(written_packets(&hid).len(), 3);
+        assert_eq!(keyboard.latency().unwrap().timings().len(), 2);
+        assert_eq!(keyboard.latency().unwrap().queued(), 0);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn alt_tab_holds_alt_between_tabs() {
+        use std::{sync::Arc, time::Duration};
+
+        use super::{Keyboard, KeyPacket};
+        use crate::{clock::VirtualClock, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_clock(Arc::new(clock.clone()));
+        keyboard.alt_tab(&mut hid, 2, Duration::from_millis(300)).unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_millis(600));
+
+        let (alt, alt_tab) = (KeyPacket::parse("LALT").unwrap(), KeyPacket::parse("LALT+Tab").unwrap());
+        let mut written = written_packets(&hid);
+        written.dedup();
+        assert_eq!(written, vec![alt.clone(), alt_tab.clone(), alt.clone(), alt_tab, alt, KeyPacket::new()]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn releases_only_stuck_modifiers() {
+        use std::{sync::Arc, time::Duration};
+
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket, Modifier};
+        use crate::{clock::VirtualClock, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_clock(Arc::new(clock.clone()));
+        keyboard.hold_mod(&Modifier::LeftControl);
+        keyboard.hold_key(&BasicKey::Char('a', KeyOrigin::Keyboard)).unwrap().forget();
+        keyboard.send(&mut hid).unwrap();
+        // no timeout, no watchdog
+        clock.advance(Duration::from_secs(60));
+        assert!(keyboard.release_stuck_modifiers(&mut hid).unwrap().is_none());
+
+        keyboard.set_stuck_modifier_timeout(Some(Duration::from_secs(30)));
+        // sending resets the idle time
+        keyboard.press_key(&BasicKey::Char('b', KeyOrigin::Keyboard)).unwrap();
+        keyboard.send(&mut hid).unwrap();
+        clock.advance(Duration::from_secs(29));
+        assert!(keyboard.release_stuck_modifiers(&mut hid).unwrap().is_none());
+        clock.advance(Duration::from_secs(1));
+        let stuck = keyboard.release_stuck_modifiers(&mut hid).unwrap().unwrap();
+        assert_eq!(stuck.modifiers, vec![Modifier::LeftControl]);
+        assert_eq!(stuck.idle, Duration::from_secs(30));
+        // held keys stay down, only the modifiers are released
+        assert_eq!(written_packets(&hid).last(), KeyPacket::parse("a").as_ref());
+        assert!(keyboard.release_stuck_modifiers(&mut hid).unwrap().is_none());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn attach_syncs_last_led_state() {
+        use std::{io::Write, time::Duration};
+
+        use super::{Keyboard, LEDState};
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        assert!(!keyboard.attach(&mut hid, Duration::ZERO).unwrap());
+
+        let mut leds = tempfile::NamedTempFile::new().unwrap();
+        leds.write_all(&[0x02]).unwrap();
+        hid.set_state_data(leds.path().to_str().unwrap()).unwrap();
+        assert!(keyboard.attach(&mut hid, Duration::ZERO).unwrap());
+        assert!(keyboard.led_state(&LEDState::CapsLock));
+        assert_eq!(hid.last_led_state(), Some(0x02));
+
+        // the host only reported once, a keyboard attached later still gets it
+        let mut late = Keyboard::new();
+        assert!(late.attach(&mut hid, Duration::ZERO).unwrap());
+        assert!(late.led_state(&LEDState::CapsLock));
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn type_with_paces_next_send() {
+        use std::time::Duration;
+
+        use super::{Keyboard, TypeOptions};
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_layout(Some("nope"));
+        let options = TypeOptions { delay: Duration::from_millis(1), ..TypeOptions::default() };
+        assert!(keyboard.type_with("a", &options).is_empty());
+        assert_eq!(keyboard.type_options().layout.as_deref(), Some("nope"));
+        assert_eq!(keyboard.pacing(), Duration::from_millis(1));
+        keyboard.flush(&mut hid).unwrap();
+        assert_eq!(keyboard.pacing(), Duration::ZERO);
+        assert_eq!(keyboard.type_with("a", &TypeOptions { layout: Some("nope".to_string()), ..options }), vec!["a"]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn history_records_every_send() {
+        use super::{BasicKey, KeyOrigin, Keyboard, KeyPacket, Modifier};
+        use crate::{decode::KeyAction, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_history_capacity(Some(16));
+        keyboard.hold_mod(&Modifier::LeftShift);
+        keyboard.reassert_holds(&mut hid).unwrap();
+        let recent = keyboard.recent_events(16);
+        assert_eq!(recent.len(), 1);
+        assert_eq!((recent[0].action, &recent[0].keys), (KeyAction::Press, &KeyPacket::parse("LSHIFT").unwrap()));
+
+        keyboard.press_key(&BasicKey::Char('a', KeyOrigin::Keyboard));
+        keyboard.send_keep(&mut hid).unwrap();
+        let recent = keyboard.recent_events(16);
+        assert_eq!(recent.len(), 3);
+        assert_eq!((recent[1].action, &recent[1].keys), (KeyAction::Press, &KeyPacket::parse("a").unwrap()));
+        assert_eq!((recent[2].action, &recent[2].keys), (KeyAction::Release, &KeyPacket::parse("a").unwrap()));
+    }
 }
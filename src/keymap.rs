@@ -0,0 +1,299 @@
+#![warn(missing_docs)]
+
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::key::Modifier;
+
+/// Named keysyms shared by console keymaps and XKB, besides single chars and Unicode keysyms
+const KEYSYMS: &[(&str, char)] = &[
+    ("space", ' '), ("exclam", '!'), ("quotedbl", '"'), ("numbersign", '#'), ("dollar", '$'), ("percent", '%'),
+    ("ampersand", '&'), ("apostrophe", '\''), ("quoteright", '\''), ("parenleft", '('), ("parenright", ')'),
+    ("asterisk", '*'), ("plus", '+'), ("comma", ','), ("minus", '-'), ("period", '.'), ("slash", '/'),
+    ("zero", '0'), ("one", '1'), ("two", '2'), ("three", '3'), ("four", '4'), ("five", '5'), ("six", '6'),
+    ("seven", '7'), ("eight", '8'), ("nine", '9'), ("colon", ':'), ("semicolon", ';'), ("less", '<'),
+    ("equal", '='), ("greater", '>'), ("question", '?'), ("at", '@'), ("bracketleft", '['), ("backslash", '\\'),
+    ("bracketright", ']'), ("asciicircum", '^'), ("underscore", '_'), ("grave", '`'), ("quoteleft", '`'),
+    ("braceleft", '{'), ("bar", '|'), ("braceright", '}'), ("asciitilde", '~'),
+    ("nobreakspace", '\u{A0}'), ("exclamdown", '¡'), ("cent", '¢'), ("sterling", '£'), ("currency", '¤'),
+    ("yen", '¥'), ("brokenbar", '¦'), ("section", '§'), ("diaeresis", '¨'), ("copyright", '©'),
+    ("ordfeminine", 'ª'), ("guillemotleft", '«'), ("notsign", '¬'), ("registered", '®'), ("degree", '°'),
+    ("plusminus", '±'), ("twosuperior", '²'), ("threesuperior", '³'), ("acute", '´'), ("mu", 'µ'),
+    ("paragraph", '¶'), ("periodcentered", '·'), ("cedilla", '¸'), ("onesuperior", '¹'), ("masculine", 'º'),
+    ("guillemotright", '»'), ("onequarter", '¼'), ("onehalf", '½'), ("threequarters", '¾'), ("questiondown", '¿'),
+    ("multiply", '×'), ("division", '÷'), ("ssharp", 'ß'), ("EuroSign", '€'), ("euro", '€'),
+    ("agrave", 'à'), ("aacute", 'á'), ("acircumflex", 'â'), ("atilde", 'ã'), ("adiaeresis", 'ä'), ("aring", 'å'),
+    ("ae", 'æ'), ("ccedilla", 'ç'), ("egrave", 'è'), ("eacute", 'é'), ("ecircumflex", 'ê'), ("ediaeresis", 'ë'),
+    ("igrave", 'ì'), ("iacute", 'í'), ("icircumflex", 'î'), ("idiaeresis", 'ï'), ("eth", 'ð'), ("ntilde", 'ñ'),
+    ("ograve", 'ò'), ("oacute", 'ó'), ("ocircumflex", 'ô'), ("otilde", 'õ'), ("odiaeresis", 'ö'), ("oslash", 'ø'),
+    ("ugrave", 'ù'), ("uacute", 'ú'), ("ucircumflex", 'û'), ("udiaeresis", 'ü'), ("yacute", 'ý'), ("thorn", 'þ'),
+    ("ydiaeresis", 'ÿ'),
+    ("Agrave", 'À'), ("Aacute", 'Á'), ("Acircumflex", 'Â'), ("Atilde", 'Ã'), ("Adiaeresis", 'Ä'), ("Aring", 'Å'),
+    ("AE", 'Æ'), ("Ccedilla", 'Ç'), ("Egrave", 'È'), ("Eacute", 'É'), ("Ecircumflex", 'Ê'), ("Ediaeresis", 'Ë'),
+    ("Igrave", 'Ì'), ("Iacute", 'Í'), ("Icircumflex", 'Î'), ("Idiaeresis", 'Ï'), ("ETH", 'Ð'), ("Ntilde", 'Ñ'),
+    ("Ograve", 'Ò'), ("Oacute", 'Ó'), ("Ocircumflex", 'Ô'), ("Otilde", 'Õ'), ("Odiaeresis", 'Ö'), ("Ooblique", 'Ø'),
+    ("Ugrave", 'Ù'), ("Uacute", 'Ú'), ("Ucircumflex", 'Û'), ("Udiaeresis", 'Ü'), ("Yacute", 'Ý'), ("THORN", 'Þ'),
+];
+
+/// Console keymap modifier bits, see keymaps(5)
+const CONSOLE_SHIFT: usize = 1;
+const CONSOLE_ALTGR: usize = 2;
+
+/// Resolve a keysym name, e.g. "a", "exclam", "U+00e9" or "U00E9". Dead keys and function keysyms resolve to None.
+fn keysym_char(keysym: &str) -> Option<char> {
+    // console keymaps mark letters affected by CapsLock with a leading +
+    let keysym = keysym.strip_prefix('+').filter(|keysym| !keysym.is_empty()).unwrap_or(keysym);
+    let mut chars = keysym.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c);
+    }
+    let hex = keysym.strip_prefix("U+").or_else(|| keysym.strip_prefix('U'));
+    if let Some(c) = hex.and_then(|hex| u32::from_str_radix(hex, 16).ok()).and_then(char::from_u32) {
+        return Some(c);
+    }
+    KEYSYMS.iter().find(|(name, _)| *name == keysym).map(|(_, c)| *c)
+}
+
+/// Convert a Linux input event keycode of the main key block to a HID keyboard usage
+fn evdev_usage(keycode: u16) -> Option<u8> {
+    const LETTERS: [(u16, u8); 26] = [
+        (16, 0x14), (17, 0x1A), (18, 0x08), (19, 0x15), (20, 0x17), (21, 0x1C), (22, 0x18), (23, 0x0C), (24, 0x12),
+        (25, 0x13), (30, 0x04), (31, 0x16), (32, 0x07), (33, 0x09), (34, 0x0A), (35, 0x0B), (36, 0x0D), (37, 0x0E),
+        (38, 0x0F), (44, 0x1D), (45, 0x1B), (46, 0x06), (47, 0x19), (48, 0x05), (49, 0x11), (50, 0x10),
+    ];
+    match keycode {
+        2..=11 => Some(0x1E + (keycode - 2) as u8),
+        12 => Some(0x2D),
+        13 => Some(0x2E),
+        15 => Some(0x2B),
+        26 => Some(0x2F),
+        27 => Some(0x30),
+        28 => Some(0x28),
+        39 => Some(0x33),
+        40 => Some(0x34),
+        41 => Some(0x35),
+        43 => Some(0x31),
+        51 => Some(0x36),
+        52 => Some(0x37),
+        53 => Some(0x38),
+        57 => Some(0x2C),
+        86 => Some(0x64),
+        _ => LETTERS.iter().find(|(evdev, _)| *evdev == keycode).map(|(_, usage)| *usage),
+    }
+}
+
+/// Convert an XKB key name of the main key block (e.g. AC01) to a Linux input event keycode
+fn xkb_evdev(name: &str) -> Option<u16> {
+    let row = |offset: u16, count: u16| {
+        name.get(2..).and_then(|n| n.parse::<u16>().ok()).filter(|n| (1..=count).contains(n)).map(|n| offset + n - 1)
+    };
+    match name {
+        "TLDE" => Some(41),
+        "BKSL" => Some(43),
+        "LSGT" => Some(86),
+        "SPCE" => Some(57),
+        _ if name.starts_with("AE") => row(2, 12),
+        _ if name.starts_with("AD") => row(16, 12),
+        _ if name.starts_with("AC") => row(30, 11),
+        _ if name.starts_with("AB") => row(44, 10),
+        _ => None,
+    }
+}
+
+/// Modifier byte of a shift level: 1 plain, 2 Shift, 3 AltGr, 4 AltGr+Shift
+fn level_modifiers(level: usize) -> Option<u8> {
+    let shift = Modifier::LeftShift.to_mkbyte();
+    let altgr = Modifier::RightAlt.to_mkbyte();
+    match level {
+        1 => Some(0x00),
+        2 => Some(shift),
+        3 => Some(altgr),
+        4 => Some(altgr | shift),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Char to keystroke table generated at runtime from a Linux console keymap or XKB symbols, for layouts
+/// the baked-in tables lack. Use it with [crate::key::Keyboard::set_key_table].
+pub struct KeyTable {
+    keys: HashMap<char, [u8; 2]>,
+}
+
+impl KeyTable {
+    /// New, empty
+    pub fn new() -> KeyTable {
+        KeyTable::default()
+    }
+
+    /// Map a char to a modifier byte and keycode. A char already mapped keeps the mapping with fewer modifiers.
+    pub fn insert(&mut self, c: char, kbytes: [u8; 2]) {
+        let modifiers = |kbytes: &[u8; 2]| kbytes[0].count_ones();
+        match self.keys.get(&c) {
+            Some(existing) if modifiers(existing) <= modifiers(&kbytes) => (),
+            _ => {
+                self.keys.insert(c, kbytes);
+            },
+        }
+    }
+
+    /// Modifier byte and keycode typing a char
+    pub fn get(&self, c: char) -> Option<[u8; 2]> {
+        self.keys.get(&c).copied()
+    }
+
+    /// Chars in the table
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.keys.keys().copied()
+    }
+
+    /// Number of chars in the table
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Check if the table is empty
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Generate from a Linux console keymap (see keymaps(5)), e.g. a decompressed file from /usr/share/keymaps.
+    /// Uses the plain, Shift, AltGr and AltGr+Shift columns of keycode lines in the main key block.
+    /// Include directives aren't followed.
+    pub fn from_console_keymap(keymap: &str) -> KeyTable {
+        let mut table = KeyTable::new();
+        let mut columns: Vec<usize> = (0..=3).collect();
+        for line in keymap.lines() {
+            let line = line.split(['#', '!']).next().unwrap_or("").trim();
+            if let Some(keymaps) = line.strip_prefix("keymaps") {
+                columns = keymaps.split(',')
+                    .filter_map(|range| {
+                        let (start, end) = range.trim().split_once('-').unwrap_or((range.trim(), range.trim()));
+                        Some(start.parse::<usize>().ok()?..=end.parse::<usize>().ok()?)
+                    })
+                    .flatten()
+                    .collect();
+                continue;
+            }
+            let Some((key, keysyms)) = line.split_once('=') else {
+                continue;
+            };
+            let mut key = key.split_whitespace();
+            // a single column line, e.g. "shift keycode 2 = exclam"
+            let mask = match key.next() {
+                Some("keycode") => None,
+                Some("plain") => Some(0),
+                Some("shift") => Some(CONSOLE_SHIFT),
+                Some("altgr") => Some(CONSOLE_ALTGR),
+                _ => continue,
+            };
+            if mask.is_some() && key.next() != Some("keycode") {
+                continue;
+            }
+            let Some(usage) = key.next().and_then(|keycode| keycode.parse().ok()).and_then(evdev_usage) else {
+                continue;
+            };
+            let keysyms: Vec<&str> = keysyms.split_whitespace().collect();
+            let masks: Vec<usize> = match mask {
+                Some(mask) => vec![mask],
+                None => columns.clone(),
+            };
+            for (mask, keysym) in masks.iter().zip(&keysyms) {
+                if mask & !(CONSOLE_SHIFT | CONSOLE_ALTGR) != 0 {
+                    continue;
+                }
+                let level = 1 + (mask & CONSOLE_SHIFT != 0) as usize + 2 * (mask & CONSOLE_ALTGR != 0) as usize;
+                if let (Some(c), Some(modifiers)) = (keysym_char(keysym), level_modifiers(level)) {
+                    table.insert(c, [modifiers, usage]);
+                }
+            }
+            // a letter with only a plain keysym types its capital with Shift
+            if mask.is_none() && keysyms.len() == 1 {
+                if let Some(c) = keysym_char(keysyms[0]).filter(|c| c.is_lowercase()) {
+                    for upper in c.to_uppercase() {
+                        table.insert(upper, [Modifier::LeftShift.to_mkbyte(), usage]);
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    /// Generate from an XKB symbols section, e.g. one xkb_symbols block from /usr/share/X11/xkb/symbols.
+    /// Uses the first group's four levels of keys in the main key block. Include statements aren't followed.
+    pub fn from_xkb_symbols(symbols: &str) -> KeyTable {
+        let mut table = KeyTable::new();
+        for statement in symbols.split(';') {
+            let statement = statement.lines()
+                .map(|line| line.split("//").next().unwrap_or(""))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let Some(start) = statement.find("key <") else {
+                continue;
+            };
+            let rest = &statement[start + 5..];
+            let Some((name, rest)) = rest.split_once('>') else {
+                continue;
+            };
+            let Some(usage) = xkb_evdev(name).and_then(evdev_usage) else {
+                continue;
+            };
+            // skip group indices, e.g. symbols[Group1] = [ ... ]
+            let levels = rest.split('[')
+                .skip(1)
+                .filter_map(|rest| rest.split_once(']').map(|(levels, _)| levels))
+                .find(|levels| !levels.trim().starts_with("Group"));
+            let Some(levels) = levels else {
+                continue;
+            };
+            for (i, keysym) in levels.split(',').enumerate() {
+                if let (Some(c), Some(modifiers)) = (keysym_char(keysym.trim()), level_modifiers(i + 1)) {
+                    table.insert(c, [modifiers, usage]);
+                }
+            }
+        }
+        table
+    }
+
+    /// Load a Linux console keymap file, see [KeyTable::from_console_keymap]
+    pub fn load_console_keymap(path: impl AsRef<Path>) -> io::Result<KeyTable> {
+        Ok(KeyTable::from_console_keymap(&fs::read_to_string(path)?))
+    }
+
+    /// Load an XKB symbols file, see [KeyTable::from_xkb_symbols]
+    pub fn load_xkb_symbols(path: impl AsRef<Path>) -> io::Result<KeyTable> {
+        Ok(KeyTable::from_xkb_symbols(&fs::read_to_string(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyTable;
+
+    #[test]
+    fn generates_tables() {
+        let console = KeyTable::from_console_keymap("
+            keymaps 0-2,4
+            keycode 16 = +a
+            keycode 3 = two quotedbl twosuperior Control_b
+            altgr keycode 26 = U+00e8 # comment
+            keycode 29 = Control
+        ");
+        assert_eq!(console.get('a'), Some([0x00, 0x14]));
+        assert_eq!(console.get('A'), Some([0x02, 0x14]));
+        assert_eq!(console.get('"'), Some([0x02, 0x1F]));
+        assert_eq!(console.get('²'), Some([0x40, 0x1F]));
+        assert_eq!(console.get('è'), Some([0x40, 0x2F]));
+        assert_eq!(console.len(), 6);
+
+        let xkb = KeyTable::from_xkb_symbols("
+            xkb_symbols \"basic\" {
+                key <AC10> { [ odiaeresis, Odiaeresis, dead_doubleacute ] };
+                key <AE02> { type[Group1] = \"FOUR_LEVEL\", symbols[Group1] = [ 2, quotedbl, at ] }; // two
+            };
+        ");
+        assert_eq!(xkb.get('ö'), Some([0x00, 0x33]));
+        assert_eq!(xkb.get('Ö'), Some([0x02, 0x33]));
+        assert_eq!(xkb.get('@'), Some([0x40, 0x1F]));
+        assert_eq!(xkb.len(), 5);
+    }
+}
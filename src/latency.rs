@@ -0,0 +1,155 @@
+#![warn(missing_docs)]
+
+use std::{fmt, time::{Duration, Instant}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Timestamps of a packet
+pub struct PacketTiming {
+    /// When the packet was queued
+    pub queued: Instant,
+    /// When the packet was written to the HID interface
+    pub written: Instant,
+    /// When the host received the packet, if known (e.g. from a loopback backend)
+    pub received: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Packet timestamps recorded in latency measurement mode
+pub struct LatencyLog {
+    queued: Vec<Instant>,
+    timings: Vec<PacketTiming>,
+}
+
+impl LatencyLog {
+    /// New
+    pub fn new() -> LatencyLog {
+        LatencyLog::default()
+    }
+
+    /// Record a packet being queued now
+    pub fn mark_queued(&mut self) {
+        self.mark_queued_at(Instant::now());
+    }
+
+    /// Record a packet being queued at a time
+    pub fn mark_queued_at(&mut self, at: Instant) {
+        self.queued.push(at);
+    }
+
+    /// Match the oldest queued packets, in order, with the times they were written. Packets queued after them stay
+    /// queued until they're written.
+    pub fn record_written(&mut self, written: &[Instant]) {
+        let count = written.len().min(self.queued.len());
+        for (queued, written) in self.queued.drain(..count).zip(written) {
+            self.timings.push(PacketTiming { queued, written: *written, received: None });
+        }
+    }
+
+    /// Packets queued but not written yet
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Forget packets queued but not written
+    pub fn discard_queued(&mut self) {
+        self.queued.clear();
+    }
+
+    /// Record the host receiving the oldest written packet that hasn't been received yet.
+    /// Returns false if every written packet has been received.
+    pub fn mark_received(&mut self, at: Instant) -> bool {
+        match self.timings.iter_mut().find(|timing| timing.received.is_none()) {
+            Some(timing) => {
+                timing.received = Some(at);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Timings of written packets
+    pub fn timings(&self) -> &[PacketTiming] {
+        &self.timings
+    }
+
+    /// Time from queueing to writing of each written packet
+    pub fn write_latencies(&self) -> Vec<Duration> {
+        self.timings.iter()
+            .map(|timing| timing.written.saturating_duration_since(timing.queued))
+            .collect()
+    }
+
+    /// Time from writing to the host receiving each received packet
+    pub fn receive_latencies(&self) -> Vec<Duration> {
+        self.timings.iter()
+            .filter_map(|timing| timing.received.map(|received| received.saturating_duration_since(timing.written)))
+            .collect()
+    }
+
+    /// Clear all timestamps
+    pub fn clear(&mut self) {
+        self.queued.clear();
+        self.timings.clear();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Latency histogram with fixed width buckets
+pub struct Histogram {
+    /// Bucket width
+    pub bucket: Duration,
+    /// Number of samples in each bucket, starting from zero
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// New histogram of samples
+    pub fn new(samples: &[Duration], bucket: Duration) -> Histogram {
+        let mut counts = Vec::new();
+        if !bucket.is_zero() {
+            for sample in samples {
+                let idx = (sample.as_nanos() / bucket.as_nanos()) as usize;
+                if idx >= counts.len() {
+                    counts.resize(idx + 1, 0);
+                }
+                counts[idx] += 1;
+            }
+        }
+        Histogram { bucket, counts }
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        for (i, count) in self.counts.iter().enumerate() {
+            let start = self.bucket * i as u32;
+            let bar = "#".repeat(count * 40 / max);
+            writeln!(f, "{:>10?} - {:>10?} | {} {}", start, start + self.bucket, bar, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::LatencyLog;
+
+    #[test]
+    fn matches_written_to_queued() {
+        let start = Instant::now();
+        let ms = |ms| start + Duration::from_millis(ms);
+        let mut log = LatencyLog::new();
+        for at in [0, 1, 2] {
+            log.mark_queued_at(ms(at));
+        }
+        log.record_written(&[ms(5), ms(7)]);
+        assert_eq!(log.queued(), 1);
+        log.mark_queued_at(ms(8));
+        log.record_written(&[ms(9), ms(10)]);
+        assert_eq!(log.queued(), 0);
+        assert_eq!(log.write_latencies(), [5, 6, 7, 2].map(Duration::from_millis));
+    }
+}
@@ -1,5 +1,15 @@
 #![doc = include_str!("../README.md")]
 
+/// Core Module: packet building, key translation, layouts and scheduling. Independent of any backend, so it builds
+/// without the `hid` feature for embedded and wasm targets.
+pub mod core;
+
+/// IO Module: backends sending what [core] builds to a host, and the helpers driving a host through them
+#[cfg(feature = "hid")]
+pub mod io;
+
+/// Prelude Module: the most used types, for `use virt_hid::prelude::*`
+pub mod prelude;
 
 /// Keyboard module
 pub mod key;
@@ -7,12 +17,191 @@ pub mod key;
 /// Key Translation Module
 mod translate;
 
+/// Random Number Module
+pub mod rng;
+
 /// Mouse Module
 pub mod mouse;
 
+/// Easing Curve Module
+pub mod easing;
+
+/// Absolute Pointer Module
+pub mod absolute;
+
+/// Config Module
+pub mod config;
+
+/// Shortcut Module
+pub mod accel;
+
+/// IME Input Module
+pub mod ime;
+
+/// Keymap Table Module
+pub mod keymap;
+
+/// Layout Pack Module
+#[cfg(feature = "layout-pack")]
+pub mod pack;
+
+/// Layout Coverage Module
+pub mod coverage;
+
+/// Logging Module
+pub mod logging;
+
+/// Key Event Decoding Module
+pub mod decode;
+
+/// Key History Module
+pub mod history;
+
+/// Lint Module
+pub mod lint;
+
+/// Adaptive Pacing Module
+pub mod pacing;
+
+/// HID Report Descriptor Module
+pub mod descriptor;
+
+/// Custom Report Layout Module
+pub mod report;
+
+/// Latency Measurement Module
+pub mod latency;
+
+/// Virtual Device Module
+pub mod device;
+
+/// Input Interceptor Module
+pub mod interceptor;
+
+/// Key Matrix Module
+pub mod matrix;
+
+/// Radial Controller Module
+pub mod dial;
+
+/// Consumer Control Module
+pub mod consumer;
+
+/// Report Scheduling Module
+pub mod schedule;
+
+/// Keyboard and Mouse Session Module
+pub mod session;
+
+/// Typing Error Simulation Module
+pub mod typo;
+
+/// Keystroke Dynamics Module
+pub mod dynamics;
 
+/// Cancellation Module
+pub mod cancel;
+
+/// Clock Module
+pub mod clock;
+
+/// Framing Module
+pub mod framing;
+
+/// Macro Module
+pub mod macros;
+
+/// Capture Import Module
+pub mod capture;
+
+/// Firmware Macro Export Module
+pub mod firmware;
+
+/// Transaction Module
+pub mod transaction;
+
+/// Activity Simulation Module
+pub mod simulate;
+
+
+/// Backend Trait Module
+mod backend;
+pub use backend::HidBackend;
+
+#[cfg(feature = "hid")]
 mod hid;
 /// HID file module
-pub use hid::HID;
+#[cfg(feature = "hid")]
+pub use hid::{HID, HostProbe, HostStage, FIRMWARE_RTT};
+
+/// Target Switching Module
+#[cfg(feature = "hid")]
+pub mod switcher;
+
+/// Fanout Module
+#[cfg(feature = "hid")]
+pub mod fanout;
+
+/// Failure Injection Module
+#[cfg(feature = "hid")]
+pub mod chaos;
+
+/// Multi-device Choreography Module
+#[cfg(feature = "hid")]
+pub mod choreography;
+
+/// Power Management Module
+#[cfg(feature = "hid")]
+pub mod power;
+
+/// Terminal Module
+#[cfg(feature = "hid")]
+pub mod terminal;
+
+/// File Transfer Module
+#[cfg(feature = "hid")]
+pub mod transfer;
+
+/// Emergency Stop Module
+#[cfg(feature = "hid")]
+pub mod stop;
+#[cfg(feature = "hid")]
+pub use stop::{emergency_stop, reset_emergency_stop};
+
+/// Interactive Flow Module
+#[cfg(feature = "hid")]
+pub mod flow;
+
+/// Vendor Defined Interface Module
+#[cfg(feature = "hid")]
+pub mod vendor;
+
+/// Echo Verification Module
+#[cfg(feature = "hid")]
+pub mod echo;
+
+/// Session Recording Module
+#[cfg(feature = "hid")]
+pub mod recorder;
+
+/// Clipboard Bridging Module
+#[cfg(feature = "hid")]
+pub mod clipboard;
+
+/// Form Filling Module
+#[cfg(feature = "hid")]
+pub mod form;
+
+/// Layout Detection Module
+#[cfg(feature = "debug")]
+pub mod detect;
+
+/// uhid Virtual Device Module
+#[cfg(feature = "uhid")]
+pub mod uhid;
+
+/// C FFI Module
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
 //^.+?num:(\d+?), byte:(0x..), ktype:KeyOrigin::(.+?),.+?Char\(vec!\[(.+?)\]\)\}, | $4 => $2, // $1, $2, $3, $4
\ No newline at end of file
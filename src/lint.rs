@@ -0,0 +1,162 @@
+#![warn(missing_docs)]
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use serde::{Serialize, Deserialize};
+
+use crate::key::{KeyPacket, BOOT_REPORT_MAX_KEYS};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Mistake a [lint] pass looks for
+pub enum LintRule {
+    /// A packet holds more keys than a boot protocol report can carry, so boot protocol hosts see ErrorRollOver
+    BootKeyCount,
+    /// Modifiers are pressed and released without a key, which most hosts ignore
+    ModifierOnly,
+    /// There is nothing to send
+    EmptyBuffer,
+    /// A key stays held longer than [LintConfig::long_hold], which hosts may auto repeat
+    LongHold,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Mistake found by a [lint] pass
+pub struct LintWarning {
+    /// Rule broken
+    pub rule: LintRule,
+    /// Index of the packet breaking it, None if it's about the whole buffer
+    pub packet: Option<usize>,
+    /// Readable description
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.packet {
+            Some(packet) => write!(f, "{:?} at packet {}: {}", self.rule, packet, self.message),
+            None => write!(f, "{:?}: {}", self.rule, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// Rules checked by a [lint] pass. The default checks every rule and only warns.
+pub struct LintConfig {
+    /// Check [LintRule::BootKeyCount]
+    pub boot_key_count: bool,
+    /// Check [LintRule::ModifierOnly]
+    pub modifier_only: bool,
+    /// Check [LintRule::EmptyBuffer]
+    pub empty_buffer: bool,
+    /// Longest a key can stay held before [LintRule::LongHold] is broken, None doesn't check
+    pub long_hold: Option<Duration>,
+    /// Refuse to send packets breaking any rule, see [crate::key::Keyboard::set_lint]
+    pub deny: bool,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            boot_key_count: true,
+            modifier_only: true,
+            empty_buffer: true,
+            long_hold: Some(Duration::from_secs(5)),
+            deny: false,
+        }
+    }
+}
+
+/// Check packets about to be sent, waiting pacing after each, for common mistakes. Warnings are in packet order,
+/// with buffer wide warnings first.
+pub fn lint(packets: &[KeyPacket], pacing: Duration, config: &LintConfig) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    if config.empty_buffer && packets.iter().all(KeyPacket::is_empty) {
+        warnings.push(LintWarning {
+            rule: LintRule::EmptyBuffer,
+            packet: None,
+            message: "no keys are pressed".to_string(),
+        });
+    }
+
+    // packet index each held key was pressed at
+    let mut pressed: HashMap<u8, usize> = HashMap::new();
+    for (i, packet) in packets.iter().enumerate() {
+        let keys = packet.keycodes();
+        if config.boot_key_count && keys.len() > BOOT_REPORT_MAX_KEYS {
+            warnings.push(LintWarning {
+                rule: LintRule::BootKeyCount,
+                packet: Some(i),
+                message: format!("{} keys held, boot reports carry at most {}", keys.len(), BOOT_REPORT_MAX_KEYS),
+            });
+        }
+
+        let modifiers = packet.modifiers();
+        if config.modifier_only && keys.is_empty() && !modifiers.is_empty() {
+            let released = packets.get(i + 1).is_some_and(|next| {
+                next.keycodes().is_empty() && modifiers.iter().any(|modifier| !next.modifiers().contains(modifier))
+            });
+            if released {
+                warnings.push(LintWarning {
+                    rule: LintRule::ModifierOnly,
+                    packet: Some(i),
+                    message: format!("{:?} released without pressing a key", modifiers),
+                });
+            }
+        }
+
+        if let Some(long_hold) = config.long_hold {
+            pressed.retain(|key, start| {
+                if keys.contains(key) {
+                    return true;
+                }
+                let held = pacing.saturating_mul((i - *start) as u32);
+                if held > long_hold {
+                    warnings.push(LintWarning {
+                        rule: LintRule::LongHold,
+                        packet: Some(*start),
+                        message: format!("key {:#04x} held for {:?}, longer than {:?}", key, held, long_hold),
+                    });
+                }
+                false
+            });
+            for key in keys {
+                pressed.entry(key).or_insert(i);
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{lint, LintConfig, LintRule};
+    use crate::key::KeyPacket;
+
+    #[test]
+    fn lint_rules() {
+        let packets = |chords: &[&str]| chords.iter()
+            .map(|chord| match chord.is_empty() {
+                true => KeyPacket::new(),
+                false => KeyPacket::parse(chord).unwrap(),
+            })
+            .collect::<Vec<_>>();
+        let rules = |packets: &[KeyPacket], pacing| lint(packets, pacing, &LintConfig::default())
+            .into_iter()
+            .map(|warning| (warning.rule, warning.packet))
+            .collect::<Vec<_>>();
+
+        assert_eq!(rules(&[], Duration::ZERO), vec![(LintRule::EmptyBuffer, None)]);
+        assert_eq!(rules(&packets(&["LSHIFT", "LSHIFT+a", ""]), Duration::ZERO), vec![]);
+        assert_eq!(rules(&packets(&["CTRL", "CTRL+ALT", ""]), Duration::ZERO), vec![(LintRule::ModifierOnly, Some(1))]);
+        assert_eq!(rules(&packets(&["a+b+c+d+e+f+g", ""]), Duration::ZERO), vec![(LintRule::BootKeyCount, Some(0))]);
+
+        let held = packets(&["a", "a+b", "a", ""]);
+        assert_eq!(rules(&held, Duration::from_secs(2)), vec![(LintRule::LongHold, Some(0))]);
+        assert_eq!(rules(&held, Duration::from_secs(1)), vec![]);
+        let config = LintConfig { long_hold: None, ..LintConfig::default() };
+        assert!(lint(&held, Duration::from_secs(2), &config).is_empty());
+    }
+}
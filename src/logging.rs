@@ -0,0 +1,71 @@
+#![warn(missing_docs)]
+
+use std::{fmt, io::{self, Write}, sync::{atomic::{AtomicBool, Ordering}, Mutex}};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static WRITER: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Stop the library writing any text. Always on with the `quiet` feature.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::SeqCst);
+}
+
+/// Check if the library writes no text
+pub fn is_quiet() -> bool {
+    cfg!(feature = "quiet") || QUIET.load(Ordering::SeqCst)
+}
+
+/// Set where the library writes text, e.g. debug mode events and [crate::key::KeyPacket::print_packet].
+/// None writes to stdout.
+pub fn set_writer(writer: Option<Box<dyn Write + Send>>) {
+    *WRITER.lock().unwrap_or_else(|e| e.into_inner()) = writer;
+}
+
+/// Write a line to the log writer unless quiet. Errors are ignored.
+pub fn write_line(args: fmt::Arguments) {
+    if is_quiet() {
+        return;
+    }
+    let mut writer = WRITER.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = match writer.as_mut() {
+        Some(writer) => writeln!(writer, "{}", args),
+        None => writeln!(io::stdout(), "{}", args),
+    };
+}
+
+/// Write a formatted line with [write_line]
+macro_rules! log_line {
+    ($($arg:tt)*) => {
+        $crate::logging::write_line(format_args!($($arg)*))
+    };
+}
+pub(crate) use log_line;
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{self, Write}, sync::{Arc, Mutex}};
+
+    use super::{set_writer, write_line};
+
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "quiet"))]
+    fn redirects_lines() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        set_writer(Some(Box::new(Capture(captured.clone()))));
+        write_line(format_args!("packet {:02x}", 4));
+        set_writer(None);
+        assert!(String::from_utf8_lossy(&captured.lock().unwrap()).contains("packet 04\n"));
+    }
+}
@@ -0,0 +1,184 @@
+#![warn(missing_docs)]
+
+use std::{ops::Range, time::Duration};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{key::{encode_string, KeyPacket}, schedule::{Report, Scheduler}};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Macro action
+pub enum MacroAction {
+    /// Type text
+    Type(String),
+    /// Send a key packet
+    Key(KeyPacket),
+    /// Send a raw mouse packet
+    Mouse([u8; 5]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Macro event
+pub struct MacroEvent {
+    /// Wait after the previous event
+    pub delay: Duration,
+    /// Action
+    pub action: MacroAction,
+}
+
+impl MacroEvent {
+    /// New
+    pub fn new(delay: Duration, action: MacroAction) -> MacroEvent {
+        MacroEvent { delay, action }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// Editable sequence of timed keyboard and mouse events
+pub struct Macro {
+    events: Vec<MacroEvent>,
+}
+
+impl Macro {
+    /// New empty macro
+    pub fn new() -> Macro {
+        Macro::default()
+    }
+
+    /// Record the reports of a schedule as key and mouse events
+    pub fn from_schedule(scheduler: &Scheduler) -> Macro {
+        let mut previous = Duration::ZERO;
+        let events = scheduler.reports().iter()
+            .map(|scheduled| {
+                let delay = scheduled.at.saturating_sub(previous);
+                previous = scheduled.at;
+                let action = match &scheduled.report {
+                    Report::Keyboard(packet) => MacroAction::Key(packet.clone()),
+                    Report::Mouse(packet) => MacroAction::Mouse(*packet),
+                };
+                MacroEvent { delay, action }
+            })
+            .collect();
+        Macro { events }
+    }
+
+    /// Events
+    pub fn events(&self) -> &[MacroEvent] {
+        &self.events
+    }
+
+    /// Number of events
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Check if there are no events
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Total of all delays
+    pub fn duration(&self) -> Duration {
+        self.events.iter().map(|event| event.delay).sum()
+    }
+
+    /// Add event to the end
+    pub fn push(&mut self, event: MacroEvent) {
+        self.events.push(event);
+    }
+
+    /// Insert event at index. Returns false if the index is past the end.
+    pub fn insert(&mut self, index: usize, event: MacroEvent) -> bool {
+        if index > self.events.len() {
+            return false;
+        }
+        self.events.insert(index, event);
+        true
+    }
+
+    /// Remove and return a range of events. Returns None if the range is out of bounds.
+    pub fn remove(&mut self, range: Range<usize>) -> Option<Vec<MacroEvent>> {
+        if range.start > range.end || range.end > self.events.len() {
+            return None;
+        }
+        Some(self.events.drain(range).collect())
+    }
+
+    /// Set the delay of an event. Returns false if there's no event at the index.
+    pub fn set_delay(&mut self, index: usize, delay: Duration) -> bool {
+        match self.events.get_mut(index) {
+            Some(event) => {
+                event.delay = delay;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Scale all delays, e.g. 0.5 replays twice as fast. Negative and non finite factors are ignored.
+    pub fn scale_delays(&mut self, factor: f64) {
+        if !factor.is_finite() || factor < 0.0 {
+            return;
+        }
+        for event in &mut self.events {
+            event.delay = event.delay.mul_f64(factor);
+        }
+    }
+
+    /// Replace text in all typed text events. Returns the number of events changed.
+    pub fn replace_text(&mut self, from: &str, to: &str) -> usize {
+        let mut changed = 0;
+        for event in &mut self.events {
+            if let MacroAction::Type(text) = &mut event.action {
+                if !from.is_empty() && text.contains(from) {
+                    *text = text.replace(from, to);
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Schedule the macro after the last scheduled report. Text is typed with a layout, None uses the basic keyboard table.
+    pub fn schedule(&self, scheduler: &mut Scheduler, layout_key: Option<&str>) {
+        let mut at = scheduler.end();
+        for event in &self.events {
+            at += event.delay;
+            match &event.action {
+                MacroAction::Type(text) => {
+                    for packet in encode_string(layout_key, text) {
+                        scheduler.push(at, Report::Keyboard(packet));
+                    }
+                },
+                MacroAction::Key(packet) => scheduler.push(at, Report::Keyboard(packet.clone())),
+                MacroAction::Mouse(packet) => scheduler.push(at, Report::Mouse(*packet)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Macro, MacroAction, MacroEvent};
+
+    #[test]
+    fn edit_macro() {
+        let mut recorded = Macro::new();
+        recorded.push(MacroEvent::new(Duration::from_millis(100), MacroAction::Type("hello world".to_string())));
+        recorded.push(MacroEvent::new(Duration::from_millis(300), MacroAction::Mouse([1, 0, 0, 0, 0])));
+        assert!(recorded.insert(1, MacroEvent::new(Duration::from_millis(50), MacroAction::Type("!".to_string()))));
+        assert!(!recorded.insert(4, MacroEvent::new(Duration::ZERO, MacroAction::Mouse([0; 5]))));
+
+        assert_eq!(recorded.replace_text("world", "there"), 1);
+        recorded.scale_delays(0.5);
+        assert_eq!(recorded.duration(), Duration::from_millis(225));
+
+        let removed = recorded.remove(1..2).unwrap();
+        assert_eq!(removed[0].action, MacroAction::Type("!".to_string()));
+        assert_eq!(recorded.remove(1..3), None);
+        assert_eq!(recorded.events()[0].action, MacroAction::Type("hello there".to_string()));
+        assert_eq!(recorded.len(), 2);
+    }
+}
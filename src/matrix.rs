@@ -0,0 +1,149 @@
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+
+use num_enum::{IntoPrimitive, FromPrimitive};
+use serde::{Serialize, Deserialize};
+
+use crate::key::KeyPacket;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// What a [KeyMatrix] does with a key press that would ghost
+pub enum GhostPolicy {
+    /// Block the key while it would ghost, like keyboards with ghost blocking. It's pressed once the keys it
+    /// conflicts with are released, if it's still held.
+    #[default]
+    Refuse,
+    /// Release the held keys it conflicts with first, then press it
+    Reorder,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Row and column wiring of a physical keyboard without diodes, for emulating its ghosting behaviour.
+/// Holding three keys on the corners of a rectangle in the matrix would make the fourth corner ghost.
+/// Modifiers and keys outside the matrix are never affected.
+pub struct KeyMatrix {
+    positions: HashMap<u8, (u8, u8)>,
+    policy: GhostPolicy,
+}
+
+impl KeyMatrix {
+    /// New, without keys
+    pub fn new(policy: GhostPolicy) -> KeyMatrix {
+        KeyMatrix { positions: HashMap::new(), policy }
+    }
+
+    /// New from rows of keycodes, with 0 for empty positions
+    pub fn from_rows(rows: &[&[u8]], policy: GhostPolicy) -> KeyMatrix {
+        let mut matrix = KeyMatrix::new(policy);
+        for (row, keycodes) in rows.iter().enumerate() {
+            for (col, keycode) in keycodes.iter().enumerate() {
+                if *keycode != 0 {
+                    matrix = matrix.key(*keycode, row as u8, col as u8);
+                }
+            }
+        }
+        matrix
+    }
+
+    /// Place a keycode at a row and column
+    pub fn key(mut self, keycode: u8, row: u8, col: u8) -> KeyMatrix {
+        self.positions.insert(keycode, (row, col));
+        self
+    }
+
+    /// Policy
+    pub fn policy(&self) -> GhostPolicy {
+        self.policy
+    }
+
+    /// Held keys a key would form a ghosting rectangle with
+    fn conflicts(&self, held: &[u8], keycode: u8) -> Vec<u8> {
+        let Some(&(row, col)) = self.positions.get(&keycode) else {
+            return Vec::new();
+        };
+        let position = |key: &u8| self.positions.get(key).copied();
+        let mut conflicts = Vec::new();
+        for a in held {
+            for b in held {
+                let (Some(pa), Some(pb)) = (position(a), position(b)) else {
+                    continue;
+                };
+                // keycode and a share a row, a and b share a column, or the rotations of that rectangle
+                let rectangle = (pa.0 == row && pa.1 != col && pb.1 == pa.1 && pb.0 != row)
+                    || (pa.1 == col && pa.0 != row && pb.0 == pa.0 && pb.1 != col)
+                    || (pa.0 == row && pb.1 == col && pa.1 != col && pb.0 != row);
+                if rectangle {
+                    conflicts.extend([*a, *b]);
+                }
+            }
+        }
+        conflicts.sort_unstable();
+        conflicts.dedup();
+        conflicts
+    }
+
+    /// Check if holding keycodes together would ghost
+    pub fn would_ghost(&self, keycodes: &[u8]) -> bool {
+        keycodes.iter().enumerate().any(|(i, keycode)| !self.conflicts(&keycodes[..i], *keycode).is_empty())
+    }
+
+    /// Rewrite a packet sequence so no packet holds keys that would ghost, according to the policy
+    pub fn apply(&self, packets: &[KeyPacket]) -> Vec<KeyPacket> {
+        self.apply_indexed(packets).into_iter().map(|(_, packet)| packet).collect()
+    }
+
+    /// Rewrite a packet sequence like [KeyMatrix::apply], pairing each output packet with the index of the packet it
+    /// was rewritten from
+    pub(crate) fn apply_indexed(&self, packets: &[KeyPacket]) -> Vec<(usize, KeyPacket)> {
+        let mut held: Vec<u8> = Vec::new();
+        let mut output = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            let modifiers = packet.as_bytes()[0];
+            let desired: Vec<u8> = packet.usages().collect();
+            held.retain(|keycode| desired.contains(keycode));
+            for keycode in desired.iter().filter(|keycode| !held.contains(keycode)).copied().collect::<Vec<_>>() {
+                let conflicts = self.conflicts(&held, keycode);
+                if conflicts.is_empty() {
+                    held.push(keycode);
+                } else if self.policy == GhostPolicy::Reorder {
+                    held.retain(|keycode| !conflicts.contains(keycode));
+                    output.push((i, KeyPacket::from_usages(modifiers, held.iter().copied())));
+                    held.push(keycode);
+                }
+            }
+            output.push((i, KeyPacket::from_usages(modifiers, held.iter().copied())));
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GhostPolicy, KeyMatrix};
+    use crate::key::KeyPacket;
+
+    const A: u8 = 0x04;
+    const B: u8 = 0x05;
+    const C: u8 = 0x06;
+    const D: u8 = 0x07;
+
+    #[test]
+    fn ghosting_combinations() {
+        let rows: &[&[u8]] = &[&[A, B], &[C, D]];
+        let mut packets: Vec<KeyPacket> = ["a", "a+b", "a+b+c", "a+c"].iter()
+            .map(|chord| KeyPacket::parse(chord).unwrap())
+            .collect();
+        packets.push(KeyPacket::new());
+        let usages = |packets: Vec<KeyPacket>| packets.iter().map(|packet| packet.usages().collect::<Vec<_>>()).collect::<Vec<_>>();
+
+        let refuse = KeyMatrix::from_rows(rows, GhostPolicy::Refuse);
+        assert!(refuse.would_ghost(&[A, B, C]));
+        assert!(!refuse.would_ghost(&[A, D]));
+        assert_eq!(usages(refuse.apply(&packets)), vec![vec![A], vec![A, B], vec![A, B], vec![A, C], vec![]]);
+
+        let reorder = KeyMatrix::from_rows(rows, GhostPolicy::Reorder);
+        assert_eq!(usages(reorder.apply(&packets)), vec![vec![A], vec![A, B], vec![], vec![C], vec![A, C], vec![]]);
+    }
+}
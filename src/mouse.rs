@@ -1,12 +1,17 @@
 #![warn(missing_docs)]
-use std::{io::{self}};
+use std::{sync::Arc, time::Instant};
+
+use std::{borrow::Cow, io, sync::OnceLock, time::Duration};
 
 use num_enum::{IntoPrimitive, FromPrimitive};
 use serde::{Serialize, Deserialize};
 
-use crate::HID;
+use crate::{descriptor, easing::Easing, report::ReportLayout, schedule::{Report, Scheduler}};
+use crate::{HidBackend, cancel::CancellationToken, clock::{Clock, SystemClock}, latency::LatencyLog};
+#[cfg(feature = "hid")]
+use crate::stop;
 
-#[derive(Debug, Clone, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
 #[repr(u32)]
 /// Mouse Button
 pub enum MouseButton {
@@ -28,9 +33,14 @@ impl MouseButton {
             MouseButton::Middle => 0x04,
         }
     }
+
+    /// Combined byte of buttons pressed together
+    pub fn chord_byte(buttons: &[MouseButton]) -> u8 {
+        buttons.iter().fold(0, |byte, button| byte | button.to_byte())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
 #[repr(u32)]
 /// Mouse movement direction
 pub enum MouseDir {
@@ -41,103 +51,1003 @@ pub enum MouseDir {
     Y
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash, Serialize, Deserialize)]
+#[serde(try_from = "i32", into = "i32")]
+/// Relative movement in one report, within -127..=127 as declared by the report descriptor. Axes move in counts
+/// (pixels before host acceleration), positive X is right and positive Y is down. The wheel moves in detents,
+/// positive scrolls up (away from the user).
+pub struct Delta(i8);
+
+impl Delta {
+    /// No movement
+    pub const ZERO: Delta = Delta(0);
+    /// Largest negative movement
+    pub const MIN: Delta = Delta(-127);
+    /// Largest positive movement
+    pub const MAX: Delta = Delta(127);
+
+    /// New, None if the value is out of range
+    pub fn new(value: i32) -> Option<Delta> {
+        (Delta::MIN.get() as i32..=Delta::MAX.get() as i32).contains(&value).then_some(Delta(value as i8))
+    }
+
+    /// New, clamping the value into range
+    pub fn saturating(value: i32) -> Delta {
+        Delta(value.clamp(Delta::MIN.get() as i32, Delta::MAX.get() as i32) as i8)
+    }
+
+    /// Split a value of any size into in-range deltas adding up to it, e.g. one per report. Empty for zero.
+    pub fn split(mut value: i32) -> Vec<Delta> {
+        let mut deltas = Vec::new();
+        while value != 0 {
+            let delta = Delta::saturating(value);
+            deltas.push(delta);
+            value -= delta.get() as i32;
+        }
+        deltas
+    }
+
+    /// Value
+    pub fn get(self) -> i8 {
+        self.0
+    }
+}
+
+impl TryFrom<i32> for Delta {
+    type Error = String;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        Delta::new(value).ok_or_else(|| format!("delta {} out of range -127..=127", value))
+    }
+}
+
+impl From<Delta> for i32 {
+    fn from(delta: Delta) -> Self {
+        delta.get() as i32
+    }
+}
 
 const MOUSE_DATA_BUT_IDX: usize = 0;
 const MOUSE_DATA_X_IDX: usize = 1;
 const MOUSE_DATA_Y_IDX: usize = 2;
-const MOUSE_DATA_WHEL_IDX: usize = 3;
+pub(crate) const MOUSE_DATA_WHEL_IDX: usize = 3;
+const MOUSE_DATA_PAN_IDX: usize = 4;
+const REPORT_INTERVAL: Duration = Duration::from_millis(16);
+const KINETIC_MIN_VELOCITY: f32 = 1.0;
+const CIRCLE_SEGMENTS: usize = 64;
+/// Reports of full moves towards the top left sent by [Mouse::home], enough to cross an 8K screen
+const HOME_REPORTS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Input report format of a mouse interface, read from its report descriptor with [descriptor::mouse_format].
+/// [Mouse] builds 5 byte packets (see [ReportLayout::mouse]) and converts them to this format when sending.
+pub struct MouseFormat {
+    /// Report layout with buttons, x and y fields, and optional report_id, wheel and pan fields
+    pub layout: ReportLayout,
+    /// Report ID prefixed to reports
+    pub report_id: Option<u8>,
+    /// Report descriptor
+    pub descriptor: Cow<'static, [u8]>,
+}
+
+impl Default for MouseFormat {
+    fn default() -> Self {
+        MouseFormat { layout: ReportLayout::mouse(), report_id: None, descriptor: Cow::Borrowed(descriptor::MOUSE) }
+    }
+}
+
+impl MouseFormat {
+    /// Convert a 5 byte packet to a report in this format. Buttons and axes the report lacks are dropped and
+    /// values are clamped to the range of their fields.
+    pub fn encode(&self, packet: &[u8; 5]) -> Vec<u8> {
+        let mut builder = self.layout.builder();
+        let fields = [
+            ("buttons", packet[MOUSE_DATA_BUT_IDX] as i64),
+            ("x", packet[MOUSE_DATA_X_IDX] as i8 as i64),
+            ("y", packet[MOUSE_DATA_Y_IDX] as i8 as i64),
+            ("wheel", packet[MOUSE_DATA_WHEL_IDX] as i8 as i64),
+            ("pan", packet[MOUSE_DATA_PAN_IDX] as i8 as i64),
+            ("report_id", self.report_id.unwrap_or(0) as i64),
+        ];
+        for (name, value) in fields {
+            let Some(field) = self.layout.get(name) else {
+                continue;
+            };
+            let value = match name {
+                "buttons" => value & field.range().1,
+                _ => value,
+            };
+            // only fields out of the report's bounds fail, and they're skipped
+            let _ = builder.set_clamped(name, value);
+        }
+        builder.build()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Estimates the absolute pointer position by integrating the relative moves sent, so code can move to approximately
+/// (x, y) without a digitizer interface. Host pointer acceleration makes the estimate drift, so recalibrate with
+/// [PointerTracker::reset] or [Mouse::home] now and then.
+pub struct PointerTracker {
+    x: f64,
+    y: f64,
+    gain: f64,
+    bounds: Option<(i32, i32)>,
+}
+
+impl Default for PointerTracker {
+    fn default() -> Self {
+        PointerTracker::new()
+    }
+}
+
+impl PointerTracker {
+    /// New, at (0, 0) with a gain of 1 pixel per count and no screen bounds
+    pub fn new() -> PointerTracker {
+        PointerTracker { x: 0.0, y: 0.0, gain: 1.0, bounds: None }
+    }
+
+    /// Set the pixels the pointer moves per count of relative movement, measured for the host's pointer speed
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain = gain;
+    }
+
+    /// Set the screen size in pixels. The estimate is clamped to the screen like the host's pointer.
+    pub fn set_bounds(&mut self, bounds: Option<(i32, i32)>) {
+        self.bounds = bounds;
+        self.clamp();
+    }
+
+    /// Set the known pointer position, e.g. after moving it into a corner
+    pub fn reset(&mut self, x: i32, y: i32) {
+        self.x = x as f64;
+        self.y = y as f64;
+        self.clamp();
+    }
+
+    /// Estimated pointer position in pixels
+    pub fn position(&self) -> (i32, i32) {
+        (self.x.round() as i32, self.y.round() as i32)
+    }
+
+    fn clamp(&mut self) {
+        if let Some((width, height)) = self.bounds {
+            self.x = self.x.clamp(0.0, (width - 1).max(0) as f64);
+            self.y = self.y.clamp(0.0, (height - 1).max(0) as f64);
+        }
+    }
+
+    /// Integrate a relative move in counts
+    pub fn track_move(&mut self, dx: i32, dy: i32) {
+        self.x += dx as f64 * self.gain;
+        self.y += dy as f64 * self.gain;
+        self.clamp();
+    }
+
+    /// Integrate the move of a raw mouse packet
+    pub fn track(&mut self, packet: &[u8; 5]) {
+        self.track_move(packet[MOUSE_DATA_X_IDX] as i8 as i32, packet[MOUSE_DATA_Y_IDX] as i8 as i32);
+    }
+
+    /// Relative moves in counts, one per report, moving the pointer approximately to a position
+    pub fn moves_to(&self, x: i32, y: i32) -> Vec<(Delta, Delta)> {
+        let counts = |from: f64, to: i32| ((to as f64 - from) / self.gain).round() as i32;
+        let (dx, dy) = (Delta::split(counts(self.x, x)), Delta::split(counts(self.y, y)));
+        (0..dx.len().max(dy.len()))
+            .map(|i| (dx.get(i).copied().unwrap_or(Delta::ZERO), dy.get(i).copied().unwrap_or(Delta::ZERO)))
+            .collect()
+    }
+}
 
 /// Virtual Mouse
 pub struct Mouse {
     data: [u8; 5],
     hold: u8,
+    format: Option<MouseFormat>,
+    tracker: Option<PointerTracker>,
+    latency: Option<LatencyLog>,
+    queued_at: Option<Instant>,
+    #[cfg(feature = "hid")]
+    stop_generation: usize,
+    clock: Arc<dyn Clock>,
 }
 
 impl Mouse {
     /// New
     pub fn new() -> Mouse {
-        Mouse{data:[0;5], hold: 0x00}
+        Mouse{
+            data:[0;5],
+            hold: 0x00,
+            format: None,
+            tracker: None,
+            latency: None,
+            queued_at: None,
+            #[cfg(feature = "hid")]
+            stop_generation: stop::generation(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// New, sending reports in the format of a report descriptor, see [descriptor::mouse_format]
+    pub fn from_descriptor(descriptor: &[u8]) -> io::Result<Mouse> {
+        let mut mouse = Mouse::new();
+        mouse.set_format(descriptor::mouse_format(descriptor)?);
+        Ok(mouse)
+    }
+
+    /// Set the report format used by [Mouse::send], [Mouse::scroll_by] and [Mouse::take_reports]. Without one the mouse
+    /// sends in the backend's format (see [crate::HidBackend::mouse_format]), which also covers reports sent by
+    /// schedules and other helpers, so prefer setting it there, e.g. with [crate::HID::set_mouse_format].
+    pub fn set_format(&mut self, format: MouseFormat) {
+        self.format = Some(format);
+    }
+
+    /// Report format, the default 5 byte format if none is set
+    pub fn format(&self) -> &MouseFormat {
+        static DEFAULT: OnceLock<MouseFormat> = OnceLock::new();
+        self.format.as_ref().unwrap_or_else(|| DEFAULT.get_or_init(MouseFormat::default))
+    }
+
+    /// Report format set with [Mouse::set_format], None if the mouse sends in the backend's format
+    pub(crate) fn own_format(&self) -> Option<&MouseFormat> {
+        self.format.as_ref()
+    }
+
+    /// Send a 5 byte packet in the mouse's format, or the backend's if it has none
+    fn send_packet(&self, hid: &mut dyn HidBackend, packet: &[u8; 5]) -> io::Result<()> {
+        match &self.format {
+            Some(format) => hid.send_mouse_packet(&format.encode(packet)),
+            None => hid.send_mouse_report(packet),
+        }
+    }
+
+    /// Set the clock [Mouse::scroll_by] paces reports on, see [crate::clock::VirtualClock]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Set a tracker estimating the pointer position from the moves taken with [Mouse::take_packets] (and so sent)
+    /// and the moves scheduled by [Mouse::glide_to] and the path drawing methods. Scheduled moves are tracked when
+    /// they're scheduled, so reset the tracker after a run that fails or is cancelled, or [Mouse::home].
+    pub fn set_tracker(&mut self, tracker: Option<PointerTracker>) {
+        self.tracker = tracker;
+    }
+
+    /// Pointer position tracker
+    pub fn tracker(&self) -> Option<&PointerTracker> {
+        self.tracker.as_ref()
+    }
+
+    /// Pointer position tracker, e.g. to recalibrate it
+    pub fn tracker_mut(&mut self) -> Option<&mut PointerTracker> {
+        self.tracker.as_mut()
+    }
+
+    /// Send buffered mouse events, then move approximately to a position estimated by the tracker, one report per move.
+    /// Fails with [io::ErrorKind::Unsupported] without a tracker, see [Mouse::set_tracker].
+    pub fn move_to(&mut self, hid: &mut dyn HidBackend, x: i32, y: i32) -> io::Result<()> {
+        let moves = match &self.tracker {
+            Some(tracker) => tracker.moves_to(x, y),
+            None => return Err(io::Error::new(io::ErrorKind::Unsupported, "no pointer tracker")),
+        };
+        self.send(hid)?;
+        for (dx, dy) in moves {
+            self.move_mouse(dx, MouseDir::X);
+            self.move_mouse(dy, MouseDir::Y);
+            self.send(hid)?;
+        }
+        Ok(())
+    }
+
+    /// Send buffered mouse events, then move far enough towards the top left to reach the corner from anywhere and
+    /// reset the tracker to (0, 0)
+    pub fn home(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+        self.send(hid)?;
+        for _ in 0..HOME_REPORTS {
+            self.move_mouse(Delta::MIN, MouseDir::X);
+            self.move_mouse(Delta::MIN, MouseDir::Y);
+            self.send(hid)?;
+        }
+        if let Some(tracker) = &mut self.tracker {
+            tracker.reset(0, 0);
+        }
+        Ok(())
+    }
+
+    /// Raw packets bringing the host to a known state after switching to it, without sending them to a HID interface:
+    /// a report with every button up, a one count jiggle right and back to wake the pointer, then a report restoring
+    /// the held buttons
+    pub fn resync_packets(&self) -> Vec<[u8; 5]> {
+        let mut right = [0; 5];
+        right[MOUSE_DATA_X_IDX] = 1;
+        let mut left = [0; 5];
+        left[MOUSE_DATA_X_IDX] = (-1i8).to_be_bytes()[0];
+        vec![[0; 5], right, left, self.button_packet(self.held_buttons())]
+    }
+
+    /// Send buffered mouse events, then resync the host's button state and pointer like KVM software does on focus
+    /// gain, in case a release was missed while switching, see [Mouse::resync_packets]
+    pub fn resync(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("resync mouse");
+        }
+        self.send(hid)?;
+        for packet in self.resync_packets() {
+            self.send_packet(hid, &packet)?;
+        }
+        Ok(())
+    }
+
+    /// Enable or disable latency measurement mode. When enabled each packet is timestamped when its
+    /// first event is queued and when it is written.
+    pub fn set_latency_mode(&mut self, enabled: bool) {
+        self.latency = enabled.then(LatencyLog::new);
+        self.queued_at = None;
+    }
+
+    /// Get the packet timestamps recorded in latency measurement mode
+    pub fn latency(&self) -> Option<&LatencyLog> {
+        self.latency.as_ref()
+    }
+
+    /// Get the packet timestamps recorded in latency measurement mode, e.g. to mark packets received by a loopback host
+    pub fn latency_mut(&mut self) -> Option<&mut LatencyLog> {
+        self.latency.as_mut()
+    }
+
+    fn mark_queued(&mut self) {
+        self.forget_stale_holds();
+        if self.latency.is_some() && self.queued_at.is_none() {
+            self.queued_at = Some(Instant::now());
+        }
+    }
+
+    /// Buttons held, none if an emergency stop released them on the host after they were pressed, see [crate::stop]
+    fn held_buttons(&self) -> u8 {
+        #[cfg(feature = "hid")]
+        if self.stop_generation != stop::generation() {
+            return 0;
+        }
+        self.hold
+    }
+
+    /// Forget the held buttons and drop the buffered events if an emergency stop released everything on the host
+    /// since they were pressed
+    fn forget_stale_holds(&mut self) {
+        #[cfg(feature = "hid")]
+        if self.stop_generation != stop::generation() {
+            self.stop_generation = stop::generation();
+            self.hold = 0;
+            self.data = [0; 5];
+            self.queued_at = None;
+        }
     }
 
     /// Click mouse button
-    pub fn press_button(&mut self, button: &MouseButton) {
+    pub fn press_button(&mut self, button: MouseButton) {
         #[cfg(feature = "debug")]
         {
-            println!("press {:?}", button);
+            crate::logging::log_line!("press {:?}", button);
         }
+        self.mark_queued();
         self.data[MOUSE_DATA_BUT_IDX] |= button.to_byte();
     }
 
     /// Hold mouse button
-    pub fn hold_button(&mut self, button: &MouseButton) {
+    pub fn hold_button(&mut self, button: MouseButton) {
         #[cfg(feature = "debug")]
         {
-            println!("hold {:?}", button);
+            crate::logging::log_line!("hold {:?}", button);
         }
+        self.mark_queued();
         self.hold |= button.to_byte();
     }
 
     /// Release mouse button
-    pub fn release_button(&mut self, button: &MouseButton) {
+    pub fn release_button(&mut self, button: MouseButton) {
         #[cfg(feature = "debug")]
         {
-            println!("release {:?}", button);
+            crate::logging::log_line!("release {:?}", button);
         }
+        self.mark_queued();
         self.hold &= !button.to_byte();
     }
 
+    /// Click several mouse buttons together in the same report, e.g. a left and right chord
+    pub fn press_chord(&mut self, buttons: &[MouseButton]) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("press {:?}", buttons);
+        }
+        self.mark_queued();
+        self.data[MOUSE_DATA_BUT_IDX] |= MouseButton::chord_byte(buttons);
+    }
+
+    /// Hold several mouse buttons, pressed together in the next report
+    pub fn hold_chord(&mut self, buttons: &[MouseButton]) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("hold {:?}", buttons);
+        }
+        self.mark_queued();
+        self.hold |= MouseButton::chord_byte(buttons);
+    }
+
+    /// Release several mouse buttons, released together in the next report
+    pub fn release_chord(&mut self, buttons: &[MouseButton]) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("release {:?}", buttons);
+        }
+        self.mark_queued();
+        self.hold &= !MouseButton::chord_byte(buttons);
+    }
+
+    /// Click mouse buttons while scrolling the wheel, in the same report
+    pub fn press_chord_scroll(&mut self, buttons: &[MouseButton], displacement: Delta) {
+        self.press_chord(buttons);
+        self.scroll_wheel(displacement);
+    }
+
     /// Move mouse a relative amount in a direction
-    pub fn move_mouse(&mut self, displacement: &i8, dir: &MouseDir) {
+    pub fn move_mouse(&mut self, displacement: Delta, dir: MouseDir) {
         #[cfg(feature = "debug")]
         {
-            println!("move {:?} {:?}", displacement, dir);
+            crate::logging::log_line!("move {:?} {:?}", displacement, dir);
         }
+        self.mark_queued();
         match dir {
-            MouseDir::X => self.data[MOUSE_DATA_X_IDX] = displacement.to_be_bytes()[0],
-            MouseDir::Y => self.data[MOUSE_DATA_Y_IDX] = displacement.to_be_bytes()[0],
+            MouseDir::X => self.data[MOUSE_DATA_X_IDX] = displacement.get().to_be_bytes()[0],
+            MouseDir::Y => self.data[MOUSE_DATA_Y_IDX] = displacement.get().to_be_bytes()[0],
         }
     }
 
     /// Scroll the scroll wheel
-    pub fn scroll_wheel(&mut self, displacement: &i8) {
+    pub fn scroll_wheel(&mut self, displacement: Delta) {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("scroll {:?}", displacement);
+        }
+        self.mark_queued();
+        self.data[MOUSE_DATA_WHEL_IDX] = displacement.get().to_be_bytes()[0];
+    }
+
+    /// Scroll the wheel a number of detents (negative scrolls down) in as few reports as fit the i8 range,
+    /// sleeping pace between reports if given so hosts with smooth scrolling drivers don't skip content.
+    /// Buffered mouse events are sent first. Held buttons stay held.
+    pub fn scroll_by(&mut self, hid: &mut dyn HidBackend, detents: i32, pace: Option<Duration>) -> io::Result<()> {
         #[cfg(feature = "debug")]
         {
-            println!("scroll {:?}", displacement);
+            crate::logging::log_line!("scroll by {:?}", detents);
+        }
+        self.send(hid)?;
+        for (i, packet) in self.scroll_packets(detents).iter().enumerate() {
+            if let (Some(pace), true) = (pace, i > 0) {
+                self.clock.sleep(pace);
+            }
+            self.send_packet(hid, packet)?;
+        }
+        Ok(())
+    }
+
+    /// Raw wheel packets scrolling a number of detents in as few reports as fit the -127..=127 range, without
+    /// sending them to a HID interface. Held buttons stay held.
+    pub fn scroll_packets(&self, detents: i32) -> Vec<[u8; 5]> {
+        Delta::split(detents).into_iter()
+            .map(|delta| {
+                let mut packet = self.button_packet(self.held_buttons());
+                packet[MOUSE_DATA_WHEL_IDX] = delta.get().to_be_bytes()[0];
+                packet
+            })
+            .collect()
+    }
+
+    /// Schedule holding a button for a duration after the last scheduled report. The press and its release are
+    /// queued together, and a run that fails or is cancelled before the release releases every button instead (see
+    /// [Scheduler::run_at]), so the button is only left held if the host can't be written to at all. Other held
+    /// buttons stay held.
+    pub fn hold_button_for(&self, scheduler: &mut Scheduler, button: MouseButton, duration: Duration) {
+        let at = scheduler.end();
+        scheduler.push(at, Report::Mouse(self.button_packet(self.held_buttons() | button.to_byte())));
+        scheduler.push(at + duration, Report::Mouse(self.button_packet(self.held_buttons())));
+    }
+
+    /// Schedule momentum scrolling after the last scheduled report, like a trackpad fling.
+    /// The wheel starts at initial_velocity detents per second (negative scrolls down) and slows down
+    /// exponentially, losing friction times its velocity per second, until it drops below 1 detent per second.
+    /// Held buttons stay held in the wheel reports. Nothing is scheduled unless friction is positive.
+    pub fn scroll_kinetic(&self, scheduler: &mut Scheduler, initial_velocity: f32, friction: f32) {
+        if friction.is_nan() || friction <= 0.0 {
+            return;
+        }
+        let dt = REPORT_INTERVAL.as_secs_f32();
+        let decay = (-friction.max(0.0) * dt).exp();
+        let mut at = scheduler.end();
+        let mut velocity = initial_velocity;
+        let mut distance = 0.0;
+        while velocity.abs() >= KINETIC_MIN_VELOCITY {
+            at += REPORT_INTERVAL;
+            distance += velocity * dt;
+            let detents = distance.trunc().clamp(i8::MIN as f32, i8::MAX as f32);
+            if detents != 0.0 {
+                distance -= detents;
+                let mut packet = self.button_packet(self.held_buttons());
+                packet[MOUSE_DATA_WHEL_IDX] = (detents as i8).to_be_bytes()[0];
+                scheduler.push(at, Report::Mouse(packet));
+            }
+            velocity *= decay;
         }
-        self.data[MOUSE_DATA_WHEL_IDX] = displacement.to_be_bytes()[0];
+    }
+
+    /// Schedule drawing a path after the last scheduled report. Points are in pixels relative to the current pointer
+    /// position, with y pointing down. The pointer moves to the first point, holds the left button, traverses the path
+    /// at constant speed over duration, then releases the button, leaving the pointer at the last point. The moves are
+    /// tracked as scheduled, see [Mouse::set_tracker].
+    pub fn draw_path(&mut self, scheduler: &mut Scheduler, points: &[(i32, i32)], duration: Duration) {
+        self.draw_path_eased(scheduler, points, duration, Easing::Linear);
+    }
+
+    /// Schedule drawing a path like [Mouse::draw_path], with the progress along the path following an easing curve
+    pub fn draw_path_eased(&mut self, scheduler: &mut Scheduler, points: &[(i32, i32)], duration: Duration, easing: Easing) {
+        let Some(&start) = points.first() else {
+            return;
+        };
+        let left = MouseButton::Left.to_byte();
+        let at = scheduler.end();
+        self.schedule_move(scheduler, at, self.held_buttons(), start);
+        self.schedule_move(scheduler, at, self.held_buttons() | left, (0, 0));
+        let at = self.schedule_traverse(scheduler, at, points, duration, easing, self.held_buttons() | left);
+        scheduler.push(at, Report::Mouse(self.button_packet(self.held_buttons())));
+    }
+
+    /// Schedule moving the pointer from its current position by a relative amount over a duration, following an easing
+    /// curve. Held buttons stay held. The move is tracked as scheduled, see [Mouse::set_tracker].
+    pub fn glide_to(&mut self, scheduler: &mut Scheduler, to: (i32, i32), duration: Duration, easing: Easing) {
+        self.schedule_traverse(scheduler, scheduler.end(), &[(0, 0), to], duration, easing, self.held_buttons());
+    }
+
+    /// Schedule dragging with the left button from the current pointer position by a relative amount, see
+    /// [Mouse::draw_path_eased]
+    pub fn drag_to(&mut self, scheduler: &mut Scheduler, to: (i32, i32), duration: Duration, easing: Easing) {
+        self.draw_path_eased(scheduler, &[(0, 0), to], duration, easing);
+    }
+
+    /// Schedule traversing a path from its first point, where the pointer is, over a duration with buttons held.
+    /// Returns the offset of the last report.
+    fn schedule_traverse(
+        &mut self,
+        scheduler: &mut Scheduler,
+        mut at: Duration,
+        points: &[(i32, i32)],
+        duration: Duration,
+        easing: Easing,
+        buttons: u8,
+    ) -> Duration {
+        let lengths: Vec<f32> = points.windows(2)
+            .map(|w| ((w[1].0 - w[0].0) as f32).hypot((w[1].1 - w[0].1) as f32))
+            .collect();
+        let length: f32 = lengths.iter().sum();
+        let steps = (duration.as_secs_f32() / REPORT_INTERVAL.as_secs_f32()).ceil().max(1.0) as u32;
+
+        let mut position = points[0];
+        let mut segment = 0;
+        let mut segment_start = 0.0;
+        for step in 1..=steps {
+            at += REPORT_INTERVAL;
+            let travelled = length * easing.apply(step as f32 / steps as f32);
+            while segment + 1 < lengths.len() && travelled > segment_start + lengths[segment] {
+                segment_start += lengths[segment];
+                segment += 1;
+            }
+            let target = match lengths.get(segment) {
+                // can be past either end of the path for curves moving past their ends
+                Some(&segment_len) if segment_len > 0.0 && step < steps => {
+                    let t = (travelled - segment_start) / segment_len;
+                    let (from, to) = (points[segment], points[segment + 1]);
+                    (
+                        from.0 + ((to.0 - from.0) as f32 * t).round() as i32,
+                        from.1 + ((to.1 - from.1) as f32 * t).round() as i32,
+                    )
+                },
+                _ if step < steps => position,
+                _ => points[points.len() - 1],
+            };
+            self.schedule_move(scheduler, at, buttons, (target.0 - position.0, target.1 - position.1));
+            position = target;
+        }
+        at
+    }
+
+    /// Schedule drawing a straight line from the current pointer position, see [Mouse::draw_path]
+    pub fn draw_line(&mut self, scheduler: &mut Scheduler, to: (i32, i32), duration: Duration) {
+        self.draw_path(scheduler, &[(0, 0), to], duration);
+    }
+
+    /// Schedule drawing a rectangle with its top left corner at the current pointer position, see [Mouse::draw_path]
+    pub fn draw_rect(&mut self, scheduler: &mut Scheduler, width: i32, height: i32, duration: Duration) {
+        self.draw_path(scheduler, &[(0, 0), (width, 0), (width, height), (0, height), (0, 0)], duration);
+    }
+
+    /// Schedule drawing a circle centered on the current pointer position, see [Mouse::draw_path].
+    /// The pointer is left on the circle's rightmost point.
+    pub fn draw_circle(&mut self, scheduler: &mut Scheduler, radius: i32, duration: Duration) {
+        let points: Vec<(i32, i32)> = (0..=CIRCLE_SEGMENTS)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / CIRCLE_SEGMENTS as f32;
+                ((radius as f32 * angle.cos()).round() as i32, (radius as f32 * angle.sin()).round() as i32)
+            })
+            .collect();
+        self.draw_path(scheduler, &points, duration);
+    }
+
+    fn button_packet(&self, buttons: u8) -> [u8; 5] {
+        let mut packet = [0; 5];
+        packet[MOUSE_DATA_BUT_IDX] = buttons;
+        packet
+    }
+
+    /// Schedule a relative move, split across reports if it doesn't fit in one.
+    /// Always schedules at least one report so button changes are sent.
+    fn schedule_move(&mut self, scheduler: &mut Scheduler, at: Duration, buttons: u8, (mut dx, mut dy): (i32, i32)) {
+        loop {
+            let step_x = dx.clamp(i8::MIN as i32, i8::MAX as i32);
+            let step_y = dy.clamp(i8::MIN as i32, i8::MAX as i32);
+            let mut packet = self.button_packet(buttons);
+            packet[MOUSE_DATA_X_IDX] = (step_x as i8).to_be_bytes()[0];
+            packet[MOUSE_DATA_Y_IDX] = (step_y as i8).to_be_bytes()[0];
+            scheduler.push(at, Report::Mouse(packet));
+            if let Some(tracker) = &mut self.tracker {
+                tracker.track(&packet);
+            }
+            dx -= step_x;
+            dy -= step_y;
+            if dx == 0 && dy == 0 {
+                break;
+            }
+        }
+    }
+
+    /// Take buffered mouse events as raw packets, followed by a packet restoring held buttons, without sending them to a HID interface
+    pub fn take_packets(&mut self) -> Vec<[u8; 5]> {
+        self.forget_stale_holds();
+        self.data[MOUSE_DATA_BUT_IDX] |= self.hold;
+        let packet = self.data;
+        self.data = [0; 5];
+        if let Some(tracker) = &mut self.tracker {
+            tracker.track(&packet);
+        }
+        let mut release = [0; 5];
+        release[MOUSE_DATA_BUT_IDX] = self.hold;
+        self.queued_at = None;
+        vec![packet, release]
+    }
+
+    /// Take buffered mouse events as reports in the mouse's format, see [Mouse::take_packets] and [Mouse::set_format]
+    pub fn take_reports(&mut self) -> Vec<Vec<u8>> {
+        self.take_packets().iter().map(|packet| self.format().encode(packet)).collect()
     }
 
     /// Full buffered mouse events
-    pub fn send(&mut self, hid: &mut HID) -> io::Result<()>{
-        if self.hold == 0x00 {
-            hid.send_mouse_packet(&self.data)?;
-            self.data = [0; 5];
-            hid.send_mouse_packet(&self.data)
-        } else {
-            self.data[MOUSE_DATA_BUT_IDX] |= self.hold;
-            hid.send_mouse_packet(&self.data)?;
-            self.data = [0;5];
-            self.data[MOUSE_DATA_BUT_IDX] = self.hold;
-            let res = hid.send_mouse_packet(&self.data);
-            self.data[MOUSE_DATA_BUT_IDX] = 0;
-            res
+    pub fn send(&mut self, hid: &mut dyn HidBackend) -> io::Result<()>{
+        self.send_with(hid, None)
+    }
+
+    /// Send like [Mouse::send], checking a cancellation token between reports. If cancelled, every button is
+    /// released on the host, held buttons are cleared and a [crate::cancel::Cancelled] error is returned.
+    pub fn send_cancellable(&mut self, hid: &mut dyn HidBackend, cancel: &CancellationToken) -> io::Result<()> {
+        self.send_with(hid, Some(cancel))
+    }
+
+    fn send_with(&mut self, hid: &mut dyn HidBackend, cancel: Option<&CancellationToken>) -> io::Result<()> {
+        let queued = self.queued_at.unwrap_or_else(Instant::now);
+        let mut written = Vec::new();
+        for packet in self.take_packets() {
+            if let Some(Err(e)) = cancel.map(CancellationToken::check) {
+                self.hold = 0;
+                self.send_packet(hid, &[0; 5])?;
+                return Err(e);
+            }
+            self.send_packet(hid, &packet)?;
+            written.push(Instant::now());
+        }
+        if let Some(latency) = &mut self.latency {
+            for _ in &written {
+                latency.mark_queued_at(queued);
+            }
+            latency.record_written(&written);
         }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Mouse, MouseDir, MouseButton};
+    use std::time::Duration;
+
+    use super::{Delta, Mouse, MouseDir, MouseButton, MOUSE_DATA_BUT_IDX, MOUSE_DATA_X_IDX, MOUSE_DATA_Y_IDX, MOUSE_DATA_WHEL_IDX};
+    use crate::schedule::{Report, Scheduler};
 
     #[test]
     fn test() {
         let mut mouse = Mouse::new();
-        mouse.press_button(&MouseButton::Middle );
-        mouse.move_mouse(&127, &MouseDir::X);
-        mouse.move_mouse(&127, &MouseDir::Y);
-        mouse.scroll_wheel(&127);
+        mouse.press_button(MouseButton::Middle );
+        mouse.move_mouse(Delta::MAX, MouseDir::X);
+        mouse.move_mouse(Delta::MAX, MouseDir::Y);
+        mouse.scroll_wheel(Delta::MAX);
         for byte in mouse.data {
             println!("{:02x}", byte);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn chord_in_one_report() {
+        let mut mouse = Mouse::new();
+        mouse.press_chord_scroll(&[MouseButton::Left, MouseButton::Right], Delta::new(-2).unwrap());
+        let packets = mouse.take_packets();
+        assert_eq!(packets[0][MOUSE_DATA_BUT_IDX], 0x03);
+        assert_eq!(packets[0][MOUSE_DATA_WHEL_IDX] as i8, -2);
+        assert_eq!(packets[1], [0; 5]);
+
+        mouse.hold_chord(&[MouseButton::Left, MouseButton::Middle]);
+        mouse.release_chord(&[MouseButton::Left]);
+        assert_eq!(mouse.take_packets()[1][MOUSE_DATA_BUT_IDX], MouseButton::Middle.to_byte());
+    }
+
+    #[test]
+    fn delta_range() {
+        assert_eq!(Delta::new(-128), None);
+        assert_eq!(Delta::saturating(300), Delta::MAX);
+        assert_eq!(Delta::split(-300), vec![Delta::MIN, Delta::MIN, Delta::new(-46).unwrap()]);
+    }
+
+    #[test]
+    fn scroll_batches_detents() {
+        let mouse = Mouse::new();
+        let detents = |n| mouse.scroll_packets(n).iter().map(|packet| packet[MOUSE_DATA_WHEL_IDX] as i8).collect::<Vec<_>>();
+        assert_eq!(detents(0), vec![]);
+        assert_eq!(detents(5), vec![5]);
+        assert_eq!(detents(300), vec![127, 127, 46]);
+        assert_eq!(detents(-130), vec![-127, -3]);
+    }
+
+    #[test]
+    fn format_from_descriptor() {
+        use crate::descriptor;
+
+        let packet = [0x07, (-5i8) as u8, 10, 1, 4];
+        assert_eq!(descriptor::mouse_format(descriptor::MOUSE).unwrap().encode(&packet), packet.to_vec());
+
+        let boot = Mouse::from_descriptor(&[
+            0x05, 0x01, 0x09, 0x02, 0xA1, 0x01, 0x09, 0x01, 0xA1, 0x00,
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x02, 0x15, 0x00, 0x25, 0x01, 0x95, 0x02, 0x75, 0x01, 0x81, 0x02,
+            0x95, 0x01, 0x75, 0x06, 0x81, 0x01,
+            0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7F, 0x75, 0x08, 0x95, 0x02, 0x81, 0x06,
+            0xC0, 0xC0,
+        ]).unwrap();
+        assert_eq!(boot.format().encode(&packet), vec![0x03, 0xFB, 0x0A]);
+
+        let mut wide = Mouse::from_descriptor(&[
+            0x05, 0x01, 0x09, 0x02, 0xA1, 0x01, 0x85, 0x02, 0x09, 0x01, 0xA1, 0x00,
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x05, 0x15, 0x00, 0x25, 0x01, 0x95, 0x05, 0x75, 0x01, 0x81, 0x02,
+            0x95, 0x01, 0x75, 0x03, 0x81, 0x01,
+            0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x16, 0x01, 0x80, 0x26, 0xFF, 0x7F, 0x75, 0x10, 0x95, 0x02, 0x81, 0x06,
+            0x09, 0x38, 0x15, 0x81, 0x25, 0x7F, 0x75, 0x08, 0x95, 0x01, 0x81, 0x06,
+            0xC0, 0xC0,
+        ]).unwrap();
+        assert_eq!(wide.format().encode(&packet), vec![0x02, 0x07, 0xFB, 0xFF, 0x0A, 0x00, 0x01]);
+        wide.hold_button(MouseButton::Left);
+        assert_eq!(wide.take_reports()[1], vec![0x02, 0x01, 0, 0, 0, 0, 0]);
+
+        assert!(descriptor::mouse_format(descriptor::DIAL).is_err());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn scheduled_glide_in_backend_format() {
+        use std::{fs, sync::Arc, time::Duration};
+
+        use crate::{clock::VirtualClock, descriptor, easing::Easing, schedule::Scheduler, HID};
+
+        let boot = descriptor::mouse_format(&[
+            0x05, 0x01, 0x09, 0x02, 0xA1, 0x01, 0x09, 0x01, 0xA1, 0x00,
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x02, 0x15, 0x00, 0x25, 0x01, 0x95, 0x02, 0x75, 0x01, 0x81, 0x02,
+            0x95, 0x01, 0x75, 0x06, 0x81, 0x01,
+            0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7F, 0x75, 0x08, 0x95, 0x02, 0x81, 0x06,
+            0xC0, 0xC0,
+        ]).unwrap();
+        let mut hid = HID::new("", "", "").unwrap();
+        hid.set_mouse_format(Some(boot));
+
+        let mut mouse = Mouse::new();
+        mouse.hold_button(MouseButton::Left);
+        let mut scheduler = Scheduler::new();
+        scheduler.set_clock(Arc::new(VirtualClock::new()));
+        mouse.glide_to(&mut scheduler, (30, -12), Duration::from_millis(50), Easing::Linear);
+        let scheduled = scheduler.reports().len();
+        scheduler.run(&mut hid).unwrap();
+
+        let written = fs::read(hid.get_mouse_path()).unwrap();
+        assert_eq!(written.len(), scheduled * 3);
+        let reports: Vec<_> = written.chunks(3).collect();
+        assert!(reports.iter().all(|report| report[0] == 0x01));
+        let moved = reports.iter().fold((0, 0), |(x, y), report| (x + report[1] as i8 as i32, y + report[2] as i8 as i32));
+        assert_eq!(moved, (30, -12));
+    }
+
+    #[test]
+    fn hold_button_for_schedules_release() {
+        let mut mouse = Mouse::new();
+        mouse.hold_button(MouseButton::Middle);
+        let mut scheduler = Scheduler::new();
+        mouse.hold_button_for(&mut scheduler, MouseButton::Left, Duration::from_millis(300));
+        let reports: Vec<(Duration, u8)> = scheduler.reports().iter()
+            .map(|scheduled| match scheduled.report {
+                Report::Mouse(packet) => (scheduled.at, packet[MOUSE_DATA_BUT_IDX]),
+                _ => panic!("expected mouse report"),
+            })
+            .collect();
+        assert_eq!(reports, vec![(Duration::ZERO, 0x05), (Duration::from_millis(300), 0x04)]);
+    }
+
+    #[test]
+    fn hold_button_for_releases_after_failed_send() {
+        use std::{io, sync::Arc};
+
+        use crate::{clock::VirtualClock, easing::Easing, HidBackend};
+
+        /// Fails the second mouse packet
+        #[derive(Default)]
+        struct Flaky {
+            mouse: Vec<Vec<u8>>,
+            attempts: usize,
+        }
+
+        impl HidBackend for Flaky {
+            fn send_key_packet(&mut self, _data: &[u8]) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                self.attempts += 1;
+                if self.attempts == 2 {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "unplugged"));
+                }
+                self.mouse.push(data.to_vec());
+                Ok(())
+            }
+
+            fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+                Ok(None)
+            }
+        }
+
+        let mut mouse = Mouse::new();
+        let mut scheduler = Scheduler::new();
+        scheduler.set_clock(Arc::new(VirtualClock::new()));
+        mouse.hold_button_for(&mut scheduler, MouseButton::Left, Duration::from_millis(100));
+        mouse.glide_to(&mut scheduler, (10, 0), Duration::from_millis(50), Easing::Linear);
+
+        let mut flaky = Flaky::default();
+        assert_eq!(scheduler.run(&mut flaky).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert!(scheduler.is_empty());
+        assert_eq!(flaky.mouse.first().map(|packet| packet[MOUSE_DATA_BUT_IDX]), Some(0x01));
+        assert_eq!(flaky.mouse.last(), Some(&vec![0; 5]));
+    }
+
+    #[test]
+    fn kinetic_scroll_decays() {
+        let mouse = Mouse::new();
+        let mut scheduler = Scheduler::new();
+        mouse.scroll_kinetic(&mut scheduler, 100.0, 4.0);
+        let detents: Vec<i8> = scheduler.reports().iter()
+            .map(|scheduled| match scheduled.report {
+                Report::Mouse(packet) => packet[MOUSE_DATA_WHEL_IDX] as i8,
+                _ => panic!("expected mouse report"),
+            })
+            .collect();
+        assert!(detents.iter().all(|detent| *detent > 0));
+        // distance travelled approaches initial velocity / friction
+        let total: i32 = detents.iter().map(|detent| *detent as i32).sum();
+        assert!((20..=25).contains(&total), "{}", total);
+        let gaps: Vec<_> = scheduler.reports().windows(2).map(|w| w[1].at - w[0].at).collect();
+        assert!(gaps.first() <= gaps.last());
+    }
+
+    #[test]
+    fn draw_rect_returns_to_start() {
+        let mut mouse = Mouse::new();
+        let mut scheduler = Scheduler::new();
+        mouse.draw_rect(&mut scheduler, 300, 40, Duration::from_millis(200));
+        let packets: Vec<[u8; 5]> = scheduler.reports().iter()
+            .map(|scheduled| match scheduled.report {
+                Report::Mouse(packet) => packet,
+                _ => panic!("expected mouse report"),
+            })
+            .collect();
+        let (mut x, mut y) = (0, 0);
+        for packet in &packets[1..packets.len() - 1] {
+            assert_eq!(packet[MOUSE_DATA_BUT_IDX], MouseButton::Left.to_byte());
+            x += packet[MOUSE_DATA_X_IDX] as i8 as i32;
+            y += packet[MOUSE_DATA_Y_IDX] as i8 as i32;
+        }
+        assert_eq!((x, y), (0, 0));
+        assert_eq!(packets.last().unwrap()[MOUSE_DATA_BUT_IDX], 0);
+    }
+
+    #[test]
+    fn tracks_pointer_position() {
+        use super::PointerTracker;
+        use crate::easing::Easing;
+
+        let mut tracker = PointerTracker::new();
+        tracker.set_gain(2.0);
+        tracker.set_bounds(Some((1920, 1080)));
+        let mut mouse = Mouse::new();
+        mouse.set_tracker(Some(tracker));
+        mouse.move_mouse(Delta::new(100).unwrap(), MouseDir::X);
+        mouse.move_mouse(Delta::new(-10).unwrap(), MouseDir::Y);
+        mouse.take_packets();
+        assert_eq!(mouse.tracker().unwrap().position(), (200, 0));
+
+        let moves = mouse.tracker().unwrap().moves_to(500, 301);
+        assert_eq!(moves.len(), 2);
+        let tracker = mouse.tracker_mut().unwrap();
+        for (dx, dy) in moves {
+            tracker.track_move(dx.into(), dy.into());
+        }
+        assert_eq!(tracker.position(), (500, 302));
+        tracker.reset(5000, -5);
+        assert_eq!(tracker.position(), (1919, 0));
+
+        tracker.reset(0, 0);
+        let mut scheduler = Scheduler::new();
+        mouse.glide_to(&mut scheduler, (300, 20), Duration::from_millis(50), Easing::EaseInOut);
+        mouse.draw_line(&mut scheduler, (-50, 0), Duration::from_millis(50));
+        assert_eq!(mouse.tracker().unwrap().position(), (500, 40));
+    }
+
+    #[test]
+    fn eased_glide() {
+        use crate::easing::Easing;
+
+        let mut mouse = Mouse::new();
+        let mut moves = |easing| {
+            let mut scheduler = Scheduler::new();
+            mouse.glide_to(&mut scheduler, (100, 0), Duration::from_millis(160), easing);
+            scheduler.reports().iter()
+                .map(|scheduled| match scheduled.report {
+                    Report::Mouse(packet) => packet[MOUSE_DATA_X_IDX] as i8 as i32,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<i32>>()
+        };
+        let linear = moves(Easing::Linear);
+        assert_eq!(linear, vec![10; 10]);
+        let eased = moves(Easing::EaseInOut);
+        assert_eq!(eased.iter().sum::<i32>(), 100);
+        assert!(eased[0] < 10 && eased[5] > 10);
+        let overshoot = moves(Easing::Overshoot(crate::easing::OVERSHOOT));
+        assert_eq!(overshoot.iter().sum::<i32>(), 100);
+        assert!(overshoot.last().unwrap() < &0);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn resync_restores_held_buttons() {
+        use super::{Mouse, MouseButton};
+        use crate::HID;
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut mouse = Mouse::new();
+        mouse.hold_button(MouseButton::Left);
+        mouse.resync(&mut hid).unwrap();
+        let written = std::fs::read(hid.get_mouse_path()).unwrap();
+        assert_eq!(written.chunks(5).skip(2).collect::<Vec<_>>(), vec![
+            &[0, 0, 0, 0, 0][..],
+            &[0, 1, 0, 0, 0][..],
+            &[0, 0xFF, 0, 0, 0][..],
+            &[1, 0, 0, 0, 0][..],
+        ]);
+    }
+}
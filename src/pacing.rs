@@ -0,0 +1,72 @@
+#![warn(missing_docs)]
+
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// Pacing that follows the host's load, see [crate::key::Keyboard::set_adaptive_pacing]. While sending, a ScrollLock
+/// heartbeat (see [crate::HID::heartbeat]) is timed every interval: slow echoes double the wait between key packets,
+/// fast echoes shorten it by a quarter, and a missed echo waits the most.
+pub struct AdaptivePacing {
+    /// Shortest wait between key packets
+    pub min: Duration,
+    /// Longest wait between key packets
+    pub max: Duration,
+    /// Echo round trip of a responsive host. Slower echoes slow down typing, echoes under half of it speed it up.
+    pub target_rtt: Duration,
+    /// Time between heartbeats
+    pub interval: Duration,
+    /// Longest wait for a heartbeat echo
+    pub timeout: Duration,
+}
+
+impl Default for AdaptivePacing {
+    fn default() -> Self {
+        AdaptivePacing {
+            min: Duration::ZERO,
+            max: Duration::from_millis(100),
+            target_rtt: Duration::from_millis(20),
+            interval: Duration::from_secs(2),
+            timeout: Duration::from_millis(500),
+        }
+    }
+}
+
+impl AdaptivePacing {
+    /// Wait between key packets after a heartbeat, from the current wait and the echo round trip (None if the host
+    /// didn't echo)
+    pub fn adjust(&self, pacing: Duration, rtt: Option<Duration>) -> Duration {
+        let pacing = match rtt {
+            None => self.max,
+            // slow down from at least a millisecond, doubling zero would never leave it
+            Some(rtt) if rtt > self.target_rtt => pacing.max(Duration::from_millis(1)).saturating_mul(2),
+            Some(rtt) if rtt < self.target_rtt / 2 => pacing * 3 / 4,
+            Some(_) => pacing,
+        };
+        pacing.clamp(self.min, self.max.max(self.min))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::AdaptivePacing;
+
+    #[test]
+    fn adjusts_to_echo_latency() {
+        let ms = Duration::from_millis;
+        let adaptive = AdaptivePacing { min: ms(2), max: ms(40), target_rtt: ms(20), ..AdaptivePacing::default() };
+        assert_eq!(adaptive.adjust(ms(8), Some(ms(30))), ms(16));
+        assert_eq!(adaptive.adjust(ms(32), Some(ms(30))), ms(40));
+        assert_eq!(adaptive.adjust(ms(8), Some(ms(15))), ms(8));
+        assert_eq!(adaptive.adjust(ms(8), Some(ms(5))), ms(6));
+        assert_eq!(adaptive.adjust(ms(2), Some(ms(5))), ms(2));
+        assert_eq!(adaptive.adjust(ms(8), None), ms(40));
+
+        let unbounded = AdaptivePacing { min: Duration::ZERO, ..adaptive };
+        assert_eq!(unbounded.adjust(Duration::ZERO, Some(ms(30))), ms(2));
+    }
+}
@@ -0,0 +1,248 @@
+#![warn(missing_docs)]
+
+use std::{collections::HashMap, fs, io::{self, Read}, path::{Path, PathBuf}};
+
+use sha2::{Digest, Sha256};
+
+use crate::keymap::KeyTable;
+
+/// Manifest of a layout pack, listing its files with their SHA-256 checksums in `sha256sum` format
+pub const MANIFEST: &str = "SHA256SUMS";
+/// Largest file fetched from a URL
+const MAX_FETCH_LEN: u64 = 16 * 1024 * 1024;
+
+/// SHA-256 checksum of data as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// File listed in a pack manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// File name, without directories
+    pub file: String,
+    /// SHA-256 checksum as lowercase hex
+    pub sha256: String,
+}
+
+/// Parse a `sha256sum` style manifest, one "checksum  file" line per file. Fails on malformed lines and on files
+/// outside the pack's directory.
+pub fn parse_manifest(manifest: &str) -> io::Result<Vec<ManifestEntry>> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid manifest line {:?}", line));
+    manifest.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (sha256, file) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            // sha256sum marks files read in binary mode with '*'
+            let file = file.trim_start().trim_start_matches('*');
+            let valid_sha256 = sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit());
+            let valid_file = !file.is_empty() && !file.contains(['/', '\\']) && file != "." && file != "..";
+            match valid_sha256 && valid_file {
+                true => Ok(ManifestEntry { file: file.to_string(), sha256: sha256.to_ascii_lowercase() }),
+                false => Err(invalid(line)),
+            }
+        })
+        .collect()
+}
+
+/// Set of layout definitions loaded at runtime, so layouts can be added or updated without recompiling.
+/// Console keymaps (.map) and XKB symbols (.xkb) files are listed in a [MANIFEST] with their checksums, and named
+/// by their file stem, e.g. "de-nodeadkeys.map" is the "de-nodeadkeys" layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutPack {
+    layouts: HashMap<String, KeyTable>,
+}
+
+impl LayoutPack {
+    /// Load the files listed in a manifest, reading each with read and verifying its checksum.
+    /// Fails if a file can't be read, doesn't match its checksum or isn't a layout definition.
+    pub fn from_manifest(manifest: &str, mut read: impl FnMut(&str) -> io::Result<Vec<u8>>) -> io::Result<LayoutPack> {
+        let mut layouts = HashMap::new();
+        for entry in parse_manifest(manifest)? {
+            let data = read(&entry.file)?;
+            verify(&entry, &data)?;
+            let (name, table) = parse_layout(&entry.file, data)?;
+            layouts.insert(name, table);
+        }
+        Ok(LayoutPack { layouts })
+    }
+
+    /// Load a pack from a directory holding a [MANIFEST] and the files it lists
+    pub fn load_dir(dir: impl AsRef<Path>) -> io::Result<LayoutPack> {
+        let dir = dir.as_ref();
+        let manifest = fs::read_to_string(dir.join(MANIFEST))?;
+        LayoutPack::from_manifest(&manifest, |file| fs::read(dir.join(file)))
+    }
+
+    /// Table of a layout, for [crate::key::Keyboard::set_key_table]
+    pub fn get(&self, name: &str) -> Option<&KeyTable> {
+        self.layouts.get(name)
+    }
+
+    /// Layout names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.layouts.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Number of layouts
+    pub fn len(&self) -> usize {
+        self.layouts.len()
+    }
+
+    /// Check if there are no layouts
+    pub fn is_empty(&self) -> bool {
+        self.layouts.is_empty()
+    }
+}
+
+fn verify(entry: &ManifestEntry, data: &[u8]) -> io::Result<()> {
+    match sha256_hex(data) == entry.sha256 {
+        true => Ok(()),
+        false => Err(io::Error::new(io::ErrorKind::InvalidData, format!("checksum mismatch for {}", entry.file))),
+    }
+}
+
+fn parse_layout(file: &str, data: Vec<u8>) -> io::Result<(String, KeyTable)> {
+    let path = Path::new(file);
+    let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(file).to_string();
+    let text = String::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let table = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("map") => KeyTable::from_console_keymap(&text),
+        Some("xkb") => KeyTable::from_xkb_symbols(&text),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} isn't a .map or .xkb file", file))),
+    };
+    Ok((name, table))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Where a [LayoutPackLoader] gets packs from
+pub enum PackSource {
+    /// Local directory holding a [MANIFEST] and the files it lists
+    Dir(PathBuf),
+    /// Base URL the [MANIFEST] and the files it lists are fetched from
+    Url(String),
+}
+
+/// Loads and refreshes a [LayoutPack] from a directory or URL, for deployments that can't recompile to get new
+/// layouts. Fetched packs are verified against their manifest, which can itself be pinned to a checksum, and saved
+/// to a cache directory so the last good pack still loads while the URL is unreachable.
+#[derive(Debug, Clone)]
+pub struct LayoutPackLoader {
+    source: PackSource,
+    cache_dir: Option<PathBuf>,
+    manifest_sha256: Option<String>,
+}
+
+impl LayoutPackLoader {
+    /// New, loading from a source
+    pub fn new(source: PackSource) -> LayoutPackLoader {
+        LayoutPackLoader { source, cache_dir: None, manifest_sha256: None }
+    }
+
+    /// Set a directory fetched packs are saved to, and loaded from if fetching fails. None doesn't cache (the default).
+    pub fn set_cache_dir(&mut self, cache_dir: Option<PathBuf>) {
+        self.cache_dir = cache_dir;
+    }
+
+    /// Pin the manifest to a SHA-256 checksum (hex), refusing any other manifest. None trusts any manifest.
+    pub fn set_manifest_checksum(&mut self, manifest_sha256: Option<String>) {
+        self.manifest_sha256 = manifest_sha256.map(|sha256| sha256.to_ascii_lowercase());
+    }
+
+    /// Load the pack from the source, fetching it again if it's a URL
+    pub fn load(&self) -> io::Result<LayoutPack> {
+        match &self.source {
+            PackSource::Dir(dir) => {
+                let manifest = fs::read(dir.join(MANIFEST))?;
+                self.load_manifest(&manifest, |file| fs::read(dir.join(file)))
+            },
+            PackSource::Url(url) => match (self.fetch(url), &self.cache_dir) {
+                (Err(_e), Some(cache_dir)) => {
+                    #[cfg(feature = "debug")]
+                    {
+                        crate::logging::log_line!("fetching layout pack failed, loading cache: {}", _e);
+                    }
+                    let manifest = fs::read(cache_dir.join(MANIFEST))?;
+                    self.load_manifest(&manifest, |file| fs::read(cache_dir.join(file)))
+                },
+                (pack, _) => pack,
+            },
+        }
+    }
+
+    fn load_manifest(&self, manifest: &[u8], read: impl FnMut(&str) -> io::Result<Vec<u8>>) -> io::Result<LayoutPack> {
+        if let Some(pinned) = &self.manifest_sha256 {
+            if sha256_hex(manifest) != *pinned {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "manifest doesn't match its pinned checksum"));
+            }
+        }
+        let manifest = std::str::from_utf8(manifest).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        LayoutPack::from_manifest(manifest, read)
+    }
+
+    /// Fetch and verify every file before replacing the cache, so a failed refresh leaves the last good pack
+    fn fetch(&self, url: &str) -> io::Result<LayoutPack> {
+        let base = url.trim_end_matches('/');
+        let manifest = fetch(&format!("{}/{}", base, MANIFEST))?;
+        let mut files = Vec::new();
+        let pack = self.load_manifest(&manifest, |file| {
+            let data = fetch(&format!("{}/{}", base, file))?;
+            files.push((file.to_string(), data.clone()));
+            Ok(data)
+        })?;
+        if let Some(cache_dir) = &self.cache_dir {
+            fs::create_dir_all(cache_dir)?;
+            for (file, data) in files.iter().chain([(MANIFEST.to_string(), manifest)].iter()) {
+                let partial = cache_dir.join(format!("{}.partial", file));
+                fs::write(&partial, data)?;
+                fs::rename(partial, cache_dir.join(file))?;
+            }
+        }
+        Ok(pack)
+    }
+}
+
+fn fetch(url: &str) -> io::Result<Vec<u8>> {
+    let response = ureq::get(url).call().map_err(io::Error::other)?;
+    let mut data = Vec::new();
+    response.into_reader().take(MAX_FETCH_LEN).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{parse_manifest, sha256_hex, LayoutPack, LayoutPackLoader, PackSource, MANIFEST};
+
+    #[test]
+    fn loads_verified_packs() {
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(parse_manifest(&format!("{}  ../escape.map", "0".repeat(64))).is_err());
+        assert!(parse_manifest("abc  us.map").is_err());
+
+        let dir = env::temp_dir().join(format!("virt-hid-pack-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let keymap = "keycode 16 = +a\n";
+        fs::write(dir.join("qwerty-a.map"), keymap).unwrap();
+        let manifest = format!("{}  qwerty-a.map\n", sha256_hex(keymap.as_bytes()));
+        fs::write(dir.join(MANIFEST), &manifest).unwrap();
+
+        let pack = LayoutPack::load_dir(&dir).unwrap();
+        assert_eq!(pack.names(), vec!["qwerty-a"]);
+        assert_eq!(pack.get("qwerty-a").unwrap().get('a'), Some([0x00, 0x14]));
+
+        let mut loader = LayoutPackLoader::new(PackSource::Dir(dir.clone()));
+        loader.set_manifest_checksum(Some(sha256_hex(manifest.as_bytes()).to_uppercase()));
+        assert_eq!(loader.load().unwrap(), pack);
+        loader.set_manifest_checksum(Some(sha256_hex(b"other")));
+        assert!(loader.load().is_err());
+
+        fs::write(dir.join("qwerty-a.map"), "keycode 16 = +q\n").unwrap();
+        assert_eq!(LayoutPack::load_dir(&dir).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+#![warn(missing_docs)]
+
+use std::{fs, io, path::{Path, PathBuf}, thread, time::{Duration, Instant}};
+
+use num_enum::{IntoPrimitive, FromPrimitive};
+use serde::{Serialize, Deserialize};
+
+const UDC_CLASS_PATH: &str = "/sys/class/udc";
+const STATE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How long a remote wakeup waits for the host to resume
+pub const RESUME_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// USB device controller state, as reported in sysfs
+pub enum UdcState {
+    /// No host connected
+    NotAttached,
+    /// Connected but not configured by the host yet
+    Connecting,
+    /// Configured by the host and running
+    Configured,
+    /// Host suspended the bus, e.g. the host is asleep
+    Suspended,
+    /// Unknown state
+    Unknown,
+}
+
+impl UdcState {
+    /// Parse a sysfs state string
+    pub fn parse(state: &str) -> UdcState {
+        match state.trim() {
+            "not attached" => UdcState::NotAttached,
+            "attached" | "powered" | "reconnecting" | "unauthenticated" | "default" | "addressed" => UdcState::Connecting,
+            "configured" => UdcState::Configured,
+            "suspended" => UdcState::Suspended,
+            _ => UdcState::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[repr(u32)]
+/// What a keyboard does with keystrokes sent while the host is suspended
+pub enum SuspendPolicy {
+    /// Keep them buffered until a send after the host resumes
+    #[default]
+    Queue,
+    /// Ask the host to wake up and send them if it resumes within [RESUME_TIMEOUT], queue them otherwise
+    Wakeup,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// USB device controller the gadget is bound to
+pub struct Udc {
+    path: PathBuf,
+}
+
+impl Udc {
+    /// USB device controller by name, e.g. fe980000.usb
+    pub fn new(name: &str) -> Udc {
+        Udc::from_path(Path::new(UDC_CLASS_PATH).join(name))
+    }
+
+    /// USB device controller at a sysfs path
+    pub fn from_path(path: impl Into<PathBuf>) -> Udc {
+        Udc { path: path.into() }
+    }
+
+    /// First USB device controller found
+    pub fn first() -> io::Result<Udc> {
+        let mut names: Vec<_> = fs::read_dir(UDC_CLASS_PATH)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect();
+        names.sort();
+        names.into_iter().next()
+            .map(Udc::from_path)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no USB device controller"))
+    }
+
+    /// Current state
+    pub fn state(&self) -> io::Result<UdcState> {
+        Ok(UdcState::parse(&fs::read_to_string(self.path.join("state"))?))
+    }
+
+    /// Check if the host suspended the bus
+    pub fn is_suspended(&self) -> io::Result<bool> {
+        Ok(self.state()? == UdcState::Suspended)
+    }
+
+    /// Signal remote wakeup to the host. The gadget's configuration must have remote wakeup enabled.
+    pub fn wakeup(&self) -> io::Result<()> {
+        fs::write(self.path.join("srp"), "1")
+    }
+
+    /// Wait for the host to resume with a timeout. Returns false if it's still suspended.
+    pub fn wait_resumed(&self, timeout: Duration) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if !self.is_suspended()? {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(STATE_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::{Udc, UdcState};
+
+    #[test]
+    fn reads_state() {
+        let path = env::temp_dir().join(format!("virt-hid-udc-{}", process::id()));
+        fs::create_dir_all(&path).unwrap();
+        let udc = Udc::from_path(&path);
+        fs::write(path.join("state"), "suspended\n").unwrap();
+        assert!(udc.is_suspended().unwrap());
+        fs::write(path.join("state"), "configured\n").unwrap();
+        assert_eq!(udc.state().unwrap(), UdcState::Configured);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
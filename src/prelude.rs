@@ -0,0 +1,9 @@
+#![warn(missing_docs)]
+
+pub use crate::accel::Accel;
+pub use crate::backend::HidBackend;
+pub use crate::config::{Config, HostOS};
+pub use crate::key::{BasicKey, KeyOrigin, KeyPacket, Keyboard, LEDState, LEDStatePacket, Modifier, SpecialKey};
+pub use crate::mouse::{Mouse, MouseButton};
+#[cfg(feature = "hid")]
+pub use crate::io::HID;
@@ -0,0 +1,332 @@
+#![warn(missing_docs)]
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{decode::{KeyAction, KeyDecoder}, key::{KeyPacket, ReportProtocol}, mouse::MouseFormat, HidBackend};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Summary of everything sent during a [SessionRecorder] session
+pub struct SessionSummary {
+    /// Session name
+    pub name: String,
+    /// When the session started
+    pub started: SystemTime,
+    /// How long the session lasted
+    pub duration: Duration,
+    /// Presses per key and modifier, by name (see [crate::decode::keycode_name])
+    pub key_presses: BTreeMap<String, u64>,
+    /// Key packets sent
+    pub key_packets: u64,
+    /// Mouse packets sent
+    pub mouse_packets: u64,
+    /// Reports sent per registered interface, see [crate::HID::register_device]
+    pub device_reports: BTreeMap<String, u64>,
+    /// Send errors, in order
+    pub errors: Vec<String>,
+    /// Whether sends were refused after the session's time limit
+    pub timed_out: bool,
+}
+
+impl SessionSummary {
+    /// Append the summary and its key counts to a report file
+    pub fn append_to(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self)?;
+        for (key, presses) in &self.key_presses {
+            writeln!(file, "    {} x{}", key, presses)?;
+        }
+        for error in &self.errors {
+            writeln!(file, "    error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let started = self.started.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        write!(
+            f,
+            "[{}] {}: {} key presses, {} key packets, {} mouse packets, {} device reports, {} errors in {:?}{}",
+            started,
+            self.name,
+            self.key_presses.values().sum::<u64>(),
+            self.key_packets,
+            self.mouse_packets,
+            self.device_reports.values().sum::<u64>(),
+            self.errors.len(),
+            self.duration,
+            if self.timed_out { " (timed out)" } else { "" },
+        )
+    }
+}
+
+/// Report sent through a HID interface
+enum Recorded<'a> {
+    Keyboard,
+    Mouse,
+    Device(&'a str),
+}
+
+/// Sends recorded by a [SessionRecorder]
+struct SessionLog {
+    summary: SessionSummary,
+    start: Instant,
+    deadline: Option<Instant>,
+    decoder: KeyDecoder,
+}
+
+impl SessionLog {
+    fn new(name: &str, time_limit: Option<Duration>) -> SessionLog {
+        let start = Instant::now();
+        SessionLog {
+            summary: SessionSummary {
+                name: name.to_string(),
+                started: SystemTime::now(),
+                duration: Duration::ZERO,
+                key_presses: BTreeMap::new(),
+                key_packets: 0,
+                mouse_packets: 0,
+                device_reports: BTreeMap::new(),
+                errors: Vec::new(),
+                timed_out: false,
+            },
+            start,
+            deadline: time_limit.map(|limit| start + limit),
+            decoder: KeyDecoder::new(),
+        }
+    }
+
+    fn finish(mut self) -> SessionSummary {
+        self.summary.duration = self.start.elapsed();
+        self.summary
+    }
+
+    fn count(&mut self, report: Recorded<'_>, data: &[u8]) {
+        match report {
+            Recorded::Keyboard => {
+                self.summary.key_packets += 1;
+                let Some(packet) = KeyPacket::from_bytes(data) else {
+                    return;
+                };
+                for event in self.decoder.decode(packet) {
+                    if event.action != KeyAction::Press {
+                        continue;
+                    }
+                    let names = event.modifiers.iter().map(|modifier| format!("{:?}", modifier)).chain(event.keys);
+                    for name in names {
+                        *self.summary.key_presses.entry(name).or_insert(0) += 1;
+                    }
+                }
+            },
+            Recorded::Mouse => self.summary.mouse_packets += 1,
+            Recorded::Device(name) => *self.summary.device_reports.entry(name.to_string()).or_insert(0) += 1,
+        }
+    }
+}
+
+/// Send a report, recording it in a session log if there is one. Fails with [io::ErrorKind::TimedOut] without sending
+/// if the session's time limit has passed, unless the report is all zeros, releasing every key or button, so a
+/// session never ends with something held.
+fn record(
+    log: &mut Option<SessionLog>,
+    report: Recorded<'_>,
+    data: &[u8],
+    send: impl FnOnce() -> io::Result<()>,
+) -> io::Result<()> {
+    let Some(log) = log else {
+        return send();
+    };
+    let result = match log.deadline {
+        Some(deadline) if Instant::now() >= deadline && data.iter().any(|byte| *byte != 0) => {
+            log.summary.timed_out = true;
+            Err(io::Error::new(io::ErrorKind::TimedOut, format!("session {:?} time limit reached", log.summary.name)))
+        },
+        _ => send(),
+    };
+    match &result {
+        Ok(()) => log.count(report, data),
+        Err(e) => log.summary.errors.push(e.to_string()),
+    }
+    result
+}
+
+/// Records everything sent through a HID backend while it's alive, for per-session reports of what was injected.
+/// Use it in place of the backend (it's a [HidBackend] itself), then [SessionRecorder::finish] it for the
+/// [SessionSummary]. If it's dropped without finishing, the summary goes to the handler set with
+/// [SessionRecorder::on_drop], if any. Sessions can be time boxed, refusing sends after a time limit except releases.
+pub struct SessionRecorder<'a> {
+    backend: &'a mut dyn HidBackend,
+    log: Option<SessionLog>,
+    on_drop: Option<Box<dyn FnOnce(SessionSummary) + 'a>>,
+}
+
+impl<'a> SessionRecorder<'a> {
+    /// Start recording a session
+    pub fn new(backend: &'a mut dyn HidBackend, name: &str) -> SessionRecorder<'a> {
+        SessionRecorder::start(backend, name, None)
+    }
+
+    /// Start recording a session refusing sends after a time limit, failing them with [io::ErrorKind::TimedOut].
+    /// All-zero reports releasing every key or button still go through.
+    pub fn with_time_limit(backend: &'a mut dyn HidBackend, name: &str, time_limit: Duration) -> SessionRecorder<'a> {
+        SessionRecorder::start(backend, name, Some(time_limit))
+    }
+
+    fn start(backend: &'a mut dyn HidBackend, name: &str, time_limit: Option<Duration>) -> SessionRecorder<'a> {
+        SessionRecorder { backend, log: Some(SessionLog::new(name, time_limit)), on_drop: None }
+    }
+
+    /// Record a session around a block using the backend, returning the block's result with the summary
+    pub fn record<R>(backend: &mut dyn HidBackend, name: &str, block: impl FnOnce(&mut SessionRecorder<'_>) -> R) -> (R, SessionSummary) {
+        let mut recorder = SessionRecorder::new(backend, name);
+        let result = block(&mut recorder);
+        (result, recorder.finish())
+    }
+
+    /// Set a handler for the summary if the recorder is dropped without finishing, e.g. by an early return
+    pub fn on_drop(&mut self, handler: impl FnOnce(SessionSummary) + 'a) {
+        self.on_drop = Some(Box::new(handler));
+    }
+
+    /// Summary of the session so far
+    pub fn summary(&mut self) -> SessionSummary {
+        match &self.log {
+            Some(log) => SessionSummary { duration: log.start.elapsed(), ..log.summary.clone() },
+            None => SessionLog::new("", None).finish(),
+        }
+    }
+
+    /// Stop recording and return the summary
+    pub fn finish(mut self) -> SessionSummary {
+        self.take_summary()
+    }
+
+    fn take_summary(&mut self) -> SessionSummary {
+        self.log.take().map(SessionLog::finish).unwrap_or_else(|| SessionLog::new("", None).finish())
+    }
+}
+
+impl HidBackend for SessionRecorder<'_> {
+    fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        record(&mut self.log, Recorded::Keyboard, data, || self.backend.send_key_packet(data))
+    }
+
+    fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        record(&mut self.log, Recorded::Mouse, data, || self.backend.send_mouse_packet(data))
+    }
+
+    fn send_device_report(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        record(&mut self.log, Recorded::Device(name), data, || self.backend.send_device_report(name, data))
+    }
+
+    fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        self.backend.receive_states_packet(timeout)
+    }
+
+    fn keyboard_protocol(&self) -> ReportProtocol {
+        self.backend.keyboard_protocol()
+    }
+
+    fn mouse_format(&self) -> Option<&MouseFormat> {
+        self.backend.mouse_format()
+    }
+
+    fn last_led_state(&self) -> Option<u8> {
+        self.backend.last_led_state()
+    }
+
+    fn wait_led_state(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        self.backend.wait_led_state(timeout)
+    }
+}
+
+impl Drop for SessionRecorder<'_> {
+    fn drop(&mut self) {
+        if self.log.is_none() {
+            return;
+        }
+        let summary = self.take_summary();
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("{}", summary);
+        }
+        if let Some(handler) = self.on_drop.take() {
+            handler(summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn summarises_sessions() {
+        use std::{cell::RefCell, time::Duration};
+
+        use super::SessionRecorder;
+        use crate::{key::Keyboard, HidBackend, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        let (sent, summary) = SessionRecorder::record(&mut hid, "login", |hid| {
+            keyboard.type_string("Aa");
+            keyboard.flush(hid)?;
+            hid.send_mouse_packet(&[0; 5])?;
+            hid.send_device_report("missing", &[0])
+        });
+        assert!(sent.is_err());
+        assert_eq!(summary.name, "login");
+        assert_eq!(summary.key_presses.get("KeyA"), Some(&2));
+        assert_eq!(summary.key_presses.get("LeftShift"), Some(&1));
+        assert_eq!(summary.mouse_packets, 1);
+        assert_eq!(summary.errors.len(), 1);
+        assert!(summary.to_string().contains("login: 3 key presses"));
+
+        let dropped = RefCell::new(None);
+        {
+            let mut recorder = SessionRecorder::with_time_limit(&mut hid, "boxed", Duration::ZERO);
+            recorder.on_drop(|summary| *dropped.borrow_mut() = Some(summary));
+            assert_eq!(recorder.send_mouse_packet(&[1, 0, 0, 0, 0]).unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+            recorder.send_mouse_packet(&[0; 5]).unwrap();
+        }
+        let dropped = dropped.into_inner().unwrap();
+        assert!(dropped.timed_out);
+        assert_eq!(dropped.mouse_packets, 1);
+        assert!(hid.send_mouse_packet(&[0; 5]).is_ok());
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn flush_straddling_time_limit() {
+        use std::{io, time::Duration};
+
+        use super::SessionRecorder;
+        use crate::{key::{Keyboard, KeyPacket}, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut keyboard = Keyboard::new();
+        keyboard.set_pacing(Duration::from_millis(100));
+        keyboard.press_basic_string_checked("ab");
+        let mut recorder = SessionRecorder::with_time_limit(&mut hid, "straddle", Duration::from_millis(50));
+        assert_eq!(keyboard.flush(&mut recorder).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        let summary = recorder.finish();
+        assert!(summary.timed_out);
+        assert_eq!(summary.key_packets, 2);
+
+        let written: Vec<KeyPacket> = std::fs::read(hid.get_keyboard_path()).unwrap()
+            .chunks(KeyPacket::new().as_bytes().len())
+            .map(|data| KeyPacket::from_bytes(data).unwrap())
+            .collect();
+        assert_eq!(written, vec![KeyPacket::parse("a").unwrap(), KeyPacket::new()]);
+    }
+}
@@ -0,0 +1,257 @@
+#![warn(missing_docs)]
+
+use std::{borrow::Cow, io};
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Field of a report. Bits are counted from the least significant bit of the first byte, as in HID reports.
+pub struct ReportField {
+    /// Name
+    pub name: String,
+    /// Offset in bits
+    pub offset: usize,
+    /// Width in bits, at most 64
+    pub width: usize,
+    /// Two's complement signed value
+    pub signed: bool,
+}
+
+impl ReportField {
+    /// Range of values the field can hold
+    pub fn range(&self) -> (i64, i64) {
+        match (self.signed, self.width) {
+            (_, 0) => (0, 0),
+            (true, width) if width >= 64 => (i64::MIN, i64::MAX),
+            (true, width) => (-(1i64 << (width - 1)), (1i64 << (width - 1)) - 1),
+            (false, width) if width >= 63 => (0, i64::MAX),
+            (false, width) => (0, (1i64 << width) - 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// Layout of a custom report, for gadgets whose descriptors differ from the built-in ones
+pub struct ReportLayout {
+    /// Report length in bytes
+    pub len: usize,
+    /// Fields
+    pub fields: Vec<ReportField>,
+}
+
+impl ReportLayout {
+    /// New layout of a report len bytes long without fields
+    pub fn new(len: usize) -> ReportLayout {
+        ReportLayout { len, fields: Vec::new() }
+    }
+
+    /// Add a field
+    pub fn field(mut self, name: &str, offset: usize, width: usize, signed: bool) -> ReportLayout {
+        self.fields.push(ReportField { name: name.to_string(), offset, width, signed });
+        self
+    }
+
+    /// Layout of the built-in mouse report, see [crate::descriptor::MOUSE]. Fields: buttons, x, y, wheel and pan.
+    pub fn mouse() -> ReportLayout {
+        ReportLayout::new(5)
+            .field("buttons", 0, 8, false)
+            .field("x", 8, 8, true)
+            .field("y", 16, 8, true)
+            .field("wheel", 24, 8, true)
+            .field("pan", 32, 8, true)
+    }
+
+    /// Layout of the boot protocol keyboard report. Fields: modifiers and key0 to key5.
+    pub fn keyboard_boot() -> ReportLayout {
+        (0..6).fold(
+            ReportLayout::new(8).field("modifiers", 0, 8, false),
+            |layout, i| layout.field(&format!("key{}", i), 16 + i * 8, 8, false),
+        )
+    }
+
+    /// Get field by name
+    pub fn get(&self, name: &str) -> Option<&ReportField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// New builder for a report with this layout
+    pub fn builder(&self) -> ReportBuilder<'_> {
+        ReportBuilder { layout: self, data: vec![0; self.len] }
+    }
+
+    /// Read a field from a report
+    pub fn read(&self, data: &[u8], name: &str) -> io::Result<i64> {
+        let field = self.field_in_bounds(name, data.len())?;
+        let mut raw = 0u64;
+        for bit in 0..field.width {
+            let pos = field.offset + bit;
+            raw |= (((data[pos / 8] >> (pos % 8)) & 1) as u64) << bit;
+        }
+        if field.signed && field.width < 64 && raw & (1 << (field.width - 1)) != 0 {
+            raw |= u64::MAX << field.width;
+        }
+        Ok(raw as i64)
+    }
+
+    fn field_in_bounds(&self, name: &str, len: usize) -> io::Result<&ReportField> {
+        let field = self.get(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no report field {:?}", name)))?;
+        if field.width == 0 || field.width > 64 || field.offset + field.width > len * 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("report field {:?} out of bounds", name)));
+        }
+        Ok(field)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Builds a report by setting named fields of a layout
+pub struct ReportBuilder<'a> {
+    layout: &'a ReportLayout,
+    data: Vec<u8>,
+}
+
+impl<'a> ReportBuilder<'a> {
+    /// Set a field. Fails if the field doesn't exist or the value doesn't fit.
+    pub fn set(&mut self, name: &str, value: i64) -> io::Result<&mut ReportBuilder<'a>> {
+        let field = self.layout.field_in_bounds(name, self.data.len())?;
+        let (min, max) = field.range();
+        if value < min || value > max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} out of range {}..={} of report field {:?}", value, min, max, name),
+            ));
+        }
+        for bit in 0..field.width {
+            let pos = field.offset + bit;
+            let mask = 1 << (pos % 8);
+            if (value as u64 >> bit) & 1 != 0 {
+                self.data[pos / 8] |= mask;
+            } else {
+                self.data[pos / 8] &= !mask;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Set a field, clamping the value to the field's range
+    pub fn set_clamped(&mut self, name: &str, value: i64) -> io::Result<&mut ReportBuilder<'a>> {
+        let (min, max) = self.layout.field_in_bounds(name, self.data.len())?.range();
+        self.set(name, value.clamp(min, max))
+    }
+
+    /// Report bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Finish building, returning the report bytes
+    pub fn build(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Rewrites report bytes just before they're written to a device, so consumers expecting another report format (e.g.
+/// older firmware bridging reports over serial) can be supported without changing how reports are built
+pub trait ReportTranscoder: Send {
+    /// Transcode a report
+    fn transcode<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Where a byte of a transcoded report comes from
+pub enum ByteSource {
+    /// Byte of the original report at an index, 0 if the report is shorter
+    Index(usize),
+    /// Fixed value
+    Fixed(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// Transcoder building each byte of the new report from a [ByteSource], which can permute, truncate and pad reports.
+/// E.g. `ByteMap::new().copy(2).copy(0).fixed(0)` swaps the first and third bytes and drops the rest except for a
+/// trailing padding byte.
+pub struct ByteMap {
+    /// Sources of the new report's bytes
+    pub bytes: Vec<ByteSource>,
+}
+
+impl ByteMap {
+    /// New map producing an empty report
+    pub fn new() -> ByteMap {
+        ByteMap::default()
+    }
+
+    /// Map copying the first len bytes of a report, truncating or padding it with zeros
+    pub fn resize(len: usize) -> ByteMap {
+        ByteMap { bytes: (0..len).map(ByteSource::Index).collect() }
+    }
+
+    /// Append a byte copied from an index of the original report
+    pub fn copy(mut self, index: usize) -> ByteMap {
+        self.bytes.push(ByteSource::Index(index));
+        self
+    }
+
+    /// Append a fixed byte
+    pub fn fixed(mut self, value: u8) -> ByteMap {
+        self.bytes.push(ByteSource::Fixed(value));
+        self
+    }
+}
+
+impl ReportTranscoder for ByteMap {
+    fn transcode<'a>(&self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        Cow::Owned(self.bytes.iter()
+            .map(|source| match source {
+                ByteSource::Index(index) => data.get(*index).copied().unwrap_or(0),
+                ByteSource::Fixed(value) => *value,
+            })
+            .collect())
+    }
+}
+
+/// Transcode a report if there is a transcoder
+#[cfg(feature = "hid")]
+pub(crate) fn transcode<'a>(transcoder: &Option<Box<dyn ReportTranscoder>>, data: &'a [u8]) -> Cow<'a, [u8]> {
+    match transcoder {
+        Some(transcoder) => transcoder.transcode(data),
+        None => Cow::Borrowed(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteMap, ReportLayout, ReportTranscoder};
+
+    #[test]
+    fn build_and_read_fields() {
+        let layout = ReportLayout::new(4)
+            .field("buttons", 0, 5, false)
+            .field("x", 5, 12, true)
+            .field("y", 17, 12, true);
+        let mut builder = layout.builder();
+        builder.set("buttons", 0b10001).unwrap().set("x", -300).unwrap().set("y", 2047).unwrap();
+        assert!(builder.set("y", 2048).is_err());
+        assert!(builder.set("z", 0).is_err());
+        builder.set_clamped("y", -5000).unwrap();
+        let report = builder.build();
+        assert_eq!(layout.read(&report, "buttons").unwrap(), 0b10001);
+        assert_eq!(layout.read(&report, "x").unwrap(), -300);
+        assert_eq!(layout.read(&report, "y").unwrap(), -2048);
+
+        let mouse = ReportLayout::mouse();
+        let mut builder = mouse.builder();
+        builder.set("x", -1).unwrap().set("wheel", 3).unwrap();
+        assert_eq!(builder.bytes(), &[0x00, 0xFF, 0x00, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn transcode_legacy_reports() {
+        // modifiers moved after the keys, reserved byte dropped
+        let legacy = ByteMap::new().copy(2).copy(3).copy(4).copy(5).copy(6).copy(7).copy(0).fixed(0xAA);
+        let report = [0x02, 0x00, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00];
+        assert_eq!(&*legacy.transcode(&report), &[0x04, 0x05, 0x00, 0x00, 0x00, 0x00, 0x02, 0xAA]);
+        assert_eq!(&*ByteMap::resize(3).transcode(&[1, 2, 3, 4]), &[1, 2, 3]);
+        assert_eq!(&*ByteMap::resize(6).transcode(&[1, 2, 3, 4, 5]), &[1, 2, 3, 4, 5, 0]);
+    }
+}
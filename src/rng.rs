@@ -0,0 +1,92 @@
+#![warn(missing_docs)]
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use rand_core::{RngCore, SeedableRng};
+
+/// Boxed random number generator, as taken by randomized features (write retry jitter, typos, simulated activity)
+pub type BoxedRng = Box<dyn RngCore + Send>;
+
+#[derive(Debug, Clone)]
+/// Small deterministic random number generator, the default of randomized features. The same seed always produces the
+/// same numbers, so runs can be reproduced from a logged seed.
+pub struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    /// New from a seed
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    /// New from a seed taken from the current time, see [entropy_seed]
+    pub fn from_entropy() -> SplitMix64 {
+        SplitMix64(entropy_seed())
+    }
+}
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SplitMix64 {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        SplitMix64(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+}
+
+/// Seed taken from the current time, for runs that don't set one. Logged with the debug feature so the run can be
+/// reproduced.
+pub fn entropy_seed() -> u64 {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    #[cfg(feature = "debug")]
+    {
+        crate::logging::log_line!("random seed {}", seed);
+    }
+    seed
+}
+
+/// Uniform random number in 0..1
+pub fn next_f64(rng: &mut (impl RngCore + ?Sized)) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_f64, RngCore, SeedableRng, SplitMix64};
+
+    #[test]
+    fn seeded_numbers() {
+        let mut rng = SplitMix64::new(1234567);
+        assert_eq!(rng.next_u64(), 6457827717110365317);
+        assert_eq!(SplitMix64::seed_from_u64(9).next_u64(), SplitMix64::from_seed(9u64.to_le_bytes()).next_u64());
+        let mut bytes = [0; 12];
+        SplitMix64::new(9).fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], SplitMix64::new(9).next_u64().to_le_bytes());
+        assert!((0..100).map(|_| next_f64(&mut rng)).all(|f| (0.0..1.0).contains(&f)));
+    }
+}
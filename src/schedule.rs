@@ -0,0 +1,201 @@
+#![warn(missing_docs)]
+
+use std::{sync::Arc, time::Duration};
+use std::{io, time::Instant};
+
+use crate::{clock::{Clock, SystemClock}, key::KeyPacket};
+use crate::{HidBackend, cancel::CancellationToken};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Raw report sent by a scheduled event
+pub enum Report {
+    /// Key packet
+    Keyboard(KeyPacket),
+    /// Mouse packet
+    Mouse([u8; 5]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Report scheduled at an offset from the start of a schedule
+pub struct ScheduledReport {
+    /// Offset from the start of the schedule
+    pub at: Duration,
+    /// Report
+    pub report: Report,
+}
+
+#[derive(Debug)]
+/// Timeline of reports sent at fixed offsets from when it's run
+pub struct Scheduler {
+    reports: Vec<ScheduledReport>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler { reports: Vec::new(), clock: Arc::new(SystemClock) }
+    }
+}
+
+impl Scheduler {
+    /// New
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Set the clock used to wait for reports when run, see [crate::clock::VirtualClock]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Clock used to wait for reports when run
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Schedule a report at an offset from the start. Reports at the same offset are sent in the order they were scheduled.
+    pub fn push(&mut self, at: Duration, report: Report) {
+        let idx = self.reports.partition_point(|scheduled| scheduled.at <= at);
+        self.reports.insert(idx, ScheduledReport { at, report });
+    }
+
+    /// Offset of the last scheduled report
+    pub fn end(&self) -> Duration {
+        self.reports.last().map(|scheduled| scheduled.at).unwrap_or(Duration::ZERO)
+    }
+
+    /// Scheduled reports in the order they will be sent
+    pub fn reports(&self) -> &[ScheduledReport] {
+        &self.reports
+    }
+
+    /// Check if nothing is scheduled
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+
+    /// Clear all scheduled reports
+    pub fn clear(&mut self) {
+        self.reports.clear();
+    }
+
+    /// Send all scheduled reports to a HID interface, waiting for each report's offset from now, then clear the schedule
+    pub fn run(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+        self.run_at(hid, self.clock.now())?;
+        Ok(())
+    }
+
+    /// Send all scheduled reports to a HID interface, waiting for each report's offset from a start time of the
+    /// scheduler's clock, then clear the schedule. Returns how late the latest report was sent. If a send fails, the
+    /// rest of the schedule (and the releases in it) is dropped, so every key and button is released on the host
+    /// before the error is returned.
+    pub fn run_at(&mut self, hid: &mut dyn HidBackend, start: Instant) -> io::Result<Duration> {
+        self.run_with(hid, start, None)
+    }
+
+    /// Run like [Scheduler::run_at], waking up early if a cancellation token is cancelled. If cancelled, the rest of the
+    /// schedule is dropped, every key and button is released on the host and a [crate::cancel::Cancelled] error is returned.
+    pub fn run_cancellable(&mut self, hid: &mut dyn HidBackend, start: Instant, cancel: &CancellationToken) -> io::Result<Duration> {
+        self.run_with(hid, start, Some(cancel))
+    }
+
+    fn run_with(&mut self, hid: &mut dyn HidBackend, start: Instant, cancel: Option<&CancellationToken>) -> io::Result<Duration> {
+        let mut lateness = Duration::ZERO;
+        for scheduled in self.reports.drain(..) {
+            let due = start + scheduled.at;
+            let wait = due.saturating_duration_since(self.clock.now());
+            match cancel {
+                Some(cancel) => if let Err(e) = self.clock.sleep_cancellable(wait, cancel) {
+                    hid.release_all()?;
+                    return Err(e);
+                },
+                None => self.clock.sleep(wait),
+            }
+            let sent = match scheduled.report {
+                Report::Keyboard(packet) => hid.send_key_report(&packet),
+                Report::Mouse(packet) => hid.send_mouse_report(&packet),
+            };
+            if let Err(e) = sent {
+                let _ = hid.release_all();
+                return Err(e);
+            }
+            lateness = lateness.max(self.clock.now().saturating_duration_since(due));
+        }
+        Ok(lateness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "debug")]
+    #[test]
+    fn cancelled_run_releases() {
+        use std::{fs, thread, time::{Duration, Instant}};
+
+        use super::{Report, Scheduler};
+        use crate::{cancel::{is_cancelled_error, CancellationToken}, key::KeyPacket, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let mut scheduler = Scheduler::new();
+        scheduler.push(Duration::ZERO, Report::Keyboard(KeyPacket::parse("a").unwrap()));
+        scheduler.push(Duration::from_secs(10), Report::Keyboard(KeyPacket::new()));
+
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let err = scheduler.run_cancellable(&mut hid, Instant::now(), &cancel).unwrap_err();
+        handle.join().unwrap();
+        assert!(is_cancelled_error(&err));
+        assert!(scheduler.is_empty());
+
+        let written = fs::read(hid.get_keyboard_path()).unwrap();
+        let packets: Vec<KeyPacket> = written.chunks(KeyPacket::new().as_bytes().len()).map(|data| KeyPacket::from_bytes(data).unwrap()).collect();
+        assert_eq!(packets, vec![KeyPacket::parse("a").unwrap(), KeyPacket::new()]);
+        assert_eq!(fs::read(hid.get_mouse_path()).unwrap(), vec![0; 5]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn cancelled_run_releases_in_protocol() {
+        use std::{fs, time::{Duration, Instant}};
+
+        use super::{Report, Scheduler};
+        use crate::{cancel::{is_cancelled_error, CancellationToken}, key::{KeyPacket, ReportProtocol}, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        hid.set_keyboard_protocol(ReportProtocol::Boot);
+        let mut scheduler = Scheduler::new();
+        scheduler.push(Duration::ZERO, Report::Keyboard(KeyPacket::parse("a").unwrap()));
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = scheduler.run_cancellable(&mut hid, Instant::now(), &cancel).unwrap_err();
+        assert!(is_cancelled_error(&err));
+        assert_eq!(fs::read(hid.get_keyboard_path()).unwrap(), vec![0; 8]);
+        assert_eq!(fs::read(hid.get_mouse_path()).unwrap(), vec![0; 5]);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn runs_on_virtual_clock() {
+        use std::{sync::Arc, time::{Duration, Instant}};
+
+        use super::{Report, Scheduler};
+        use crate::{clock::VirtualClock, key::KeyPacket, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        let clock = VirtualClock::new();
+        let mut scheduler = Scheduler::new();
+        scheduler.set_clock(Arc::new(clock.clone()));
+        scheduler.push(Duration::from_secs(60), Report::Keyboard(KeyPacket::new()));
+        scheduler.push(Duration::from_secs(3600), Report::Mouse([0; 5]));
+
+        let start = Instant::now();
+        scheduler.run(&mut hid).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3600));
+    }
+}
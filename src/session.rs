@@ -0,0 +1,240 @@
+#![warn(missing_docs)]
+
+use std::io;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{key::{Keyboard, KeyPacket, Modifier}, mouse::{Mouse, MOUSE_DATA_WHEL_IDX}};
+use crate::HidBackend;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+/// How a session flushes keyboard and mouse reports
+pub enum FlushOrdering {
+    /// Send steps in the order they were queued. Before switching interfaces, wait until the host has
+    /// received the previous interface's reports, so e.g. a click lands strictly after typed text.
+    #[default]
+    Strict,
+    /// Interleave keyboard and mouse reports without waiting on each other
+    Parallel,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+/// Queued session step
+pub enum Step {
+    /// Key packets, ending with a release packet or the held keys
+    Keyboard(Vec<KeyPacket>),
+    /// Mouse packets, ending with a packet restoring held buttons
+    Mouse(Vec<[u8; 5]>),
+}
+
+/// Keyboard and mouse operations queued together and flushed with a chosen ordering.
+/// Key packets are sent in the bitmap report protocol.
+#[derive(Debug, Default)]
+pub struct Session {
+    steps: Vec<Step>,
+    ordering: FlushOrdering,
+}
+
+impl Session {
+    /// New
+    pub fn new(ordering: FlushOrdering) -> Session {
+        Session { steps: Vec::new(), ordering }
+    }
+
+    /// Set flush ordering
+    pub fn set_ordering(&mut self, ordering: FlushOrdering) {
+        self.ordering = ordering;
+    }
+
+    /// Queue the keyboard's buffered keystrokes as the next step
+    pub fn queue_keyboard(&mut self, keyboard: &mut Keyboard) {
+        let packets = keyboard.take_packets();
+        if !packets.is_empty() {
+            self.steps.push(Step::Keyboard(packets));
+        }
+    }
+
+    /// Queue the mouse's buffered events as the next step
+    pub fn queue_mouse(&mut self, mouse: &mut Mouse) {
+        self.steps.push(Step::Mouse(mouse.take_packets()));
+    }
+
+    /// Queue scrolling the wheel a number of detents (negative scrolls down) with modifiers held: the keyboard's
+    /// buffered keystrokes, then the modifiers pressed, the wheel reports and the modifiers released, each a step so
+    /// strict ordering keeps the wheel inside the modifiers. Modifiers and buttons already held stay held.
+    pub fn queue_modified_scroll(&mut self, keyboard: &mut Keyboard, mouse: &Mouse, modifiers: &[Modifier], detents: i32) {
+        self.queue_keyboard(keyboard);
+        let held = keyboard.held().modifiers();
+        let pressed: Vec<&Modifier> = modifiers.iter().filter(|modifier| !held.contains(modifier)).collect();
+        for modifier in &pressed {
+            keyboard.hold_mod(modifier);
+        }
+        self.queue_keyboard(keyboard);
+
+        let mut packets = mouse.scroll_packets(detents);
+        if let Some(&last) = packets.last() {
+            let mut rest = last;
+            rest[MOUSE_DATA_WHEL_IDX] = 0;
+            packets.push(rest);
+            self.steps.push(Step::Mouse(packets));
+        }
+
+        for modifier in &pressed {
+            keyboard.release_mod(modifier);
+        }
+        self.queue_keyboard(keyboard);
+    }
+
+    /// Queue zooming with Ctrl+wheel, a number of steps (negative zooms out), see [Session::queue_modified_scroll]
+    pub fn queue_zoom(&mut self, keyboard: &mut Keyboard, mouse: &Mouse, steps: i32) {
+        self.queue_modified_scroll(keyboard, mouse, &[Modifier::LeftControl], steps);
+    }
+
+    /// Queue scrolling horizontally with Shift+wheel, a number of detents (negative scrolls right), see
+    /// [Session::queue_modified_scroll]
+    pub fn queue_horizontal_scroll(&mut self, keyboard: &mut Keyboard, mouse: &Mouse, detents: i32) {
+        self.queue_modified_scroll(keyboard, mouse, &[Modifier::LeftShift], detents);
+    }
+
+    /// Queued steps
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Send all queued steps to HID interface
+    pub fn flush(&mut self, hid: &mut dyn HidBackend) -> io::Result<()> {
+        let steps = std::mem::take(&mut self.steps);
+        match self.ordering {
+            FlushOrdering::Strict => Session::flush_strict(&steps, hid),
+            FlushOrdering::Parallel => Session::flush_parallel(&steps, hid),
+        }
+    }
+
+    fn flush_strict(steps: &[Step], hid: &mut dyn HidBackend) -> io::Result<()> {
+        for (i, step) in steps.iter().enumerate() {
+            let switching = matches!(
+                (step, steps.get(i + 1)),
+                (Step::Keyboard(_), Some(Step::Mouse(_))) | (Step::Mouse(_), Some(Step::Keyboard(_)))
+            );
+            // A gadget write only blocks until the previous report on the same interface has been
+            // taken by the host, so repeating the last report (a release, which changes nothing)
+            // is a write barrier for the reports before it.
+            match step {
+                Step::Keyboard(packets) => {
+                    for packet in packets {
+                        packet.send(hid)?;
+                    }
+                    if let (true, Some(last)) = (switching, packets.last()) {
+                        last.send(hid)?;
+                    }
+                },
+                Step::Mouse(packets) => {
+                    for packet in packets {
+                        hid.send_mouse_report(packet)?;
+                    }
+                    if let (true, Some(last)) = (switching, packets.last()) {
+                        hid.send_mouse_report(last)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_parallel(steps: &[Step], hid: &mut dyn HidBackend) -> io::Result<()> {
+        let mut key_packets = steps.iter()
+            .filter_map(|step| match step {
+                Step::Keyboard(packets) => Some(packets.iter()),
+                _ => None,
+            })
+            .flatten();
+        let mut mouse_packets = steps.iter()
+            .filter_map(|step| match step {
+                Step::Mouse(packets) => Some(packets.iter()),
+                _ => None,
+            })
+            .flatten();
+        loop {
+            let key_packet = key_packets.next();
+            let mouse_packet = mouse_packets.next();
+            if key_packet.is_none() && mouse_packet.is_none() {
+                return Ok(());
+            }
+            if let Some(packet) = key_packet {
+                packet.send(hid)?;
+            }
+            if let Some(packet) = mouse_packet {
+                hid.send_mouse_report(packet)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlushOrdering, Session, Step};
+    use crate::{key::{Keyboard, KeyPacket, Modifier}, mouse::{Mouse, MouseButton}};
+
+    #[test]
+    fn modified_scroll_steps() {
+        let mut session = Session::new(FlushOrdering::Strict);
+        let mut keyboard = Keyboard::new();
+        let mut mouse = Mouse::new();
+        keyboard.hold_mod(&Modifier::LeftShift);
+        mouse.hold_button(MouseButton::Left);
+        session.queue_zoom(&mut keyboard, &mouse, -200);
+        assert_eq!(session.steps(), &[
+            Step::Keyboard(vec![KeyPacket::parse("LSHIFT").unwrap(); 2]),
+            Step::Keyboard(vec![KeyPacket::parse("LSHIFT+LCTRL").unwrap(); 2]),
+            Step::Mouse(vec![[1, 0, 0, 0x81, 0], [1, 0, 0, 0xB7, 0], [1, 0, 0, 0, 0]]),
+            Step::Keyboard(vec![KeyPacket::parse("LSHIFT").unwrap(); 2]),
+        ]);
+
+        let mut session = Session::new(FlushOrdering::Strict);
+        session.queue_horizontal_scroll(&mut keyboard, &mouse, 1);
+        assert_eq!(session.steps(), &[Step::Mouse(vec![[1, 0, 0, 1, 0], [1, 0, 0, 0, 0]])]);
+        assert_eq!(keyboard.held(), &KeyPacket::parse("LSHIFT").unwrap());
+    }
+
+    /// Backend keeping the interface of each write in order
+    #[derive(Default)]
+    struct Interleaving(String);
+
+    impl crate::HidBackend for Interleaving {
+        fn send_key_packet(&mut self, _data: &[u8]) -> std::io::Result<()> {
+            self.0.push('k');
+            Ok(())
+        }
+
+        fn send_mouse_packet(&mut self, _data: &[u8]) -> std::io::Result<()> {
+            self.0.push('m');
+            Ok(())
+        }
+
+        fn receive_states_packet(&mut self, _timeout: std::time::Duration) -> std::io::Result<Option<u8>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn flush_orderings() {
+        let queue = |ordering| {
+            let mut session = Session::new(ordering);
+            let mut keyboard = Keyboard::new();
+            let mut mouse = Mouse::new();
+            keyboard.press_basic_string_checked("ab");
+            session.queue_keyboard(&mut keyboard);
+            mouse.press_button(MouseButton::Left);
+            session.queue_mouse(&mut mouse);
+            keyboard.press_basic_string_checked("c");
+            session.queue_keyboard(&mut keyboard);
+            let mut backend = Interleaving::default();
+            session.flush(&mut backend).unwrap();
+            assert!(session.steps().is_empty());
+            backend.0
+        };
+        // strict repeats the last report of a step before switching interfaces
+        assert_eq!(queue(FlushOrdering::Strict), "kkkkkkmmmkkk");
+        assert_eq!(queue(FlushOrdering::Parallel), "kmkmkkkkkk");
+    }
+}
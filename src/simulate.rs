@@ -0,0 +1,273 @@
+#![warn(missing_docs)]
+
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    dynamics::DynamicsProfile,
+    easing::Easing,
+    key::KeyPacket,
+    mouse::Mouse,
+    rng::{next_f64, BoxedRng, RngCore, SplitMix64},
+    schedule::{Report, Scheduler},
+};
+
+/// Shortest time an activity scheduled by [Simulator::schedule] takes
+pub const MIN_ACTIVITY: Duration = Duration::from_millis(1);
+/// Furthest [ActivityProfile::wander_distance], so moves across the whole range still fit an i32
+pub const MAX_WANDER_DISTANCE: i32 = i32::MAX / 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// Statistical profile of a user's session. Each activity is picked at random by weight and its length, distance or
+/// pause drawn uniformly from its range.
+pub struct ActivityProfile {
+    /// Words typing bursts are made of
+    pub words: Vec<String>,
+    /// Least and most words in a typing burst
+    pub burst_words: (u32, u32),
+    /// Keystroke timing of typing bursts
+    pub typing: DynamicsProfile,
+    /// Shortest and longest idle pause
+    pub pause: (Duration, Duration),
+    /// Furthest the pointer wanders from where it started, in each axis, up to [MAX_WANDER_DISTANCE]
+    pub wander_distance: i32,
+    /// Shortest and longest pointer movement
+    pub wander_duration: (Duration, Duration),
+    /// Most Tab presses in a window switch chord
+    pub switch_tabs: u32,
+    /// Relative weight of typing bursts
+    pub typing_weight: f64,
+    /// Relative weight of pointer movements
+    pub wander_weight: f64,
+    /// Relative weight of window switch chords
+    pub switch_weight: f64,
+    /// Relative weight of idle pauses
+    pub pause_weight: f64,
+}
+
+impl Default for ActivityProfile {
+    fn default() -> Self {
+        ActivityProfile {
+            words: [
+                "the", "and", "report", "meeting", "update", "data", "server", "test", "review", "email", "hello",
+                "thanks", "please", "check", "team", "schedule", "today", "with", "for", "is",
+            ].iter().map(|word| word.to_string()).collect(),
+            burst_words: (3, 12),
+            typing: DynamicsProfile::default(),
+            pause: (Duration::from_millis(500), Duration::from_secs(10)),
+            wander_distance: 400,
+            wander_duration: (Duration::from_millis(200), Duration::from_millis(1500)),
+            switch_tabs: 3,
+            typing_weight: 0.4,
+            wander_weight: 0.35,
+            switch_weight: 0.05,
+            pause_weight: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Activity picked by a [Simulator]
+pub enum Activity {
+    /// Type text
+    Typing(String),
+    /// Move the pointer by a relative amount over a duration
+    Wander((i32, i32), Duration),
+    /// Switch window with Alt and a number of Tab presses
+    SwitchWindow(u32),
+    /// Do nothing for a duration
+    Pause(Duration),
+}
+
+/// Generates plausible keyboard and mouse activity from an [ActivityProfile] and a seed, e.g. to soak test remote
+/// desktop infrastructure through real USB input. The same profile and seed always generate the same session.
+pub struct Simulator {
+    profile: ActivityProfile,
+    rng: BoxedRng,
+    pointer: (i32, i32),
+}
+
+impl Simulator {
+    /// New, generating activity from a profile and a seed
+    pub fn new(profile: ActivityProfile, seed: u64) -> Simulator {
+        #[cfg(feature = "debug")]
+        {
+            crate::logging::log_line!("simulator seed {}", seed);
+        }
+        Simulator::with_rng(profile, SplitMix64::new(seed))
+    }
+
+    /// New, generating activity from a profile and a random number generator
+    pub fn with_rng(profile: ActivityProfile, rng: impl RngCore + Send + 'static) -> Simulator {
+        Simulator { profile, rng: Box::new(rng), pointer: (0, 0) }
+    }
+
+    /// Get the profile
+    pub fn profile(&self) -> &ActivityProfile {
+        &self.profile
+    }
+
+    /// Pointer offset from where the session started
+    pub fn pointer(&self) -> (i32, i32) {
+        self.pointer
+    }
+
+    fn range_u32(&mut self, (min, max): (u32, u32)) -> u32 {
+        min + (self.rng.next_u64() % (max.saturating_sub(min) as u64 + 1)) as u32
+    }
+
+    fn range_duration(&mut self, (min, max): (Duration, Duration)) -> Duration {
+        min + max.saturating_sub(min).mul_f64(next_f64(self.rng.as_mut()))
+    }
+
+    /// Pick the next activity
+    pub fn next_activity(&mut self) -> Activity {
+        let weights = [
+            self.profile.typing_weight,
+            self.profile.wander_weight,
+            self.profile.switch_weight,
+            self.profile.pause_weight,
+        ].map(|weight| weight.max(0.0));
+        let total: f64 = weights.iter().sum();
+        let mut pick = next_f64(self.rng.as_mut()) * total;
+        let mut kind = weights.len() - 1;
+        for (i, weight) in weights.iter().enumerate() {
+            if pick < *weight {
+                kind = i;
+                break;
+            }
+            pick -= weight;
+        }
+
+        match kind {
+            0 if !self.profile.words.is_empty() => {
+                let count = self.range_u32(self.profile.burst_words).max(1);
+                let words: Vec<String> = (0..count)
+                    .map(|_| self.profile.words[(self.rng.next_u64() % self.profile.words.len() as u64) as usize].clone())
+                    .collect();
+                Activity::Typing(format!("{} ", words.join(" ")))
+            },
+            1 => {
+                let distance = self.profile.wander_distance.clamp(0, MAX_WANDER_DISTANCE);
+                let mut target = || (next_f64(self.rng.as_mut()) * (2 * distance + 1) as f64) as i32 - distance;
+                let (x, y) = (target(), target());
+                let to = (x - self.pointer.0, y - self.pointer.1);
+                self.pointer = (x, y);
+                Activity::Wander(to, self.range_duration(self.profile.wander_duration))
+            },
+            2 => Activity::SwitchWindow(self.range_u32((1, self.profile.switch_tabs.max(1)))),
+            _ => Activity::Pause(self.range_duration(self.profile.pause)),
+        }
+    }
+
+    /// Schedule an activity after the last scheduled report
+    pub fn schedule_activity(&self, scheduler: &mut Scheduler, activity: &Activity) {
+        match activity {
+            Activity::Typing(text) => {
+                self.profile.typing.schedule(scheduler, text);
+            },
+            Activity::Wander(to, duration) => Mouse::new().glide_to(scheduler, *to, *duration, Easing::EaseInOut),
+            Activity::SwitchWindow(tabs) => {
+                let (alt, alt_tab) = (KeyPacket::parse("LALT"), KeyPacket::parse("LALT+Tab"));
+                let (Some(alt), Some(alt_tab)) = (alt, alt_tab) else {
+                    return;
+                };
+                let dwell = self.profile.typing.default_dwell;
+                let down_down = self.profile.typing.default_down_down;
+                let mut at = scheduler.end();
+                scheduler.push(at, Report::Keyboard(alt.clone()));
+                for _ in 0..*tabs {
+                    at += down_down;
+                    scheduler.push(at, Report::Keyboard(alt_tab.clone()));
+                    scheduler.push(at + dwell, Report::Keyboard(alt.clone()));
+                }
+                scheduler.push(at + down_down, Report::Keyboard(KeyPacket::new()));
+            },
+            // nothing to send, an empty report marks the end of the pause
+            Activity::Pause(duration) => scheduler.push(scheduler.end() + *duration, Report::Keyboard(KeyPacket::new())),
+        }
+    }
+
+    /// Schedule activities after the last scheduled report until the schedule spans at least a duration, returning
+    /// them. Long sessions can be generated in parts by scheduling and running one part at a time.
+    /// Every activity takes at least [MIN_ACTIVITY], so profiles with zero length pauses or timings still end.
+    pub fn schedule(&mut self, scheduler: &mut Scheduler, duration: Duration) -> Vec<Activity> {
+        let end = scheduler.end() + duration;
+        let mut activities = Vec::new();
+        while scheduler.end() < end {
+            let start = scheduler.end();
+            let activity = self.next_activity();
+            self.schedule_activity(scheduler, &activity);
+            if scheduler.end() < start + MIN_ACTIVITY {
+                scheduler.push(start + MIN_ACTIVITY, Report::Keyboard(KeyPacket::new()));
+            }
+            activities.push(activity);
+        }
+        activities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Activity, ActivityProfile, Simulator, MAX_WANDER_DISTANCE};
+    use crate::schedule::Scheduler;
+
+    #[test]
+    fn seeded_sessions() {
+        let session = |seed| {
+            let mut scheduler = Scheduler::new();
+            let activities = Simulator::new(ActivityProfile::default(), seed).schedule(&mut scheduler, Duration::from_secs(600));
+            assert!(scheduler.end() >= Duration::from_secs(600));
+            activities
+        };
+        let activities = session(7);
+        assert_eq!(activities, session(7));
+        assert_ne!(activities, session(8));
+        assert!(activities.iter().any(|activity| matches!(activity, Activity::Typing(_))));
+        assert!(activities.iter().any(|activity| matches!(activity, Activity::Wander(..))));
+        assert!(activities.iter().any(|activity| matches!(activity, Activity::Pause(_))));
+
+        let mut simulator = Simulator::new(ActivityProfile::default(), 7);
+        for _ in 0..200 {
+            simulator.next_activity();
+            let (x, y) = simulator.pointer();
+            assert!(x.abs() <= 400 && y.abs() <= 400);
+        }
+    }
+
+    #[test]
+    fn clamps_wander_distance() {
+        let profile = ActivityProfile {
+            wander_distance: i32::MAX,
+            typing_weight: 0.0,
+            switch_weight: 0.0,
+            pause_weight: 0.0,
+            ..ActivityProfile::default()
+        };
+        let mut simulator = Simulator::new(profile, 7);
+        for _ in 0..200 {
+            assert!(matches!(simulator.next_activity(), Activity::Wander(..)));
+            let (x, y) = simulator.pointer();
+            assert!(x.abs() <= MAX_WANDER_DISTANCE && y.abs() <= MAX_WANDER_DISTANCE);
+        }
+    }
+
+    #[test]
+    fn zero_length_activities_end() {
+        let profile = ActivityProfile {
+            pause: (Duration::ZERO, Duration::ZERO),
+            typing_weight: 0.0,
+            wander_weight: 0.0,
+            switch_weight: 0.0,
+            ..ActivityProfile::default()
+        };
+        let mut scheduler = Scheduler::new();
+        let activities = Simulator::new(profile, 7).schedule(&mut scheduler, Duration::from_millis(5));
+        assert_eq!(activities, vec![Activity::Pause(Duration::ZERO); 5]);
+        assert_eq!(scheduler.end(), Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,176 @@
+#![warn(missing_docs)]
+
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, Write},
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Mutex},
+};
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+/// Emergency stops so far, so keyboards and mice can tell their held keys and buttons were released
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// Keyboard and mouse interfaces of open HID interfaces, released on an emergency stop
+static INTERFACES: Mutex<Vec<Interface>> = Mutex::new(Vec::new());
+
+/// Keyboard and mouse interfaces of a HID interface, with the reports releasing everything on them
+struct Interface {
+    id: usize,
+    keyboard: File,
+    mouse: File,
+    key_release: Vec<u8>,
+    mouse_release: Vec<u8>,
+}
+
+impl Interface {
+    /// Write both releases, returning the first error
+    fn release(&mut self) -> io::Result<()> {
+        let keyboard = self.keyboard.write_all(&self.key_release);
+        let mouse = self.mouse.write_all(&self.mouse_release);
+        keyboard.and(mouse)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error returned by sends after [emergency_stop], until [reset_emergency_stop]
+pub struct Stopped;
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "emergency stop")
+    }
+}
+
+impl Error for Stopped {}
+
+/// Kill switch. Can be tripped from any thread: releases every key and button on all open HID interfaces, in each
+/// interface's report protocol and format, then makes all sends fail with a [Stopped] error until
+/// [reset_emergency_stop]. Keyboards and mice forget their held keys and buttons, and drop what they buffered before
+/// the stop. Every interface is released even if some fail, the first write error is returned.
+pub fn emergency_stop() -> io::Result<()> {
+    STOPPED.store(true, Ordering::SeqCst);
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    let mut interfaces = INTERFACES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut result = Ok(());
+    for interface in interfaces.iter_mut() {
+        let released = interface.release();
+        #[cfg(feature = "debug")]
+        if let Err(e) = &released {
+            crate::logging::log_line!("emergency stop release failed: {}", e);
+        }
+        result = result.and(released);
+    }
+    result
+}
+
+/// Allow sending again after [emergency_stop]
+pub fn reset_emergency_stop() {
+    STOPPED.store(false, Ordering::SeqCst);
+}
+
+/// Check if the emergency stop is tripped
+pub fn is_stopped() -> bool {
+    STOPPED.load(Ordering::SeqCst)
+}
+
+/// Check if an error was caused by the emergency stop
+pub fn is_stopped_error(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<Stopped>())
+}
+
+/// Fail if the emergency stop is tripped
+pub(crate) fn check() -> io::Result<()> {
+    if is_stopped() {
+        return Err(io::Error::other(Stopped));
+    }
+    Ok(())
+}
+
+/// Emergency stops so far
+pub(crate) fn generation() -> usize {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// Register the interfaces of a HID interface to be released on an emergency stop, with the reports releasing
+/// everything on them. Returns an id for [set_releases] and [unregister].
+pub(crate) fn register(keyboard: &File, mouse: &File, key_release: Vec<u8>, mouse_release: Vec<u8>) -> io::Result<usize> {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let interface = Interface { id, keyboard: keyboard.try_clone()?, mouse: mouse.try_clone()?, key_release, mouse_release };
+    INTERFACES.lock().unwrap_or_else(|e| e.into_inner()).push(interface);
+    Ok(id)
+}
+
+/// Update the reports releasing a registered HID interface, after its protocol, format or transcoders changed
+pub(crate) fn set_releases(id: usize, key_release: Vec<u8>, mouse_release: Vec<u8>) {
+    let mut interfaces = INTERFACES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(interface) = interfaces.iter_mut().find(|interface| interface.id == id) {
+        interface.key_release = key_release;
+        interface.mouse_release = mouse_release;
+    }
+}
+
+/// Write the releases of a single registered HID interface, as an emergency stop would
+#[cfg(test)]
+pub(crate) fn release(id: usize) -> io::Result<()> {
+    let mut interfaces = INTERFACES.lock().unwrap_or_else(|e| e.into_inner());
+    match interfaces.iter_mut().find(|interface| interface.id == id) {
+        Some(interface) => interface.release(),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "interface not registered")),
+    }
+}
+
+/// Stop releasing a HID interface's interfaces on an emergency stop
+pub(crate) fn unregister(id: usize) {
+    INTERFACES.lock().unwrap_or_else(|e| e.into_inner()).retain(|interface| interface.id != id);
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "debug"))]
+    #[test]
+    fn failed_open_stays_unregistered() {
+        use std::{fs, io, sync::atomic::Ordering};
+
+        use super::{release, NEXT_ID};
+        use crate::HID;
+
+        let dir = std::env::temp_dir().join(format!("virt-hid-stop-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (mouse, keyboard) = (dir.join("mouse"), dir.join("keyboard"));
+        fs::write(&mouse, b"").unwrap();
+        fs::write(&keyboard, b"").unwrap();
+        let next = NEXT_ID.load(Ordering::SeqCst);
+        let opened = HID::new(mouse.to_str().unwrap(), keyboard.to_str().unwrap(), dir.join("led").to_str().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(opened.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+        assert_eq!(release(next).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn releases_in_protocol() {
+        use std::fs;
+
+        use super::release;
+        use crate::{descriptor, key::ReportProtocol, report::ByteMap, HID};
+
+        let mut hid = HID::new("", "", "").unwrap();
+        release(hid.stop_id()).unwrap();
+        assert_eq!(fs::read(hid.get_keyboard_path()).unwrap(), vec![0; 33]);
+        assert_eq!(fs::read(hid.get_mouse_path()).unwrap(), vec![0; 5]);
+
+        let mut boot = HID::new("", "", "").unwrap();
+        boot.set_keyboard_protocol(ReportProtocol::Boot);
+        boot.set_mouse_format(Some(descriptor::mouse_format(descriptor::ABSOLUTE_MOUSE).unwrap()));
+        release(boot.stop_id()).unwrap();
+        assert_eq!(fs::read(boot.get_keyboard_path()).unwrap(), vec![0; 8]);
+        let format = boot.mouse_format().unwrap();
+        assert_eq!(fs::read(boot.get_mouse_path()).unwrap(), format.encode(&[0; 5]));
+
+        hid.set_keyboard_transcoder(Some(Box::new(ByteMap::resize(4).fixed(0xAA))));
+        release(hid.stop_id()).unwrap();
+        assert_eq!(fs::read(hid.get_keyboard_path()).unwrap()[33..], [0, 0, 0, 0, 0xAA]);
+    }
+}
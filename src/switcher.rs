@@ -0,0 +1,169 @@
+#![warn(missing_docs)]
+
+use std::io;
+
+use crate::{HidBackend, key::KeyPacket};
+
+const MOUSE_RELEASE: [u8; 5] = [0; 5];
+
+/// Held state of a target, as last sent to it
+struct Target {
+    hid: Box<dyn HidBackend>,
+    keys: KeyPacket,
+    buttons: u8,
+}
+
+/// Switches one controller between several target machines (e.g. multiple gadget ports or remote agents, each behind
+/// its own backend), releasing everything held on the old target when switching so no key stays stuck there
+pub struct TargetSwitcher {
+    targets: Vec<Target>,
+    active: usize,
+}
+
+impl TargetSwitcher {
+    /// New, with the first target active. Fails if there are no targets.
+    pub fn new(targets: Vec<Box<dyn HidBackend>>) -> io::Result<TargetSwitcher> {
+        if targets.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no targets"));
+        }
+        Ok(TargetSwitcher {
+            targets: targets.into_iter()
+                .map(|hid| Target { hid, keys: KeyPacket::new(), buttons: 0 })
+                .collect(),
+            active: 0,
+        })
+    }
+
+    /// Add a target, returning its index
+    pub fn add(&mut self, hid: Box<dyn HidBackend>) -> usize {
+        self.targets.push(Target { hid, keys: KeyPacket::new(), buttons: 0 });
+        self.targets.len() - 1
+    }
+
+    /// Number of targets
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Check if there are no targets, never true
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Index of the active target
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Active target's backend, e.g. for [crate::key::Keyboard::send]. Packets sent through it directly aren't
+    /// tracked, but switching away still releases everything.
+    pub fn active_hid(&mut self) -> &mut dyn HidBackend {
+        &mut *self.targets[self.active].hid
+    }
+
+    /// Target's backend
+    pub fn target(&mut self, index: usize) -> Option<&mut dyn HidBackend> {
+        self.targets.get_mut(index).map(|target| &mut *target.hid as &mut dyn HidBackend)
+    }
+
+    /// Keys and mouse buttons held on a target, as tracked from packets sent through the switcher
+    pub fn held(&self, index: usize) -> Option<(&KeyPacket, u8)> {
+        self.targets.get(index).map(|target| (&target.keys, target.buttons))
+    }
+
+    /// Switch to another target, first releasing all keys and buttons on the active one
+    pub fn switch_to(&mut self, index: usize) -> io::Result<()> {
+        if index >= self.targets.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such target"));
+        }
+        if index == self.active {
+            return Ok(());
+        }
+        let old = &mut self.targets[self.active];
+        old.hid.send_key_report(&KeyPacket::new())?;
+        old.hid.send_mouse_report(&MOUSE_RELEASE)?;
+        old.keys = KeyPacket::new();
+        old.buttons = 0;
+        self.active = index;
+        Ok(())
+    }
+
+    /// Switch to the next target, wrapping around
+    pub fn switch_next(&mut self) -> io::Result<()> {
+        self.switch_to((self.active + 1) % self.targets.len())
+    }
+
+    /// Send raw key packet to the active target
+    pub fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let target = &mut self.targets[self.active];
+        target.hid.send_key_packet(data)?;
+        if let Some(packet) = KeyPacket::from_bytes(data) {
+            target.keys = packet;
+        }
+        Ok(())
+    }
+
+    /// Send raw mouse packet to the active target
+    pub fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let target = &mut self.targets[self.active];
+        target.hid.send_mouse_packet(data)?;
+        target.buttons = data.first().copied().unwrap_or(0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn switch_releases_old_target() {
+        use std::{io, sync::{Arc, Mutex}, time::Duration};
+
+        use super::TargetSwitcher;
+        use crate::{HidBackend, key::{KeyPacket, ReportProtocol}};
+
+        /// Records the key and mouse reports written to it
+        struct Target {
+            protocol: ReportProtocol,
+            sent: Arc<Mutex<Vec<Vec<u8>>>>,
+        }
+
+        impl HidBackend for Target {
+            fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                self.sent.lock().unwrap().push(data.to_vec());
+                Ok(())
+            }
+
+            fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                self.sent.lock().unwrap().push(data.to_vec());
+                Ok(())
+            }
+
+            fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+                Ok(None)
+            }
+
+            fn keyboard_protocol(&self) -> ReportProtocol {
+                self.protocol
+            }
+        }
+
+        let (boot, bitmap) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(Vec::new())));
+        let mut switcher = TargetSwitcher::new(vec![
+            Box::new(Target { protocol: ReportProtocol::Boot, sent: boot.clone() }),
+            Box::new(Target { protocol: ReportProtocol::Bitmap, sent: bitmap.clone() }),
+        ]).unwrap();
+        switcher.send_key_packet(&[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]).unwrap();
+        switcher.send_mouse_packet(&[1, 0, 0, 0, 0]).unwrap();
+        assert_eq!(switcher.held(0).unwrap(), (&KeyPacket::from_keycodes(0, 0x04), 1));
+
+        switcher.switch_next().unwrap();
+        assert_eq!(switcher.active(), 1);
+        assert_eq!(switcher.held(0).unwrap(), (&KeyPacket::new(), 0));
+        // released in the old target's protocol
+        assert_eq!(boot.lock().unwrap()[2..], [vec![0; 8], vec![0; 5]]);
+
+        switcher.switch_to(0).unwrap();
+        assert_eq!(*bitmap.lock().unwrap(), vec![KeyPacket::new().as_bytes().to_vec(), vec![0; 5]]);
+        assert!(switcher.switch_to(2).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+#![warn(missing_docs)]
+
+use std::{io, time::Duration};
+
+use crate::{HidBackend, config::HostOS, backend::LockKeys, key::{BasicKey, Keyboard, SpecialKey}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Wait after each command before typing the next one
+pub enum CommandWait {
+    /// Don't wait
+    #[default]
+    None,
+    /// Wait a fixed delay on the keyboard's clock
+    Delay(Duration),
+    /// Wait for a NumLock handshake with the host, with a timeout, see [crate::HID::handshake]
+    Handshake(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Types commands into a terminal on the host
+pub struct Terminal {
+    host: HostOS,
+    wait: CommandWait,
+}
+
+impl Terminal {
+    /// New terminal on a host OS
+    pub fn new(host: HostOS) -> Terminal {
+        Terminal { host, wait: CommandWait::None }
+    }
+
+    /// Set the wait after each command
+    pub fn set_wait(&mut self, wait: CommandWait) {
+        self.wait = wait;
+    }
+
+    /// Host OS
+    pub fn host(&self) -> HostOS {
+        self.host
+    }
+
+    /// Type a command followed by Enter, then wait. Returns the grapheme clusters that couldn't be typed.
+    pub fn run(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, command: &str) -> io::Result<Vec<String>> {
+        let unmappable = self.type_lines(keyboard, hid, &[command.to_string()])?;
+        self.wait(keyboard, hid)?;
+        Ok(unmappable)
+    }
+
+    /// Type a command with multi-line input passed as a here-doc (a here-string piped into the command on Windows),
+    /// then wait. Returns the grapheme clusters that couldn't be typed.
+    pub fn run_heredoc(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, command: &str, delimiter: &str, input: &str) -> io::Result<Vec<String>> {
+        let unmappable = self.type_lines(keyboard, hid, &self.heredoc_lines(command, delimiter, input))?;
+        self.wait(keyboard, hid)?;
+        Ok(unmappable)
+    }
+
+    /// Lines typed for a here-doc. Each line is entered with a single Enter, whether the input uses LF or CRLF.
+    pub fn heredoc_lines(&self, command: &str, delimiter: &str, input: &str) -> Vec<String> {
+        let input = input.lines().map(|line| line.to_string());
+        match self.host {
+            HostOS::Windows => [String::from("@'")].into_iter()
+                .chain(input)
+                .chain([format!("'@ | {}", command)])
+                .collect(),
+            HostOS::Linux | HostOS::MacOS => [format!("{} <<'{}'", command, delimiter)].into_iter()
+                .chain(input)
+                .chain([delimiter.to_string()])
+                .collect(),
+        }
+    }
+
+    /// Type each line followed by Enter, sending line by line
+    fn type_lines(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, lines: &[String]) -> io::Result<Vec<String>> {
+        let mut unmappable = Vec::new();
+        for line in lines {
+            unmappable.extend(keyboard.type_string(line));
+            keyboard.press_key(&BasicKey::Special(SpecialKey::ReturnEnter));
+            keyboard.send(hid)?;
+        }
+        Ok(unmappable)
+    }
+
+    fn wait(&self, keyboard: &Keyboard, hid: &mut dyn HidBackend) -> io::Result<()> {
+        match self.wait {
+            CommandWait::None => (),
+            CommandWait::Delay(delay) => keyboard.clock().sleep(delay),
+            CommandWait::Handshake(timeout) => {
+                hid.handshake(timeout)?;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Terminal;
+    use crate::config::HostOS;
+
+    #[test]
+    fn heredoc_lines() {
+        let input = "a\r\nb\n";
+        assert_eq!(Terminal::new(HostOS::Linux).heredoc_lines("cat > f", "EOF", input), vec!["cat > f <<'EOF'", "a", "b", "EOF"]);
+        assert_eq!(Terminal::new(HostOS::Windows).heredoc_lines("Set-Content f", "EOF", input), vec!["@'", "a", "b", "'@ | Set-Content f"]);
+    }
+}
@@ -0,0 +1,318 @@
+#![warn(missing_docs)]
+
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{clock::{Clock, SystemClock}, decode::KeyDecoder, key::{KeyPacket, Keyboard}, mouse::Mouse, schedule::Report};
+use crate::HidBackend;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// What happened to a staged transaction
+pub enum AuditOutcome {
+    /// Sent in full
+    Committed,
+    /// Sending failed part way, with the error
+    Failed(String),
+    /// Discarded without sending
+    Aborted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Audit record of a staged transaction
+pub struct AuditRecord {
+    /// Description of the transaction
+    pub description: String,
+    /// Who approved or aborted it
+    pub approved_by: String,
+    /// Preview shown before approval, see [Transaction::to_pretty_string]
+    pub preview: String,
+    /// When the transaction was staged
+    pub staged_at: SystemTime,
+    /// When it was committed or aborted
+    pub decided_at: SystemTime,
+    /// Outcome
+    pub outcome: AuditOutcome,
+}
+
+impl AuditRecord {
+    /// Append the record and its preview to an audit log file
+    pub fn append_to(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self)?;
+        for line in self.preview.lines() {
+            writeln!(file, "    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        let outcome = match &self.outcome {
+            AuditOutcome::Committed => String::from("committed"),
+            AuditOutcome::Failed(e) => format!("failed ({})", e),
+            AuditOutcome::Aborted => String::from("aborted"),
+        };
+        write!(f, "[{}] {} by {}: {} (staged {})", secs(self.decided_at), outcome, self.approved_by, self.description, secs(self.staged_at))
+    }
+}
+
+/// Bytes written for a staged report
+enum Encoded {
+    /// Reports in a keyboard's protocol, followed by a wait of its pacing
+    Keyboard(Vec<Vec<u8>>, Duration),
+    /// Report in a mouse's own format
+    Mouse(Vec<u8>),
+    /// Converted by the backend when sent, see [crate::HidBackend::send_key_report] and
+    /// [crate::HidBackend::send_mouse_report]
+    Backend,
+}
+
+/// Two stage commit for destructive sequences: stage reports, review a readable preview and only send them after an
+/// explicit [Transaction::commit], which returns an [AuditRecord]
+pub struct Transaction {
+    description: String,
+    reports: Vec<Report>,
+    encoded: Vec<Encoded>,
+    key_release: Option<Vec<Vec<u8>>>,
+    mouse_release: Option<Vec<u8>>,
+    staged_at: Option<SystemTime>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Transaction {
+    /// New empty transaction with a description for the audit record
+    pub fn new(description: &str) -> Transaction {
+        Transaction {
+            description: description.to_string(),
+            reports: Vec::new(),
+            encoded: Vec::new(),
+            key_release: None,
+            mouse_release: None,
+            staged_at: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Set the clock used for the keyboards' pacing when committing, see [crate::clock::VirtualClock]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Stage a keyboard's buffered keystrokes, see [Keyboard::take_packets]. They're checked and encoded the way
+    /// [Keyboard::flush] would send them, with its lint rules, key matrix, report protocol and pacing, so staging
+    /// fails (taking nothing) where the flush would.
+    pub fn stage_keyboard(&mut self, keyboard: &mut Keyboard) -> io::Result<()> {
+        let pacing = keyboard.pacing();
+        for (packet, reports) in keyboard.take_encoded()? {
+            self.stage(Report::Keyboard(packet), Encoded::Keyboard(reports, pacing));
+        }
+        self.key_release = Some(keyboard.release_reports()?);
+        Ok(())
+    }
+
+    /// Stage key packets, sent in the backend's report protocol
+    pub fn stage_packets(&mut self, packets: impl IntoIterator<Item = KeyPacket>) {
+        for packet in packets {
+            self.stage(Report::Keyboard(packet), Encoded::Backend);
+        }
+    }
+
+    /// Stage a mouse's buffered events, see [Mouse::take_packets], in the mouse's format
+    pub fn stage_mouse(&mut self, mouse: &mut Mouse) {
+        for packet in mouse.take_packets() {
+            let encoded = match mouse.own_format() {
+                Some(format) => Encoded::Mouse(format.encode(&packet)),
+                None => Encoded::Backend,
+            };
+            self.stage(Report::Mouse(packet), encoded);
+        }
+        if let Some(format) = mouse.own_format() {
+            self.mouse_release = Some(format.encode(&[0; 5]));
+        }
+    }
+
+    fn stage(&mut self, report: Report, encoded: Encoded) {
+        self.reports.push(report);
+        self.encoded.push(encoded);
+        self.staged_at.get_or_insert_with(SystemTime::now);
+    }
+
+    /// Staged reports in the order they will be sent
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    /// Readable preview of the staged reports, one decoded event per line, e.g. "1. press LeftControl+KeyA"
+    pub fn to_pretty_string(&self) -> String {
+        let mut decoder = KeyDecoder::new();
+        let mut lines = vec![format!("{} ({} reports)", self.description, self.reports.len())];
+        for report in &self.reports {
+            match report {
+                Report::Keyboard(packet) => lines.extend(decoder.decode(packet.clone()).iter().map(|event| event.to_string())),
+                Report::Mouse(packet) => lines.push(format!(
+                    "mouse buttons 0x{:02X} x {} y {} wheel {}",
+                    packet[0], packet[1] as i8, packet[2] as i8, packet[3] as i8,
+                )),
+            }
+        }
+        lines.iter()
+            .enumerate()
+            .map(|(i, line)| if i == 0 { line.clone() } else { format!("{}. {}", i, line) })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn record(&self, approved_by: &str, outcome: AuditOutcome) -> AuditRecord {
+        let decided_at = SystemTime::now();
+        AuditRecord {
+            description: self.description.clone(),
+            approved_by: approved_by.to_string(),
+            preview: self.to_pretty_string(),
+            staged_at: self.staged_at.unwrap_or(decided_at),
+            decided_at,
+            outcome,
+        }
+    }
+
+    /// Discard the staged reports without sending them
+    pub fn abort(self, aborted_by: &str) -> AuditRecord {
+        self.record(aborted_by, AuditOutcome::Aborted)
+    }
+
+    /// Send the staged reports, as encoded when staged. The record's outcome is [AuditOutcome::Failed] if a send
+    /// failed, in which case every key and button is released if possible, in the staged keyboard's protocol and
+    /// mouse's format.
+    pub fn commit(self, hid: &mut dyn HidBackend, approved_by: &str) -> AuditRecord {
+        let sent = self.reports.iter().zip(&self.encoded).try_for_each(|(report, encoded)| match (report, encoded) {
+            (_, Encoded::Keyboard(reports, pacing)) => {
+                for report in reports {
+                    hid.send_key_packet(report)?;
+                }
+                self.clock.sleep(*pacing);
+                Ok(())
+            },
+            (_, Encoded::Mouse(report)) => hid.send_mouse_packet(report),
+            (Report::Keyboard(packet), Encoded::Backend) => hid.send_key_report(packet),
+            (Report::Mouse(packet), Encoded::Backend) => hid.send_mouse_report(packet),
+        });
+        let outcome = match sent {
+            Ok(()) => AuditOutcome::Committed,
+            Err(e) => {
+                let _ = match &self.key_release {
+                    Some(reports) => reports.iter().try_for_each(|report| hid.send_key_packet(report)),
+                    None => hid.send_key_report(&KeyPacket::new()),
+                };
+                let _ = match &self.mouse_release {
+                    Some(report) => hid.send_mouse_packet(report),
+                    None => hid.send_mouse_report(&[0; 5]),
+                };
+                AuditOutcome::Failed(e.to_string())
+            },
+        };
+        self.record(approved_by, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuditOutcome, Transaction};
+    use crate::{key::{BasicKey, KeyOrigin, Keyboard, Modifier}, mouse::{Mouse, MouseButton}};
+
+    #[test]
+    fn previews_staged_reports() {
+        let mut keyboard = Keyboard::new();
+        keyboard.press_shortcut(&[Modifier::LeftControl], &BasicKey::Char('a', KeyOrigin::Keyboard));
+        let mut mouse = Mouse::new();
+        mouse.press_button(MouseButton::Left);
+
+        let mut transaction = Transaction::new("select all");
+        transaction.stage_keyboard(&mut keyboard).unwrap();
+        transaction.stage_mouse(&mut mouse);
+        assert_eq!(transaction.reports().len(), 6);
+        assert_eq!(transaction.to_pretty_string(), "select all (6 reports)\n\
+            1. press LeftControl+KeyA\n\
+            2. release LeftControl+KeyA\n\
+            3. mouse buttons 0x01 x 0 y 0 wheel 0\n\
+            4. mouse buttons 0x00 x 0 y 0 wheel 0");
+
+        let record = transaction.abort("alex");
+        assert_eq!(record.outcome, AuditOutcome::Aborted);
+        assert!(record.to_string().contains("aborted by alex: select all"));
+    }
+
+    #[test]
+    fn commits_as_staged() {
+        use std::{io, time::{Duration, SystemTime}};
+
+        use crate::{key::{BootOverflow, ReportProtocol}, HidBackend};
+
+        /// Records key reports, failing after a number of them
+        struct Recording {
+            keys: Vec<Vec<u8>>,
+            fail_after: usize,
+        }
+
+        impl HidBackend for Recording {
+            fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                if self.keys.len() == self.fail_after {
+                    self.fail_after = usize::MAX;
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "unplugged"));
+                }
+                self.keys.push(data.to_vec());
+                Ok(())
+            }
+
+            fn send_mouse_packet(&mut self, _data: &[u8]) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+                Ok(None)
+            }
+        }
+
+        let mut keyboard = Keyboard::new();
+        keyboard.set_protocol(ReportProtocol::Boot);
+        keyboard.set_boot_overflow(BootOverflow::Split);
+        keyboard.press_basic_string_checked("ab");
+
+        let mut transaction = Transaction::new("type ab");
+        transaction.stage_keyboard(&mut keyboard).unwrap();
+        let staged = SystemTime::now();
+        keyboard.press_basic_string_checked("c");
+        transaction.stage_keyboard(&mut keyboard).unwrap();
+
+        let staged_reports = transaction.reports().len();
+        let mut backend = Recording { keys: Vec::new(), fail_after: usize::MAX };
+        let record = transaction.commit(&mut backend, "alex");
+        assert_eq!(record.outcome, AuditOutcome::Committed);
+        assert!(record.staged_at <= staged);
+        assert_eq!(backend.keys.len(), staged_reports);
+        assert!(backend.keys.iter().all(|report| report.len() == 8));
+
+        keyboard.press_basic_string_checked("ab");
+        let mut transaction = Transaction::new("type ab");
+        transaction.stage_keyboard(&mut keyboard).unwrap();
+        let mut backend = Recording { keys: Vec::new(), fail_after: 1 };
+        let record = transaction.commit(&mut backend, "alex");
+        assert!(matches!(record.outcome, AuditOutcome::Failed(_)));
+        assert_eq!(backend.keys.last(), Some(&vec![0; 8]));
+
+        keyboard.set_boot_overflow(BootOverflow::Error);
+        keyboard.hold_string("abcdefg");
+        let mut transaction = Transaction::new("too many keys");
+        assert!(transaction.stage_keyboard(&mut keyboard).is_err());
+        assert!(transaction.reports().is_empty());
+        assert!(!keyboard.take_packets().is_empty());
+    }
+}
@@ -0,0 +1,224 @@
+#![warn(missing_docs)]
+
+use std::{fs, io, path::Path, time::Duration};
+
+use crate::{HidBackend, config::HostOS, backend::LockKeys, key::{Keyboard, TypeErrorPolicy, TypeOptions}, terminal::{CommandWait, Terminal}};
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode bytes as padded base64
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Quote a word for sh, closing the quotes around each '
+fn sh_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Quote a word for PowerShell, doubling each single quote, including the typographic ones PowerShell also accepts
+fn powershell_quote(word: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in word.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Transfers a file to an air-gapped host by typing it as base64 and decoding it with a host-side command
+/// (certutil from PowerShell on Windows, base64 -d otherwise)
+pub struct FileTransfer {
+    /// Host OS
+    pub target_os: HostOS,
+    /// Base64 characters typed per command
+    pub chunk_len: usize,
+    /// Wait after each command
+    pub wait: CommandWait,
+    /// Number of commands between checkpoints. At a checkpoint the host is handshaked to confirm it caught up.
+    pub checkpoint_every: usize,
+    /// Handshake timeout at checkpoints
+    pub checkpoint_timeout: Duration,
+}
+
+impl FileTransfer {
+    /// New transfer to a host OS with default chunking and pacing
+    pub fn new(target_os: HostOS) -> FileTransfer {
+        FileTransfer {
+            target_os,
+            chunk_len: 512,
+            wait: CommandWait::Delay(Duration::from_millis(50)),
+            checkpoint_every: 16,
+            checkpoint_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Commands that recreate the data as a file named name on the host
+    pub fn commands(&self, name: &str, data: &[u8]) -> Vec<String> {
+        let encoded = base64_encode(data);
+        let encoded_name = format!("{}.b64", name);
+        let quote = match self.target_os {
+            HostOS::Windows => powershell_quote,
+            HostOS::Linux | HostOS::MacOS => sh_quote,
+        };
+        let (name, encoded_name) = (quote(name), quote(&encoded_name));
+        let chunks = encoded.as_bytes()
+            .chunks(self.chunk_len.max(4))
+            .map(|chunk| String::from_utf8_lossy(chunk));
+        let mut commands = Vec::new();
+        match self.target_os {
+            HostOS::Windows => {
+                commands.push(format!("Set-Content -NoNewline {} ''", encoded_name));
+                commands.extend(chunks.map(|chunk| format!("Add-Content {} '{}'", encoded_name, chunk)));
+                commands.push(format!("certutil -f -decode {0} {1}; if ($?) {{ Remove-Item {0} }}", encoded_name, name));
+            },
+            HostOS::Linux | HostOS::MacOS => {
+                commands.push(format!(": > {}", encoded_name));
+                commands.extend(chunks.map(|chunk| format!("echo {} >> {}", chunk, encoded_name)));
+                commands.push(format!("base64 -d < {0} > {1} && rm {0}", encoded_name, name));
+            },
+        }
+        commands
+    }
+
+    /// Type the file at path into the host's current directory under the same file name, starting from command
+    /// resume_from (0 for a new transfer). on_checkpoint is called with the number of commands completed at each
+    /// checkpoint, pass it back as resume_from to continue an interrupted transfer. A command the keyboard's layout
+    /// can't type fully isn't typed, and the transfer stops with an InvalidInput error.
+    pub fn send(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, path: &Path, resume_from: usize, on_checkpoint: impl FnMut(usize)) -> io::Result<()> {
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+        let data = fs::read(path)?;
+
+        let options = keyboard.type_options();
+        keyboard.set_type_options(TypeOptions { error_policy: TypeErrorPolicy::Abort, ..options.clone() });
+        let sent = self.send_commands(keyboard, hid, &self.commands(name, &data), resume_from, on_checkpoint);
+        keyboard.set_type_options(options);
+        sent
+    }
+
+    /// Type commands from resume_from on, see [FileTransfer::send]
+    fn send_commands(&self, keyboard: &mut Keyboard, hid: &mut dyn HidBackend, commands: &[String], resume_from: usize, mut on_checkpoint: impl FnMut(usize)) -> io::Result<()> {
+        let mut terminal = Terminal::new(self.target_os);
+        terminal.set_wait(self.wait);
+        for (i, command) in commands.iter().enumerate().skip(resume_from) {
+            let unmappable = terminal.run(keyboard, hid, command)?;
+            if !unmappable.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("can't type {:?}", unmappable)));
+            }
+            let done = i + 1;
+            if self.checkpoint_every != 0 && done % self.checkpoint_every == 0 && done != commands.len() {
+                hid.handshake(self.checkpoint_timeout)?;
+                on_checkpoint(done);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Type the file at path to a host OS with the default [FileTransfer] settings
+pub fn transfer_file_via_typing(keyboard: &mut Keyboard, hid: &mut dyn HidBackend, path: &Path, target_os: HostOS) -> io::Result<()> {
+    FileTransfer::new(target_os).send(keyboard, hid, path, 0, |_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_encode, FileTransfer};
+    use crate::config::HostOS;
+
+    #[test]
+    fn base64_commands() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+
+        let mut transfer = FileTransfer::new(HostOS::Linux);
+        transfer.chunk_len = 4;
+        assert_eq!(transfer.commands("f", b"foob"), vec![
+            ": > 'f.b64'",
+            "echo Zm9v >> 'f.b64'",
+            "echo Yg== >> 'f.b64'",
+            "base64 -d < 'f.b64' > 'f' && rm 'f.b64'",
+        ]);
+        transfer.target_os = HostOS::Windows;
+        assert_eq!(transfer.commands("f", b"foob"), vec![
+            "Set-Content -NoNewline 'f.b64' ''",
+            "Add-Content 'f.b64' 'Zm9v'",
+            "Add-Content 'f.b64' 'Yg=='",
+            "certutil -f -decode 'f.b64' 'f'; if ($?) { Remove-Item 'f.b64' }",
+        ]);
+    }
+
+    #[test]
+    fn quotes_names() {
+        let mut transfer = FileTransfer::new(HostOS::Linux);
+        assert_eq!(
+            transfer.commands("it's", b"").last().unwrap(),
+            "base64 -d < 'it'\\''s.b64' > 'it'\\''s' && rm 'it'\\''s.b64'"
+        );
+        transfer.target_os = HostOS::Windows;
+        assert_eq!(
+            transfer.commands("it's \u{2019}x", b"").last().unwrap(),
+            "certutil -f -decode 'it''s \u{2019}\u{2019}x.b64' 'it''s \u{2019}\u{2019}x'; if ($?) { Remove-Item 'it''s \u{2019}\u{2019}x.b64' }"
+        );
+    }
+    #[test]
+    fn stops_at_untypable_commands() {
+        use std::{fs, io, time::Duration};
+
+        use crate::{key::{KeyPacket, Keyboard, TypeErrorPolicy}, terminal::CommandWait, HidBackend};
+
+        #[derive(Default)]
+        struct Typed(Vec<Vec<u8>>);
+
+        impl HidBackend for Typed {
+            fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+                self.0.push(data.to_vec());
+                Ok(())
+            }
+
+            fn send_mouse_packet(&mut self, _data: &[u8]) -> io::Result<()> {
+                Ok(())
+            }
+
+            fn receive_states_packet(&mut self, _timeout: Duration) -> io::Result<Option<u8>> {
+                Ok(None)
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("virt-hid-transfer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("caf\u{e9}");
+        fs::write(&path, b"foob").unwrap();
+
+        let mut transfer = FileTransfer::new(HostOS::Linux);
+        transfer.wait = CommandWait::None;
+        let mut keyboard = Keyboard::new();
+        let mut typed = Typed::default();
+        let res = transfer.send(&mut keyboard, &mut typed, &path, 0, |_| ());
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        // nothing of the command was typed, only Enter on an empty line
+        let enter = KeyPacket::parse("ReturnEnter").unwrap();
+        assert_eq!(typed.0, vec![enter.as_bytes().to_vec(), KeyPacket::new().as_bytes().to_vec()]);
+        assert_eq!(keyboard.type_options().error_policy, TypeErrorPolicy::Skip);
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Serialize, Deserialize};
 
 
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
+#[derive(Debug, Eq, Hash, PartialEq, Clone, Copy, Serialize, Deserialize, IntoPrimitive, FromPrimitive)]
 #[repr(u32)]
 /// Modifier Keys
 pub enum Modifier {
@@ -27,6 +27,14 @@ pub enum Modifier {
 }
 
 impl Modifier {
+    /// Modifiers set in a keycode byte
+    pub fn from_byte(byte: u8) -> Vec<Modifier> {
+        (0..8)
+            .map(Modifier::from)
+            .filter(|modifier| byte & modifier.to_mkbyte() != 0)
+            .collect()
+    }
+
     /// A list of modifiers to keycode bytes
     pub fn all_to_byte(modifiers: &[Modifier]) -> u8 {
         modifiers.iter()
@@ -358,7 +366,23 @@ pub enum SpecialKey {
     Comma,
 }
 
+const FUNCTION_KEYS: [SpecialKey; 24] = [
+    SpecialKey::F1, SpecialKey::F2, SpecialKey::F3, SpecialKey::F4, SpecialKey::F5, SpecialKey::F6, SpecialKey::F7, SpecialKey::F8, SpecialKey::F9, SpecialKey::F10, SpecialKey::F11, SpecialKey::F12,
+    SpecialKey::F13, SpecialKey::F14, SpecialKey::F15, SpecialKey::F16, SpecialKey::F17, SpecialKey::F18, SpecialKey::F19, SpecialKey::F20, SpecialKey::F21, SpecialKey::F22, SpecialKey::F23, SpecialKey::F24,
+];
+
 impl SpecialKey {
+    /// Function key Fn, for n in 1..=24. The keyboard usage page has no usages past F24, hosts
+    /// that support more map them from consumer or vendor usages, which must be sent through those interfaces.
+    pub fn function_key(n: u8) -> Option<SpecialKey> {
+        FUNCTION_KEYS.get((n as usize).checked_sub(1)?).copied()
+    }
+
+    /// Function key number, if this is a function key
+    pub fn function_number(&self) -> Option<u8> {
+        FUNCTION_KEYS.iter().position(|key| key == self).map(|i| i as u8 + 1)
+    }
+
     /// Special Key to Byte
     pub fn to_kbyte(&self) -> u8 {
         match self {
@@ -519,7 +543,7 @@ impl ToKBytes for char {
     fn to_kbytes(&self, key_origin: &KeyOrigin) -> Option<[u8;2]> {
         match key_origin {
             KeyOrigin::Keyboard => match self {
-                '\n' =>  Some([0x00, SpecialKey::Enter.to_kbyte()]),
+                '\n' =>  Some([0x00, SpecialKey::ReturnEnter.to_kbyte()]),
                 '\t' =>  Some([0x00, SpecialKey::Tab.to_kbyte()]),
                 ' ' => Some([0x00, SpecialKey::Spacebar.to_kbyte()]),
                 'a' => Some([0x00, 0x04]), // 4, Some([0x00, 0x04]), Keyboard, 'a', 'A'
@@ -649,4 +673,40 @@ impl ToKBytes for char {
             KeyOrigin::Misc => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Chars the translation table of a key origin can type without a layout, in code point order.
+/// Other chars need a layout (see [crate::key::Keyboard::set_layout]) or the Unicode fallback.
+pub fn supported_chars(key_origin: KeyOrigin) -> impl Iterator<Item = char> {
+    (char::MIN..=char::MAX).filter(move |c| c.to_kbytes(&key_origin).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{supported_chars, KeyOrigin, SpecialKey};
+
+    #[test]
+    fn function_keys() {
+        assert_eq!(SpecialKey::function_key(0), None);
+        assert_eq!(SpecialKey::function_key(25), None);
+        assert_eq!(SpecialKey::function_key(1), Some(SpecialKey::F1));
+        assert_eq!(SpecialKey::function_key(24), Some(SpecialKey::F24));
+        for n in 1..=24 {
+            let key = SpecialKey::function_key(n).unwrap();
+            assert_eq!(key.function_number(), Some(n));
+            let usage = if n <= 12 { 0x3A + n - 1 } else { 0x68 + n - 13 };
+            assert_eq!(key.to_kbyte(), usage, "F{}", n);
+        }
+        assert_eq!(SpecialKey::Escape.function_number(), None);
+    }
+
+    #[test]
+    fn enumerates_supported_chars() {
+        let keyboard: Vec<char> = supported_chars(KeyOrigin::Keyboard).collect();
+        assert!(keyboard.contains(&'a') && keyboard.contains(&'~') && keyboard.contains(&'\n'));
+        assert!(!keyboard.contains(&'é'));
+        assert!(keyboard.windows(2).all(|w| w[0] < w[1]));
+        assert!(supported_chars(KeyOrigin::Keypad).any(|c| c == '*'));
+        assert_eq!(supported_chars(KeyOrigin::Misc).count(), 0);
+    }
+}
@@ -0,0 +1,138 @@
+#![warn(missing_docs)]
+
+use serde::{Serialize, Deserialize};
+
+use crate::{key::{BasicKey, Keyboard, SpecialKey}, rng::{next_f64, RngCore, SplitMix64}};
+
+const QWERTY_ROWS: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Step of typing text with simulated typos
+pub enum TypoStep {
+    /// Type text
+    Type(String),
+    /// Press backspace a number of times
+    Backspace(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// Statistical model of typing errors. The same seed always produces the same typos for the same text.
+pub struct TypoModel {
+    /// Probability of mistyping each letter or digit as a neighbouring key (QWERTY)
+    pub rate: f64,
+    /// Most characters typed after a typo before it's noticed and corrected
+    pub max_noticed_after: usize,
+    /// Random seed
+    pub seed: u64,
+}
+
+impl Default for TypoModel {
+    fn default() -> Self {
+        TypoModel { rate: 0.03, max_noticed_after: 2, seed: 0 }
+    }
+}
+
+impl TypoModel {
+    /// New
+    pub fn new(rate: f64, max_noticed_after: usize, seed: u64) -> TypoModel {
+        TypoModel { rate, max_noticed_after, seed }
+    }
+
+    /// Plan typing text with typos, each followed by backspacing to it and retyping correctly
+    pub fn plan(&self, text: &str) -> Vec<TypoStep> {
+        self.plan_with(text, &mut SplitMix64::new(self.seed))
+    }
+
+    /// Plan typing text with typos like [TypoModel::plan], drawing from a random number generator instead of the seed
+    pub fn plan_with(&self, text: &str, rng: &mut dyn RngCore) -> Vec<TypoStep> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut steps = Vec::new();
+        let mut typed = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let typo = match neighbour_key(chars[i], rng) {
+                Some(typo) if next_f64(rng) < self.rate => typo,
+                _ => {
+                    typed.push(chars[i]);
+                    i += 1;
+                    continue;
+                },
+            };
+            let noticed_after = (rng.next_u64() % (self.max_noticed_after as u64 + 1)) as usize;
+            let noticed_after = noticed_after.min(chars.len() - i - 1);
+            typed.push(typo);
+            typed.extend(&chars[i + 1..i + 1 + noticed_after]);
+            steps.push(TypoStep::Type(std::mem::take(&mut typed)));
+            steps.push(TypoStep::Backspace(noticed_after + 1));
+            typed.push(chars[i]);
+            i += 1;
+        }
+        if !typed.is_empty() {
+            steps.push(TypoStep::Type(typed));
+        }
+        steps
+    }
+}
+
+/// Random key next to c on a QWERTY keyboard, keeping its case
+fn neighbour_key(c: char, rng: &mut dyn RngCore) -> Option<char> {
+    let lower = c.to_ascii_lowercase();
+    let (row, col) = QWERTY_ROWS.iter()
+        .enumerate()
+        .find_map(|(row, keys)| keys.find(lower).map(|col| (row, col)))?;
+    let mut neighbours = Vec::new();
+    for (r, keys) in QWERTY_ROWS.iter().enumerate() {
+        if r.abs_diff(row) > 1 {
+            continue;
+        }
+        for (k, key) in keys.chars().enumerate() {
+            if k.abs_diff(col) <= 1 && key != lower {
+                neighbours.push(key);
+            }
+        }
+    }
+    let typo = neighbours[(rng.next_u64() % neighbours.len() as u64) as usize];
+    Some(if c.is_ascii_uppercase() { typo.to_ascii_uppercase() } else { typo })
+}
+
+impl Keyboard {
+    /// Send keystrokes of a string using the configured layout, injecting typos and their corrections from a model.
+    /// Returns the grapheme clusters that couldn't be typed.
+    pub fn type_with_typos(&mut self, str: &str, model: &TypoModel) -> Vec<String> {
+        let mut unmappable = Vec::new();
+        for step in model.plan(str) {
+            match step {
+                TypoStep::Type(text) => unmappable.extend(self.type_string(&text)),
+                TypoStep::Backspace(n) => {
+                    for _ in 0..n {
+                        self.press_key(&BasicKey::Special(SpecialKey::Backspace));
+                    }
+                },
+            }
+        }
+        unmappable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TypoModel, TypoStep};
+
+    #[test]
+    fn typos_are_corrected() {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let model = TypoModel::new(0.2, 3, 42);
+        let steps = model.plan(text);
+        assert_eq!(steps, model.plan(text));
+        assert!(steps.iter().any(|step| matches!(step, TypoStep::Backspace(_))));
+
+        let mut result = String::new();
+        for step in steps {
+            match step {
+                TypoStep::Type(typed) => result.push_str(&typed),
+                TypoStep::Backspace(n) => result.truncate(result.len() - n),
+            }
+        }
+        assert_eq!(result, text);
+    }
+}
@@ -0,0 +1,143 @@
+#![warn(missing_docs)]
+
+use std::{fs::{File, OpenOptions}, io::{self, Read, Write}, time::{Duration, Instant}};
+
+use crate::{HidBackend, descriptor, device::VirtualDevice, hid::wait_readable};
+
+const UHID_PATH: &str = "/dev/uhid";
+
+const UHID_DESTROY: u32 = 1;
+const UHID_OUTPUT: u32 = 6;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+
+const UHID_NAME_LEN: usize = 128;
+const UHID_PHYS_LEN: usize = 64;
+const UHID_UNIQ_LEN: usize = 64;
+const UHID_DATA_MAX: usize = 4096;
+const UHID_EVENT_LEN: usize = 4380;
+const UHID_OUTPUT_DATA_IDX: usize = 4;
+const UHID_OUTPUT_SIZE_IDX: usize = UHID_OUTPUT_DATA_IDX + UHID_DATA_MAX;
+
+const BUS_USB: u16 = 0x03;
+const VENDOR_ID: u32 = 0x1D6B;
+const PRODUCT_ID: u32 = 0x0104;
+
+/// Kernel virtual HID device created through /dev/uhid. The device is destroyed on drop.
+pub struct UHIDDevice {
+    file: File,
+}
+
+impl UHIDDevice {
+    /// Create a virtual HID device from a report descriptor
+    pub fn create(name: &str, descriptor: &[u8]) -> io::Result<UHIDDevice> {
+        if descriptor.len() > UHID_DATA_MAX {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "report descriptor too long"));
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(UHID_PATH)?;
+
+        let mut name_bytes = [0; UHID_NAME_LEN];
+        let name_len = name.len().min(UHID_NAME_LEN - 1);
+        name_bytes[..name_len].copy_from_slice(&name.as_bytes()[..name_len]);
+
+        // struct uhid_event { type; struct uhid_create2_req } packed
+        let mut event = Vec::new();
+        event.extend(UHID_CREATE2.to_ne_bytes());
+        event.extend(name_bytes);
+        event.extend([0; UHID_PHYS_LEN]);
+        event.extend([0; UHID_UNIQ_LEN]);
+        event.extend((descriptor.len() as u16).to_ne_bytes());
+        event.extend(BUS_USB.to_ne_bytes());
+        event.extend(VENDOR_ID.to_ne_bytes());
+        event.extend(PRODUCT_ID.to_ne_bytes());
+        event.extend(0u32.to_ne_bytes()); // version
+        event.extend(0u32.to_ne_bytes()); // country
+        event.extend(descriptor);
+        file.write_all(&event)?;
+
+        Ok(UHIDDevice { file })
+    }
+
+    /// Create a virtual HID device for a [VirtualDevice], named after its interface
+    pub fn from_device(device: &dyn VirtualDevice) -> io::Result<UHIDDevice> {
+        UHIDDevice::create(device.interface_name(), &device.descriptor())
+    }
+
+    /// Feed an input report to the kernel
+    pub fn send_input(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() > UHID_DATA_MAX {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "input report too long"));
+        }
+        // struct uhid_event { type; struct uhid_input2_req } packed
+        let mut event = Vec::new();
+        event.extend(UHID_INPUT2.to_ne_bytes());
+        event.extend((data.len() as u16).to_ne_bytes());
+        event.extend(data);
+        self.file.write_all(&event)
+    }
+
+    /// Receive the next output report from the kernel with a timeout, skipping other events
+    pub fn receive_output(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + timeout;
+        let mut event = vec![0; UHID_EVENT_LEN];
+        while wait_readable(&self.file, deadline.saturating_duration_since(Instant::now()))? {
+            let len = self.file.read(&mut event)?;
+            if len < UHID_OUTPUT_SIZE_IDX + 2 {
+                continue;
+            }
+            if u32::from_ne_bytes([event[0], event[1], event[2], event[3]]) == UHID_OUTPUT {
+                let size = u16::from_ne_bytes([event[UHID_OUTPUT_SIZE_IDX], event[UHID_OUTPUT_SIZE_IDX + 1]]) as usize;
+                let data = &event[UHID_OUTPUT_DATA_IDX..UHID_OUTPUT_DATA_IDX + size.min(UHID_DATA_MAX)];
+                return Ok(Some(data.to_vec()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for UHIDDevice {
+    fn drop(&mut self) {
+        let _ = self.file.write_all(&UHID_DESTROY.to_ne_bytes());
+    }
+}
+
+/// Virtual host loopback. Creates a kernel keyboard and mouse from the crate's report descriptors
+/// and feeds packets into them, so the kernel parses them exactly as a host would. Keyboards and mice send to it
+/// like to any other [HidBackend].
+pub struct UHIDLoopback {
+    keyboard: UHIDDevice,
+    mouse: UHIDDevice,
+}
+
+impl UHIDLoopback {
+    /// Name of the virtual keyboard
+    pub const KEYBOARD_NAME: &'static str = "virt-hid keyboard";
+    /// Name of the virtual mouse
+    pub const MOUSE_NAME: &'static str = "virt-hid mouse";
+
+    /// Create new loopback
+    pub fn new() -> io::Result<UHIDLoopback> {
+        Ok(UHIDLoopback {
+            keyboard: UHIDDevice::create(UHIDLoopback::KEYBOARD_NAME, descriptor::KEYBOARD)?,
+            mouse: UHIDDevice::create(UHIDLoopback::MOUSE_NAME, descriptor::MOUSE)?,
+        })
+    }
+
+}
+
+impl HidBackend for UHIDLoopback {
+    fn send_key_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        self.keyboard.send_input(data)
+    }
+
+    fn send_mouse_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        self.mouse.send_input(data)
+    }
+
+    fn receive_states_packet(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        Ok(self.keyboard.receive_output(timeout)?.and_then(|data| data.first().copied()))
+    }
+}
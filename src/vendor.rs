@@ -0,0 +1,91 @@
+#![warn(missing_docs)]
+
+use std::{fs::{File, OpenOptions}, io::{self, Read, Write}, time::Duration};
+
+use crate::hid::wait_readable;
+
+/// Vendor defined HID interface, e.g. /dev/hidg2 set up with [crate::descriptor::vendor].
+/// Exchanges fixed length reports with a custom host agent alongside the keyboard and mouse.
+pub struct VendorHID {
+    file: File,
+    report_len: usize,
+}
+
+impl VendorHID {
+    /// Open vendor HID interface with reports of report_len bytes
+    pub fn new(path: &str, report_len: usize) -> io::Result<VendorHID> {
+        if report_len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "report length must be at least 1"));
+        }
+        Ok(VendorHID {
+            file: OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)?,
+            report_len,
+        })
+    }
+
+    /// Report length in bytes
+    pub fn report_len(&self) -> usize {
+        self.report_len
+    }
+
+    /// Send one IN report. Data shorter than the report length is zero padded.
+    pub fn send_report(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() > self.report_len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "data longer than report length"));
+        }
+        let mut report = vec![0; self.report_len];
+        report[..data.len()].copy_from_slice(data);
+        self.file.write_all(&report)?;
+        self.file.sync_all()
+    }
+
+    /// Send data of any length as a series of IN reports, zero padding the last one.
+    /// The host agent is responsible for knowing where the data ends.
+    pub fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        for chunk in data.chunks(self.report_len) {
+            self.send_report(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Receive one OUT report from the host with a timeout
+    pub fn receive_report(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        if !wait_readable(&self.file, timeout)? {
+            return Ok(None);
+        }
+        let mut report = vec![0; self.report_len];
+        let len = self.file.read(&mut report)?;
+        report.truncate(len);
+        Ok(Some(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::ErrorKind, process, time::Duration};
+
+    use super::VendorHID;
+    use crate::descriptor::{application_usage, vendor};
+
+    #[test]
+    fn exchanges_padded_reports() {
+        assert_eq!(application_usage(&vendor(64)), Some((0xFF00, 0x01)));
+
+        let path = env::temp_dir().join(format!("virt-hid-vendor-{}", process::id()));
+        fs::write(&path, b"").unwrap();
+        let path_str = path.to_str().unwrap();
+        assert_eq!(VendorHID::new(path_str, 0).err().map(|e| e.kind()), Some(ErrorKind::InvalidInput));
+
+        let mut hid = VendorHID::new(path_str, 4).unwrap();
+        hid.send(b"hello").unwrap();
+        assert_eq!(hid.send_report(b"hello").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&path).unwrap(), b"hello\0\0\0");
+
+        let mut host = VendorHID::new(path_str, 4).unwrap();
+        assert_eq!(host.receive_report(Duration::ZERO).unwrap(), Some(b"hell".to_vec()));
+        fs::remove_file(path).unwrap();
+    }
+}
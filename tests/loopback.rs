@@ -0,0 +1,262 @@
+// Hardware in the loop tests. They need a device acting as both gadget and host (e.g. a Pi 4
+// with its OTG port cabled to one of its own USB host ports, or a uhid loopback) and read back
+// what the host sees through evdev.
+//
+// Set the device paths and run as root:
+//   VIRT_HID_KEYBOARD=/dev/hidg0 VIRT_HID_MOUSE=/dev/hidg1 VIRT_HID_LED=/dev/hidg0 \
+//   VIRT_HID_EVDEV_KEYBOARD=/dev/input/event0 VIRT_HID_EVDEV_MOUSE=/dev/input/event1 \
+//   cargo test --test loopback -- --ignored
+//
+// The uhid tests need no hardware, only root access to /dev/uhid:
+//   cargo test --features uhid --test loopback -- --ignored uhid
+
+use std::{
+    collections::BTreeSet,
+    env,
+    fs::File,
+    io::Read,
+    mem,
+    sync::{mpsc::{self, Receiver}, Mutex},
+    thread,
+    time::Duration,
+};
+
+use virt_hid::{HID, key::{BasicKey, KeyOrigin, Keyboard, Modifier}, mouse::{Delta, Mouse, MouseButton, MouseDir}};
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_WHEEL: u16 = 0x08;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const KEY_REPEAT: i32 = 2;
+
+const KEY_1: u16 = 2;
+const KEY_A: u16 = 30;
+const KEY_ENTER: u16 = 28;
+const KEY_H: u16 = 35;
+const KEY_I: u16 = 23;
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_SPACE: u16 = 57;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+// Tests share the devices so run them one at a time
+static DEVICES: Mutex<()> = Mutex::new(());
+
+/// Input events (type, code, value) reported between two EV_SYN events
+type Frame = BTreeSet<(u16, u16, i32)>;
+
+struct EventReader {
+    frames: Receiver<Frame>,
+}
+
+impl EventReader {
+    fn open(path: &str) -> EventReader {
+        let mut file = File::open(path).unwrap_or_else(|e| panic!("open {}: {}", path, e));
+        let (tx, frames) = mpsc::channel();
+        thread::spawn(move || {
+            // struct input_event { struct timeval time; __u16 type; __u16 code; __s32 value; }
+            let time_len = 2 * mem::size_of::<usize>();
+            let mut buf = vec![0; time_len + 8];
+            let mut frame = Frame::new();
+            while file.read_exact(&mut buf).is_ok() {
+                let ev_type = u16::from_ne_bytes([buf[time_len], buf[time_len + 1]]);
+                let code = u16::from_ne_bytes([buf[time_len + 2], buf[time_len + 3]]);
+                let value = i32::from_ne_bytes([buf[time_len + 4], buf[time_len + 5], buf[time_len + 6], buf[time_len + 7]]);
+                match ev_type {
+                    EV_SYN if !frame.is_empty() => match tx.send(mem::take(&mut frame)) {
+                        Ok(()) => (),
+                        Err(_) => return,
+                    },
+                    EV_KEY if value != KEY_REPEAT => {
+                        frame.insert((ev_type, code, value));
+                    },
+                    EV_REL => {
+                        frame.insert((ev_type, code, value));
+                    },
+                    _ => (),
+                }
+            }
+        });
+        EventReader { frames }
+    }
+
+    fn drain(&self) {
+        while self.frames.recv_timeout(Duration::from_millis(100)).is_ok() {}
+    }
+
+    fn read(&self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        while let Ok(frame) = self.frames.recv_timeout(TIMEOUT) {
+            frames.push(frame);
+        }
+        frames
+    }
+}
+
+struct Loopback {
+    hid: HID,
+    keyboard_events: EventReader,
+    mouse_events: EventReader,
+}
+
+fn env_path(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("set {} to run the loopback tests", name))
+}
+
+impl Loopback {
+    fn new() -> Loopback {
+        let hid = HID::new(&env_path("VIRT_HID_MOUSE"), &env_path("VIRT_HID_KEYBOARD"), &env_path("VIRT_HID_LED"))
+            .expect("open gadget");
+        let loopback = Loopback {
+            hid,
+            keyboard_events: EventReader::open(&env_path("VIRT_HID_EVDEV_KEYBOARD")),
+            mouse_events: EventReader::open(&env_path("VIRT_HID_EVDEV_MOUSE")),
+        };
+        loopback.keyboard_events.drain();
+        loopback.mouse_events.drain();
+        loopback
+    }
+}
+
+fn frame(events: &[(u16, u16, i32)]) -> Frame {
+    events.iter().cloned().collect()
+}
+
+fn taps(codes: &[u16]) -> Vec<Frame> {
+    codes.iter()
+        .flat_map(|code| [frame(&[(EV_KEY, *code, 1)]), frame(&[(EV_KEY, *code, 0)])])
+        .collect()
+}
+
+#[test]
+#[ignore]
+fn types_basic_string() {
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = Loopback::new();
+    let mut keyboard = Keyboard::new();
+    keyboard.press_basic_string_checked("hi 1\n");
+    keyboard.send(&mut loopback.hid).unwrap();
+    assert_eq!(loopback.keyboard_events.read(), taps(&[KEY_H, KEY_I, KEY_SPACE, KEY_1, KEY_ENTER]));
+}
+
+#[test]
+#[ignore]
+fn types_shifted_char() {
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = Loopback::new();
+    let mut keyboard = Keyboard::new();
+    keyboard.press_basic_string_checked("A");
+    keyboard.send(&mut loopback.hid).unwrap();
+    assert_eq!(loopback.keyboard_events.read(), vec![
+        frame(&[(EV_KEY, KEY_LEFTSHIFT, 1), (EV_KEY, KEY_A, 1)]),
+        frame(&[(EV_KEY, KEY_LEFTSHIFT, 0), (EV_KEY, KEY_A, 0)]),
+    ]);
+}
+
+#[test]
+#[ignore]
+fn presses_shortcut() {
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = Loopback::new();
+    let mut keyboard = Keyboard::new();
+    keyboard.press_shortcut(&[Modifier::LeftControl], &BasicKey::Char('a', KeyOrigin::Keyboard));
+    keyboard.send(&mut loopback.hid).unwrap();
+    assert_eq!(loopback.keyboard_events.read(), vec![
+        frame(&[(EV_KEY, KEY_LEFTCTRL, 1), (EV_KEY, KEY_A, 1)]),
+        frame(&[(EV_KEY, KEY_LEFTCTRL, 0), (EV_KEY, KEY_A, 0)]),
+    ]);
+}
+
+#[test]
+#[ignore]
+fn holds_and_releases_key() {
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = Loopback::new();
+    let mut keyboard = Keyboard::new();
+    keyboard.hold_mod(&Modifier::LeftShift);
+    keyboard.send(&mut loopback.hid).unwrap();
+    keyboard.release_mod(&Modifier::LeftShift);
+    keyboard.send(&mut loopback.hid).unwrap();
+    assert_eq!(loopback.keyboard_events.read(), taps(&[KEY_LEFTSHIFT]));
+}
+
+#[test]
+#[ignore]
+fn moves_and_scrolls_mouse() {
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = Loopback::new();
+    let mut mouse = Mouse::new();
+    mouse.move_mouse(Delta::new(10).unwrap(), MouseDir::X);
+    mouse.move_mouse(Delta::new(-5).unwrap(), MouseDir::Y);
+    mouse.scroll_wheel(Delta::new(1).unwrap());
+    mouse.send(&mut loopback.hid).unwrap();
+    assert_eq!(loopback.mouse_events.read(), vec![
+        frame(&[(EV_REL, REL_X, 10), (EV_REL, REL_Y, -5), (EV_REL, REL_WHEEL, 1)]),
+    ]);
+}
+
+#[test]
+#[ignore]
+fn clicks_mouse_buttons() {
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = Loopback::new();
+    let mut mouse = Mouse::new();
+    mouse.press_button(MouseButton::Left);
+    mouse.send(&mut loopback.hid).unwrap();
+    mouse.hold_button(MouseButton::Right);
+    mouse.send(&mut loopback.hid).unwrap();
+    mouse.release_button(MouseButton::Right);
+    mouse.send(&mut loopback.hid).unwrap();
+    assert_eq!(loopback.mouse_events.read(), taps(&[BTN_LEFT, BTN_RIGHT]));
+}
+
+#[cfg(feature = "uhid")]
+fn find_event_node(name: &str) -> String {
+    for _ in 0..50 {
+        for entry in std::fs::read_dir("/sys/class/input").unwrap().flatten() {
+            let node = entry.file_name().to_string_lossy().to_string();
+            let device_name = std::fs::read_to_string(entry.path().join("device/name")).unwrap_or_default();
+            if node.starts_with("event") && device_name.trim() == name {
+                return format!("/dev/input/{}", node);
+            }
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("no input device named {:?}", name)
+}
+
+#[test]
+#[ignore]
+#[cfg(feature = "uhid")]
+fn uhid_types_and_moves() {
+    use virt_hid::uhid::UHIDLoopback;
+
+    let _lock = DEVICES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut loopback = UHIDLoopback::new().expect("create uhid devices");
+    let keyboard_events = EventReader::open(&find_event_node(UHIDLoopback::KEYBOARD_NAME));
+    let mouse_events = EventReader::open(&find_event_node(UHIDLoopback::MOUSE_NAME));
+    keyboard_events.drain();
+    mouse_events.drain();
+
+    let mut keyboard = Keyboard::new();
+    keyboard.press_basic_string_checked("hA");
+    keyboard.flush(&mut loopback).unwrap();
+    let mut expected = taps(&[KEY_H]);
+    expected.push(frame(&[(EV_KEY, KEY_LEFTSHIFT, 1), (EV_KEY, KEY_A, 1)]));
+    expected.push(frame(&[(EV_KEY, KEY_LEFTSHIFT, 0), (EV_KEY, KEY_A, 0)]));
+    assert_eq!(keyboard_events.read(), expected);
+
+    let mut mouse = Mouse::new();
+    mouse.move_mouse(Delta::new(10).unwrap(), MouseDir::X);
+    mouse.press_button(MouseButton::Left);
+    mouse.send(&mut loopback).unwrap();
+    assert_eq!(mouse_events.read(), vec![
+        frame(&[(EV_KEY, BTN_LEFT, 1), (EV_REL, REL_X, 10)]),
+        frame(&[(EV_KEY, BTN_LEFT, 0)]),
+    ]);
+}